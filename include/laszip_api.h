@@ -0,0 +1,407 @@
+/*
+ * C API of the laz crate, compatible with the one of the LASzip DLL.
+ *
+ * Generated from src/capi.rs by cbindgen, do not edit manually.
+ *
+ * Build the library with the `capi` feature, e.g.:
+ *
+ *   cargo rustc --release --lib --features capi --crate-type cdylib
+ *
+ * All functions return 0 on success and 1 on error,
+ * use laszip_get_error to retrieve the error message.
+ *
+ * Spatial indexing, the compatibility mode and C++ streams are not supported,
+ * the functions related to them always return an error.
+ */
+
+#ifndef LASZIP_API_H
+#define LASZIP_API_H
+
+#include <stdint.h>
+#define LASZIP_API
+
+typedef struct laszip_point {
+  int32_t X;
+  int32_t Y;
+  int32_t Z;
+  uint16_t intensity;
+  uint8_t return_number : 3;
+  uint8_t number_of_returns : 3;
+  uint8_t scan_direction_flag : 1;
+  uint8_t edge_of_flight_line : 1;
+  uint8_t classification : 5;
+  uint8_t synthetic_flag : 1;
+  uint8_t keypoint_flag : 1;
+  uint8_t withheld_flag : 1;
+  int8_t scan_angle_rank;
+  uint8_t user_data;
+  uint16_t point_source_ID;
+
+  /* LAS 1.4 only */
+  int16_t extended_scan_angle;
+  uint8_t extended_point_type : 2;
+  uint8_t extended_scanner_channel : 2;
+  uint8_t extended_classification_flags : 4;
+  uint8_t extended_classification;
+  uint8_t extended_return_number : 4;
+  uint8_t extended_number_of_returns : 4;
+
+  /* for 8 byte alignment of the GPS time */
+  uint8_t dummy[7];
+
+  double gps_time;
+  uint16_t rgb[4];
+  uint8_t wave_packet[29];
+
+  int32_t num_extra_bytes;
+  uint8_t* extra_bytes;
+} laszip_point_struct;
+
+typedef int32_t laszip_I32;
+
+typedef uint8_t laszip_U8;
+
+typedef uint16_t laszip_U16;
+
+typedef uint32_t laszip_U32;
+
+typedef void *laszip_POINTER;
+
+typedef char laszip_CHAR;
+
+typedef double laszip_F64;
+
+typedef uint64_t laszip_U64;
+
+typedef struct laszip_vlr_struct {
+  laszip_U16 reserved;
+  laszip_CHAR user_id[16];
+  laszip_U16 record_id;
+  laszip_U16 record_length_after_header;
+  laszip_CHAR description[32];
+  laszip_U8 *data;
+} laszip_vlr_struct;
+
+typedef struct laszip_header_struct {
+  laszip_U16 file_source_ID;
+  laszip_U16 global_encoding;
+  laszip_U32 project_ID_GUID_data_1;
+  laszip_U16 project_ID_GUID_data_2;
+  laszip_U16 project_ID_GUID_data_3;
+  laszip_CHAR project_ID_GUID_data_4[8];
+  laszip_U8 version_major;
+  laszip_U8 version_minor;
+  laszip_CHAR system_identifier[32];
+  laszip_CHAR generating_software[32];
+  laszip_U16 file_creation_day;
+  laszip_U16 file_creation_year;
+  laszip_U16 header_size;
+  laszip_U32 offset_to_point_data;
+  laszip_U32 number_of_variable_length_records;
+  laszip_U8 point_data_format;
+  laszip_U16 point_data_record_length;
+  laszip_U32 number_of_point_records;
+  laszip_U32 number_of_points_by_return[5];
+  laszip_F64 x_scale_factor;
+  laszip_F64 y_scale_factor;
+  laszip_F64 z_scale_factor;
+  laszip_F64 x_offset;
+  laszip_F64 y_offset;
+  laszip_F64 z_offset;
+  laszip_F64 max_x;
+  laszip_F64 min_x;
+  laszip_F64 max_y;
+  laszip_F64 min_y;
+  laszip_F64 max_z;
+  laszip_F64 min_z;
+  laszip_U64 start_of_waveform_data_packet_record;
+  laszip_U64 start_of_first_extended_variable_length_record;
+  laszip_U32 number_of_extended_variable_length_records;
+  laszip_U64 extended_number_of_point_records;
+  laszip_U64 extended_number_of_points_by_return[15];
+  laszip_U32 user_data_in_header_size;
+  laszip_U8 *user_data_in_header;
+  struct laszip_vlr_struct *vlrs;
+  laszip_U32 user_data_after_header_size;
+  laszip_U8 *user_data_after_header;
+} laszip_header_struct;
+
+typedef int64_t laszip_I64;
+
+typedef struct laszip_geokey_struct {
+  laszip_U16 key_id;
+  laszip_U16 tiff_tag_location;
+  laszip_U16 count;
+  laszip_U16 value_offset;
+} laszip_geokey_struct;
+
+typedef int32_t laszip_BOOL;
+
+typedef int8_t laszip_I8;
+
+typedef int16_t laszip_I16;
+
+typedef float laszip_F32;
+
+/*
+ Version of the LASzip API that is emulated
+ */
+#define LASZIP_API_VERSION_MAJOR 3
+
+#define LASZIP_API_VERSION_MINOR 4
+
+#define LASZIP_API_VERSION_REVISION 3
+
+/*
+ Bits of `laszip_decompress_selective`
+ */
+#define laszip_DECOMPRESS_SELECTIVE_ALL 4294967295
+
+#define laszip_DECOMPRESS_SELECTIVE_CHANNEL_RETURNS_XY 0
+
+#define laszip_DECOMPRESS_SELECTIVE_Z (1 << 0)
+
+#define laszip_DECOMPRESS_SELECTIVE_CLASSIFICATION (1 << 1)
+
+#define laszip_DECOMPRESS_SELECTIVE_FLAGS (1 << 2)
+
+#define laszip_DECOMPRESS_SELECTIVE_INTENSITY (1 << 3)
+
+#define laszip_DECOMPRESS_SELECTIVE_SCAN_ANGLE (1 << 4)
+
+#define laszip_DECOMPRESS_SELECTIVE_USER_DATA (1 << 5)
+
+#define laszip_DECOMPRESS_SELECTIVE_POINT_SOURCE (1 << 6)
+
+#define laszip_DECOMPRESS_SELECTIVE_GPS_TIME (1 << 7)
+
+#define laszip_DECOMPRESS_SELECTIVE_RGB (1 << 8)
+
+#define laszip_DECOMPRESS_SELECTIVE_NIR (1 << 9)
+
+#define laszip_DECOMPRESS_SELECTIVE_WAVEPACKET (1 << 10)
+
+/*
+ One bit per extra byte for the first 16 extra bytes,
+ the following ones are decompressed if any of these bits is set
+ */
+#define laszip_DECOMPRESS_SELECTIVE_BYTE0 (1 << 16)
+
+#define laszip_DECOMPRESS_SELECTIVE_BYTE1 (1 << 17)
+
+#define laszip_DECOMPRESS_SELECTIVE_BYTE2 (1 << 18)
+
+#define laszip_DECOMPRESS_SELECTIVE_BYTE3 (1 << 19)
+
+#define laszip_DECOMPRESS_SELECTIVE_BYTE4 (1 << 20)
+
+#define laszip_DECOMPRESS_SELECTIVE_BYTE5 (1 << 21)
+
+#define laszip_DECOMPRESS_SELECTIVE_BYTE6 (1 << 22)
+
+#define laszip_DECOMPRESS_SELECTIVE_BYTE7 (1 << 23)
+
+#define laszip_DECOMPRESS_SELECTIVE_EXTRA_BYTES 4294901760
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+LASZIP_API
+laszip_I32 laszip_get_version(laszip_U8 *version_major,
+                              laszip_U8 *version_minor,
+                              laszip_U16 *version_revision,
+                              laszip_U32 *version_build);
+
+/*
+ Nothing to load, the library is linked directly, always returns `0`
+ */
+LASZIP_API laszip_I32 laszip_load_dll(void);
+
+/*
+ Nothing to unload, always returns `0`
+ */
+LASZIP_API laszip_I32 laszip_unload_dll(void);
+
+LASZIP_API laszip_I32 laszip_create(laszip_POINTER *pointer);
+
+LASZIP_API laszip_I32 laszip_get_error(laszip_POINTER pointer, laszip_CHAR **error);
+
+LASZIP_API laszip_I32 laszip_get_warning(laszip_POINTER pointer, laszip_CHAR **warning);
+
+LASZIP_API laszip_I32 laszip_clean(laszip_POINTER pointer);
+
+LASZIP_API laszip_I32 laszip_destroy(laszip_POINTER pointer);
+
+LASZIP_API
+laszip_I32 laszip_get_header_pointer(laszip_POINTER pointer,
+                                     struct laszip_header_struct **header_pointer);
+
+LASZIP_API
+laszip_I32 laszip_get_point_pointer(laszip_POINTER pointer,
+                                    laszip_point_struct **point_pointer);
+
+LASZIP_API laszip_I32 laszip_get_point_count(laszip_POINTER pointer, laszip_I64 *count);
+
+LASZIP_API
+laszip_I32 laszip_set_header(laszip_POINTER pointer,
+                             const struct laszip_header_struct *header);
+
+LASZIP_API
+laszip_I32 laszip_set_point_type_and_size(laszip_POINTER pointer,
+                                          laszip_U8 point_type,
+                                          laszip_U16 point_size);
+
+LASZIP_API laszip_I32 laszip_check_for_integer_overflow(laszip_POINTER pointer);
+
+LASZIP_API laszip_I32 laszip_auto_offset(laszip_POINTER pointer);
+
+LASZIP_API laszip_I32 laszip_set_point(laszip_POINTER pointer, const laszip_point_struct *point);
+
+LASZIP_API laszip_I32 laszip_set_coordinates(laszip_POINTER pointer, const laszip_F64 *coordinates);
+
+LASZIP_API laszip_I32 laszip_get_coordinates(laszip_POINTER pointer, laszip_F64 *coordinates);
+
+LASZIP_API
+laszip_I32 laszip_add_vlr(laszip_POINTER pointer,
+                          const laszip_CHAR *user_id,
+                          laszip_U16 record_id,
+                          laszip_U16 record_length_after_header,
+                          const laszip_CHAR *description,
+                          const laszip_U8 *data);
+
+/*
+ Adds the GeoKeyDirectoryTag VLR, the key directory header is written
+ before the `number` entries of `key_entries`
+ */
+LASZIP_API
+laszip_I32 laszip_set_geokeys(laszip_POINTER pointer,
+                              laszip_U32 number,
+                              const struct laszip_geokey_struct *key_entries);
+
+/*
+ Adds the GeoDoubleParamsTag VLR
+ */
+LASZIP_API
+laszip_I32 laszip_set_geodouble_params(laszip_POINTER pointer,
+                                       laszip_U32 number,
+                                       const laszip_F64 *geodouble_params);
+
+/*
+ Adds the GeoAsciiParamsTag VLR, `number` is the number of characters
+ */
+LASZIP_API
+laszip_I32 laszip_set_geoascii_params(laszip_POINTER pointer,
+                                      laszip_U32 number,
+                                      const laszip_CHAR *geoascii_params);
+
+LASZIP_API
+laszip_I32 laszip_remove_vlr(laszip_POINTER pointer,
+                             const laszip_CHAR *user_id,
+                             laszip_U16 record_id);
+
+LASZIP_API
+laszip_I32 laszip_preserve_generating_software(laszip_POINTER pointer,
+                                               laszip_BOOL _preserve);
+
+LASZIP_API laszip_I32 laszip_request_native_extension(laszip_POINTER pointer, laszip_BOOL request);
+
+/*
+ Not supported, returns an error when `request` is true
+ */
+LASZIP_API
+laszip_I32 laszip_request_compatibility_mode(laszip_POINTER pointer,
+                                             laszip_BOOL request);
+
+LASZIP_API laszip_I32 laszip_set_chunk_size(laszip_POINTER pointer, laszip_U32 chunk_size);
+
+/*
+ Not supported, always returns an error
+ */
+LASZIP_API
+laszip_I32 laszip_create_spatial_index(laszip_POINTER pointer,
+                                       laszip_BOOL _create,
+                                       laszip_BOOL _append);
+
+LASZIP_API
+laszip_I32 laszip_open_writer(laszip_POINTER pointer,
+                              const laszip_CHAR *file_name,
+                              laszip_BOOL compress);
+
+/*
+ Not supported (the LASzip DLL takes a C++ `std::ostream`), always returns an error
+ */
+LASZIP_API
+laszip_I32 laszip_open_writer_stream(laszip_POINTER pointer,
+                                     void *_stream,
+                                     laszip_BOOL _compress,
+                                     laszip_BOOL _do_not_write_header);
+
+LASZIP_API laszip_I32 laszip_write_point(laszip_POINTER pointer);
+
+/*
+ Not supported, always returns an error
+ */
+LASZIP_API laszip_I32 laszip_write_indexed_point(laszip_POINTER pointer);
+
+LASZIP_API laszip_I32 laszip_update_inventory(laszip_POINTER pointer);
+
+LASZIP_API laszip_I32 laszip_close_writer(laszip_POINTER pointer);
+
+/*
+ Not supported, returns an error when `exploit` is true
+ */
+LASZIP_API laszip_I32 laszip_exploit_spatial_index(laszip_POINTER pointer, laszip_BOOL exploit);
+
+LASZIP_API
+laszip_I32 laszip_decompress_selective(laszip_POINTER pointer,
+                                       laszip_U32 decompress_selective);
+
+LASZIP_API
+laszip_I32 laszip_open_reader(laszip_POINTER pointer,
+                              const laszip_CHAR *file_name,
+                              laszip_BOOL *is_compressed);
+
+/*
+ Not supported (the LASzip DLL takes a C++ `std::istream`), always returns an error
+ */
+LASZIP_API
+laszip_I32 laszip_open_reader_stream(laszip_POINTER pointer,
+                                     void *_stream,
+                                     laszip_BOOL *_is_compressed);
+
+/*
+ Files are never indexed
+ */
+LASZIP_API
+laszip_I32 laszip_has_spatial_index(laszip_POINTER pointer,
+                                    laszip_BOOL *is_indexed,
+                                    laszip_BOOL *is_appended);
+
+/*
+ Not supported, always returns an error
+ */
+LASZIP_API
+laszip_I32 laszip_inside_rectangle(laszip_POINTER pointer,
+                                   laszip_F64 _min_x,
+                                   laszip_F64 _min_y,
+                                   laszip_F64 _max_x,
+                                   laszip_F64 _max_y,
+                                   laszip_BOOL *_is_empty);
+
+LASZIP_API laszip_I32 laszip_seek_point(laszip_POINTER pointer, laszip_I64 index);
+
+LASZIP_API laszip_I32 laszip_read_point(laszip_POINTER pointer);
+
+/*
+ Not supported, always returns an error
+ */
+LASZIP_API laszip_I32 laszip_read_inside_point(laszip_POINTER pointer, laszip_BOOL *_is_done);
+
+LASZIP_API laszip_I32 laszip_close_reader(laszip_POINTER pointer);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* LASZIP_API_H */
@@ -0,0 +1,738 @@
+//! Command line tool to inspect, compress, decompress, verify
+//! and benchmark LAS / LAZ files.
+//!
+//! Only the parts of the LAS format needed to move the points around are handled:
+//! the header and VLRs are copied as is (with the fields related to compression
+//! updated), EVLRs of LAS 1.4 files are copied after the points.
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use clap::{Args, Parser, Subcommand};
+use rayon::prelude::*;
+
+use laz::las::file::{point_format_id_compressed_to_uncompressd, QuickHeader};
+use laz::laszip::{ChunkTable, LazDecompressor};
+use laz::{
+    CompressedField, CompressionReport, LasZipCompressor, LasZipDecompressor, LazItemRecordBuilder,
+    LazVlr, LazVlrBuilder, ParLasZipCompressor, ParLasZipDecompressor,
+};
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+/// Number of points read / written at once, when not working on whole chunks
+const POINTS_PER_BATCH: usize = 50_000;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "laz",
+    version,
+    about = "Inspect, compress & decompress LAZ files"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Prints the header, the Laszip VLR and the chunk table statistics of a file
+    Info(InfoArgs),
+    /// Compresses a LAS file into a LAZ file
+    Compress(CompressArgs),
+    /// Decompresses a LAZ file into a LAS file
+    Decompress(DecompressArgs),
+    /// Checks the consistency of a LAZ file and decodes all its points
+    Verify(VerifyArgs),
+    /// Measures the compression & decompression throughput of each backend
+    Bench(BenchArgs),
+}
+
+#[derive(Args, Debug)]
+struct InfoArgs {
+    input: PathBuf,
+    /// Also prints the number of compressed bytes of each field
+    #[arg(long)]
+    report: bool,
+}
+
+#[derive(Args, Debug)]
+struct CompressArgs {
+    input: PathBuf,
+    output: PathBuf,
+    /// Use multiple threads
+    #[arg(long)]
+    parallel: bool,
+    /// Number of points per chunk
+    #[arg(long, default_value_t = 50_000)]
+    chunk_size: u32,
+    /// Write variable-size chunks (each of `chunk-size` points)
+    /// instead of fixed-size chunks
+    #[arg(long)]
+    variable_chunks: bool,
+}
+
+#[derive(Args, Debug)]
+struct DecompressArgs {
+    input: PathBuf,
+    output: PathBuf,
+    /// Use multiple threads
+    #[arg(long)]
+    parallel: bool,
+}
+
+#[derive(Args, Debug)]
+struct VerifyArgs {
+    input: PathBuf,
+    /// Use multiple threads
+    #[arg(long)]
+    parallel: bool,
+}
+
+#[derive(Args, Debug)]
+struct BenchArgs {
+    /// LAS or LAZ file, its points are loaded in memory
+    input: PathBuf,
+    /// Number of times each measure is made, the best one is kept
+    #[arg(long, default_value_t = 3)]
+    iterations: u32,
+}
+
+const COMPRESSED_POINT_FORMAT_BIT: u8 = 0x80;
+
+/// Position of header fields that need to be updated
+const OFFSET_TO_POINTS_POS: usize = 96;
+const NUM_VLRS_POS: usize = 100;
+const POINT_FORMAT_ID_POS: usize = 104;
+const START_OF_FIRST_EVLR_POS: usize = 235;
+const NUM_EVLRS_POS: usize = 243;
+
+const VLR_HEADER_SIZE: usize = 54;
+
+/// A VLR, stored as it is in the file
+struct RawVlr {
+    record_id: u16,
+    user_id: String,
+    /// The bytes of the whole VLR (header & record data)
+    bytes: Vec<u8>,
+}
+
+impl RawVlr {
+    fn read_from<R: Read>(src: &mut R) -> Result<Self> {
+        let mut bytes = vec![0u8; VLR_HEADER_SIZE];
+        src.read_exact(&mut bytes)?;
+        let user_id = String::from_utf8_lossy(&bytes[2..18])
+            .trim_end_matches('\0')
+            .to_owned();
+        let record_id = (&bytes[18..20]).read_u16::<LittleEndian>()?;
+        let record_length = (&bytes[20..22]).read_u16::<LittleEndian>()?;
+        bytes.resize(VLR_HEADER_SIZE + record_length as usize, 0u8);
+        src.read_exact(&mut bytes[VLR_HEADER_SIZE..])?;
+        Ok(Self {
+            record_id,
+            user_id,
+            bytes,
+        })
+    }
+
+    fn is_laszip(&self) -> bool {
+        self.record_id == LazVlr::RECORD_ID && self.user_id == LazVlr::USER_ID
+    }
+
+    fn from_laz_vlr(vlr: &LazVlr) -> Result<Self> {
+        let mut record_data = Vec::new();
+        vlr.write_to(&mut record_data)?;
+
+        let mut bytes = Vec::with_capacity(VLR_HEADER_SIZE + record_data.len());
+        bytes.write_u16::<LittleEndian>(0)?;
+        let mut user_id = [0u8; 16];
+        user_id[..LazVlr::USER_ID.len()].copy_from_slice(LazVlr::USER_ID.as_bytes());
+        bytes.extend_from_slice(&user_id);
+        bytes.write_u16::<LittleEndian>(LazVlr::RECORD_ID)?;
+        bytes.write_u16::<LittleEndian>(u16::try_from(record_data.len())?)?;
+        let mut description = [0u8; 32];
+        description[..LazVlr::DESCRIPTION.len()].copy_from_slice(LazVlr::DESCRIPTION.as_bytes());
+        bytes.extend_from_slice(&description);
+        bytes.extend_from_slice(&record_data);
+
+        Ok(Self {
+            record_id: LazVlr::RECORD_ID,
+            user_id: LazVlr::USER_ID.to_owned(),
+            bytes,
+        })
+    }
+}
+
+/// Everything in a LAS file that is not the points
+struct LasLayout {
+    header: QuickHeader,
+    header_bytes: Vec<u8>,
+    /// VLRs, excluding the Laszip one
+    vlrs: Vec<RawVlr>,
+    laz_vlr: Option<LazVlr>,
+    /// Bytes between the VLRs and the points
+    padding: Vec<u8>,
+    /// Position and number of the EVLRs (LAS >= 1.4)
+    evlrs: Option<(u64, u32)>,
+}
+
+impl LasLayout {
+    /// Reads the layout of the file, the `src` is left
+    /// at the start of the points
+    fn read_from<R: Read + Seek>(src: &mut R) -> Result<Self> {
+        let header = QuickHeader::read_from(src)?;
+        src.seek(SeekFrom::Start(0))?;
+        let mut header_bytes = vec![0u8; header.header_size as usize];
+        src.read_exact(&mut header_bytes)?;
+
+        let mut vlrs = Vec::with_capacity(header.num_vlrs as usize);
+        let mut laz_vlr = None;
+        for _ in 0..header.num_vlrs {
+            let vlr = RawVlr::read_from(src)?;
+            if vlr.is_laszip() {
+                laz_vlr = Some(LazVlr::read_from(&vlr.bytes[VLR_HEADER_SIZE..])?);
+            } else {
+                vlrs.push(vlr);
+            }
+        }
+
+        let end_of_vlrs = src.stream_position()?;
+        let offset_to_points = u64::from(header.offset_to_points);
+        if end_of_vlrs > offset_to_points {
+            return Err("VLRs overlap the point data".into());
+        }
+        let mut padding = vec![0u8; (offset_to_points - end_of_vlrs) as usize];
+        src.read_exact(&mut padding)?;
+
+        let evlrs =
+            if header.major == 1 && header.minor >= 4 && header_bytes.len() >= NUM_EVLRS_POS + 4 {
+                let start =
+                    (&header_bytes[START_OF_FIRST_EVLR_POS..]).read_u64::<LittleEndian>()?;
+                let count = (&header_bytes[NUM_EVLRS_POS..]).read_u32::<LittleEndian>()?;
+                (count > 0 && start > 0).then_some((start, count))
+            } else {
+                None
+            };
+
+        Ok(Self {
+            header,
+            header_bytes,
+            vlrs,
+            laz_vlr,
+            padding,
+            evlrs,
+        })
+    }
+
+    fn is_compressed(&self) -> bool {
+        self.laz_vlr.is_some()
+    }
+
+    fn point_format_id(&self) -> u8 {
+        point_format_id_compressed_to_uncompressd(self.header.point_format_id)
+    }
+
+    /// Writes the header, the VLRs (with the `laz_vlr` if some) and the padding,
+    /// leaving the `dst` where the points start.
+    fn write_to<W: Write>(&self, dst: &mut W, laz_vlr: Option<&LazVlr>) -> Result<()> {
+        let laz_vlr = laz_vlr.map(RawVlr::from_laz_vlr).transpose()?;
+        let vlrs = self.vlrs.iter().chain(laz_vlr.as_ref());
+
+        let mut header_bytes = self.header_bytes.clone();
+        let num_vlrs = vlrs.clone().count() as u32;
+        let offset_to_points = header_bytes.len()
+            + vlrs.clone().map(|vlr| vlr.bytes.len()).sum::<usize>()
+            + self.padding.len();
+        let point_format_id = if laz_vlr.is_some() {
+            self.point_format_id() | COMPRESSED_POINT_FORMAT_BIT
+        } else {
+            self.point_format_id()
+        };
+        (&mut header_bytes[OFFSET_TO_POINTS_POS..])
+            .write_u32::<LittleEndian>(u32::try_from(offset_to_points)?)?;
+        (&mut header_bytes[NUM_VLRS_POS..]).write_u32::<LittleEndian>(num_vlrs)?;
+        header_bytes[POINT_FORMAT_ID_POS] = point_format_id;
+
+        dst.write_all(&header_bytes)?;
+        for vlr in vlrs {
+            dst.write_all(&vlr.bytes)?;
+        }
+        dst.write_all(&self.padding)?;
+        Ok(())
+    }
+
+    /// Copies the EVLRs of the `src` at the current position of the `dst`
+    /// and updates the position of the first EVLR in the header.
+    fn copy_evlrs<R: Read + Seek, W: Write + Seek>(&self, src: &mut R, dst: &mut W) -> Result<()> {
+        if let Some((start, _)) = self.evlrs {
+            let new_start = dst.stream_position()?;
+            src.seek(SeekFrom::Start(start))?;
+            std::io::copy(src, dst)?;
+            dst.seek(SeekFrom::Start(START_OF_FIRST_EVLR_POS as u64))?;
+            dst.write_u64::<LittleEndian>(new_start)?;
+            dst.seek(SeekFrom::End(0))?;
+        }
+        Ok(())
+    }
+}
+
+fn open_input(path: &Path) -> Result<(BufReader<File>, LasLayout)> {
+    let mut src = BufReader::new(File::open(path)?);
+    let layout = LasLayout::read_from(&mut src)?;
+    Ok((src, layout))
+}
+
+fn compressed_vlr(layout: &LasLayout) -> Result<&LazVlr> {
+    layout
+        .laz_vlr
+        .as_ref()
+        .ok_or_else(|| "the file is not compressed (no Laszip VLR)".into())
+}
+
+/// Reads the chunk table, leaving the `src` at the start of the first chunk
+fn read_chunk_table<R: Read + Seek>(
+    src: &mut R,
+    layout: &LasLayout,
+    vlr: &LazVlr,
+) -> Result<ChunkTable> {
+    src.seek(SeekFrom::Start(u64::from(layout.header.offset_to_points)))?;
+    Ok(ChunkTable::read_from(src, vlr)?)
+}
+
+/// Returns the number of points of each chunk of the table,
+/// taking care of the last chunk of fixed-size chunks.
+fn chunk_point_counts(chunk_table: &ChunkTable, vlr: &LazVlr, num_points: u64) -> Vec<u64> {
+    let mut counts = chunk_table
+        .as_ref()
+        .iter()
+        .map(|entry| entry.point_count)
+        .collect::<Vec<_>>();
+    if !vlr.uses_variable_size_chunks() {
+        if let Some(last) = counts.last_mut() {
+            let num_points_before = u64::from(vlr.chunk_size()) * (chunk_table.len() as u64 - 1);
+            *last = num_points.saturating_sub(num_points_before);
+        }
+    }
+    counts
+}
+
+fn print_min_max_mean(name: &str, values: impl Iterator<Item = u64> + Clone) {
+    let count = values.clone().count() as u64;
+    if count == 0 {
+        return;
+    }
+    let min = values.clone().min().unwrap_or(0);
+    let max = values.clone().max().unwrap_or(0);
+    let mean = values.sum::<u64>() / count;
+    println!("  {}: min {}, max {}, mean {}", name, min, max, mean);
+}
+
+fn info(args: InfoArgs) -> Result<()> {
+    let (mut src, layout) = open_input(&args.input)?;
+    let header = &layout.header;
+    println!("Header");
+    println!("  version: {}.{}", header.major, header.minor);
+    println!("  point format id: {}", layout.point_format_id());
+    println!("  point size: {}", header.point_size);
+    println!("  number of points: {}", header.num_points);
+    println!("  offset to points: {}", header.offset_to_points);
+    println!("  number of VLRs: {}", header.num_vlrs);
+    if let Some((_, count)) = layout.evlrs {
+        println!("  number of EVLRs: {}", count);
+    }
+
+    let vlr = match layout.laz_vlr.as_ref() {
+        Some(vlr) => vlr,
+        None => {
+            println!("The file is not compressed");
+            return Ok(());
+        }
+    };
+    println!("Laszip VLR");
+    println!("  compressor: {:?}", vlr.compressor_type());
+    if vlr.uses_variable_size_chunks() {
+        println!("  chunk size: variable");
+    } else {
+        println!("  chunk size: {}", vlr.chunk_size());
+    }
+    println!("  items:");
+    for item in vlr.items() {
+        println!(
+            "    {:?} (size: {}, version: {})",
+            item.item_type(),
+            item.size(),
+            item.version()
+        );
+    }
+
+    let chunk_table = read_chunk_table(&mut src, &layout, vlr)?;
+    let point_counts = chunk_point_counts(&chunk_table, vlr, header.num_points);
+    let compressed_size = chunk_table
+        .as_ref()
+        .iter()
+        .map(|entry| entry.byte_count)
+        .sum::<u64>();
+    let uncompressed_size = header.num_points * u64::from(header.point_size);
+    println!("Chunk table");
+    println!("  number of chunks: {}", chunk_table.len());
+    print_min_max_mean("points per chunk", point_counts.iter().copied());
+    print_min_max_mean(
+        "bytes per chunk",
+        chunk_table.as_ref().iter().map(|entry| entry.byte_count),
+    );
+    println!("  compressed size: {} bytes", compressed_size);
+    if compressed_size > 0 {
+        println!(
+            "  compression ratio: {:.2}",
+            uncompressed_size as f64 / compressed_size as f64
+        );
+    }
+
+    if args.report {
+        src.seek(SeekFrom::Start(u64::from(header.offset_to_points)))?;
+        let report = CompressionReport::read_from(&mut src, vlr, header.num_points)?;
+        println!("Compressed bytes per field");
+        for field in report.field_totals() {
+            let name = match field.field {
+                CompressedField::Layer(kind) => format!("{:?}", kind),
+                CompressedField::Item(item_type) => format!("{:?}", item_type),
+            };
+            println!(
+                "  {}: {} bytes ({:.1}%)",
+                name,
+                field.byte_count,
+                100.0 * field.byte_count as f64 / report.byte_count().max(1) as f64
+            );
+        }
+    }
+    Ok(())
+}
+
+fn compress(args: CompressArgs) -> Result<()> {
+    if args.chunk_size == 0 {
+        return Err("the chunk size must be greater than 0".into());
+    }
+    let (mut src, layout) = open_input(&args.input)?;
+    if layout.is_compressed() {
+        return Err("the input file is already compressed".into());
+    }
+    let point_format_id = layout.point_format_id();
+    let point_size = usize::from(layout.header.point_size);
+    let base_items = LazItemRecordBuilder::default_for_point_format_id(point_format_id, 0)?;
+    let base_size = base_items.iter().map(|item| item.size()).sum::<u16>();
+    let num_extra_bytes = layout
+        .header
+        .point_size
+        .checked_sub(base_size)
+        .ok_or("the point size is too small for the point format")?;
+    let items =
+        LazItemRecordBuilder::default_for_point_format_id(point_format_id, num_extra_bytes)?;
+    let builder = LazVlrBuilder::new(items);
+    let vlr = if args.variable_chunks {
+        builder.with_variable_chunk_size()
+    } else {
+        builder.with_fixed_chunk_size(args.chunk_size)
+    }
+    .build();
+
+    let mut dst = BufWriter::new(File::create(&args.output)?);
+    layout.write_to(&mut dst, Some(&vlr))?;
+
+    let chunk_size_in_bytes = args.chunk_size as usize * point_size;
+    let num_chunks_per_batch = if args.parallel {
+        rayon::current_num_threads()
+    } else {
+        1
+    };
+    let num_points_per_batch = args.chunk_size as u64 * num_chunks_per_batch as u64;
+    let mut buffer = Vec::<u8>::new();
+    let mut num_points_left = layout.header.num_points;
+    if args.parallel {
+        let mut compressor = ParLasZipCompressor::new(&mut dst, vlr.clone())?;
+        while num_points_left > 0 {
+            let num_points = num_points_per_batch.min(num_points_left);
+            buffer.resize(num_points as usize * point_size, 0u8);
+            src.read_exact(&mut buffer)?;
+            if args.variable_chunks {
+                compressor.compress_chunks(buffer.par_chunks(chunk_size_in_bytes))?;
+            } else {
+                compressor.compress_many(&buffer)?;
+            }
+            num_points_left -= num_points;
+        }
+        compressor.done()?;
+    } else {
+        let mut compressor = LasZipCompressor::new(&mut dst, vlr.clone())?;
+        while num_points_left > 0 {
+            let num_points = num_points_per_batch.min(num_points_left);
+            buffer.resize(num_points as usize * point_size, 0u8);
+            src.read_exact(&mut buffer)?;
+            if args.variable_chunks {
+                compressor.compress_chunks(buffer.chunks(chunk_size_in_bytes))?;
+            } else {
+                compressor.compress_many(&buffer)?;
+            }
+            num_points_left -= num_points;
+        }
+        compressor.done()?;
+    }
+
+    layout.copy_evlrs(&mut src, &mut dst)?;
+    dst.flush()?;
+    Ok(())
+}
+
+fn decompress(args: DecompressArgs) -> Result<()> {
+    let (mut src, layout) = open_input(&args.input)?;
+    let vlr = compressed_vlr(&layout)?.clone();
+    let point_size = usize::from(layout.header.point_size);
+    if vlr.items_size() != point_size as u64 {
+        return Err("the Laszip VLR items do not match the point size".into());
+    }
+
+    let mut dst = BufWriter::new(File::create(&args.output)?);
+    layout.write_to(&mut dst, None)?;
+
+    let num_points_per_batch = if args.parallel && !vlr.uses_variable_size_chunks() {
+        vlr.chunk_size() as u64 * rayon::current_num_threads() as u64
+    } else {
+        POINTS_PER_BATCH as u64
+    };
+    {
+        let mut decompressor = create_decompressor(&mut src, vlr, args.parallel)?;
+        let mut buffer = Vec::<u8>::new();
+        let mut num_points_left = layout.header.num_points;
+        while num_points_left > 0 {
+            let num_points = num_points_per_batch.min(num_points_left);
+            buffer.resize(num_points as usize * point_size, 0u8);
+            decompressor.decompress_many(&mut buffer)?;
+            dst.write_all(&buffer)?;
+            num_points_left -= num_points;
+        }
+    }
+
+    layout.copy_evlrs(&mut src, &mut dst)?;
+    dst.flush()?;
+    Ok(())
+}
+
+fn create_decompressor<'a, R: Read + Seek + Send + Sync + 'a>(
+    src: R,
+    vlr: LazVlr,
+    parallel: bool,
+) -> Result<Box<dyn LazDecompressor + 'a>> {
+    Ok(if parallel {
+        Box::new(ParLasZipDecompressor::new(src, vlr)?)
+    } else {
+        Box::new(LasZipDecompressor::new(src, vlr)?)
+    })
+}
+
+fn verify(args: VerifyArgs) -> Result<()> {
+    let (mut src, layout) = open_input(&args.input)?;
+    let vlr = compressed_vlr(&layout)?.clone();
+    let num_points = layout.header.num_points;
+    let point_size = usize::from(layout.header.point_size);
+
+    let mut errors = Vec::<String>::new();
+    if vlr.items_size() != point_size as u64 {
+        errors.push(format!(
+            "the size of the Laszip VLR items ({}) does not match the point size ({})",
+            vlr.items_size(),
+            point_size
+        ));
+    }
+
+    let offset_to_points = u64::from(layout.header.offset_to_points);
+    src.seek(SeekFrom::Start(offset_to_points))?;
+    let mut offset_to_chunk_table = src.read_i64::<LittleEndian>()?;
+    if offset_to_chunk_table <= offset_to_points as i64 {
+        // The writer could not update the offset (e.g. it was not seekable),
+        // in that case it is written after the chunk table, at the end of the file
+        src.seek(SeekFrom::End(-(ChunkTable::OFFSET_SIZE as i64)))?;
+        offset_to_chunk_table = src.read_i64::<LittleEndian>()?;
+    }
+    let chunk_table = read_chunk_table(&mut src, &layout, &vlr)?;
+    let compressed_size = chunk_table
+        .as_ref()
+        .iter()
+        .map(|entry| entry.byte_count)
+        .sum::<u64>();
+    let expected_offset = offset_to_points + ChunkTable::OFFSET_SIZE as u64 + compressed_size;
+    if offset_to_chunk_table != expected_offset as i64 {
+        errors.push(format!(
+            "the chunk table is at {} but the chunks end at {}",
+            offset_to_chunk_table, expected_offset
+        ));
+    }
+    if vlr.uses_variable_size_chunks() {
+        let num_points_in_table = chunk_table
+            .as_ref()
+            .iter()
+            .map(|entry| entry.point_count)
+            .sum::<u64>();
+        if num_points_in_table != num_points {
+            errors.push(format!(
+                "the chunk table has {} points, the header {}",
+                num_points_in_table, num_points
+            ));
+        }
+    } else {
+        let chunk_size = u64::from(vlr.chunk_size());
+        let expected_num_chunks = num_points.div_ceil(chunk_size);
+        if chunk_table.len() as u64 != expected_num_chunks {
+            errors.push(format!(
+                "the chunk table has {} chunks, {} were expected",
+                chunk_table.len(),
+                expected_num_chunks
+            ));
+        }
+    }
+
+    if errors.is_empty() {
+        src.seek(SeekFrom::Start(offset_to_points))?;
+        let mut decompressor = create_decompressor(&mut src, vlr, args.parallel)?;
+        let mut buffer = Vec::<u8>::new();
+        let mut num_points_left = num_points;
+        while num_points_left > 0 {
+            let num_points_in_batch = (POINTS_PER_BATCH as u64).min(num_points_left);
+            buffer.resize(num_points_in_batch as usize * point_size, 0u8);
+            if let Err(error) = decompressor.decompress_many(&mut buffer) {
+                errors.push(format!(
+                    "failed to decode point {}: {}",
+                    num_points - num_points_left,
+                    error
+                ));
+                break;
+            }
+            num_points_left -= num_points_in_batch;
+        }
+    }
+
+    if errors.is_empty() {
+        println!("{}: OK", args.input.display());
+        Ok(())
+    } else {
+        for error in &errors {
+            eprintln!("{}: {}", args.input.display(), error);
+        }
+        Err(format!("{} error(s) found", errors.len()).into())
+    }
+}
+
+/// Runs `f` `iterations` times and returns the shortest duration
+fn best_of<F: FnMut() -> Result<()>>(iterations: u32, mut f: F) -> Result<Duration> {
+    let mut best = Duration::MAX;
+    for _ in 0..iterations.max(1) {
+        let start = Instant::now();
+        f()?;
+        best = best.min(start.elapsed());
+    }
+    Ok(best)
+}
+
+fn print_throughput(name: &str, duration: Duration, num_points: u64, num_bytes: usize) {
+    let seconds = duration.as_secs_f64().max(f64::EPSILON);
+    println!(
+        "  {:<24} {:>10.3} s {:>12.0} points/s {:>10.1} MiB/s",
+        name,
+        seconds,
+        num_points as f64 / seconds,
+        num_bytes as f64 / seconds / (1024.0 * 1024.0)
+    );
+}
+
+fn bench(args: BenchArgs) -> Result<()> {
+    let (mut src, layout) = open_input(&args.input)?;
+    let num_points = layout.header.num_points;
+    let point_size = usize::from(layout.header.point_size);
+    let mut points = vec![0u8; num_points as usize * point_size];
+
+    let vlr = match layout.laz_vlr.as_ref() {
+        Some(vlr) => {
+            LasZipDecompressor::new(&mut src, vlr.clone())?.decompress_many(&mut points)?;
+            LazVlrBuilder::new(vlr.items().clone()).build()
+        }
+        None => {
+            src.read_exact(&mut points)?;
+            let point_format_id = layout.point_format_id();
+            let base_items = LazItemRecordBuilder::default_for_point_format_id(point_format_id, 0)?;
+            let base_size = base_items.iter().map(|item| item.size()).sum::<u16>();
+            let items = LazItemRecordBuilder::default_for_point_format_id(
+                point_format_id,
+                layout.header.point_size.saturating_sub(base_size),
+            )?;
+            LazVlrBuilder::new(items).build()
+        }
+    };
+    println!(
+        "{} points of {} bytes, {} threads",
+        num_points,
+        point_size,
+        rayon::current_num_threads()
+    );
+
+    let mut compressed = Cursor::new(Vec::<u8>::new());
+    let duration = best_of(args.iterations, || {
+        compressed = Cursor::new(Vec::with_capacity(points.len() / 4));
+        let mut compressor = LasZipCompressor::new(&mut compressed, vlr.clone())?;
+        compressor.compress_many(&points)?;
+        compressor.done()?;
+        Ok(())
+    })?;
+    print_throughput("sequential compression", duration, num_points, points.len());
+
+    let duration = best_of(args.iterations, || {
+        let mut output = Cursor::new(Vec::with_capacity(points.len() / 4));
+        let mut compressor = ParLasZipCompressor::new(&mut output, vlr.clone())?;
+        compressor.compress_many(&points)?;
+        compressor.done()?;
+        Ok(())
+    })?;
+    print_throughput("parallel compression", duration, num_points, points.len());
+
+    let mut decompressed = vec![0u8; points.len()];
+    let duration = best_of(args.iterations, || {
+        compressed.set_position(0);
+        let mut decompressor = LasZipDecompressor::new(&mut compressed, vlr.clone())?;
+        decompressor.decompress_many(&mut decompressed)?;
+        Ok(())
+    })?;
+    print_throughput(
+        "sequential decompression",
+        duration,
+        num_points,
+        points.len(),
+    );
+
+    let duration = best_of(args.iterations, || {
+        compressed.set_position(0);
+        let mut decompressor = ParLasZipDecompressor::new(&mut compressed, vlr.clone())?;
+        decompressor.decompress_many(&mut decompressed)?;
+        Ok(())
+    })?;
+    print_throughput("parallel decompression", duration, num_points, points.len());
+
+    if decompressed != points {
+        return Err("the decompressed points do not match the original ones".into());
+    }
+    Ok(())
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Command::Info(args) => info(args),
+        Command::Compress(args) => compress(args),
+        Command::Decompress(args) => decompress(args),
+        Command::Verify(args) => verify(args),
+        Command::Bench(args) => bench(args),
+    };
+    if let Err(error) = result {
+        eprintln!("error: {}", error);
+        std::process::exit(1);
+    }
+}
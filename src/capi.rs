@@ -0,0 +1,1891 @@
+//! C API compatible with the one of the LASzip DLL (`laszip_api.h`)
+//!
+//! This module exposes, behind the `capi` feature, a C ABI that mirrors
+//! the `laszip_dll` API of LASzip so that applications written against it
+//! can link to this crate instead.
+//!
+//! The matching header is `include/laszip_api.h`, it is generated from this module
+//! with cbindgen (see `cbindgen.toml`), the library to link to is built with:
+//!
+//! ```text
+//! cargo rustc --release --lib --features capi --crate-type cdylib # or staticlib
+//! ```
+//!
+//! Functions return `0` on success and `1` on error, the error message can then
+//! be retrieved with [`laszip_get_error`].
+//!
+//! # Supported subset
+//!
+//! - creating / destroying handles, errors and warnings
+//! - reading LAS/LAZ files (`laszip_open_reader`, `laszip_seek_point`, `laszip_read_point`, ...)
+//! - writing LAS/LAZ files (`laszip_open_writer`, `laszip_write_point`, `laszip_update_inventory`, ...)
+//! - selective decompression
+//! - header, point, coordinates & VLR manipulation
+//! - GeoTIFF VLRs (`laszip_set_geokeys`, `laszip_set_geodouble_params`, `laszip_set_geoascii_params`)
+//! - `laszip_load_dll` / `laszip_unload_dll`, which do nothing as the library is linked directly
+//!
+//! Spatial indexing (`.lax` files), the compatibility mode and the
+//! C++ stream based functions are not supported: `laszip_create_spatial_index`,
+//! `laszip_exploit_spatial_index`, `laszip_inside_rectangle`, `laszip_read_inside_point`,
+//! `laszip_open_reader_stream` and `laszip_open_writer_stream` are exported
+//! but return an error (available with [`laszip_get_error`]).
+//!
+//! # Safety
+//!
+//! All the functions are `unsafe`: pointers given to them must either be null
+//! or valid for the access implied by the LASzip API, and the `pointer` handle
+//! must have been created by [`laszip_create`] and not yet destroyed.
+#![allow(
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    clippy::missing_safety_doc
+)]
+
+use std::ffi::{c_char, CStr, CString};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::{
+    DecompressionSelection, ExtraBytesSelection, LasZipCompressor, LasZipDecompressor,
+    LazItemRecordBuilder, LazVlr, LazVlrBuilder,
+};
+
+pub type laszip_BOOL = i32;
+pub type laszip_U8 = u8;
+pub type laszip_U16 = u16;
+pub type laszip_U32 = u32;
+pub type laszip_U64 = u64;
+pub type laszip_I8 = i8;
+pub type laszip_I16 = i16;
+pub type laszip_I32 = i32;
+pub type laszip_I64 = i64;
+pub type laszip_CHAR = c_char;
+pub type laszip_F32 = f32;
+pub type laszip_F64 = f64;
+pub type laszip_POINTER = *mut std::ffi::c_void;
+
+/// Version of the LASzip API that is emulated
+pub const LASZIP_API_VERSION_MAJOR: laszip_U8 = 3;
+pub const LASZIP_API_VERSION_MINOR: laszip_U8 = 4;
+pub const LASZIP_API_VERSION_REVISION: laszip_U16 = 3;
+const LASZIP_VERSION_BUILD_DATE: u32 = 191111;
+
+/// Bits of `laszip_decompress_selective`
+pub const laszip_DECOMPRESS_SELECTIVE_ALL: laszip_U32 = 0xFFFFFFFF;
+pub const laszip_DECOMPRESS_SELECTIVE_CHANNEL_RETURNS_XY: laszip_U32 = 0x00000000;
+pub const laszip_DECOMPRESS_SELECTIVE_Z: laszip_U32 = 1 << 0;
+pub const laszip_DECOMPRESS_SELECTIVE_CLASSIFICATION: laszip_U32 = 1 << 1;
+pub const laszip_DECOMPRESS_SELECTIVE_FLAGS: laszip_U32 = 1 << 2;
+pub const laszip_DECOMPRESS_SELECTIVE_INTENSITY: laszip_U32 = 1 << 3;
+pub const laszip_DECOMPRESS_SELECTIVE_SCAN_ANGLE: laszip_U32 = 1 << 4;
+pub const laszip_DECOMPRESS_SELECTIVE_USER_DATA: laszip_U32 = 1 << 5;
+pub const laszip_DECOMPRESS_SELECTIVE_POINT_SOURCE: laszip_U32 = 1 << 6;
+pub const laszip_DECOMPRESS_SELECTIVE_GPS_TIME: laszip_U32 = 1 << 7;
+pub const laszip_DECOMPRESS_SELECTIVE_RGB: laszip_U32 = 1 << 8;
+pub const laszip_DECOMPRESS_SELECTIVE_NIR: laszip_U32 = 1 << 9;
+pub const laszip_DECOMPRESS_SELECTIVE_WAVEPACKET: laszip_U32 = 1 << 10;
+/// One bit per extra byte for the first 16 extra bytes,
+/// the following ones are decompressed if any of these bits is set
+pub const laszip_DECOMPRESS_SELECTIVE_BYTE0: laszip_U32 = 1 << 16;
+pub const laszip_DECOMPRESS_SELECTIVE_BYTE1: laszip_U32 = 1 << 17;
+pub const laszip_DECOMPRESS_SELECTIVE_BYTE2: laszip_U32 = 1 << 18;
+pub const laszip_DECOMPRESS_SELECTIVE_BYTE3: laszip_U32 = 1 << 19;
+pub const laszip_DECOMPRESS_SELECTIVE_BYTE4: laszip_U32 = 1 << 20;
+pub const laszip_DECOMPRESS_SELECTIVE_BYTE5: laszip_U32 = 1 << 21;
+pub const laszip_DECOMPRESS_SELECTIVE_BYTE6: laszip_U32 = 1 << 22;
+pub const laszip_DECOMPRESS_SELECTIVE_BYTE7: laszip_U32 = 1 << 23;
+pub const laszip_DECOMPRESS_SELECTIVE_EXTRA_BYTES: laszip_U32 = 0xFFFF0000;
+
+const VLR_HEADER_SIZE: u32 = 54;
+const PROJECTION_USER_ID: &str = "LASF_Projection";
+const GEO_KEY_DIRECTORY_RECORD_ID: u16 = 34735;
+const GEO_DOUBLE_PARAMS_RECORD_ID: u16 = 34736;
+const GEO_ASCII_PARAMS_RECORD_ID: u16 = 34737;
+const DEFAULT_CHUNK_SIZE: u32 = 50_000;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct laszip_geokey {
+    pub key_id: laszip_U16,
+    pub tiff_tag_location: laszip_U16,
+    pub count: laszip_U16,
+    pub value_offset: laszip_U16,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct laszip_vlr {
+    pub reserved: laszip_U16,
+    pub user_id: [laszip_CHAR; 16],
+    pub record_id: laszip_U16,
+    pub record_length_after_header: laszip_U16,
+    pub description: [laszip_CHAR; 32],
+    pub data: *mut laszip_U8,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct laszip_header {
+    pub file_source_ID: laszip_U16,
+    pub global_encoding: laszip_U16,
+    pub project_ID_GUID_data_1: laszip_U32,
+    pub project_ID_GUID_data_2: laszip_U16,
+    pub project_ID_GUID_data_3: laszip_U16,
+    pub project_ID_GUID_data_4: [laszip_CHAR; 8],
+    pub version_major: laszip_U8,
+    pub version_minor: laszip_U8,
+    pub system_identifier: [laszip_CHAR; 32],
+    pub generating_software: [laszip_CHAR; 32],
+    pub file_creation_day: laszip_U16,
+    pub file_creation_year: laszip_U16,
+    pub header_size: laszip_U16,
+    pub offset_to_point_data: laszip_U32,
+    pub number_of_variable_length_records: laszip_U32,
+    pub point_data_format: laszip_U8,
+    pub point_data_record_length: laszip_U16,
+    pub number_of_point_records: laszip_U32,
+    pub number_of_points_by_return: [laszip_U32; 5],
+    pub x_scale_factor: laszip_F64,
+    pub y_scale_factor: laszip_F64,
+    pub z_scale_factor: laszip_F64,
+    pub x_offset: laszip_F64,
+    pub y_offset: laszip_F64,
+    pub z_offset: laszip_F64,
+    pub max_x: laszip_F64,
+    pub min_x: laszip_F64,
+    pub max_y: laszip_F64,
+    pub min_y: laszip_F64,
+    pub max_z: laszip_F64,
+    pub min_z: laszip_F64,
+
+    // LAS 1.3 and higher only
+    pub start_of_waveform_data_packet_record: laszip_U64,
+
+    // LAS 1.4 and higher only
+    pub start_of_first_extended_variable_length_record: laszip_U64,
+    pub number_of_extended_variable_length_records: laszip_U32,
+    pub extended_number_of_point_records: laszip_U64,
+    pub extended_number_of_points_by_return: [laszip_U64; 15],
+
+    // optional
+    pub user_data_in_header_size: laszip_U32,
+    pub user_data_in_header: *mut laszip_U8,
+
+    // optional VLRs
+    pub vlrs: *mut laszip_vlr,
+
+    // optional
+    pub user_data_after_header_size: laszip_U32,
+    pub user_data_after_header: *mut laszip_U8,
+}
+
+/// The point, bit fields of the C struct are stored in the `*_bits` bytes.
+///
+/// - `returns_bits`: return_number:3, number_of_returns:3, scan_direction_flag:1, edge_of_flight_line:1
+/// - `classification_bits`: classification:5, synthetic_flag:1, keypoint_flag:1, withheld_flag:1
+/// - `extended_bits`: extended_point_type:2, extended_scanner_channel:2, extended_classification_flags:4
+/// - `extended_returns_bits`: extended_return_number:4, extended_number_of_returns:4
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct laszip_point {
+    pub X: laszip_I32,
+    pub Y: laszip_I32,
+    pub Z: laszip_I32,
+    pub intensity: laszip_U16,
+    pub returns_bits: laszip_U8,
+    pub classification_bits: laszip_U8,
+    pub scan_angle_rank: laszip_I8,
+    pub user_data: laszip_U8,
+    pub point_source_ID: laszip_U16,
+
+    // LAS 1.4 only
+    pub extended_scan_angle: laszip_I16,
+    pub extended_bits: laszip_U8,
+    pub extended_classification: laszip_U8,
+    pub extended_returns_bits: laszip_U8,
+    pub dummy: [laszip_U8; 7],
+
+    pub gps_time: laszip_F64,
+    pub rgb: [laszip_U16; 4],
+    pub wave_packet: [laszip_U8; 29],
+
+    pub num_extra_bytes: laszip_I32,
+    pub extra_bytes: *mut laszip_U8,
+}
+
+impl laszip_point {
+    fn classification(&self) -> u8 {
+        self.classification_bits & 0x1F
+    }
+
+    fn legacy_flags(&self) -> u8 {
+        self.classification_bits >> 5
+    }
+
+    fn extended_classification_flags(&self) -> u8 {
+        self.extended_bits >> 4
+    }
+
+    fn extended_return_number(&self) -> u8 {
+        self.extended_returns_bits & 0xF
+    }
+
+    fn extended_number_of_returns(&self) -> u8 {
+        self.extended_returns_bits >> 4
+    }
+}
+
+/// Size of the standard part of the header for a LAS version
+fn standard_header_size(version_minor: u8) -> u16 {
+    match version_minor {
+        0..=2 => 227,
+        3 => 235,
+        _ => 375,
+    }
+}
+
+/// Size of the point record (without extra bytes) for a point format
+fn base_point_size(point_format: u8) -> Option<u16> {
+    match point_format {
+        0 => Some(20),
+        1 => Some(28),
+        2 => Some(26),
+        3 => Some(34),
+        4 => Some(57),
+        5 => Some(63),
+        6 => Some(30),
+        7 => Some(36),
+        8 => Some(38),
+        9 => Some(59),
+        10 => Some(67),
+        _ => None,
+    }
+}
+
+fn fixed_str<const N: usize>(value: &str) -> [c_char; N] {
+    let mut array = [0 as c_char; N];
+    for (dst, src) in array.iter_mut().zip(value.bytes().take(N - 1)) {
+        *dst = src as c_char;
+    }
+    array
+}
+
+fn read_chars<R: Read, const N: usize>(src: &mut R) -> std::io::Result<[c_char; N]> {
+    let mut bytes = [0u8; N];
+    src.read_exact(&mut bytes)?;
+    Ok(bytes.map(|b| b as c_char))
+}
+
+fn write_chars<W: Write>(dst: &mut W, chars: &[c_char]) -> std::io::Result<()> {
+    let bytes = chars.iter().map(|c| *c as u8).collect::<Vec<u8>>();
+    dst.write_all(&bytes)
+}
+
+/// Compares a fixed size, nul padded, C string with a nul terminated one
+fn chars_eq(chars: &[c_char], other: &[u8]) -> bool {
+    let len = chars.iter().position(|c| *c == 0).unwrap_or(chars.len());
+    chars[..len]
+        .iter()
+        .map(|c| *c as u8)
+        .eq(other.iter().copied())
+}
+
+/// Bounding box & return counts of written points
+#[derive(Default)]
+struct Inventory {
+    number_of_point_records: u64,
+    number_of_points_by_return: [u64; 16],
+    min: [i32; 3],
+    max: [i32; 3],
+}
+
+impl Inventory {
+    fn add(&mut self, point: &laszip_point, is_extended: bool) {
+        let return_number = if is_extended {
+            point.extended_return_number()
+        } else {
+            point.returns_bits & 0x7
+        };
+        self.number_of_points_by_return[return_number as usize] += 1;
+        let coords = [point.X, point.Y, point.Z];
+        if self.number_of_point_records == 0 {
+            self.min = coords;
+            self.max = coords;
+        } else {
+            for ((min, max), coord) in self.min.iter_mut().zip(&mut self.max).zip(coords) {
+                *min = (*min).min(coord);
+                *max = (*max).max(coord);
+            }
+        }
+        self.number_of_point_records += 1;
+    }
+}
+
+enum PointReader {
+    Raw(BufReader<File>),
+    Compressed(LasZipDecompressor<'static, BufReader<File>>),
+}
+
+enum PointWriter {
+    Raw(BufWriter<File>),
+    Compressed(Box<LasZipCompressor<'static, BufWriter<File>>>),
+}
+
+/// What a `laszip_POINTER` points to
+struct LasZipDll {
+    header: laszip_header,
+    point: laszip_point,
+    // Owned storage of the data the header and point point to
+    vlrs: Vec<laszip_vlr>,
+    vlrs_data: Vec<Vec<u8>>,
+    user_data_in_header: Vec<u8>,
+    user_data_after_header: Vec<u8>,
+    extra_bytes: Vec<u8>,
+
+    error: Option<CString>,
+    warning: Option<CString>,
+
+    selection: DecompressionSelection,
+    extra_bytes_selection: ExtraBytesSelection,
+    chunk_size: u32,
+    reader: Option<PointReader>,
+    writer: Option<PointWriter>,
+    inventory: Option<Inventory>,
+    /// Number of points in the file being read
+    npoints: i64,
+    /// Number of points read / written
+    p_count: i64,
+    point_buffer: Vec<u8>,
+}
+
+type CResult = std::result::Result<(), String>;
+
+fn err_str<E: std::fmt::Display>(error: E) -> String {
+    error.to_string()
+}
+
+impl LasZipDll {
+    fn new() -> Self {
+        let mut dll = Self {
+            header: unsafe { std::mem::zeroed() },
+            point: unsafe { std::mem::zeroed() },
+            vlrs: vec![],
+            vlrs_data: vec![],
+            user_data_in_header: vec![],
+            user_data_after_header: vec![],
+            extra_bytes: vec![],
+            error: None,
+            warning: None,
+            selection: DecompressionSelection::all(),
+            extra_bytes_selection: ExtraBytesSelection::none(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            reader: None,
+            writer: None,
+            inventory: None,
+            npoints: 0,
+            p_count: 0,
+            point_buffer: vec![],
+        };
+        dll.clean();
+        dll
+    }
+
+    /// Resets everything to the same defaults as LASzip
+    fn clean(&mut self) {
+        self.header = unsafe { std::mem::zeroed() };
+        self.header.system_identifier = fixed_str("LASzip DLL");
+        self.header.generating_software =
+            fixed_str(&format!("laz-rs {}", env!("CARGO_PKG_VERSION")));
+        self.header.version_major = 1;
+        self.header.version_minor = 2;
+        self.header.header_size = 227;
+        self.header.offset_to_point_data = 227;
+        self.header.point_data_format = 0;
+        self.header.point_data_record_length = 20;
+        self.header.x_scale_factor = 0.01;
+        self.header.y_scale_factor = 0.01;
+        self.header.z_scale_factor = 0.01;
+        self.point = unsafe { std::mem::zeroed() };
+        self.vlrs.clear();
+        self.vlrs_data.clear();
+        self.user_data_in_header.clear();
+        self.user_data_after_header.clear();
+        self.extra_bytes.clear();
+        self.error = None;
+        self.warning = None;
+        self.selection = DecompressionSelection::all();
+        self.extra_bytes_selection = ExtraBytesSelection::none();
+        self.chunk_size = DEFAULT_CHUNK_SIZE;
+        self.reader = None;
+        self.writer = None;
+        self.inventory = None;
+        self.npoints = 0;
+        self.p_count = 0;
+        self.sync_pointers();
+    }
+
+    /// Makes the pointers of the header and point point to the owned storage
+    fn sync_pointers(&mut self) {
+        for (vlr, data) in self.vlrs.iter_mut().zip(self.vlrs_data.iter_mut()) {
+            vlr.data = if data.is_empty() {
+                std::ptr::null_mut()
+            } else {
+                data.as_mut_ptr()
+            };
+        }
+        fn ptr_of<T>(values: &mut Vec<T>) -> *mut T {
+            if values.is_empty() {
+                std::ptr::null_mut()
+            } else {
+                values.as_mut_ptr()
+            }
+        }
+        self.header.vlrs = ptr_of(&mut self.vlrs);
+        self.header.number_of_variable_length_records = self.vlrs.len() as u32;
+        self.header.user_data_in_header = ptr_of(&mut self.user_data_in_header);
+        self.header.user_data_in_header_size = self.user_data_in_header.len() as u32;
+        self.header.user_data_after_header = ptr_of(&mut self.user_data_after_header);
+        self.header.user_data_after_header_size = self.user_data_after_header.len() as u32;
+        self.point.extra_bytes = ptr_of(&mut self.extra_bytes);
+        self.point.num_extra_bytes = self.extra_bytes.len() as i32;
+    }
+
+    fn is_busy(&self) -> bool {
+        self.reader.is_some() || self.writer.is_some()
+    }
+
+    fn check_not_busy(&self) -> CResult {
+        if self.reader.is_some() {
+            return Err("cannot do that while a reader is open".into());
+        }
+        if self.writer.is_some() {
+            return Err("cannot do that while a writer is open".into());
+        }
+        Ok(())
+    }
+
+    /// Checks the point type & size and sizes the extra bytes accordingly
+    fn set_point_type_and_size(&mut self, point_type: u8, point_size: u16) -> CResult {
+        let base_size = base_point_size(point_type)
+            .ok_or_else(|| format!("point type {} not supported", point_type))?;
+        if point_size < base_size {
+            return Err(format!(
+                "point size {} too small for point type {} by {} bytes",
+                point_size,
+                point_type,
+                base_size - point_size
+            ));
+        }
+        self.header.point_data_format = point_type;
+        self.header.point_data_record_length = point_size;
+        self.extra_bytes = vec![0u8; (point_size - base_size) as usize];
+        self.point.extended_bits = if point_type > 5 { 1 } else { 0 };
+        self.sync_pointers();
+        Ok(())
+    }
+
+    fn set_header(&mut self, header: &laszip_header) -> CResult {
+        self.header = *header;
+        self.user_data_in_header = if header.user_data_in_header_size == 0 {
+            vec![]
+        } else if header.user_data_in_header.is_null() {
+            return Err(format!(
+                "header->user_data_in_header_size is {} but header->user_data_in_header is NULL",
+                header.user_data_in_header_size
+            ));
+        } else {
+            unsafe {
+                std::slice::from_raw_parts(
+                    header.user_data_in_header,
+                    header.user_data_in_header_size as usize,
+                )
+            }
+            .to_vec()
+        };
+        self.user_data_after_header = if header.user_data_after_header_size == 0 {
+            vec![]
+        } else if header.user_data_after_header.is_null() {
+            return Err(format!(
+                "header->user_data_after_header_size is {} but header->user_data_after_header is NULL",
+                header.user_data_after_header_size
+            ));
+        } else {
+            unsafe {
+                std::slice::from_raw_parts(
+                    header.user_data_after_header,
+                    header.user_data_after_header_size as usize,
+                )
+            }
+            .to_vec()
+        };
+        self.vlrs.clear();
+        self.vlrs_data.clear();
+        if header.number_of_variable_length_records != 0 {
+            if header.vlrs.is_null() {
+                return Err(format!(
+                    "header->number_of_variable_length_records is {} but header->vlrs is NULL",
+                    header.number_of_variable_length_records
+                ));
+            }
+            let vlrs = unsafe {
+                std::slice::from_raw_parts(
+                    header.vlrs,
+                    header.number_of_variable_length_records as usize,
+                )
+            };
+            for vlr in vlrs {
+                let data = if vlr.record_length_after_header == 0 {
+                    vec![]
+                } else if vlr.data.is_null() {
+                    return Err(format!(
+                        "header->vlrs[{}].record_length_after_header is {} but header->vlrs[{}].data is NULL",
+                        self.vlrs.len(),
+                        vlr.record_length_after_header,
+                        self.vlrs.len()
+                    ));
+                } else {
+                    unsafe {
+                        std::slice::from_raw_parts(
+                            vlr.data,
+                            vlr.record_length_after_header as usize,
+                        )
+                    }
+                    .to_vec()
+                };
+                self.vlrs.push(*vlr);
+                self.vlrs_data.push(data);
+            }
+        }
+        self.set_point_type_and_size(header.point_data_format, header.point_data_record_length)
+    }
+
+    fn set_point(&mut self, point: &laszip_point) -> CResult {
+        if point.num_extra_bytes as usize != self.extra_bytes.len() {
+            return Err(format!(
+                "target point has {} extra bytes but source point has {}",
+                self.extra_bytes.len(),
+                point.num_extra_bytes
+            ));
+        }
+        if !self.extra_bytes.is_empty() {
+            if point.extra_bytes.is_null() {
+                return Err(format!(
+                    "source point has {} extra bytes but they are NULL",
+                    point.num_extra_bytes
+                ));
+            }
+            let extra_bytes =
+                unsafe { std::slice::from_raw_parts(point.extra_bytes, self.extra_bytes.len()) };
+            self.extra_bytes.copy_from_slice(extra_bytes);
+        }
+        self.point = *point;
+        self.sync_pointers();
+        Ok(())
+    }
+
+    fn add_vlr(&mut self, vlr: laszip_vlr, data: Vec<u8>) {
+        let existing = self
+            .vlrs
+            .iter()
+            .position(|v| v.record_id == vlr.record_id && v.user_id[..] == vlr.user_id[..]);
+        match existing {
+            Some(i) => {
+                self.vlrs[i] = vlr;
+                self.vlrs_data[i] = data;
+            }
+            None => {
+                self.vlrs.push(vlr);
+                self.vlrs_data.push(data);
+            }
+        }
+        self.sync_pointers();
+    }
+
+    /// Adds (or replaces) one of the GeoTIFF vlrs of the "LASF_Projection" user
+    fn add_projection_vlr(&mut self, record_id: u16, data: Vec<u8>) -> CResult {
+        let record_length_after_header = u16::try_from(data.len())
+            .map_err(|_| format!("{} bytes of geo data do not fit in a VLR", data.len()))?;
+        let vlr = laszip_vlr {
+            reserved: 0,
+            user_id: fixed_str(PROJECTION_USER_ID),
+            record_id,
+            record_length_after_header,
+            description: fixed_str(&format!("by laz-rs {}", env!("CARGO_PKG_VERSION"))),
+            data: std::ptr::null_mut(),
+        };
+        self.add_vlr(vlr, data);
+        Ok(())
+    }
+
+    fn remove_vlr(&mut self, user_id: &[u8], record_id: u16) -> CResult {
+        let position = self
+            .vlrs
+            .iter()
+            .position(|v| v.record_id == record_id && chars_eq(&v.user_id, user_id))
+            .ok_or_else(|| {
+                format!(
+                    "cannot find VLR with user_id '{}' and record_id {}",
+                    String::from_utf8_lossy(user_id),
+                    record_id
+                )
+            })?;
+        self.vlrs.remove(position);
+        self.vlrs_data.remove(position);
+        self.sync_pointers();
+        Ok(())
+    }
+
+    fn open_reader(&mut self, file_name: &str) -> std::result::Result<bool, String> {
+        let file = File::open(file_name)
+            .map_err(|e| format!("cannot open file '{}': {}", file_name, e))?;
+        let mut src = BufReader::new(file);
+        self.read_header(&mut src).map_err(err_str)?;
+
+        let laz_vlr_position = self.vlrs.iter().position(|vlr| {
+            vlr.record_id == LazVlr::RECORD_ID && chars_eq(&vlr.user_id, LazVlr::USER_ID.as_bytes())
+        });
+        let is_compressed = (self.header.point_data_format & 0x80) != 0;
+        self.header.point_data_format &= 0x3F;
+
+        let reader = match (is_compressed, laz_vlr_position) {
+            (true, Some(position)) => {
+                let laz_vlr = LazVlr::from_buffer(&self.vlrs_data[position]).map_err(err_str)?;
+                let point_data_start = self.header.offset_to_point_data as u64;
+                // The laszip vlr is not exposed to the user
+                self.header.offset_to_point_data -=
+                    VLR_HEADER_SIZE + self.vlrs[position].record_length_after_header as u32;
+                self.vlrs.remove(position);
+                self.vlrs_data.remove(position);
+                self.sync_pointers();
+                let selection = self.selection;
+                src.seek(SeekFrom::Start(point_data_start))
+                    .map_err(err_str)?;
+                let mut decompressor =
+                    LasZipDecompressor::selective(src, laz_vlr, selection).map_err(err_str)?;
+                decompressor.set_extra_bytes_selection(self.extra_bytes_selection);
+                PointReader::Compressed(decompressor)
+            }
+            (true, None) => return Err("file is compressed but has no laszip VLR".into()),
+            (false, _) => {
+                src.seek(SeekFrom::Start(self.header.offset_to_point_data as u64))
+                    .map_err(err_str)?;
+                PointReader::Raw(src)
+            }
+        };
+
+        self.set_point_type_and_size(
+            self.header.point_data_format,
+            self.header.point_data_record_length,
+        )?;
+        self.npoints = if self.header.number_of_point_records != 0 {
+            self.header.number_of_point_records as i64
+        } else {
+            self.header.extended_number_of_point_records as i64
+        };
+        self.point_buffer = vec![0u8; self.header.point_data_record_length as usize];
+        self.p_count = 0;
+        self.reader = Some(reader);
+        Ok(is_compressed)
+    }
+
+    /// Reads the header, VLRs and user data of a LAS file
+    fn read_header<R: Read + Seek>(&mut self, src: &mut R) -> std::io::Result<()> {
+        let mut signature = [0u8; 4];
+        src.read_exact(&mut signature)?;
+        if &signature != b"LASF" {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "wrong file signature, not a LAS/LAZ file",
+            ));
+        }
+        let h = &mut self.header;
+        h.file_source_ID = src.read_u16::<LittleEndian>()?;
+        h.global_encoding = src.read_u16::<LittleEndian>()?;
+        h.project_ID_GUID_data_1 = src.read_u32::<LittleEndian>()?;
+        h.project_ID_GUID_data_2 = src.read_u16::<LittleEndian>()?;
+        h.project_ID_GUID_data_3 = src.read_u16::<LittleEndian>()?;
+        h.project_ID_GUID_data_4 = read_chars(src)?;
+        h.version_major = src.read_u8()?;
+        h.version_minor = src.read_u8()?;
+        h.system_identifier = read_chars(src)?;
+        h.generating_software = read_chars(src)?;
+        h.file_creation_day = src.read_u16::<LittleEndian>()?;
+        h.file_creation_year = src.read_u16::<LittleEndian>()?;
+        h.header_size = src.read_u16::<LittleEndian>()?;
+        h.offset_to_point_data = src.read_u32::<LittleEndian>()?;
+        h.number_of_variable_length_records = src.read_u32::<LittleEndian>()?;
+        h.point_data_format = src.read_u8()?;
+        h.point_data_record_length = src.read_u16::<LittleEndian>()?;
+        h.number_of_point_records = src.read_u32::<LittleEndian>()?;
+        for count in h.number_of_points_by_return.iter_mut() {
+            *count = src.read_u32::<LittleEndian>()?;
+        }
+        h.x_scale_factor = src.read_f64::<LittleEndian>()?;
+        h.y_scale_factor = src.read_f64::<LittleEndian>()?;
+        h.z_scale_factor = src.read_f64::<LittleEndian>()?;
+        h.x_offset = src.read_f64::<LittleEndian>()?;
+        h.y_offset = src.read_f64::<LittleEndian>()?;
+        h.z_offset = src.read_f64::<LittleEndian>()?;
+        h.max_x = src.read_f64::<LittleEndian>()?;
+        h.min_x = src.read_f64::<LittleEndian>()?;
+        h.max_y = src.read_f64::<LittleEndian>()?;
+        h.min_y = src.read_f64::<LittleEndian>()?;
+        h.max_z = src.read_f64::<LittleEndian>()?;
+        h.min_z = src.read_f64::<LittleEndian>()?;
+
+        let standard_size = standard_header_size(h.version_minor);
+        if h.version_minor >= 3 {
+            h.start_of_waveform_data_packet_record = src.read_u64::<LittleEndian>()?;
+        }
+        if h.version_minor >= 4 {
+            h.start_of_first_extended_variable_length_record = src.read_u64::<LittleEndian>()?;
+            h.number_of_extended_variable_length_records = src.read_u32::<LittleEndian>()?;
+            h.extended_number_of_point_records = src.read_u64::<LittleEndian>()?;
+            for count in h.extended_number_of_points_by_return.iter_mut() {
+                *count = src.read_u64::<LittleEndian>()?;
+            }
+        }
+        let header_size = h.header_size;
+        let offset_to_point_data = h.offset_to_point_data as u64;
+        let number_of_vlrs = h.number_of_variable_length_records;
+
+        self.user_data_in_header = vec![0u8; header_size.saturating_sub(standard_size) as usize];
+        src.read_exact(&mut self.user_data_in_header)?;
+        src.seek(SeekFrom::Start(header_size as u64))?;
+
+        self.vlrs.clear();
+        self.vlrs_data.clear();
+        for _ in 0..number_of_vlrs {
+            let mut vlr = laszip_vlr {
+                reserved: src.read_u16::<LittleEndian>()?,
+                user_id: read_chars(src)?,
+                record_id: src.read_u16::<LittleEndian>()?,
+                record_length_after_header: src.read_u16::<LittleEndian>()?,
+                description: [0; 32],
+                data: std::ptr::null_mut(),
+            };
+            vlr.description = read_chars(src)?;
+            let mut data = vec![0u8; vlr.record_length_after_header as usize];
+            src.read_exact(&mut data)?;
+            self.vlrs.push(vlr);
+            self.vlrs_data.push(data);
+        }
+
+        let position = src.stream_position()?;
+        self.user_data_after_header =
+            vec![0u8; offset_to_point_data.saturating_sub(position) as usize];
+        src.read_exact(&mut self.user_data_after_header)?;
+        self.sync_pointers();
+        Ok(())
+    }
+
+    fn seek_point(&mut self, index: i64) -> CResult {
+        if index < 0 || index > self.npoints {
+            return Err(format!(
+                "index {} is out of range, the file has {} points",
+                index, self.npoints
+            ));
+        }
+        match self.reader.as_mut() {
+            Some(PointReader::Raw(src)) => {
+                let position = self.header.offset_to_point_data as u64
+                    + index as u64 * self.header.point_data_record_length as u64;
+                src.seek(SeekFrom::Start(position)).map_err(err_str)?;
+            }
+            Some(PointReader::Compressed(decompressor)) => {
+                decompressor.seek(index as u64).map_err(err_str)?;
+            }
+            None => return Err("reading points before reader was opened".into()),
+        }
+        self.p_count = index;
+        Ok(())
+    }
+
+    fn read_point(&mut self) -> CResult {
+        match self.reader.as_mut() {
+            Some(PointReader::Raw(src)) => {
+                src.read_exact(&mut self.point_buffer).map_err(err_str)?
+            }
+            Some(PointReader::Compressed(decompressor)) => decompressor
+                .decompress_one(&mut self.point_buffer)
+                .map_err(err_str)?,
+            None => return Err("reading points before reader was opened".into()),
+        }
+        let buffer = std::mem::take(&mut self.point_buffer);
+        self.point_from_raw(&buffer);
+        self.point_buffer = buffer;
+        self.p_count += 1;
+        Ok(())
+    }
+
+    fn point_from_raw(&mut self, raw: &[u8]) {
+        let format = self.header.point_data_format;
+        let read_u16 = |offset: usize| u16::from_le_bytes([raw[offset], raw[offset + 1]]);
+        let read_f64 =
+            |offset: usize| f64::from_le_bytes(raw[offset..offset + 8].try_into().unwrap());
+        let p = &mut self.point;
+        p.X = i32::from_le_bytes(raw[0..4].try_into().unwrap());
+        p.Y = i32::from_le_bytes(raw[4..8].try_into().unwrap());
+        p.Z = i32::from_le_bytes(raw[8..12].try_into().unwrap());
+        p.intensity = read_u16(12);
+        let (mut offset, has_gps, has_rgb, has_nir, has_wave_packet) = if format <= 5 {
+            p.returns_bits = raw[14];
+            p.classification_bits = raw[15];
+            p.scan_angle_rank = raw[16] as i8;
+            p.user_data = raw[17];
+            p.point_source_ID = read_u16(18);
+            (
+                20,
+                matches!(format, 1 | 3 | 4 | 5),
+                matches!(format, 2 | 3 | 5),
+                false,
+                matches!(format, 4 | 5),
+            )
+        } else {
+            let return_number = raw[14] & 0xF;
+            let number_of_returns = raw[14] >> 4;
+            p.extended_returns_bits = raw[14];
+            // Same mapping as LASzip for the legacy fields
+            let (legacy_return_number, legacy_number_of_returns) = if number_of_returns > 7 {
+                if return_number > 6 {
+                    if return_number >= number_of_returns {
+                        (7, 7)
+                    } else {
+                        (6, 7)
+                    }
+                } else {
+                    (return_number, 7)
+                }
+            } else {
+                (return_number, number_of_returns)
+            };
+            let flags = raw[15] & 0xF;
+            let scanner_channel = (raw[15] >> 4) & 0x3;
+            p.returns_bits =
+                legacy_return_number | (legacy_number_of_returns << 3) | (raw[15] & 0xC0);
+            p.extended_classification = raw[16];
+            let legacy_class = if raw[16] < 32 { raw[16] } else { 0 };
+            p.classification_bits = legacy_class | ((flags & 0x7) << 5);
+            p.extended_bits = 1 | (scanner_channel << 2) | (flags << 4);
+            p.user_data = raw[17];
+            p.extended_scan_angle = read_u16(18) as i16;
+            p.scan_angle_rank = (0.006f32 * p.extended_scan_angle as f32)
+                .round()
+                .clamp(-128.0, 127.0) as i8;
+            p.point_source_ID = read_u16(20);
+            p.gps_time = read_f64(22);
+            (
+                30,
+                false,
+                matches!(format, 7 | 8 | 10),
+                matches!(format, 8 | 10),
+                matches!(format, 9 | 10),
+            )
+        };
+        if has_gps {
+            p.gps_time = read_f64(offset);
+            offset += 8;
+        }
+        if has_rgb {
+            for i in 0..3 {
+                p.rgb[i] = read_u16(offset);
+                offset += 2;
+            }
+        }
+        if has_nir {
+            p.rgb[3] = read_u16(offset);
+            offset += 2;
+        }
+        if has_wave_packet {
+            p.wave_packet.copy_from_slice(&raw[offset..offset + 29]);
+            offset += 29;
+        }
+        self.extra_bytes.copy_from_slice(&raw[offset..]);
+    }
+
+    fn point_to_raw(&self, raw: &mut [u8]) -> CResult {
+        let format = self.header.point_data_format;
+        let p = &self.point;
+        raw[0..4].copy_from_slice(&p.X.to_le_bytes());
+        raw[4..8].copy_from_slice(&p.Y.to_le_bytes());
+        raw[8..12].copy_from_slice(&p.Z.to_le_bytes());
+        raw[12..14].copy_from_slice(&p.intensity.to_le_bytes());
+        let (mut offset, has_gps, has_rgb, has_nir, has_wave_packet) = if format <= 5 {
+            raw[14] = p.returns_bits;
+            raw[15] = p.classification_bits;
+            raw[16] = p.scan_angle_rank as u8;
+            raw[17] = p.user_data;
+            raw[18..20].copy_from_slice(&p.point_source_ID.to_le_bytes());
+            (
+                20,
+                matches!(format, 1 | 3 | 4 | 5),
+                matches!(format, 2 | 3 | 5),
+                false,
+                matches!(format, 4 | 5),
+            )
+        } else {
+            // Same checks as LASzip
+            if (p.extended_classification_flags() & 0x7) != p.legacy_flags() {
+                return Err("legacy flags and extended flags are not identical".into());
+            }
+            if p.classification() != 0 && p.classification() != p.extended_classification {
+                return Err(
+                    "legacy classification and extended classification are not compatible".into(),
+                );
+            }
+            raw[14] = p.extended_return_number() | (p.extended_number_of_returns() << 4);
+            raw[15] = p.extended_classification_flags()
+                | (((p.extended_bits >> 2) & 0x3) << 4)
+                | (p.returns_bits & 0xC0);
+            raw[16] = p.extended_classification;
+            raw[17] = p.user_data;
+            raw[18..20].copy_from_slice(&p.extended_scan_angle.to_le_bytes());
+            raw[20..22].copy_from_slice(&p.point_source_ID.to_le_bytes());
+            raw[22..30].copy_from_slice(&p.gps_time.to_le_bytes());
+            (
+                30,
+                false,
+                matches!(format, 7 | 8 | 10),
+                matches!(format, 8 | 10),
+                matches!(format, 9 | 10),
+            )
+        };
+        if has_gps {
+            raw[offset..offset + 8].copy_from_slice(&p.gps_time.to_le_bytes());
+            offset += 8;
+        }
+        if has_rgb {
+            for i in 0..3 {
+                raw[offset..offset + 2].copy_from_slice(&p.rgb[i].to_le_bytes());
+                offset += 2;
+            }
+        }
+        if has_nir {
+            raw[offset..offset + 2].copy_from_slice(&p.rgb[3].to_le_bytes());
+            offset += 2;
+        }
+        if has_wave_packet {
+            raw[offset..offset + 29].copy_from_slice(&p.wave_packet);
+            offset += 29;
+        }
+        raw[offset..].copy_from_slice(&self.extra_bytes);
+        Ok(())
+    }
+
+    fn open_writer(&mut self, file_name: &str, compress: bool) -> CResult {
+        let point_format = self.header.point_data_format;
+        let point_size = self.header.point_data_record_length;
+        let base_size = base_point_size(point_format)
+            .ok_or_else(|| format!("point type {} not supported", point_format))?;
+        let num_extra_bytes = point_size
+            .checked_sub(base_size)
+            .ok_or_else(|| format!("point size {} too small", point_size))?;
+
+        let laz_vlr = if compress {
+            let items =
+                LazItemRecordBuilder::default_for_point_format_id(point_format, num_extra_bytes)
+                    .map_err(err_str)?;
+            Some(
+                LazVlrBuilder::new(items)
+                    .with_fixed_chunk_size(self.chunk_size)
+                    .build(),
+            )
+        } else {
+            None
+        };
+
+        let file = File::create(file_name)
+            .map_err(|e| format!("cannot open file '{}': {}", file_name, e))?;
+        let mut dst = BufWriter::new(file);
+        self.write_header(&mut dst, laz_vlr.as_ref())
+            .map_err(err_str)?;
+
+        let writer = match laz_vlr {
+            Some(vlr) => {
+                PointWriter::Compressed(Box::new(LasZipCompressor::new(dst, vlr).map_err(err_str)?))
+            }
+            None => PointWriter::Raw(dst),
+        };
+        self.point_buffer = vec![0u8; point_size as usize];
+        self.p_count = 0;
+        self.writer = Some(writer);
+        Ok(())
+    }
+
+    fn write_header<W: Write>(
+        &mut self,
+        dst: &mut W,
+        laz_vlr: Option<&LazVlr>,
+    ) -> std::io::Result<()> {
+        let mut laz_vlr_data = vec![];
+        if let Some(vlr) = laz_vlr {
+            vlr.write_to(&mut laz_vlr_data)?;
+        }
+
+        let h = &mut self.header;
+        h.header_size =
+            standard_header_size(h.version_minor) + self.user_data_in_header.len() as u16;
+        let vlrs_size = self
+            .vlrs
+            .iter()
+            .map(|vlr| VLR_HEADER_SIZE + vlr.record_length_after_header as u32)
+            .sum::<u32>();
+        let laz_vlr_size = if laz_vlr.is_some() {
+            VLR_HEADER_SIZE + laz_vlr_data.len() as u32
+        } else {
+            0
+        };
+        h.offset_to_point_data = h.header_size as u32
+            + vlrs_size
+            + laz_vlr_size
+            + self.user_data_after_header.len() as u32;
+        let number_of_vlrs = self.vlrs.len() as u32 + laz_vlr.is_some() as u32;
+        let point_format = if laz_vlr.is_some() {
+            h.point_data_format | 0x80
+        } else {
+            h.point_data_format
+        };
+
+        dst.write_all(b"LASF")?;
+        dst.write_u16::<LittleEndian>(h.file_source_ID)?;
+        dst.write_u16::<LittleEndian>(h.global_encoding)?;
+        dst.write_u32::<LittleEndian>(h.project_ID_GUID_data_1)?;
+        dst.write_u16::<LittleEndian>(h.project_ID_GUID_data_2)?;
+        dst.write_u16::<LittleEndian>(h.project_ID_GUID_data_3)?;
+        write_chars(dst, &h.project_ID_GUID_data_4)?;
+        dst.write_u8(h.version_major)?;
+        dst.write_u8(h.version_minor)?;
+        write_chars(dst, &h.system_identifier)?;
+        write_chars(dst, &h.generating_software)?;
+        dst.write_u16::<LittleEndian>(h.file_creation_day)?;
+        dst.write_u16::<LittleEndian>(h.file_creation_year)?;
+        dst.write_u16::<LittleEndian>(h.header_size)?;
+        dst.write_u32::<LittleEndian>(h.offset_to_point_data)?;
+        dst.write_u32::<LittleEndian>(number_of_vlrs)?;
+        dst.write_u8(point_format)?;
+        dst.write_u16::<LittleEndian>(h.point_data_record_length)?;
+        dst.write_u32::<LittleEndian>(h.number_of_point_records)?;
+        for count in &h.number_of_points_by_return {
+            dst.write_u32::<LittleEndian>(*count)?;
+        }
+        for value in [
+            h.x_scale_factor,
+            h.y_scale_factor,
+            h.z_scale_factor,
+            h.x_offset,
+            h.y_offset,
+            h.z_offset,
+            h.max_x,
+            h.min_x,
+            h.max_y,
+            h.min_y,
+            h.max_z,
+            h.min_z,
+        ] {
+            dst.write_f64::<LittleEndian>(value)?;
+        }
+        if h.version_minor >= 3 {
+            dst.write_u64::<LittleEndian>(h.start_of_waveform_data_packet_record)?;
+        }
+        if h.version_minor >= 4 {
+            dst.write_u64::<LittleEndian>(h.start_of_first_extended_variable_length_record)?;
+            dst.write_u32::<LittleEndian>(h.number_of_extended_variable_length_records)?;
+            dst.write_u64::<LittleEndian>(h.extended_number_of_point_records)?;
+            for count in &h.extended_number_of_points_by_return {
+                dst.write_u64::<LittleEndian>(*count)?;
+            }
+        }
+        dst.write_all(&self.user_data_in_header)?;
+
+        for (vlr, data) in self.vlrs.iter().zip(&self.vlrs_data) {
+            dst.write_u16::<LittleEndian>(vlr.reserved)?;
+            write_chars(dst, &vlr.user_id)?;
+            dst.write_u16::<LittleEndian>(vlr.record_id)?;
+            dst.write_u16::<LittleEndian>(vlr.record_length_after_header)?;
+            write_chars(dst, &vlr.description)?;
+            dst.write_all(data)?;
+        }
+        if laz_vlr.is_some() {
+            dst.write_u16::<LittleEndian>(0)?;
+            write_chars(dst, &fixed_str::<16>(LazVlr::USER_ID))?;
+            dst.write_u16::<LittleEndian>(LazVlr::RECORD_ID)?;
+            dst.write_u16::<LittleEndian>(laz_vlr_data.len() as u16)?;
+            write_chars(dst, &fixed_str::<32>(LazVlr::DESCRIPTION))?;
+            dst.write_all(&laz_vlr_data)?;
+        }
+        dst.write_all(&self.user_data_after_header)?;
+        Ok(())
+    }
+
+    fn write_point(&mut self) -> CResult {
+        if self.writer.is_none() {
+            return Err("writing points before writer was opened".into());
+        }
+        let mut buffer = std::mem::take(&mut self.point_buffer);
+        let result = self.point_to_raw(&mut buffer);
+        self.point_buffer = buffer;
+        result?;
+        match self.writer.as_mut() {
+            Some(PointWriter::Raw(dst)) => dst.write_all(&self.point_buffer).map_err(err_str)?,
+            Some(PointWriter::Compressed(compressor)) => compressor
+                .compress_one(&self.point_buffer)
+                .map_err(err_str)?,
+            None => unreachable!(),
+        }
+        self.p_count += 1;
+        Ok(())
+    }
+
+    fn update_inventory(&mut self) -> CResult {
+        let is_extended = self.header.point_data_format > 5;
+        self.inventory
+            .get_or_insert_with(Inventory::default)
+            .add(&self.point, is_extended);
+        Ok(())
+    }
+
+    fn close_writer(&mut self) -> CResult {
+        let mut dst = match self.writer.take() {
+            Some(PointWriter::Raw(dst)) => dst,
+            Some(PointWriter::Compressed(mut compressor)) => {
+                compressor.done().map_err(err_str)?;
+                compressor.into_inner()
+            }
+            None => return Err("closing writer before it was opened".into()),
+        };
+        if let Some(inventory) = self.inventory.take() {
+            self.write_inventory(&mut dst, &inventory)
+                .map_err(err_str)?;
+        }
+        dst.flush().map_err(err_str)?;
+        Ok(())
+    }
+
+    /// Updates the point counts and bounds of the written header
+    fn write_inventory<W: Write + Seek>(
+        &mut self,
+        dst: &mut W,
+        inventory: &Inventory,
+    ) -> std::io::Result<()> {
+        let h = &mut self.header;
+        let count = inventory.number_of_point_records;
+        h.number_of_point_records = if h.point_data_format <= 5 && count <= u32::MAX as u64 {
+            count as u32
+        } else {
+            0
+        };
+        for i in 0..5 {
+            h.number_of_points_by_return[i] = if h.number_of_point_records != 0 {
+                inventory.number_of_points_by_return[i + 1] as u32
+            } else {
+                0
+            };
+        }
+        h.max_x = inventory.max[0] as f64 * h.x_scale_factor + h.x_offset;
+        h.min_x = inventory.min[0] as f64 * h.x_scale_factor + h.x_offset;
+        h.max_y = inventory.max[1] as f64 * h.y_scale_factor + h.y_offset;
+        h.min_y = inventory.min[1] as f64 * h.y_scale_factor + h.y_offset;
+        h.max_z = inventory.max[2] as f64 * h.z_scale_factor + h.z_offset;
+        h.min_z = inventory.min[2] as f64 * h.z_scale_factor + h.z_offset;
+
+        dst.seek(SeekFrom::Start(107))?;
+        dst.write_u32::<LittleEndian>(h.number_of_point_records)?;
+        for count in &h.number_of_points_by_return {
+            dst.write_u32::<LittleEndian>(*count)?;
+        }
+        dst.seek(SeekFrom::Start(179))?;
+        for value in [h.max_x, h.min_x, h.max_y, h.min_y, h.max_z, h.min_z] {
+            dst.write_f64::<LittleEndian>(value)?;
+        }
+        if h.version_minor >= 4 {
+            h.extended_number_of_point_records = count;
+            for i in 0..15 {
+                h.extended_number_of_points_by_return[i] =
+                    inventory.number_of_points_by_return[i + 1];
+            }
+            dst.seek(SeekFrom::Start(247))?;
+            dst.write_u64::<LittleEndian>(h.extended_number_of_point_records)?;
+            for count in &h.extended_number_of_points_by_return {
+                dst.write_u64::<LittleEndian>(*count)?;
+            }
+        }
+        dst.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+}
+
+/// Runs `f` on the handle, storing the error message if any
+unsafe fn with_dll<F>(pointer: laszip_POINTER, f: F) -> laszip_I32
+where
+    F: FnOnce(&mut LasZipDll) -> CResult,
+{
+    if pointer.is_null() {
+        return 1;
+    }
+    let dll = &mut *(pointer as *mut LasZipDll);
+    let result = catch_unwind(AssertUnwindSafe(|| f(dll)))
+        .unwrap_or_else(|_| Err("internal error (panic)".to_string()));
+    match result {
+        Ok(()) => {
+            dll.error = None;
+            0
+        }
+        Err(message) => {
+            dll.error = Some(CString::new(message.replace('\0', "")).unwrap_or_default());
+            1
+        }
+    }
+}
+
+/// Converts a C string argument
+unsafe fn str_arg<'a>(
+    value: *const laszip_CHAR,
+    name: &str,
+) -> std::result::Result<&'a str, String> {
+    if value.is_null() {
+        return Err(format!("{} pointer is zero", name));
+    }
+    CStr::from_ptr(value)
+        .to_str()
+        .map_err(|_| format!("{} is not valid UTF-8", name))
+}
+
+macro_rules! check_out_ptr {
+    ($ptr:ident) => {
+        if $ptr.is_null() {
+            return Err(concat!(stringify!($ptr), " pointer is zero").to_string());
+        }
+    };
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_get_version(
+    version_major: *mut laszip_U8,
+    version_minor: *mut laszip_U8,
+    version_revision: *mut laszip_U16,
+    version_build: *mut laszip_U32,
+) -> laszip_I32 {
+    if version_major.is_null()
+        || version_minor.is_null()
+        || version_revision.is_null()
+        || version_build.is_null()
+    {
+        return 1;
+    }
+    *version_major = LASZIP_API_VERSION_MAJOR;
+    *version_minor = LASZIP_API_VERSION_MINOR;
+    *version_revision = LASZIP_API_VERSION_REVISION;
+    *version_build = LASZIP_VERSION_BUILD_DATE;
+    0
+}
+
+/// Nothing to load, the library is linked directly, always returns `0`
+#[no_mangle]
+pub unsafe extern "C" fn laszip_load_dll() -> laszip_I32 {
+    0
+}
+
+/// Nothing to unload, always returns `0`
+#[no_mangle]
+pub unsafe extern "C" fn laszip_unload_dll() -> laszip_I32 {
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_create(pointer: *mut laszip_POINTER) -> laszip_I32 {
+    if pointer.is_null() {
+        return 1;
+    }
+    *pointer = Box::into_raw(Box::new(LasZipDll::new())) as laszip_POINTER;
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_get_error(
+    pointer: laszip_POINTER,
+    error: *mut *mut laszip_CHAR,
+) -> laszip_I32 {
+    if pointer.is_null() || error.is_null() {
+        return 1;
+    }
+    let dll = &mut *(pointer as *mut LasZipDll);
+    *error = dll
+        .error
+        .as_ref()
+        .map_or(std::ptr::null_mut(), |e| e.as_ptr() as *mut laszip_CHAR);
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_get_warning(
+    pointer: laszip_POINTER,
+    warning: *mut *mut laszip_CHAR,
+) -> laszip_I32 {
+    if pointer.is_null() || warning.is_null() {
+        return 1;
+    }
+    let dll = &mut *(pointer as *mut LasZipDll);
+    *warning = dll
+        .warning
+        .as_ref()
+        .map_or(std::ptr::null_mut(), |w| w.as_ptr() as *mut laszip_CHAR);
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_clean(pointer: laszip_POINTER) -> laszip_I32 {
+    with_dll(pointer, |dll| {
+        dll.check_not_busy()?;
+        dll.clean();
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_destroy(pointer: laszip_POINTER) -> laszip_I32 {
+    if pointer.is_null() {
+        return 1;
+    }
+    let dll = Box::from_raw(pointer as *mut LasZipDll);
+    if dll.is_busy() {
+        // Give it back, the user has to close the reader / writer first
+        let dll = Box::into_raw(dll);
+        (*dll).error = CString::new("cannot destroy while a reader or writer is open").ok();
+        return 1;
+    }
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_get_header_pointer(
+    pointer: laszip_POINTER,
+    header_pointer: *mut *mut laszip_header,
+) -> laszip_I32 {
+    with_dll(pointer, |dll| {
+        check_out_ptr!(header_pointer);
+        *header_pointer = &mut dll.header;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_get_point_pointer(
+    pointer: laszip_POINTER,
+    point_pointer: *mut *mut laszip_point,
+) -> laszip_I32 {
+    with_dll(pointer, |dll| {
+        check_out_ptr!(point_pointer);
+        *point_pointer = &mut dll.point;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_get_point_count(
+    pointer: laszip_POINTER,
+    count: *mut laszip_I64,
+) -> laszip_I32 {
+    with_dll(pointer, |dll| {
+        check_out_ptr!(count);
+        if !dll.is_busy() {
+            return Err("getting count before reader or writer was opened".into());
+        }
+        *count = dll.p_count;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_set_header(
+    pointer: laszip_POINTER,
+    header: *const laszip_header,
+) -> laszip_I32 {
+    with_dll(pointer, |dll| {
+        if header.is_null() {
+            return Err("laszip_header_struct pointer 'header' is zero".into());
+        }
+        dll.check_not_busy()?;
+        dll.set_header(&*header)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_set_point_type_and_size(
+    pointer: laszip_POINTER,
+    point_type: laszip_U8,
+    point_size: laszip_U16,
+) -> laszip_I32 {
+    with_dll(pointer, |dll| {
+        dll.check_not_busy()?;
+        dll.set_point_type_and_size(point_type, point_size)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_check_for_integer_overflow(pointer: laszip_POINTER) -> laszip_I32 {
+    with_dll(pointer, |dll| {
+        let h = &dll.header;
+        let check = |min: f64, max: f64, scale: f64, offset: f64, name: &str| {
+            let quantize = |v: f64| ((v - offset) / scale).round();
+            if quantize(min) < i32::MIN as f64 || quantize(max) > i32::MAX as f64 {
+                Err(format!(
+                    "quantized {} bounds overflow 32 bit integers",
+                    name
+                ))
+            } else {
+                Ok(())
+            }
+        };
+        check(h.min_x, h.max_x, h.x_scale_factor, h.x_offset, "x")?;
+        check(h.min_y, h.max_y, h.y_scale_factor, h.y_offset, "y")?;
+        check(h.min_z, h.max_z, h.z_scale_factor, h.z_offset, "z")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_auto_offset(pointer: laszip_POINTER) -> laszip_I32 {
+    with_dll(pointer, |dll| {
+        dll.check_not_busy()?;
+        let h = &mut dll.header;
+        let auto_offset = |min: f64, max: f64, scale: f64| {
+            let unit = if scale > 0.0099 {
+                1_000_000.0
+            } else {
+                100_000.0
+            };
+            ((min + max) / unit / 2.0).round() * unit
+        };
+        h.x_offset = auto_offset(h.min_x, h.max_x, h.x_scale_factor);
+        h.y_offset = auto_offset(h.min_y, h.max_y, h.y_scale_factor);
+        h.z_offset = auto_offset(h.min_z, h.max_z, h.z_scale_factor);
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_set_point(
+    pointer: laszip_POINTER,
+    point: *const laszip_point,
+) -> laszip_I32 {
+    with_dll(pointer, |dll| {
+        if point.is_null() {
+            return Err("laszip_point_struct pointer 'point' is zero".into());
+        }
+        dll.set_point(&*point)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_set_coordinates(
+    pointer: laszip_POINTER,
+    coordinates: *const laszip_F64,
+) -> laszip_I32 {
+    with_dll(pointer, |dll| {
+        if coordinates.is_null() {
+            return Err("laszip_F64 pointer 'coordinates' is zero".into());
+        }
+        let coordinates = std::slice::from_raw_parts(coordinates, 3);
+        let h = &dll.header;
+        dll.point.X = ((coordinates[0] - h.x_offset) / h.x_scale_factor).round() as i32;
+        dll.point.Y = ((coordinates[1] - h.y_offset) / h.y_scale_factor).round() as i32;
+        dll.point.Z = ((coordinates[2] - h.z_offset) / h.z_scale_factor).round() as i32;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_get_coordinates(
+    pointer: laszip_POINTER,
+    coordinates: *mut laszip_F64,
+) -> laszip_I32 {
+    with_dll(pointer, |dll| {
+        check_out_ptr!(coordinates);
+        let coordinates = std::slice::from_raw_parts_mut(coordinates, 3);
+        let h = &dll.header;
+        coordinates[0] = dll.point.X as f64 * h.x_scale_factor + h.x_offset;
+        coordinates[1] = dll.point.Y as f64 * h.y_scale_factor + h.y_offset;
+        coordinates[2] = dll.point.Z as f64 * h.z_scale_factor + h.z_offset;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_add_vlr(
+    pointer: laszip_POINTER,
+    user_id: *const laszip_CHAR,
+    record_id: laszip_U16,
+    record_length_after_header: laszip_U16,
+    description: *const laszip_CHAR,
+    data: *const laszip_U8,
+) -> laszip_I32 {
+    with_dll(pointer, |dll| {
+        dll.check_not_busy()?;
+        let user_id = str_arg(user_id, "user_id")?;
+        let description = if description.is_null() {
+            format!("by laz-rs {}", env!("CARGO_PKG_VERSION"))
+        } else {
+            str_arg(description, "description")?.to_string()
+        };
+        let data = if record_length_after_header == 0 {
+            vec![]
+        } else if data.is_null() {
+            return Err(format!(
+                "record_length_after_header of VLR is {} but data pointer is zero",
+                record_length_after_header
+            ));
+        } else {
+            std::slice::from_raw_parts(data, record_length_after_header as usize).to_vec()
+        };
+        let vlr = laszip_vlr {
+            reserved: 0,
+            user_id: fixed_str(user_id),
+            record_id,
+            record_length_after_header,
+            description: fixed_str(&description),
+            data: std::ptr::null_mut(),
+        };
+        dll.add_vlr(vlr, data);
+        Ok(())
+    })
+}
+
+/// Adds the GeoKeyDirectoryTag VLR, the key directory header is written
+/// before the `number` entries of `key_entries`
+#[no_mangle]
+pub unsafe extern "C" fn laszip_set_geokeys(
+    pointer: laszip_POINTER,
+    number: laszip_U32,
+    key_entries: *const laszip_geokey,
+) -> laszip_I32 {
+    with_dll(pointer, |dll| {
+        dll.check_not_busy()?;
+        if number == 0 {
+            return Err("number of key_entries is zero".into());
+        }
+        check_out_ptr!(key_entries);
+        let number_of_keys = u16::try_from(number)
+            .map_err(|_| format!("number of key_entries {} is too big", number))?;
+        let header = laszip_geokey {
+            key_id: 1,
+            tiff_tag_location: 1,
+            count: 0,
+            value_offset: number_of_keys,
+        };
+        let key_entries = std::slice::from_raw_parts(key_entries, number as usize);
+        let mut data = Vec::with_capacity(8 * (key_entries.len() + 1));
+        for key in std::iter::once(&header).chain(key_entries) {
+            for value in [
+                key.key_id,
+                key.tiff_tag_location,
+                key.count,
+                key.value_offset,
+            ] {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        dll.add_projection_vlr(GEO_KEY_DIRECTORY_RECORD_ID, data)
+    })
+}
+
+/// Adds the GeoDoubleParamsTag VLR
+#[no_mangle]
+pub unsafe extern "C" fn laszip_set_geodouble_params(
+    pointer: laszip_POINTER,
+    number: laszip_U32,
+    geodouble_params: *const laszip_F64,
+) -> laszip_I32 {
+    with_dll(pointer, |dll| {
+        dll.check_not_busy()?;
+        if number == 0 {
+            return Err("number of geodouble_params is zero".into());
+        }
+        check_out_ptr!(geodouble_params);
+        let data = std::slice::from_raw_parts(geodouble_params, number as usize)
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        dll.add_projection_vlr(GEO_DOUBLE_PARAMS_RECORD_ID, data)
+    })
+}
+
+/// Adds the GeoAsciiParamsTag VLR, `number` is the number of characters
+#[no_mangle]
+pub unsafe extern "C" fn laszip_set_geoascii_params(
+    pointer: laszip_POINTER,
+    number: laszip_U32,
+    geoascii_params: *const laszip_CHAR,
+) -> laszip_I32 {
+    with_dll(pointer, |dll| {
+        dll.check_not_busy()?;
+        if number == 0 {
+            return Err("number of geoascii_params is zero".into());
+        }
+        check_out_ptr!(geoascii_params);
+        let data = std::slice::from_raw_parts(geoascii_params as *const u8, number as usize);
+        dll.add_projection_vlr(GEO_ASCII_PARAMS_RECORD_ID, data.to_vec())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_remove_vlr(
+    pointer: laszip_POINTER,
+    user_id: *const laszip_CHAR,
+    record_id: laszip_U16,
+) -> laszip_I32 {
+    with_dll(pointer, |dll| {
+        dll.check_not_busy()?;
+        let user_id = str_arg(user_id, "user_id")?;
+        dll.remove_vlr(user_id.as_bytes(), record_id)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_preserve_generating_software(
+    pointer: laszip_POINTER,
+    _preserve: laszip_BOOL,
+) -> laszip_I32 {
+    // The generating software of the header is always written as is
+    with_dll(pointer, |dll| dll.check_not_busy())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_request_native_extension(
+    pointer: laszip_POINTER,
+    request: laszip_BOOL,
+) -> laszip_I32 {
+    with_dll(pointer, |dll| {
+        dll.check_not_busy()?;
+        if request == 0 {
+            return Err("only the native extension of LAS 1.4 point types is supported".into());
+        }
+        Ok(())
+    })
+}
+
+/// Not supported, returns an error when `request` is true
+#[no_mangle]
+pub unsafe extern "C" fn laszip_request_compatibility_mode(
+    pointer: laszip_POINTER,
+    request: laszip_BOOL,
+) -> laszip_I32 {
+    with_dll(pointer, |dll| {
+        dll.check_not_busy()?;
+        if request != 0 {
+            return Err("compatibility mode is not supported".into());
+        }
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_set_chunk_size(
+    pointer: laszip_POINTER,
+    chunk_size: laszip_U32,
+) -> laszip_I32 {
+    with_dll(pointer, |dll| {
+        dll.check_not_busy()?;
+        if chunk_size == 0 {
+            return Err("chunk_size of 0 is not supported".into());
+        }
+        dll.chunk_size = chunk_size;
+        Ok(())
+    })
+}
+
+/// Not supported, always returns an error
+#[no_mangle]
+pub unsafe extern "C" fn laszip_create_spatial_index(
+    pointer: laszip_POINTER,
+    _create: laszip_BOOL,
+    _append: laszip_BOOL,
+) -> laszip_I32 {
+    with_dll(pointer, |_| Err("spatial indexing is not supported".into()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_open_writer(
+    pointer: laszip_POINTER,
+    file_name: *const laszip_CHAR,
+    compress: laszip_BOOL,
+) -> laszip_I32 {
+    with_dll(pointer, |dll| {
+        let file_name = str_arg(file_name, "file_name")?;
+        dll.check_not_busy()?;
+        dll.open_writer(file_name, compress != 0)
+    })
+}
+
+/// Not supported (the LASzip DLL takes a C++ `std::ostream`), always returns an error
+#[no_mangle]
+pub unsafe extern "C" fn laszip_open_writer_stream(
+    pointer: laszip_POINTER,
+    _stream: *mut std::ffi::c_void,
+    _compress: laszip_BOOL,
+    _do_not_write_header: laszip_BOOL,
+) -> laszip_I32 {
+    with_dll(pointer, |_| Err("C++ streams are not supported".into()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_write_point(pointer: laszip_POINTER) -> laszip_I32 {
+    with_dll(pointer, |dll| dll.write_point())
+}
+
+/// Not supported, always returns an error
+#[no_mangle]
+pub unsafe extern "C" fn laszip_write_indexed_point(pointer: laszip_POINTER) -> laszip_I32 {
+    with_dll(pointer, |_| Err("spatial indexing is not supported".into()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_update_inventory(pointer: laszip_POINTER) -> laszip_I32 {
+    with_dll(pointer, |dll| dll.update_inventory())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_close_writer(pointer: laszip_POINTER) -> laszip_I32 {
+    with_dll(pointer, |dll| dll.close_writer())
+}
+
+/// Not supported, returns an error when `exploit` is true
+#[no_mangle]
+pub unsafe extern "C" fn laszip_exploit_spatial_index(
+    pointer: laszip_POINTER,
+    exploit: laszip_BOOL,
+) -> laszip_I32 {
+    with_dll(pointer, |dll| {
+        dll.check_not_busy()?;
+        if exploit != 0 {
+            return Err("spatial indexing is not supported".into());
+        }
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_decompress_selective(
+    pointer: laszip_POINTER,
+    decompress_selective: laszip_U32,
+) -> laszip_I32 {
+    with_dll(pointer, |dll| {
+        dll.check_not_busy()?;
+        // LASzip has one bit per extra byte starting at bit 16 for the first
+        // 16 extra bytes, the following ones are decompressed if any of these bits is set
+        if decompress_selective == laszip_DECOMPRESS_SELECTIVE_ALL {
+            dll.selection = DecompressionSelection::all();
+            dll.extra_bytes_selection = ExtraBytesSelection::none();
+        } else {
+            dll.selection = DecompressionSelection::from_bits(decompress_selective & 0x7FF);
+            dll.extra_bytes_selection =
+                if decompress_selective & laszip_DECOMPRESS_SELECTIVE_EXTRA_BYTES != 0 {
+                    (0..ExtraBytesSelection::MAX_SELECTABLE_EXTRA_BYTES)
+                        .filter(|&i| {
+                            i >= 16
+                                || decompress_selective & (laszip_DECOMPRESS_SELECTIVE_BYTE0 << i)
+                                    != 0
+                        })
+                        .fold(
+                            ExtraBytesSelection::none(),
+                            ExtraBytesSelection::decompress_byte,
+                        )
+                } else {
+                    ExtraBytesSelection::none()
+                };
+        }
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_open_reader(
+    pointer: laszip_POINTER,
+    file_name: *const laszip_CHAR,
+    is_compressed: *mut laszip_BOOL,
+) -> laszip_I32 {
+    with_dll(pointer, |dll| {
+        let file_name = str_arg(file_name, "file_name")?;
+        check_out_ptr!(is_compressed);
+        dll.check_not_busy()?;
+        *is_compressed = dll.open_reader(file_name)? as laszip_BOOL;
+        Ok(())
+    })
+}
+
+/// Not supported (the LASzip DLL takes a C++ `std::istream`), always returns an error
+#[no_mangle]
+pub unsafe extern "C" fn laszip_open_reader_stream(
+    pointer: laszip_POINTER,
+    _stream: *mut std::ffi::c_void,
+    _is_compressed: *mut laszip_BOOL,
+) -> laszip_I32 {
+    with_dll(pointer, |_| Err("C++ streams are not supported".into()))
+}
+
+/// Files are never indexed
+#[no_mangle]
+pub unsafe extern "C" fn laszip_has_spatial_index(
+    pointer: laszip_POINTER,
+    is_indexed: *mut laszip_BOOL,
+    is_appended: *mut laszip_BOOL,
+) -> laszip_I32 {
+    with_dll(pointer, |dll| {
+        check_out_ptr!(is_indexed);
+        if dll.reader.is_none() {
+            return Err("reader is not open".into());
+        }
+        *is_indexed = 0;
+        if !is_appended.is_null() {
+            *is_appended = 0;
+        }
+        Ok(())
+    })
+}
+
+/// Not supported, always returns an error
+#[no_mangle]
+pub unsafe extern "C" fn laszip_inside_rectangle(
+    pointer: laszip_POINTER,
+    _min_x: laszip_F64,
+    _min_y: laszip_F64,
+    _max_x: laszip_F64,
+    _max_y: laszip_F64,
+    _is_empty: *mut laszip_BOOL,
+) -> laszip_I32 {
+    with_dll(pointer, |_| Err("spatial indexing is not supported".into()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_seek_point(
+    pointer: laszip_POINTER,
+    index: laszip_I64,
+) -> laszip_I32 {
+    with_dll(pointer, |dll| dll.seek_point(index))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_read_point(pointer: laszip_POINTER) -> laszip_I32 {
+    with_dll(pointer, |dll| dll.read_point())
+}
+
+/// Not supported, always returns an error
+#[no_mangle]
+pub unsafe extern "C" fn laszip_read_inside_point(
+    pointer: laszip_POINTER,
+    _is_done: *mut laszip_BOOL,
+) -> laszip_I32 {
+    with_dll(pointer, |_| Err("spatial indexing is not supported".into()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn laszip_close_reader(pointer: laszip_POINTER) -> laszip_I32 {
+    with_dll(pointer, |dll| {
+        if dll.reader.take().is_none() {
+            return Err("closing reader before it was opened".into());
+        }
+        Ok(())
+    })
+}
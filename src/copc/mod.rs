@@ -0,0 +1,230 @@
+//! Cloud Optimized Point Cloud (COPC) support
+//!
+//! A COPC file is a LAS 1.4 file with LAZ compressed, **variable-size** chunks
+//! (point formats 6, 7 or 8), where each chunk holds the points of one node of an octree.
+//!
+//! - The `copc` info VLR ([`CopcInfo`]) is the first VLR, it describes the octree cube.
+//! - The hierarchy EVLR holds pages of [`Entry`], they give for each node ([`VoxelKey`])
+//!   where its chunk is, or where the page describing its sub-tree is.
+//!
+//! See <https://copc.io> for the specification.
+use std::io::{Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+pub use reader::{CopcHeader, CopcReader, RangeSource};
+pub use writer::CopcWriter;
+
+mod reader;
+mod writer;
+
+/// Size of a LAS 1.4 header
+const HEADER_SIZE: usize = 375;
+const VLR_HEADER_SIZE: usize = 54;
+const EVLR_HEADER_SIZE: usize = 60;
+
+/// Axis aligned bounding box
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bounds {
+    pub min: [f64; 3],
+    pub max: [f64; 3],
+}
+
+impl Bounds {
+    /// Returns whether the two bounds overlap, touching counts as overlapping.
+    pub fn intersects(&self, other: &Bounds) -> bool {
+        (0..3).all(|i| self.min[i] <= other.max[i] && other.min[i] <= self.max[i])
+    }
+
+    /// Returns whether the point is inside the bounds (limits included).
+    pub fn contains(&self, point: [f64; 3]) -> bool {
+        (0..3).all(|i| self.min[i] <= point[i] && point[i] <= self.max[i])
+    }
+}
+
+/// The data of the `copc` info VLR
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CopcInfo {
+    /// Center of the octree cube
+    pub center: [f64; 3],
+    /// Half the size of a side of the octree cube
+    pub halfsize: f64,
+    /// Space between points at the root level,
+    /// it is halved at each level.
+    pub spacing: f64,
+    /// File offset of the first hierarchy page
+    pub root_hier_offset: u64,
+    /// Size of the first hierarchy page
+    pub root_hier_size: u64,
+    pub gpstime_minimum: f64,
+    pub gpstime_maximum: f64,
+}
+
+impl CopcInfo {
+    pub const USER_ID: &'static str = "copc";
+    pub const RECORD_ID: u16 = 1;
+    pub const DESCRIPTION: &'static str = "COPC info VLR";
+    /// Size of the VLR data
+    pub const SIZE: u16 = 160;
+
+    pub fn read_from<R: Read>(mut src: R) -> std::io::Result<Self> {
+        let mut info = Self::default();
+        for value in info.center.iter_mut() {
+            *value = src.read_f64::<LittleEndian>()?;
+        }
+        info.halfsize = src.read_f64::<LittleEndian>()?;
+        info.spacing = src.read_f64::<LittleEndian>()?;
+        info.root_hier_offset = src.read_u64::<LittleEndian>()?;
+        info.root_hier_size = src.read_u64::<LittleEndian>()?;
+        info.gpstime_minimum = src.read_f64::<LittleEndian>()?;
+        info.gpstime_maximum = src.read_f64::<LittleEndian>()?;
+        let mut reserved = [0u8; 88];
+        src.read_exact(&mut reserved)?;
+        Ok(info)
+    }
+
+    pub fn write_to<W: Write>(&self, mut dst: W) -> std::io::Result<()> {
+        for value in self.center {
+            dst.write_f64::<LittleEndian>(value)?;
+        }
+        dst.write_f64::<LittleEndian>(self.halfsize)?;
+        dst.write_f64::<LittleEndian>(self.spacing)?;
+        dst.write_u64::<LittleEndian>(self.root_hier_offset)?;
+        dst.write_u64::<LittleEndian>(self.root_hier_size)?;
+        dst.write_f64::<LittleEndian>(self.gpstime_minimum)?;
+        dst.write_f64::<LittleEndian>(self.gpstime_maximum)?;
+        dst.write_all(&[0u8; 88])
+    }
+
+    /// Bounds of the octree cube
+    pub fn bounds(&self) -> Bounds {
+        Bounds {
+            min: self.center.map(|c| c - self.halfsize),
+            max: self.center.map(|c| c + self.halfsize),
+        }
+    }
+
+    /// Space between points at the given level
+    pub fn spacing_at(&self, level: i32) -> f64 {
+        self.spacing / 2f64.powi(level)
+    }
+}
+
+/// Identifies a node of the octree
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VoxelKey {
+    pub level: i32,
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl VoxelKey {
+    /// The key of the root node
+    pub const ROOT: VoxelKey = VoxelKey {
+        level: 0,
+        x: 0,
+        y: 0,
+        z: 0,
+    };
+
+    /// Returns the key of one of the 8 children,
+    /// bit 0, 1, 2 of `direction` selects the upper half along x, y, z.
+    pub fn child(&self, direction: u8) -> VoxelKey {
+        VoxelKey {
+            level: self.level + 1,
+            x: (self.x << 1) | i32::from(direction & 0x1),
+            y: (self.y << 1) | i32::from((direction >> 1) & 0x1),
+            z: (self.z << 1) | i32::from((direction >> 2) & 0x1),
+        }
+    }
+
+    /// Returns the key of the parent node, `None` for the root
+    pub fn parent(&self) -> Option<VoxelKey> {
+        if self.level == 0 {
+            return None;
+        }
+        Some(VoxelKey {
+            level: self.level - 1,
+            x: self.x >> 1,
+            y: self.y >> 1,
+            z: self.z >> 1,
+        })
+    }
+
+    /// Bounds of the node
+    pub fn bounds(&self, info: &CopcInfo) -> Bounds {
+        let root = info.bounds();
+        let side = 2.0 * info.halfsize / 2f64.powi(self.level);
+        let min = [
+            root.min[0] + side * f64::from(self.x),
+            root.min[1] + side * f64::from(self.y),
+            root.min[2] + side * f64::from(self.z),
+        ];
+        Bounds {
+            min,
+            max: min.map(|m| m + side),
+        }
+    }
+
+    pub fn read_from<R: Read>(mut src: R) -> std::io::Result<Self> {
+        Ok(Self {
+            level: src.read_i32::<LittleEndian>()?,
+            x: src.read_i32::<LittleEndian>()?,
+            y: src.read_i32::<LittleEndian>()?,
+            z: src.read_i32::<LittleEndian>()?,
+        })
+    }
+
+    pub fn write_to<W: Write>(&self, mut dst: W) -> std::io::Result<()> {
+        dst.write_i32::<LittleEndian>(self.level)?;
+        dst.write_i32::<LittleEndian>(self.x)?;
+        dst.write_i32::<LittleEndian>(self.y)?;
+        dst.write_i32::<LittleEndian>(self.z)
+    }
+}
+
+/// An entry of a hierarchy page
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub key: VoxelKey,
+    /// File offset of the chunk, or of the hierarchy page
+    /// if `point_count` is -1
+    pub offset: u64,
+    /// Size of the chunk, or of the hierarchy page
+    pub byte_size: i32,
+    /// Number of points in the chunk, -1 means the entry
+    /// is a reference to a hierarchy page.
+    pub point_count: i32,
+}
+
+impl Entry {
+    /// Size of an entry in a hierarchy page
+    pub const SIZE: u64 = 32;
+
+    /// Returns whether this entry references a hierarchy page
+    pub fn is_page(&self) -> bool {
+        self.point_count == -1
+    }
+
+    pub fn read_from<R: Read>(mut src: R) -> std::io::Result<Self> {
+        Ok(Self {
+            key: VoxelKey::read_from(&mut src)?,
+            offset: src.read_u64::<LittleEndian>()?,
+            byte_size: src.read_i32::<LittleEndian>()?,
+            point_count: src.read_i32::<LittleEndian>()?,
+        })
+    }
+
+    pub fn write_to<W: Write>(&self, mut dst: W) -> std::io::Result<()> {
+        self.key.write_to(&mut dst)?;
+        dst.write_u64::<LittleEndian>(self.offset)?;
+        dst.write_i32::<LittleEndian>(self.byte_size)?;
+        dst.write_i32::<LittleEndian>(self.point_count)
+    }
+}
+
+/// Record id of the hierarchy EVLR (its user id is [`CopcInfo::USER_ID`])
+pub const HIERARCHY_RECORD_ID: u16 = 1000;
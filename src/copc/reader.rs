@@ -0,0 +1,557 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::copc::{Bounds, CopcInfo, Entry, VoxelKey, HEADER_SIZE, VLR_HEADER_SIZE};
+use crate::las::selective::DecompressionSelection;
+use crate::laszip::details::record_decompressor_from_laz_items;
+use crate::laszip::{CompressorType, LazVlr, Quantization};
+
+/// A source of bytes that can be read at any offset,
+/// e.g. a file or an HTTP resource read with range requests.
+pub trait RangeSource {
+    /// Reads exactly `buffer.len()` bytes starting at `offset`
+    fn read_range(&mut self, offset: u64, buffer: &mut [u8]) -> std::io::Result<()>;
+}
+
+impl<R: Read + Seek> RangeSource for R {
+    fn read_range(&mut self, offset: u64, buffer: &mut [u8]) -> std::io::Result<()> {
+        self.seek(SeekFrom::Start(offset))?;
+        self.read_exact(buffer)
+    }
+}
+
+/// The parts of the LAS header needed to read COPC data
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CopcHeader {
+    pub point_format_id: u8,
+    pub point_size: u16,
+    pub point_count: u64,
+    pub scale: [f64; 3],
+    pub offset: [f64; 3],
+    pub bounds: Bounds,
+    pub offset_to_points: u32,
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Checks the values of an entry read from a hierarchy page,
+/// returns its byte size.
+fn checked_byte_size(entry: &Entry, header: &CopcHeader) -> std::io::Result<usize> {
+    let byte_size = u64::try_from(entry.byte_size)
+        .map_err(|_| invalid_data(format!("entry {:?} has a negative byte size", entry.key)))?;
+    if entry.offset > i64::MAX as u64 || entry.offset.checked_add(byte_size).is_none() {
+        return Err(invalid_data(format!(
+            "entry {:?} has an invalid offset ({})",
+            entry.key, entry.offset
+        )));
+    }
+    if entry.point_count < -1 || (entry.point_count as i64) > header.point_count as i64 {
+        return Err(invalid_data(format!(
+            "entry {:?} has an invalid point count ({})",
+            entry.key, entry.point_count
+        )));
+    }
+    Ok(byte_size as usize)
+}
+
+/// Reader of COPC files.
+///
+/// The hierarchy pages are only read when a query needs them,
+/// so that only the relevant parts of remote files get fetched.
+pub struct CopcReader<S: RangeSource> {
+    source: S,
+    header: CopcHeader,
+    info: CopcInfo,
+    laz_vlr: LazVlr,
+    /// Entries of the pages read so far, excluding page references
+    nodes: HashMap<VoxelKey, Entry>,
+    /// Pages that are not yet read
+    pending_pages: Vec<Entry>,
+}
+
+impl CopcHeader {
+    /// Scale & offset of the coordinates
+    pub fn quantization(&self) -> Quantization {
+        Quantization::new(self.scale, self.offset)
+    }
+}
+
+impl<S: RangeSource> CopcReader<S> {
+    /// Reads the header and VLRs of the COPC file
+    pub fn new(mut source: S) -> crate::Result<Self> {
+        let mut raw_header = [0u8; HEADER_SIZE];
+        source.read_range(0, &mut raw_header)?;
+        if &raw_header[0..4] != b"LASF" {
+            return Err(invalid_data("not a LAS file".to_string()).into());
+        }
+        if (raw_header[24], raw_header[25]) != (1, 4) {
+            return Err(invalid_data(format!(
+                "COPC requires LAS 1.4, not {}.{}",
+                raw_header[24], raw_header[25]
+            ))
+            .into());
+        }
+        let mut src = Cursor::new(&raw_header[..]);
+        src.set_position(94);
+        let header_size = src.read_u16::<LittleEndian>()?;
+        let offset_to_points = src.read_u32::<LittleEndian>()?;
+        let num_vlrs = src.read_u32::<LittleEndian>()?;
+        let point_format_id = src.read_u8()? & 0x3F;
+        let point_size = src.read_u16::<LittleEndian>()?;
+        src.set_position(131);
+        let mut values = [0f64; 12];
+        for value in values.iter_mut() {
+            *value = src.read_f64::<LittleEndian>()?;
+        }
+        src.set_position(247);
+        let point_count = src.read_u64::<LittleEndian>()?;
+        let header = CopcHeader {
+            point_format_id,
+            point_size,
+            point_count,
+            scale: [values[0], values[1], values[2]],
+            offset: [values[3], values[4], values[5]],
+            bounds: Bounds {
+                min: [values[7], values[9], values[11]],
+                max: [values[6], values[8], values[10]],
+            },
+            offset_to_points,
+        };
+
+        let mut info = None;
+        let mut laz_vlr = None;
+        let mut position = u64::from(header_size);
+        for _ in 0..num_vlrs {
+            let mut vlr_header = [0u8; VLR_HEADER_SIZE];
+            source.read_range(position, &mut vlr_header)?;
+            let user_id = &vlr_header[2..18];
+            let user_id = &user_id[..user_id.iter().position(|b| *b == 0).unwrap_or(16)];
+            let record_id = u16::from_le_bytes([vlr_header[18], vlr_header[19]]);
+            let record_length = u16::from_le_bytes([vlr_header[20], vlr_header[21]]);
+            let data_position = position + VLR_HEADER_SIZE as u64;
+
+            if user_id == CopcInfo::USER_ID.as_bytes() && record_id == CopcInfo::RECORD_ID {
+                let mut data = vec![0u8; record_length as usize];
+                source.read_range(data_position, &mut data)?;
+                info = Some(CopcInfo::read_from(data.as_slice())?);
+            } else if user_id == LazVlr::USER_ID.as_bytes() && record_id == LazVlr::RECORD_ID {
+                let mut data = vec![0u8; record_length as usize];
+                source.read_range(data_position, &mut data)?;
+                laz_vlr = Some(LazVlr::from_buffer(&data)?);
+            }
+            position = data_position + u64::from(record_length);
+        }
+
+        let info = info.ok_or_else(|| invalid_data("no COPC info VLR".to_string()))?;
+        let laz_vlr = laz_vlr.ok_or_else(|| invalid_data("no laszip VLR".to_string()))?;
+        if laz_vlr.compressor_type() != CompressorType::LayeredChunked
+            || !laz_vlr.uses_variable_size_chunks()
+        {
+            return Err(invalid_data(
+                "COPC data must use variable-size layered chunks".to_string(),
+            )
+            .into());
+        }
+
+        let root_page = Entry {
+            key: VoxelKey::ROOT,
+            offset: info.root_hier_offset,
+            byte_size: i32::try_from(info.root_hier_size).map_err(|_| {
+                invalid_data(format!(
+                    "invalid root hierarchy page size ({})",
+                    info.root_hier_size
+                ))
+            })?,
+            point_count: -1,
+        };
+        Ok(Self {
+            source,
+            header,
+            info,
+            laz_vlr,
+            nodes: HashMap::new(),
+            pending_pages: vec![root_page],
+        })
+    }
+
+    pub fn header(&self) -> &CopcHeader {
+        &self.header
+    }
+
+    pub fn info(&self) -> &CopcInfo {
+        &self.info
+    }
+
+    pub fn laz_vlr(&self) -> &LazVlr {
+        &self.laz_vlr
+    }
+
+    /// Bounds of the node
+    pub fn node_bounds(&self, key: &VoxelKey) -> Bounds {
+        key.bounds(&self.info)
+    }
+
+    /// Returns the smallest level at which the space between points
+    /// is at most `resolution`.
+    pub fn level_for_resolution(&self, resolution: f64) -> i32 {
+        let mut level = 0;
+        while self.info.spacing_at(level) > resolution && level < 64 {
+            level += 1;
+        }
+        level
+    }
+
+    /// Returns the nodes with points that intersect the `bounds` and which level is
+    /// at most `max_level`, `None` means no restriction.
+    ///
+    /// The nodes are sorted by level, then position.
+    pub fn query(
+        &mut self,
+        bounds: Option<&Bounds>,
+        max_level: Option<i32>,
+    ) -> crate::Result<Vec<Entry>> {
+        let is_wanted = |key: &VoxelKey, info: &CopcInfo| {
+            max_level.map_or(true, |max| key.level <= max)
+                && bounds.map_or(true, |b| key.bounds(info).intersects(b))
+        };
+
+        // Pages contain the sub-tree of their key, so only the pages
+        // which key is wanted may contain wanted nodes
+        while let Some(index) = self
+            .pending_pages
+            .iter()
+            .position(|page| is_wanted(&page.key, &self.info))
+        {
+            let page = self.pending_pages.swap_remove(index);
+            self.read_page(&page)?;
+        }
+
+        let mut entries = self
+            .nodes
+            .values()
+            .filter(|entry| entry.point_count > 0 && is_wanted(&entry.key, &self.info))
+            .copied()
+            .collect::<Vec<Entry>>();
+        entries.sort_by_key(|entry| entry.key);
+        Ok(entries)
+    }
+
+    /// Returns all the nodes with points
+    pub fn nodes(&mut self) -> crate::Result<Vec<Entry>> {
+        self.query(None, None)
+    }
+
+    /// Decompresses the points of the node
+    pub fn read_node(&mut self, entry: &Entry) -> crate::Result<Vec<u8>> {
+        self.read_node_selective(entry, DecompressionSelection::all())
+    }
+
+    /// Decompresses the points of the node, only decompressing
+    /// the fields in the `selection`.
+    pub fn read_node_selective(
+        &mut self,
+        entry: &Entry,
+        selection: DecompressionSelection,
+    ) -> crate::Result<Vec<u8>> {
+        let byte_size = checked_byte_size(entry, &self.header)?;
+        if entry.point_count <= 0 {
+            return Ok(vec![]);
+        }
+        let mut chunk = vec![0u8; byte_size];
+        self.source.read_range(entry.offset, &mut chunk)?;
+
+        let mut decompressor =
+            record_decompressor_from_laz_items(self.laz_vlr.items(), Cursor::new(chunk))?;
+        decompressor.set_selection(selection);
+        let mut points = vec![0u8; entry.point_count as usize * self.laz_vlr.items_size() as usize];
+        decompressor.decompress_many(&mut points)?;
+        Ok(points)
+    }
+
+    /// Consumes the reader and returns the source
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+
+    fn read_page(&mut self, page: &Entry) -> crate::Result<()> {
+        let mut data = vec![0u8; checked_byte_size(page, &self.header)?];
+        self.source.read_range(page.offset, &mut data)?;
+        let mut src = data.as_slice();
+        while !src.is_empty() {
+            let entry = Entry::read_from(&mut src)?;
+            checked_byte_size(&entry, &self.header)?;
+            if entry.is_page() {
+                self.pending_pages.push(entry);
+            } else {
+                self.nodes.insert(entry.key, entry);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use byteorder::WriteBytesExt;
+
+    use super::*;
+    use crate::copc::EVLR_HEADER_SIZE;
+    use crate::{LasZipCompressor, LazItemRecordBuilder, LazVlrBuilder};
+
+    fn point(xyz: [i32; 3], gps_time: f64) -> Vec<u8> {
+        let mut point = vec![0u8; 30];
+        point[0..4].copy_from_slice(&xyz[0].to_le_bytes());
+        point[4..8].copy_from_slice(&xyz[1].to_le_bytes());
+        point[8..12].copy_from_slice(&xyz[2].to_le_bytes());
+        point[14] = 0x11;
+        point[22..30].copy_from_slice(&gps_time.to_le_bytes());
+        point
+    }
+
+    /// Writes a COPC file with a cube from 0 to 100 (scale 0.01), with points in the root
+    /// and in two children, the second child being described in its own page.
+    fn copc_file(nodes: &[(VoxelKey, Vec<u8>)]) -> Vec<u8> {
+        let vlr =
+            LazVlrBuilder::new(LazItemRecordBuilder::default_for_point_format_id(6, 0).unwrap())
+                .with_variable_chunk_size()
+                .build();
+        let mut laz_vlr_data = vec![];
+        vlr.write_to(&mut laz_vlr_data).unwrap();
+        let offset_to_points =
+            HEADER_SIZE + 2 * VLR_HEADER_SIZE + CopcInfo::SIZE as usize + laz_vlr_data.len();
+        let point_count = nodes.iter().map(|(_, p)| p.len() / 30).sum::<usize>();
+
+        let mut header = vec![0u8; HEADER_SIZE];
+        header[0..4].copy_from_slice(b"LASF");
+        header[24] = 1;
+        header[25] = 4;
+        header[94..96].copy_from_slice(&(HEADER_SIZE as u16).to_le_bytes());
+        header[96..100].copy_from_slice(&(offset_to_points as u32).to_le_bytes());
+        header[100..104].copy_from_slice(&2u32.to_le_bytes());
+        header[104] = 6 | 0x80;
+        header[105..107].copy_from_slice(&30u16.to_le_bytes());
+        for i in 0..3 {
+            let start = 131 + 8 * i;
+            header[start..start + 8].copy_from_slice(&0.01f64.to_le_bytes());
+        }
+        header[247..255].copy_from_slice(&(point_count as u64).to_le_bytes());
+
+        let mut data = Cursor::new(header);
+        data.seek(SeekFrom::End(0)).unwrap();
+        let mut info = CopcInfo {
+            center: [50.0, 50.0, 50.0],
+            halfsize: 50.0,
+            spacing: 10.0,
+            ..Default::default()
+        };
+        let write_vlr_header = |data: &mut Cursor<Vec<u8>>, user_id: &str, id: u16, len: u16| {
+            data.write_u16::<LittleEndian>(0).unwrap();
+            let mut user_id_bytes = [0u8; 16];
+            user_id_bytes[..user_id.len()].copy_from_slice(user_id.as_bytes());
+            data.write_all(&user_id_bytes).unwrap();
+            data.write_u16::<LittleEndian>(id).unwrap();
+            data.write_u16::<LittleEndian>(len).unwrap();
+            data.write_all(&[0u8; 32]).unwrap();
+        };
+        write_vlr_header(
+            &mut data,
+            CopcInfo::USER_ID,
+            CopcInfo::RECORD_ID,
+            CopcInfo::SIZE,
+        );
+        let info_position = data.position();
+        info.write_to(&mut data).unwrap();
+        write_vlr_header(
+            &mut data,
+            LazVlr::USER_ID,
+            LazVlr::RECORD_ID,
+            laz_vlr_data.len() as u16,
+        );
+        data.write_all(&laz_vlr_data).unwrap();
+        assert_eq!(data.position(), offset_to_points as u64);
+
+        let mut compressor = LasZipCompressor::new(data, vlr).unwrap();
+        compressor
+            .compress_chunks(nodes.iter().map(|(_, points)| points))
+            .unwrap();
+        compressor.done().unwrap();
+        let mut data = compressor.into_inner();
+
+        let mut chunk_offset = offset_to_points as u64 + 8;
+        let mut entries = vec![];
+        let mut chunk_source = Cursor::new(data.get_ref().clone());
+        chunk_source.set_position(offset_to_points as u64);
+        let chunk_table = crate::laszip::ChunkTable::read_from(
+            &mut chunk_source,
+            &LazVlrBuilder::new(LazItemRecordBuilder::default_for_point_format_id(6, 0).unwrap())
+                .with_variable_chunk_size()
+                .build(),
+        )
+        .unwrap();
+        for ((key, _), chunk) in nodes.iter().zip(chunk_table.as_ref()) {
+            entries.push(Entry {
+                key: *key,
+                offset: chunk_offset,
+                byte_size: chunk.byte_count as i32,
+                point_count: chunk.point_count as i32,
+            });
+            chunk_offset += chunk.byte_count;
+        }
+
+        // The last node goes in its own page
+        let sub_page_entry = entries.pop().unwrap();
+        let hierarchy_position = data.seek(SeekFrom::End(0)).unwrap() + EVLR_HEADER_SIZE as u64;
+        let root_page_size = (entries.len() + 1) as u64 * Entry::SIZE;
+        entries.push(Entry {
+            key: sub_page_entry.key,
+            offset: hierarchy_position + root_page_size,
+            byte_size: Entry::SIZE as i32,
+            point_count: -1,
+        });
+        // EVLR header
+        data.write_u16::<LittleEndian>(0).unwrap();
+        data.write_all(b"copc\0\0\0\0\0\0\0\0\0\0\0\0").unwrap();
+        data.write_u16::<LittleEndian>(crate::copc::HIERARCHY_RECORD_ID)
+            .unwrap();
+        data.write_u64::<LittleEndian>(root_page_size + Entry::SIZE)
+            .unwrap();
+        data.write_all(&[0u8; 32]).unwrap();
+        assert_eq!(data.position(), hierarchy_position);
+        for entry in entries.iter().chain(std::iter::once(&sub_page_entry)) {
+            entry.write_to(&mut data).unwrap();
+        }
+
+        info.root_hier_offset = hierarchy_position;
+        info.root_hier_size = root_page_size;
+        data.set_position(info_position);
+        info.write_to(&mut data).unwrap();
+        data.into_inner()
+    }
+
+    #[test]
+    fn test_copc_reader() {
+        let root_points = (0..10)
+            .flat_map(|i| point([i * 1000, i * 1000, i * 1000], i as f64))
+            .collect::<Vec<u8>>();
+        // lower child
+        let child_0 = VoxelKey::ROOT.child(0);
+        let child_0_points = (0..20)
+            .flat_map(|i| point([i * 100, 200, 300], 100.0 + i as f64))
+            .collect::<Vec<u8>>();
+        // upper child
+        let child_7 = VoxelKey::ROOT.child(7);
+        let child_7_points = (0..30)
+            .flat_map(|i| point([6000 + i * 100, 7000, 8000], 200.0 + i as f64))
+            .collect::<Vec<u8>>();
+        let data = copc_file(&[
+            (VoxelKey::ROOT, root_points.clone()),
+            (child_0, child_0_points.clone()),
+            (child_7, child_7_points.clone()),
+        ]);
+
+        let mut reader = CopcReader::new(Cursor::new(data)).unwrap();
+        assert_eq!(reader.header().point_count, 60);
+        assert_eq!(reader.header().point_format_id, 6);
+        assert_eq!(reader.info().halfsize, 50.0);
+
+        // Only the root page is needed for level 0
+        let nodes = reader.query(None, Some(0)).unwrap();
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(reader.pending_pages.len(), 1);
+        assert_eq!(reader.read_node(&nodes[0]).unwrap(), root_points);
+
+        let lower = Bounds {
+            min: [0.0, 0.0, 0.0],
+            max: [10.0, 10.0, 10.0],
+        };
+        let nodes = reader.query(Some(&lower), None).unwrap();
+        assert_eq!(
+            nodes.iter().map(|e| e.key).collect::<Vec<_>>(),
+            vec![VoxelKey::ROOT, child_0]
+        );
+        assert_eq!(reader.pending_pages.len(), 1);
+        assert_eq!(reader.read_node(&nodes[1]).unwrap(), child_0_points);
+
+        let nodes = reader.nodes().unwrap();
+        assert_eq!(nodes.len(), 3);
+        assert!(reader.pending_pages.is_empty());
+        assert_eq!(nodes[2].key, child_7);
+        assert_eq!(reader.read_node(&nodes[2]).unwrap(), child_7_points);
+        assert_eq!(
+            reader.node_bounds(&child_7),
+            Bounds {
+                min: [50.0, 50.0, 50.0],
+                max: [100.0, 100.0, 100.0]
+            }
+        );
+
+        let selection = DecompressionSelection::base().decompress_gps_time();
+        let points = reader.read_node_selective(&nodes[2], selection).unwrap();
+        for (p, expected) in points.chunks(30).zip(child_7_points.chunks(30)) {
+            assert_eq!(p[0..8], expected[0..8]);
+            assert_eq!(p[22..30], expected[22..30]);
+        }
+
+        assert_eq!(reader.level_for_resolution(10.0), 0);
+        assert_eq!(reader.level_for_resolution(3.0), 2);
+    }
+
+    #[test]
+    fn test_invalid_entries() {
+        let root_points = (0..10)
+            .flat_map(|i| point([i * 1000, i * 1000, i * 1000], i as f64))
+            .collect::<Vec<u8>>();
+        let child_points = (0..20)
+            .flat_map(|i| point([i * 100, 200, 300], 100.0 + i as f64))
+            .collect::<Vec<u8>>();
+        let data = copc_file(&[
+            (VoxelKey::ROOT, root_points),
+            (VoxelKey::ROOT.child(0), child_points),
+        ]);
+
+        let mut reader = CopcReader::new(Cursor::new(data.clone())).unwrap();
+        let root = reader.query(None, Some(0)).unwrap()[0];
+        let is_invalid_data = |result: crate::Result<Vec<u8>>| match result {
+            Err(crate::LasZipError::IoError(e)) => e.kind() == std::io::ErrorKind::InvalidData,
+            _ => false,
+        };
+        let entries = [
+            Entry {
+                byte_size: -1,
+                ..root
+            },
+            Entry {
+                point_count: -2,
+                ..root
+            },
+            Entry {
+                point_count: i32::MAX,
+                ..root
+            },
+            Entry {
+                offset: u64::MAX,
+                ..root
+            },
+        ];
+        for entry in &entries {
+            assert!(is_invalid_data(reader.read_node(entry)), "{:?}", entry);
+        }
+
+        // Negative byte size of the first entry of the root page
+        let byte_size_position = reader.info().root_hier_offset as usize + 24;
+        let mut data = data;
+        data[byte_size_position..byte_size_position + 4].copy_from_slice(&(-1i32).to_le_bytes());
+        let mut reader = CopcReader::new(Cursor::new(data)).unwrap();
+        assert!(matches!(
+            reader.nodes(),
+            Err(crate::LasZipError::IoError(e)) if e.kind() == std::io::ErrorKind::InvalidData
+        ));
+    }
+}
@@ -0,0 +1,582 @@
+use std::io::{Seek, SeekFrom, Write};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use crate::copc::{
+    CopcInfo, Entry, VoxelKey, EVLR_HEADER_SIZE, HEADER_SIZE, HIERARCHY_RECORD_ID, VLR_HEADER_SIZE,
+};
+use crate::laszip::{LasZipCompressor, LazVlr, LazVlrBuilder, Quantization};
+use crate::LasZipError;
+
+/// Nodes at this level are never split, whatever their number of points
+const MAX_LEVEL: i32 = 24;
+
+/// User id & record id of the OGC coordinate system WKT VLR
+const WKT_USER_ID: &str = "LASF_Projection";
+const WKT_RECORD_ID: u16 = 2112;
+
+/// Bit of the global encoding telling that the coordinate system is a WKT
+const GLOBAL_ENCODING_WKT: u16 = 0x10;
+
+fn write_fixed_str<W: Write>(dst: &mut W, value: &str, len: usize) -> std::io::Result<()> {
+    let mut bytes = vec![0u8; len];
+    for (dst, src) in bytes.iter_mut().zip(value.bytes().take(len - 1)) {
+        *dst = src;
+    }
+    dst.write_all(&bytes)
+}
+
+/// Writer of COPC files.
+///
+/// Points are given as raw LAS point records (point formats 6, 7 or 8),
+/// they are kept in memory until [`done`](Self::done) is called, as the whole
+/// point cloud is needed to build the octree.
+///
+/// Nodes with more than `max_points_per_node` points keep an evenly spaced
+/// subset of their points and give the others to their children.
+pub struct CopcWriter<W: Write + Seek + Send + Sync> {
+    output: W,
+    vlr: LazVlr,
+    point_format_id: u8,
+    scale: [f64; 3],
+    offset: [f64; 3],
+    max_points_per_node: usize,
+    wkt: Option<String>,
+    points: Vec<u8>,
+}
+
+impl<W: Write + Seek + Send + Sync> CopcWriter<W> {
+    /// Default maximum number of points of a node
+    pub const DEFAULT_MAX_POINTS_PER_NODE: usize = 100_000;
+
+    /// Creates a writer of points of the given format,
+    /// the file is written from the start of the `output`.
+    ///
+    /// The scale defaults to 0.01 and the offset to 0.
+    pub fn new(output: W, point_format_id: u8, num_extra_bytes: u16) -> crate::Result<Self> {
+        if !(6..=8).contains(&point_format_id) {
+            return Err(LasZipError::UnsupportedPointFormat(point_format_id));
+        }
+        let vlr = LazVlrBuilder::default()
+            .with_point_format(point_format_id, num_extra_bytes)?
+            .with_variable_chunk_size()
+            .build();
+        Ok(Self {
+            output,
+            vlr,
+            point_format_id,
+            scale: [0.01; 3],
+            offset: [0.0; 3],
+            max_points_per_node: Self::DEFAULT_MAX_POINTS_PER_NODE,
+            wkt: None,
+            points: vec![],
+        })
+    }
+
+    /// Sets the scale of the coordinates of the points
+    pub fn with_scale(mut self, scale: [f64; 3]) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Sets the offset of the coordinates of the points
+    pub fn with_offset(mut self, offset: [f64; 3]) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Sets the maximum number of points of a node (at least 1)
+    pub fn with_max_points_per_node(mut self, max_points_per_node: usize) -> Self {
+        self.max_points_per_node = max_points_per_node.max(1);
+        self
+    }
+
+    /// Sets the coordinate system of the points, written in an OGC WKT VLR.
+    ///
+    /// Without it, the file has no coordinate system VLR. The WKT bit of the
+    /// global encoding is set in any case, as COPC (point formats 6 to 8) requires it.
+    pub fn with_wkt(mut self, wkt: impl Into<String>) -> Self {
+        self.wkt = Some(wkt.into());
+        self
+    }
+
+    /// Returns the vlr used to compress the nodes
+    pub fn vlr(&self) -> &LazVlr {
+        &self.vlr
+    }
+
+    /// Adds one point
+    pub fn write_one(&mut self, point: &[u8]) -> std::io::Result<()> {
+        self.write_many(point)
+    }
+
+    /// Adds all the points contained in the `points` slice
+    pub fn write_many(&mut self, points: &[u8]) -> std::io::Result<()> {
+        if points.len() % self.point_size() != 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "points buffer size ({}) is not a multiple of the point size ({})",
+                    points.len(),
+                    self.point_size()
+                ),
+            ));
+        }
+        self.points.extend_from_slice(points);
+        Ok(())
+    }
+
+    /// Builds the octree, writes the file and returns the output
+    pub fn done(self) -> crate::Result<W> {
+        let point_size = self.point_size();
+        let points = self.points.chunks_exact(point_size).collect::<Vec<&[u8]>>();
+        let coordinates = Quantization::new(self.scale, self.offset)
+            .coordinates_of_many(&self.points, point_size);
+
+        let mut min = [f64::MAX; 3];
+        let mut max = [f64::MIN; 3];
+        for xyz in &coordinates {
+            for i in 0..3 {
+                min[i] = min[i].min(xyz[i]);
+                max[i] = max[i].max(xyz[i]);
+            }
+        }
+        if points.is_empty() {
+            min = [0.0; 3];
+            max = [0.0; 3];
+        }
+
+        let mut gps_time_range = [f64::MAX, f64::MIN];
+        let mut points_by_return = [0u64; 15];
+        for point in &points {
+            let gps_time = f64::from_le_bytes(point[22..30].try_into().unwrap());
+            gps_time_range[0] = gps_time_range[0].min(gps_time);
+            gps_time_range[1] = gps_time_range[1].max(gps_time);
+            let return_number = point[14] & 0xF;
+            if (1..=15).contains(&return_number) {
+                points_by_return[usize::from(return_number) - 1] += 1;
+            }
+        }
+        if points.is_empty() {
+            gps_time_range = [0.0; 2];
+        }
+
+        // The cube must not be empty, even if all points are at the same position
+        let smallest_halfsize = self.scale.iter().copied().fold(0.0, f64::max);
+        let halfsize = (0..3)
+            .map(|i| (max[i] - min[i]) / 2.0)
+            .fold(smallest_halfsize, f64::max);
+        let mut info = CopcInfo {
+            center: [0, 1, 2].map(|i| (min[i] + max[i]) / 2.0),
+            halfsize,
+            spacing: 2.0 * halfsize / (self.max_points_per_node as f64).sqrt(),
+            gpstime_minimum: gps_time_range[0],
+            gpstime_maximum: gps_time_range[1],
+            ..Default::default()
+        };
+        let nodes = self.build_octree(&info, &coordinates);
+
+        let mut laz_vlr_data = vec![];
+        self.vlr.write_to(&mut laz_vlr_data)?;
+        // The WKT is nul terminated
+        let wkt_data = self.wkt.as_ref().map(|wkt| {
+            let mut data = wkt.as_bytes().to_vec();
+            data.push(0);
+            data
+        });
+        let wkt_vlr_size = match &wkt_data {
+            Some(data) if data.len() > usize::from(u16::MAX) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("the WKT is too long ({} bytes)", data.len()),
+                )
+                .into());
+            }
+            Some(data) => VLR_HEADER_SIZE + data.len(),
+            None => 0,
+        };
+        let offset_to_points = HEADER_SIZE
+            + VLR_HEADER_SIZE
+            + CopcInfo::SIZE as usize
+            + VLR_HEADER_SIZE
+            + laz_vlr_data.len()
+            + wkt_vlr_size;
+
+        // The header and the info VLR are written once all offsets are known
+        let mut output = self.output;
+        output.seek(SeekFrom::Start(0))?;
+        output.write_all(&vec![
+            0u8;
+            HEADER_SIZE + VLR_HEADER_SIZE + CopcInfo::SIZE as usize
+        ])?;
+        write_vlr_header(
+            &mut output,
+            LazVlr::USER_ID,
+            LazVlr::RECORD_ID,
+            laz_vlr_data.len() as u16,
+            LazVlr::DESCRIPTION,
+        )?;
+        output.write_all(&laz_vlr_data)?;
+        if let Some(data) = &wkt_data {
+            write_vlr_header(
+                &mut output,
+                WKT_USER_ID,
+                WKT_RECORD_ID,
+                data.len() as u16,
+                "OGC WKT",
+            )?;
+            output.write_all(data)?;
+        }
+
+        let mut compressor = LasZipCompressor::new(output, self.vlr.clone())?;
+        let mut node_points = vec![];
+        for (i, (_, indices)) in nodes.iter().enumerate() {
+            node_points.clear();
+            for index in indices {
+                node_points.extend_from_slice(points[*index]);
+            }
+            compressor.compress_many(&node_points)?;
+            if i + 1 != nodes.len() {
+                compressor.finish_current_chunk()?;
+            }
+        }
+        compressor.done()?;
+
+        let mut entries = Vec::with_capacity(nodes.len());
+        let mut chunk_offset = offset_to_points as u64 + std::mem::size_of::<i64>() as u64;
+        for ((key, _), chunk) in nodes.iter().zip(compressor.chunk_table().as_ref()) {
+            entries.push(Entry {
+                key: *key,
+                offset: chunk_offset,
+                byte_size: hierarchy_field(chunk.byte_count, "byte size")?,
+                point_count: hierarchy_field(chunk.point_count, "point count")?,
+            });
+            chunk_offset += chunk.byte_count;
+        }
+
+        // All the entries are in the root page
+        let mut output = compressor.into_inner();
+        let evlr_start = output.seek(SeekFrom::End(0))?;
+        let hierarchy_size = entries.len() as u64 * Entry::SIZE;
+        output.write_u16::<LittleEndian>(0)?;
+        write_fixed_str(&mut output, CopcInfo::USER_ID, 16)?;
+        output.write_u16::<LittleEndian>(HIERARCHY_RECORD_ID)?;
+        output.write_u64::<LittleEndian>(hierarchy_size)?;
+        write_fixed_str(&mut output, "EPT hierarchy", 32)?;
+        for entry in &entries {
+            entry.write_to(&mut output)?;
+        }
+        info.root_hier_offset = evlr_start + EVLR_HEADER_SIZE as u64;
+        info.root_hier_size = hierarchy_size;
+
+        output.seek(SeekFrom::Start(0))?;
+        output.write_all(b"LASF")?;
+        output.write_u16::<LittleEndian>(0)?; // file source id
+        output.write_u16::<LittleEndian>(GLOBAL_ENCODING_WKT)?;
+        output.write_all(&[0u8; 16])?; // project id
+        output.write_u8(1)?;
+        output.write_u8(4)?;
+        write_fixed_str(&mut output, "", 32)?;
+        write_fixed_str(
+            &mut output,
+            &format!("laz-rs {}", env!("CARGO_PKG_VERSION")),
+            32,
+        )?;
+        output.write_u16::<LittleEndian>(0)?; // creation day
+        output.write_u16::<LittleEndian>(0)?; // creation year
+        output.write_u16::<LittleEndian>(HEADER_SIZE as u16)?;
+        output.write_u32::<LittleEndian>(offset_to_points as u32)?;
+        let num_vlrs = if wkt_data.is_some() { 3 } else { 2 };
+        output.write_u32::<LittleEndian>(num_vlrs)?;
+        output.write_u8(self.point_format_id | 0x80)?;
+        output.write_u16::<LittleEndian>(point_size as u16)?;
+        // legacy point counts are 0 for point formats >= 6
+        output.write_all(&[0u8; 4 + 5 * 4])?;
+        for value in self.scale.iter().chain(&self.offset) {
+            output.write_f64::<LittleEndian>(*value)?;
+        }
+        for i in 0..3 {
+            output.write_f64::<LittleEndian>(max[i])?;
+            output.write_f64::<LittleEndian>(min[i])?;
+        }
+        output.write_u64::<LittleEndian>(0)?; // waveform data
+        output.write_u64::<LittleEndian>(evlr_start)?;
+        output.write_u32::<LittleEndian>(1)?;
+        output.write_u64::<LittleEndian>(points.len() as u64)?;
+        for count in points_by_return {
+            output.write_u64::<LittleEndian>(count)?;
+        }
+        write_vlr_header(
+            &mut output,
+            CopcInfo::USER_ID,
+            CopcInfo::RECORD_ID,
+            CopcInfo::SIZE,
+            CopcInfo::DESCRIPTION,
+        )?;
+        info.write_to(&mut output)?;
+        output.seek(SeekFrom::End(0))?;
+        Ok(output)
+    }
+
+    fn point_size(&self) -> usize {
+        self.vlr.items_size() as usize
+    }
+
+    /// Returns the nodes with the indices of their points, sorted by key
+    fn build_octree(
+        &self,
+        info: &CopcInfo,
+        coordinates: &[[f64; 3]],
+    ) -> Vec<(VoxelKey, Vec<usize>)> {
+        let max_points = self.max_points_per_node;
+        let mut nodes = vec![];
+        let mut to_split = vec![(VoxelKey::ROOT, (0..coordinates.len()).collect::<Vec<_>>())];
+        while let Some((key, indices)) = to_split.pop() {
+            if indices.len() <= max_points || key.level >= MAX_LEVEL {
+                nodes.push((key, indices));
+                continue;
+            }
+
+            let bounds = key.bounds(info);
+            let center = [0, 1, 2].map(|i| (bounds.min[i] + bounds.max[i]) / 2.0);
+            let step = indices.len() as f64 / max_points as f64;
+            let mut kept = Vec::with_capacity(max_points);
+            let mut children: [Vec<usize>; 8] = Default::default();
+            for (i, index) in indices.into_iter().enumerate() {
+                if i as f64 >= kept.len() as f64 * step {
+                    kept.push(index);
+                } else {
+                    let xyz = coordinates[index];
+                    let direction = (0..3)
+                        .filter(|axis| xyz[*axis] >= center[*axis])
+                        .fold(0u8, |direction, axis| direction | (1 << axis));
+                    children[usize::from(direction)].push(index);
+                }
+            }
+            nodes.push((key, kept));
+            for (direction, child) in children.into_iter().enumerate() {
+                if !child.is_empty() {
+                    to_split.push((key.child(direction as u8), child));
+                }
+            }
+        }
+        nodes.sort_by_key(|(key, _)| *key);
+        nodes
+    }
+}
+
+/// Converts the byte size / point count of a node to the `i32` of its hierarchy entry
+fn hierarchy_field(value: u64, name: &str) -> std::io::Result<i32> {
+    i32::try_from(value).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "the {} of a node ({}) does not fit in a hierarchy entry",
+                name, value
+            ),
+        )
+    })
+}
+
+fn write_vlr_header<W: Write>(
+    dst: &mut W,
+    user_id: &str,
+    record_id: u16,
+    record_length: u16,
+    description: &str,
+) -> std::io::Result<()> {
+    dst.write_u16::<LittleEndian>(0)?;
+    write_fixed_str(dst, user_id, 16)?;
+    dst.write_u16::<LittleEndian>(record_id)?;
+    dst.write_u16::<LittleEndian>(record_length)?;
+    write_fixed_str(dst, description, 32)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::copc::CopcReader;
+    use crate::LasZipDecompressor;
+
+    fn points(count: usize) -> Vec<u8> {
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let mut points = vec![];
+        for i in 0..count {
+            let mut point = [0u8; 30];
+            point[0..4].copy_from_slice(&((next() % 100_000) as i32).to_le_bytes());
+            point[4..8].copy_from_slice(&((next() % 50_000) as i32 - 1000).to_le_bytes());
+            point[8..12].copy_from_slice(&((next() % 2_000) as i32).to_le_bytes());
+            point[12..14].copy_from_slice(&(next() as u16).to_le_bytes());
+            point[14] = 0x11 + (i % 2) as u8;
+            point[16] = (next() % 10) as u8;
+            point[22..30].copy_from_slice(&(1000.0 + i as f64 * 0.5).to_le_bytes());
+            points.extend_from_slice(&point);
+        }
+        points
+    }
+
+    #[test]
+    fn test_copc_writer() {
+        let input = points(5_000);
+        let mut writer = CopcWriter::new(Cursor::new(vec![]), 6, 0)
+            .unwrap()
+            .with_offset([100.0, 200.0, 0.0])
+            .with_max_points_per_node(600);
+        writer.write_many(&input).unwrap();
+        assert!(writer.write_one(&input[..29]).is_err());
+        let data = writer.done().unwrap().into_inner();
+
+        let mut reader = CopcReader::new(Cursor::new(data.as_slice())).unwrap();
+        assert_eq!(reader.header().point_count, 5_000);
+        assert_eq!(reader.header().offset, [100.0, 200.0, 0.0]);
+        assert_eq!(reader.info().gpstime_minimum, 1000.0);
+        assert_eq!(reader.info().gpstime_maximum, 1000.0 + 4999.0 * 0.5);
+        assert_eq!(
+            u32::from_le_bytes(data[96..100].try_into().unwrap()),
+            reader.header().offset_to_points
+        );
+
+        let nodes = reader.nodes().unwrap();
+        assert!(nodes.len() > 8);
+        assert_eq!(nodes[0].key, VoxelKey::ROOT);
+        let mut output = vec![];
+        for node in &nodes {
+            assert!(node.point_count as usize <= 600);
+            if let Some(parent) = node.key.parent() {
+                assert!(nodes.iter().any(|n| n.key == parent));
+            }
+            let node_bounds = reader.node_bounds(&node.key);
+            let points = reader.read_node(node).unwrap();
+            assert_eq!(points.len(), node.point_count as usize * 30);
+            for point in points.chunks_exact(30) {
+                let xyz = [0, 1, 2].map(|i| {
+                    let raw = i32::from_le_bytes(point[4 * i..4 * (i + 1)].try_into().unwrap());
+                    f64::from(raw) * 0.01 + reader.header().offset[i]
+                });
+                assert!(node_bounds.contains(xyz));
+            }
+            output.extend(points.chunks_exact(30).map(|p| p.to_vec()));
+        }
+        let mut expected = input
+            .chunks_exact(30)
+            .map(|p| p.to_vec())
+            .collect::<Vec<_>>();
+        expected.sort();
+        output.sort();
+        assert_eq!(output, expected);
+
+        // The point data is also readable as regular LAZ data
+        let mut source = Cursor::new(data.as_slice());
+        source.set_position(u64::from(reader.header().offset_to_points));
+        let mut decompressor = LasZipDecompressor::new(source, reader.laz_vlr().clone()).unwrap();
+        let mut all_points = vec![0u8; input.len()];
+        decompressor.decompress_many(&mut all_points).unwrap();
+        let mut all_points = all_points
+            .chunks_exact(30)
+            .map(|p| p.to_vec())
+            .collect::<Vec<_>>();
+        all_points.sort();
+        assert_eq!(all_points, expected);
+    }
+
+    /// Returns the user id, record id & data of the VLRs of the file
+    fn read_vlrs(data: &[u8]) -> Vec<(String, u16, Vec<u8>)> {
+        let num_vlrs = u32::from_le_bytes(data[100..104].try_into().unwrap());
+        let mut position = usize::from(u16::from_le_bytes(data[94..96].try_into().unwrap()));
+        let mut vlrs = vec![];
+        for _ in 0..num_vlrs {
+            let header = &data[position..position + VLR_HEADER_SIZE];
+            let user_id = String::from_utf8_lossy(&header[2..18])
+                .trim_end_matches('\0')
+                .to_string();
+            let record_id = u16::from_le_bytes(header[18..20].try_into().unwrap());
+            let record_length = usize::from(u16::from_le_bytes(header[20..22].try_into().unwrap()));
+            position += VLR_HEADER_SIZE;
+            vlrs.push((
+                user_id,
+                record_id,
+                data[position..position + record_length].to_vec(),
+            ));
+            position += record_length;
+        }
+        assert_eq!(
+            position,
+            u32::from_le_bytes(data[96..100].try_into().unwrap()) as usize
+        );
+        vlrs
+    }
+
+    #[test]
+    fn test_hierarchy_field_overflow() {
+        assert_eq!(
+            hierarchy_field(i32::MAX as u64, "point count").unwrap(),
+            i32::MAX
+        );
+        let error = hierarchy_field(i32::MAX as u64 + 1, "point count").unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_copc_writer_wkt() {
+        let global_encoding = |data: &[u8]| u16::from_le_bytes(data[6..8].try_into().unwrap());
+        let input = points(100);
+
+        let mut writer = CopcWriter::new(Cursor::new(vec![]), 6, 0).unwrap();
+        writer.write_many(&input).unwrap();
+        let data = writer.done().unwrap().into_inner();
+        assert_eq!(
+            global_encoding(&data) & GLOBAL_ENCODING_WKT,
+            GLOBAL_ENCODING_WKT
+        );
+        let vlrs = read_vlrs(&data);
+        assert_eq!(
+            vlrs.iter()
+                .map(|(user_id, record_id, _)| (user_id.as_str(), *record_id))
+                .collect::<Vec<_>>(),
+            vec![
+                (CopcInfo::USER_ID, CopcInfo::RECORD_ID),
+                (LazVlr::USER_ID, LazVlr::RECORD_ID),
+            ]
+        );
+
+        let wkt = "PROJCS[\"WGS 84 / UTM zone 31N\"]";
+        let mut writer = CopcWriter::new(Cursor::new(vec![]), 6, 0)
+            .unwrap()
+            .with_wkt(wkt);
+        writer.write_many(&input).unwrap();
+        let data = writer.done().unwrap().into_inner();
+        assert_eq!(
+            global_encoding(&data) & GLOBAL_ENCODING_WKT,
+            GLOBAL_ENCODING_WKT
+        );
+        let vlrs = read_vlrs(&data);
+        assert_eq!(vlrs.len(), 3);
+        assert_eq!(
+            (vlrs[0].0.as_str(), vlrs[0].1),
+            (CopcInfo::USER_ID, CopcInfo::RECORD_ID)
+        );
+        assert_eq!(
+            (vlrs[2].0.as_str(), vlrs[2].1),
+            (WKT_USER_ID, WKT_RECORD_ID)
+        );
+        assert_eq!(vlrs[2].2, [wkt.as_bytes(), &[0]].concat());
+
+        let mut reader = CopcReader::new(Cursor::new(data.as_slice())).unwrap();
+        let nodes = reader.nodes().unwrap();
+        let num_points = nodes.iter().map(|node| node.point_count).sum::<i32>();
+        assert_eq!(num_points, 100);
+
+        let writer = CopcWriter::new(Cursor::new(vec![]), 6, 0)
+            .unwrap()
+            .with_wkt("A".repeat(usize::from(u16::MAX)));
+        assert!(writer.done().is_err());
+    }
+}
@@ -104,6 +104,9 @@ pub struct ArithmeticEncoder<T: Write> {
     base: u32,
     length: u32,
 
+    /// Number of bytes output by the interval renormalizations
+    num_renorm_bytes: u64,
+
     out_stream: T,
 }
 
@@ -120,6 +123,7 @@ impl<T: Write> ArithmeticEncoder<T> {
             end_byte,
             base: 0,
             length: decoders::AC_MAX_LENGTH,
+            num_renorm_bytes: 0,
             out_stream,
         }
     }
@@ -132,6 +136,7 @@ impl<T: Write> ArithmeticEncoder<T> {
     pub fn reset(&mut self) {
         self.base = 0;
         self.length = decoders::AC_MAX_LENGTH;
+        self.num_renorm_bytes = 0;
         self.out_buffer.fill(0);
         self.out_byte = self.out_buffer.as_mut_ptr();
         self.end_byte = self.end_of_buffer();
@@ -352,6 +357,16 @@ impl<T: Write> ArithmeticEncoder<T> {
         self.write_int((sym >> 32) as u32)
     }
 
+    /// Returns the number of bits of information encoded since the creation
+    /// (or last reset) of the encoder.
+    ///
+    /// It accounts for the bytes already output and for the part of the
+    /// current interval that is used, so the difference between two calls
+    /// is (a close estimation of) what the symbols encoded in between cost.
+    pub(crate) fn num_encoded_bits(&self) -> f64 {
+        (self.num_renorm_bytes * 8) as f64 + 32.0 - f64::from(self.length).log2()
+    }
+
     pub fn get_ref(&self) -> &T {
         &self.out_stream
     }
@@ -396,6 +411,7 @@ impl<T: Write> ArithmeticEncoder<T> {
                 *self.out_byte = (self.base >> 24) as u8;
             }
             self.out_byte = self.out_byte.wrapping_add(1);
+            self.num_renorm_bytes += 1;
 
             if self.out_byte.cast_const() == self.end_byte {
                 self.manage_out_buffer()?;
@@ -2,7 +2,7 @@
 
 use std::fmt;
 
-use crate::laszip::{CompressorType, LazItemType};
+use crate::laszip::{CompressorType, InvalidVlrReason, LazItemType};
 
 /// Errors of this crate
 #[derive(Debug)]
@@ -23,6 +23,25 @@ pub enum LasZipError {
     /// The chunk table could not be found in the file
     /// and it is required for the operation.
     MissingChunkTable,
+    /// Two sets of [`LazItem`](crate::LazItem)s were expected to be the same
+    /// but they are not (e.g. when merging LAZ data)
+    MismatchedLazItems,
+    /// The items of a [`LazVlr`](crate::LazVlr) break one of the rules
+    InvalidVlr(InvalidVlrReason),
+    /// The points decompressed from a chunk that was just compressed
+    /// are not the points that were given to the compressor
+    VerificationFailed {
+        /// Index of the chunk in the chunk table
+        chunk_index: usize,
+        /// Index of the point in the chunk
+        point_index: u64,
+        /// Offset of the first differing byte in the point record
+        byte_offset: usize,
+        /// Value of the byte in the input point
+        expected: u8,
+        /// Value of the byte in the decompressed point
+        actual: u8,
+    },
 }
 
 impl From<std::io::Error> for LasZipError {
@@ -52,6 +71,21 @@ impl fmt::Display for LasZipError {
                 write!(f, "Point format {} is not supported", id)
             }
             LasZipError::MissingChunkTable => write!(f, "The chunk table could not be found"),
+            LasZipError::MismatchedLazItems => {
+                write!(f, "The LazItems of the inputs do not match")
+            }
+            LasZipError::InvalidVlr(reason) => write!(f, "Invalid LAZ vlr: {}", reason),
+            LasZipError::VerificationFailed {
+                chunk_index,
+                point_index,
+                byte_offset,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Verification failed in chunk {}, point {}, byte {}: expected {:#04x}, got {:#04x}",
+                chunk_index, point_index, byte_offset, expected, actual
+            ),
         }
     }
 }
@@ -64,6 +64,74 @@ impl LasExtraBytes for ExtraBytes {
     }
 }
 
+/// An attribute stored in the extra bytes of the points,
+/// as described by the Extra Bytes VLR of LAS files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtraBytesAttribute {
+    pub name: String,
+    /// Data type as defined by the LAS specification (0 for undocumented bytes)
+    pub data_type: u8,
+    /// Position of the first byte of the attribute in the extra bytes of a point
+    pub offset: usize,
+    /// Number of bytes of the attribute
+    pub size: usize,
+}
+
+impl ExtraBytesAttribute {
+    /// The user id of the Extra Bytes VLR
+    pub const USER_ID: &'static str = "LASF_Spec";
+    /// The record id of the Extra Bytes VLR
+    pub const RECORD_ID: u16 = 4;
+    /// Size of the description of one attribute in the VLR
+    const DESCRIPTOR_SIZE: usize = 192;
+
+    /// Reads the attributes described in the record data of an Extra Bytes VLR
+    pub fn read_all_from(record_data: &[u8]) -> std::io::Result<Vec<Self>> {
+        if record_data.len() % Self::DESCRIPTOR_SIZE != 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "the Extra Bytes VLR size is not a multiple of 192",
+            ));
+        }
+        let mut offset = 0;
+        let mut attributes = Vec::with_capacity(record_data.len() / Self::DESCRIPTOR_SIZE);
+        for descriptor in record_data.chunks_exact(Self::DESCRIPTOR_SIZE) {
+            let data_type = descriptor[2];
+            let options = descriptor[3];
+            let size = match data_type {
+                0 => usize::from(options),
+                1..=30 => {
+                    let base_type = (data_type - 1) % 10;
+                    let num_values = usize::from((data_type - 1) / 10 + 1);
+                    [1, 1, 2, 2, 4, 4, 8, 8, 4, 8][usize::from(base_type)] * num_values
+                }
+                _ => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("unknown extra bytes data type {}", data_type),
+                    ))
+                }
+            };
+            let name = String::from_utf8_lossy(&descriptor[4..36])
+                .trim_end_matches('\0')
+                .to_string();
+            attributes.push(Self {
+                name,
+                data_type,
+                offset,
+                size,
+            });
+            offset += size;
+        }
+        Ok(attributes)
+    }
+
+    /// Returns the range of the attribute bytes in the extra bytes of a point
+    pub fn byte_range(&self) -> std::ops::Range<usize> {
+        self.offset..self.offset + self.size
+    }
+}
+
 pub mod v1 {
     //! The Algorithm is simple:
     //! encode the difference between byte for each extra bytes
@@ -186,7 +254,7 @@ mod v3_v4 {
     use crate::decoders::ArithmeticDecoder;
     use crate::encoders::ArithmeticEncoder;
     use crate::las::extra_bytes::ExtraBytes;
-    use crate::las::selective::DecompressionSelection;
+    use crate::las::selective::{DecompressionSelection, ExtraBytesSelection};
     use crate::las::utils::{copy_bytes_into_decoder, copy_encoder_content_to};
     use crate::models::{ArithmeticModel, ArithmeticModelBuilder};
     use crate::record::{LayeredFieldCompressor, LayeredFieldDecompressor};
@@ -213,6 +281,9 @@ mod v3_v4 {
         decoders: Vec<ArithmeticDecoder<Cursor<Vec<u8>>>>,
         num_bytes_per_layer: Vec<u32>,
         is_requested: Vec<bool>,
+        // What the selections given by the user request
+        all_requested: bool,
+        bytes_requested: ExtraBytesSelection,
         should_load_bytes: Vec<bool>,
         contexts: Vec<ExtraBytesContext>,
         // Last & contexts are separated for the same reasons as in v3_v4::RGB
@@ -229,6 +300,8 @@ mod v3_v4 {
                     .collect(),
                 num_bytes_per_layer: vec![0; count],
                 is_requested: vec![true; count],
+                all_requested: true,
+                bytes_requested: ExtraBytesSelection::none(),
                 should_load_bytes: vec![true; count],
                 contexts: (0..4).map(|_i| ExtraBytesContext::new(count)).collect(),
                 last_bytes: (0..4).map(|_| ExtraBytes::new(count)).collect(),
@@ -236,6 +309,13 @@ mod v3_v4 {
                 last_context_used: 0,
             }
         }
+
+        fn update_requested_bytes(&mut self) {
+            for (i, is_requested) in self.is_requested.iter_mut().enumerate() {
+                *is_requested =
+                    self.all_requested || self.bytes_requested.should_decompress_byte(i);
+            }
+        }
     }
 
     impl<const VERSION: usize, R: Read + Seek> LayeredFieldDecompressor<R>
@@ -246,8 +326,13 @@ mod v3_v4 {
         }
 
         fn set_selection(&mut self, selection: DecompressionSelection) {
-            self.is_requested
-                .fill(selection.should_decompress_extra_bytes());
+            self.all_requested = selection.should_decompress_extra_bytes();
+            self.update_requested_bytes();
+        }
+
+        fn set_extra_bytes_selection(&mut self, selection: ExtraBytesSelection) {
+            self.bytes_requested = selection;
+            self.update_requested_bytes();
         }
 
         fn init_first_point(
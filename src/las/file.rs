@@ -13,6 +13,7 @@ use crate::laszip::{LasZipDecompressor, LazVlr};
 /// LAS header with only the minimum information
 /// to be able to read points contained in a LAS file.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct QuickHeader {
     pub major: u8,
     pub minor: u8,
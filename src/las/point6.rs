@@ -309,6 +309,8 @@ mod test {
     }
 }
 
+pub(crate) use v3_v4::ClassificationLayerEncoder;
+
 mod v3_v4 {
     //! Implementation shared by the version 3 and version 4 of the point 6
     //! compression / decompression.
@@ -1961,6 +1963,80 @@ mod v3_v4 {
         }
     }
 
+    /// Encodes only the classification layer of a chunk,
+    /// exactly as the [`LasPoint6CompressorImpl`] would.
+    ///
+    /// This allows to re-encode the classification of points
+    /// without having to re-encode the other layers.
+    ///
+    /// The points given only need to have valid return number, number of returns,
+    /// scanner channel and classification, as these are the only fields used.
+    pub(crate) struct ClassificationLayerEncoder {
+        encoder: ArithmeticEncoder<Cursor<Vec<u8>>>,
+        models: [Vec<Option<ArithmeticModel>>; 4],
+        /// Classification of the last point of each context,
+        /// `None` if the context is not used yet.
+        last_classifications: [Option<u8>; 4],
+        current_context: usize,
+        has_changed: bool,
+    }
+
+    impl ClassificationLayerEncoder {
+        pub(crate) fn new(first_point: &[u8]) -> Self {
+            let first_point = Point6::unpack_from(first_point);
+            let current_context = first_point.scanner_channel() as usize;
+            let mut last_classifications = [None; 4];
+            last_classifications[current_context] = Some(first_point.classification);
+            Self {
+                encoder: ArithmeticEncoder::new(Cursor::new(Vec::<u8>::new())),
+                models: [(); 4].map(|_| (0..64).map(|_| None).collect()),
+                last_classifications,
+                current_context,
+                has_changed: false,
+            }
+        }
+
+        pub(crate) fn encode_next(&mut self, point: &[u8]) -> std::io::Result<()> {
+            let point = Point6::unpack_from(point);
+            let scanner_channel = point.scanner_channel() as usize;
+            if scanner_channel != self.current_context {
+                if self.last_classifications[scanner_channel].is_none() {
+                    self.last_classifications[scanner_channel] =
+                        self.last_classifications[self.current_context];
+                }
+                self.current_context = scanner_channel;
+            }
+
+            let n = point.number_of_returns_of_given_pulse();
+            let r = point.return_number();
+            let mut cpr = if r == 1 { 2 } else { 0 }; //first ?
+            cpr += if r >= n { 1 } else { 0 }; // last ?
+
+            let last_classification = self.last_classifications[self.current_context]
+                .expect("current context should be initialized");
+            if point.classification != last_classification {
+                self.has_changed = true;
+            }
+            let ccc = ((last_classification & 0x1F) << 1) as usize + (cpr == 3) as usize;
+            let model = self.models[self.current_context][ccc]
+                .get_or_insert_with(|| ArithmeticModelBuilder::new(256).build());
+            self.encoder
+                .encode_symbol(model, u32::from(point.classification))?;
+            self.last_classifications[self.current_context] = Some(point.classification);
+            Ok(())
+        }
+
+        /// Returns the bytes of the layer,
+        /// which are empty if the classification never changed.
+        pub(crate) fn into_layer(mut self) -> std::io::Result<Vec<u8>> {
+            if !self.has_changed {
+                return Ok(vec![]);
+            }
+            self.encoder.done()?;
+            Ok(self.encoder.into_inner().into_inner())
+        }
+    }
+
     #[cfg(test)]
     mod test {
         use std::io::SeekFrom;
@@ -1,5 +1,7 @@
 //! Selective decompression builder
 
+use crate::las::extra_bytes::ExtraBytesAttribute;
+
 /// Struct for selective decompression
 ///
 /// Contains the information of which fields the user wants
@@ -10,7 +12,10 @@
 /// Selective decompression is not supported by all
 /// point formats. On point formats which do not support it,
 /// it will be ignored and all data will be decompressed.
+///
+/// To decompress only some of the extra bytes, see [`ExtraBytesSelection`].
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 // The inner value is pub to facilitate language bindings
 pub struct DecompressionSelection(pub u32);
 
@@ -68,6 +73,11 @@ impl DecompressionSelection {
     pub const WAVEPACKET: u32 = 1 << 10;
     pub const ALL_EXTRA_BYTES: u32 = 1 << 11;
 
+    /// Creates the selection from the bits of the fields to decompress
+    pub fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
     /// To decompress all the possible fields
     pub fn all() -> Self {
         Self(Self::ALL)
@@ -128,6 +138,11 @@ impl DecompressionSelection {
     accessor!(should_decompress_wavepacket = Self::WAVEPACKET);
     accessor!(should_decompress_extra_bytes = Self::ALL_EXTRA_BYTES);
 
+    /// Returns the selection of the fields of both selections
+    pub fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
     fn set(self, bit_mask: u32) -> Self {
         Self(self.0 | bit_mask)
     }
@@ -140,3 +155,228 @@ impl DecompressionSelection {
         (self.0 & bit_mask) != 0
     }
 }
+
+impl From<u32> for DecompressionSelection {
+    fn from(bits: u32) -> Self {
+        Self::from_bits(bits)
+    }
+}
+
+/// Selection of individual extra bytes, to use along with a [`DecompressionSelection`]
+///
+/// An extra byte is decompressed if it is selected here, or if the
+/// [`DecompressionSelection`] selects all the extra bytes.
+///
+/// Only the first [`MAX_SELECTABLE_EXTRA_BYTES`](Self::MAX_SELECTABLE_EXTRA_BYTES)
+/// extra bytes can be selected one by one, and only layered data (point formats >= 6)
+/// can skip the extra bytes that are not selected.
+#[derive(Debug, Copy, Clone, Default, Ord, PartialOrd, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExtraBytesSelection([u64; 4]);
+
+impl ExtraBytesSelection {
+    /// Number of extra bytes that can be selected one by one
+    pub const MAX_SELECTABLE_EXTRA_BYTES: usize = 256;
+
+    /// Selects no extra bytes
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// Decompresses the extra byte at `index` (0 being the first extra byte of a point)
+    ///
+    /// # Panics
+    ///
+    /// If `index` is not less than [`Self::MAX_SELECTABLE_EXTRA_BYTES`]
+    pub fn decompress_byte(mut self, index: usize) -> Self {
+        assert!(
+            index < Self::MAX_SELECTABLE_EXTRA_BYTES,
+            "only the first {} extra bytes can be selected one by one",
+            Self::MAX_SELECTABLE_EXTRA_BYTES
+        );
+        self.0[index / 64] |= 1 << (index % 64);
+        self
+    }
+
+    /// Decompresses the extra bytes of the attribute named `name`
+    ///
+    /// `attributes` are the attributes described by the Extra Bytes VLR of the file,
+    /// see [`ExtraBytesAttribute::read_all_from`].
+    pub fn decompress_attribute(
+        self,
+        attributes: &[ExtraBytesAttribute],
+        name: &str,
+    ) -> crate::Result<Self> {
+        let attribute = attributes
+            .iter()
+            .find(|attribute| attribute.name == name)
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("there is no extra bytes attribute named '{}'", name),
+                )
+            })?;
+        let bytes = attribute.byte_range();
+        if bytes.end > Self::MAX_SELECTABLE_EXTRA_BYTES {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "the attribute '{}' is after the first {} extra bytes",
+                    name,
+                    Self::MAX_SELECTABLE_EXTRA_BYTES
+                ),
+            )
+            .into());
+        }
+        Ok(bytes.fold(self, Self::decompress_byte))
+    }
+
+    /// Returns whether the extra byte at `index` is selected
+    pub fn should_decompress_byte(self, index: usize) -> bool {
+        index < Self::MAX_SELECTABLE_EXTRA_BYTES && (self.0[index / 64] & (1 << (index % 64))) != 0
+    }
+
+    /// Returns whether no extra byte is selected
+    pub fn is_empty(self) -> bool {
+        self.0.iter().all(|word| *word == 0)
+    }
+
+    /// Returns the selection of the extra bytes of both selections
+    pub fn union(self, other: Self) -> Self {
+        let mut words = self.0;
+        for (word, other_word) in words.iter_mut().zip(other.0) {
+            *word |= other_word;
+        }
+        Self(words)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{LasZipCompressor, LasZipDecompressor, LazVlrBuilder};
+
+    fn extra_bytes_vlr_data(attributes: &[(&str, u8, u8)]) -> Vec<u8> {
+        let mut data = vec![0u8; 192 * attributes.len()];
+        for ((name, data_type, options), descriptor) in
+            attributes.iter().zip(data.chunks_exact_mut(192))
+        {
+            descriptor[2] = *data_type;
+            descriptor[3] = *options;
+            descriptor[4..4 + name.len()].copy_from_slice(name.as_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_read_extra_bytes_attributes() {
+        let data = extra_bytes_vlr_data(&[("amplitude", 3, 0), ("raw", 0, 3), ("xyz", 20, 0)]);
+        let attributes = ExtraBytesAttribute::read_all_from(&data).unwrap();
+        let ranges = attributes
+            .iter()
+            .map(|attribute| (attribute.name.as_str(), attribute.byte_range()))
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, [("amplitude", 0..2), ("raw", 2..5), ("xyz", 5..21)]);
+
+        assert!(ExtraBytesAttribute::read_all_from(&data[..100]).is_err());
+        assert!(
+            ExtraBytesAttribute::read_all_from(&extra_bytes_vlr_data(&[("a", 31, 0)])).is_err()
+        );
+    }
+
+    #[test]
+    fn test_selection_of_extra_bytes() {
+        let selection = ExtraBytesSelection::none().decompress_byte(3);
+        assert!(selection.should_decompress_byte(3));
+        assert!(!selection.should_decompress_byte(2));
+        assert!(!selection.should_decompress_byte(1000));
+        assert!(ExtraBytesSelection::none().is_empty());
+        assert_eq!(
+            selection.union(ExtraBytesSelection::none().decompress_byte(200)),
+            ExtraBytesSelection::none()
+                .decompress_byte(200)
+                .decompress_byte(3)
+        );
+        assert_eq!(
+            DecompressionSelection::base().union(DecompressionSelection::base().decompress_z()),
+            DecompressionSelection::base().decompress_z()
+        );
+    }
+
+    #[test]
+    fn test_decompress_extra_attribute() {
+        let attributes = ExtraBytesAttribute::read_all_from(&extra_bytes_vlr_data(&[
+            ("amplitude", 3, 0),
+            ("deviation", 3, 0),
+            ("echo", 1, 0),
+        ]))
+        .unwrap();
+        let vlr = LazVlrBuilder::default()
+            .with_point_format(6, 5)
+            .unwrap()
+            .with_fixed_chunk_size(100)
+            .build();
+        let point_size = vlr.items_size() as usize;
+        let mut points = vec![0u8; 250 * point_size];
+        for (i, point) in points.chunks_exact_mut(point_size).enumerate() {
+            point[0..4].copy_from_slice(&(i as i32).to_le_bytes());
+            for (j, byte) in point[30..].iter_mut().enumerate() {
+                *byte = (i * (j + 1)) as u8;
+            }
+        }
+        let mut compressor = LasZipCompressor::new(Cursor::new(vec![]), vlr.clone()).unwrap();
+        compressor.compress_many(&points).unwrap();
+        compressor.done().unwrap();
+        let data = compressor.into_inner().into_inner();
+
+        let extra_bytes = ExtraBytesSelection::none()
+            .decompress_attribute(&attributes, "deviation")
+            .unwrap();
+        let mut decompressor = LasZipDecompressor::selective(
+            Cursor::new(data.as_slice()),
+            vlr,
+            DecompressionSelection::base(),
+        )
+        .unwrap();
+        decompressor.set_extra_bytes_selection(extra_bytes);
+        let mut decompressed = vec![0u8; points.len()];
+        decompressor.decompress_many(&mut decompressed).unwrap();
+
+        let mut num_skipped_values = 0;
+        for (point, expected) in decompressed
+            .chunks_exact(point_size)
+            .zip(points.chunks_exact(point_size))
+        {
+            assert_eq!(point[32..34], expected[32..34]);
+            if point[30..32] != expected[30..32] {
+                num_skipped_values += 1;
+            }
+        }
+        assert!(num_skipped_values > 0);
+
+        #[cfg(feature = "parallel")]
+        {
+            let mut decompressor = crate::ParLasZipDecompressor::selective(
+                Cursor::new(data.as_slice()),
+                decompressor.vlr().clone(),
+                DecompressionSelection::base(),
+            )
+            .unwrap();
+            decompressor.set_extra_bytes_selection(extra_bytes);
+            let mut par_decompressed = vec![0u8; points.len()];
+            decompressor.decompress_many(&mut par_decompressed).unwrap();
+            for (point, expected) in par_decompressed
+                .chunks_exact(point_size)
+                .zip(points.chunks_exact(point_size))
+            {
+                assert_eq!(point[32..34], expected[32..34]);
+            }
+        }
+
+        assert!(ExtraBytesSelection::none()
+            .decompress_attribute(&attributes, "intensity")
+            .is_err());
+    }
+}
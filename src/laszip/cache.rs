@@ -0,0 +1,310 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+use crate::laszip::chunk_table::ChunkTable;
+use crate::laszip::details::record_decompressor_from_laz_items;
+use crate::laszip::LazVlr;
+
+/// Number of chunks kept by default by a [`CachedLazReader`]
+const DEFAULT_MAX_CHUNKS: usize = 16;
+
+/// Hits & misses of the cache of a [`CachedLazReader`]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Number of chunks accesses served by the cache
+    pub hits: u64,
+    /// Number of chunks that had to be read & decompressed
+    pub misses: u64,
+    /// Number of chunks removed from the cache to make room for others
+    pub evictions: u64,
+}
+
+/// Reader giving random access to the points of LAZ data,
+/// keeping the most recently used decompressed chunks in memory.
+///
+/// Points that are in a cached chunk are served without any i/o or decompression,
+/// other points cost the decompression of their whole chunk.
+///
+/// The chunk table is required.
+pub struct CachedLazReader<R> {
+    source: R,
+    vlr: LazVlr,
+    chunk_table: ChunkTable,
+    /// Position of the first chunk in the source
+    data_start: u64,
+    chunks: HashMap<usize, Vec<u8>>,
+    /// Cached chunks, from the least to the most recently used
+    lru: VecDeque<usize>,
+    max_chunks: usize,
+    max_bytes: Option<usize>,
+    cached_bytes: usize,
+    stats: CacheStats,
+}
+
+impl<R: Read + Seek> CachedLazReader<R> {
+    /// Creates the reader and reads the chunk table.
+    ///
+    /// `source` must be positioned as for [`ChunkTable::read_from`](crate::laszip::ChunkTable::read_from),
+    /// `point_count` is the number of points in the source.
+    pub fn new(mut source: R, vlr: LazVlr, point_count: u64) -> crate::Result<Self> {
+        let start = source.stream_position()?;
+        let mut chunk_table = ChunkTable::read_from(&mut source, &vlr)?;
+        if !vlr.uses_variable_size_chunks() {
+            chunk_table.fix_last_point_count(point_count)?;
+        }
+
+        Ok(Self {
+            source,
+            vlr,
+            chunk_table,
+            data_start: start + std::mem::size_of::<i64>() as u64,
+            chunks: HashMap::new(),
+            lru: VecDeque::new(),
+            max_chunks: DEFAULT_MAX_CHUNKS,
+            max_bytes: None,
+            cached_bytes: 0,
+            stats: CacheStats::default(),
+        })
+    }
+
+    /// Sets the maximum number of chunks kept in memory (16 by default)
+    pub fn with_max_chunks(mut self, max_chunks: usize) -> Self {
+        self.max_chunks = max_chunks.max(1);
+        self
+    }
+
+    /// Sets the maximum number of bytes of decompressed points kept in memory.
+    ///
+    /// The chunk being accessed is always kept, even if it is larger than the limit.
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Returns the number of points
+    pub fn point_count(&self) -> u64 {
+        self.chunk_table.point_count()
+    }
+
+    /// Returns the point at the given index
+    pub fn get_point(&mut self, index: u64) -> crate::Result<&[u8]> {
+        if index >= self.point_count() {
+            return Err(out_of_bounds(index, self.point_count()).into());
+        }
+        let chunk_index = self.chunk_table.chunk_index_of_point(index).unwrap();
+        self.load_chunk(chunk_index)?;
+        let point_size = self.vlr.items_size() as usize;
+        let first_point = self
+            .chunk_table
+            .point_range_of_chunk(chunk_index)
+            .unwrap()
+            .start;
+        let start = (index - first_point) as usize * point_size;
+        Ok(&self.chunks[&chunk_index][start..start + point_size])
+    }
+
+    /// Returns the `len` points starting at the index `start`
+    pub fn get_range(&mut self, start: u64, len: u64) -> crate::Result<Vec<u8>> {
+        let end = start.saturating_add(len);
+        if end > self.point_count() {
+            return Err(out_of_bounds(end.saturating_sub(1), self.point_count()).into());
+        }
+        let point_size = self.vlr.items_size() as usize;
+        let mut points = Vec::with_capacity(len as usize * point_size);
+        let mut index = start;
+        while index < end {
+            let chunk_index = self.chunk_table.chunk_index_of_point(index).unwrap();
+            self.load_chunk(chunk_index)?;
+            let chunk_points = self.chunk_table.point_range_of_chunk(chunk_index).unwrap();
+            let first_point = chunk_points.start;
+            let chunk_end = chunk_points.end.min(end);
+            let chunk = &self.chunks[&chunk_index];
+            points.extend_from_slice(
+                &chunk[(index - first_point) as usize * point_size
+                    ..(chunk_end - first_point) as usize * point_size],
+            );
+            index = chunk_end;
+        }
+        Ok(points)
+    }
+
+    /// Returns the hits & misses so far
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Returns the number of bytes of decompressed points currently in memory
+    pub fn cached_bytes(&self) -> usize {
+        self.cached_bytes
+    }
+
+    /// Removes all the chunks from the cache
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.lru.clear();
+        self.cached_bytes = 0;
+    }
+
+    pub fn vlr(&self) -> &LazVlr {
+        &self.vlr
+    }
+
+    pub fn into_inner(self) -> R {
+        self.source
+    }
+
+    /// Makes sure the chunk is in the cache, and marks it as the most recently used
+    fn load_chunk(&mut self, chunk_index: usize) -> crate::Result<()> {
+        if self.chunks.contains_key(&chunk_index) {
+            self.stats.hits += 1;
+            let lru_index = self.lru.iter().position(|i| *i == chunk_index).unwrap();
+            self.lru.remove(lru_index);
+            self.lru.push_back(chunk_index);
+            return Ok(());
+        }
+
+        self.stats.misses += 1;
+        let entry = self.chunk_table[chunk_index];
+        let bytes = self.chunk_table.byte_range_of_chunk(chunk_index).unwrap();
+        let mut compressed = vec![0u8; entry.byte_count as usize];
+        self.source
+            .seek(SeekFrom::Start(self.data_start + bytes.start))?;
+        self.source.read_exact(&mut compressed)?;
+
+        let mut points = vec![0u8; entry.point_count as usize * self.vlr.items_size() as usize];
+        let mut decompressor =
+            record_decompressor_from_laz_items(self.vlr.items(), Cursor::new(compressed))?;
+        decompressor.decompress_many(&mut points)?;
+
+        self.cached_bytes += points.len();
+        self.chunks.insert(chunk_index, points);
+        self.lru.push_back(chunk_index);
+        while self.lru.len() > 1
+            && (self.lru.len() > self.max_chunks
+                || self
+                    .max_bytes
+                    .is_some_and(|max_bytes| self.cached_bytes > max_bytes))
+        {
+            let evicted = self.lru.pop_front().unwrap();
+            let points = self.chunks.remove(&evicted).unwrap();
+            self.cached_bytes -= points.len();
+            self.stats.evictions += 1;
+        }
+        Ok(())
+    }
+}
+
+fn out_of_bounds(index: u64, point_count: u64) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!(
+            "point index {} is out of bounds, there are {} points",
+            index, point_count
+        ),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::{compress, compress_in_chunks, test_points_and_vlr};
+
+    fn compressed_points() -> (Vec<u8>, Vec<u8>, LazVlr) {
+        let (points, vlr) = test_points_and_vlr(100);
+        let data = compress(&points, &vlr);
+        (points, data, vlr)
+    }
+
+    #[test]
+    fn test_cached_reader() {
+        let (points, data, vlr) = compressed_points();
+        let point_size = vlr.items_size() as usize;
+        let point_count = (points.len() / point_size) as u64;
+
+        let mut reader = CachedLazReader::new(Cursor::new(data), vlr, point_count)
+            .unwrap()
+            .with_max_chunks(2);
+        assert_eq!(reader.point_count(), point_count);
+
+        for index in [5, 50, 150, 99, 1050, 160, 7] {
+            let start = index as usize * point_size;
+            assert_eq!(
+                reader.get_point(index).unwrap(),
+                &points[start..start + point_size]
+            );
+        }
+        // chunks 0, 0, 1, 0, 10 (evicts 1), 1 (evicts 0), 0 (evicts 10)
+        assert_eq!(
+            reader.stats(),
+            CacheStats {
+                hits: 2,
+                misses: 5,
+                evictions: 3
+            }
+        );
+        assert_eq!(reader.cached_bytes(), 200 * point_size);
+
+        let range = reader.get_range(90, 250).unwrap();
+        assert_eq!(range, points[90 * point_size..340 * point_size]);
+        let range = reader.get_range(point_count - 10, 10).unwrap();
+        assert_eq!(range, points[points.len() - 10 * point_size..]);
+
+        assert!(reader.get_point(point_count).is_err());
+        assert!(reader.get_range(point_count - 10, 11).is_err());
+    }
+
+    #[test]
+    fn test_cached_reader_max_bytes() {
+        let (points, data, vlr) = compressed_points();
+        let point_size = vlr.items_size() as usize;
+        let point_count = (points.len() / point_size) as u64;
+
+        let mut reader = CachedLazReader::new(Cursor::new(data), vlr, point_count)
+            .unwrap()
+            .with_max_bytes(250 * point_size);
+        for index in (0..point_count).step_by(100) {
+            reader.get_point(index).unwrap();
+        }
+        // The last two chunks are kept, the last one is not full
+        let last_chunk_size = (point_count % 100) as usize;
+        assert_eq!(reader.cached_bytes(), (100 + last_chunk_size) * point_size);
+        reader.get_point(0).unwrap();
+        assert_eq!(reader.stats().hits, 0);
+    }
+
+    #[test]
+    fn test_cached_reader_variable_size_chunks() {
+        let (points, vlr) = test_points_and_vlr(100);
+        let vlr = vlr.with_variable_chunk_size();
+        let point_size = vlr.items_size() as usize;
+        let point_count = (points.len() / point_size) as u64;
+        let data = compress_in_chunks(&points, &vlr, &[1, 600, 3]);
+
+        let mut reader = CachedLazReader::new(Cursor::new(data), vlr, point_count)
+            .unwrap()
+            .with_max_chunks(1);
+        // Crosses the 4 chunks, the last one being the remaining points
+        let range = reader.get_range(0, point_count).unwrap();
+        assert_eq!(range, points);
+        assert_eq!(reader.stats().misses, 4);
+        assert_eq!(
+            reader.cached_bytes(),
+            (point_count - 604) as usize * point_size
+        );
+        assert_eq!(
+            reader.get_point(601).unwrap(),
+            &points[601 * point_size..602 * point_size]
+        );
+    }
+
+    #[test]
+    fn test_cached_reader_empty() {
+        let (_, vlr) = test_points_and_vlr(100);
+        let data = compress(&[], &vlr);
+        let mut reader = CachedLazReader::new(Cursor::new(data), vlr, 0).unwrap();
+        assert_eq!(reader.point_count(), 0);
+        assert!(reader.get_point(0).is_err());
+        assert_eq!(reader.get_range(0, 0).unwrap(), Vec::<u8>::new());
+        assert_eq!(reader.stats(), CacheStats::default());
+    }
+}
@@ -1,6 +1,6 @@
 //! Module with all the things related to LAZ chunk tables
 use std::io::{Read, Seek, SeekFrom, Write};
-use std::ops::Index;
+use std::ops::{Index, Range};
 use std::slice::SliceIndex;
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
@@ -21,6 +21,7 @@ const BYTE_COUNT_CONTEXT: u32 = 1;
 /// - The number of bytes in the compressed chunk
 /// - The number of points in the compressed
 #[derive(Copy, Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ChunkTableEntry {
     pub point_count: u64,
     pub byte_count: u64,
@@ -33,8 +34,17 @@ pub struct ChunkTableEntry {
 ///
 /// - fixed-size chunks -> Only the number of bytes of the chunk is stored
 /// - variable-size chunks -> Both the number of points and the number of bytes are stored
+///
+/// The cumulative point & byte counts are kept along the entries, so that
+/// finding the chunk of a point or the position of a chunk is a binary search.
 #[derive(Default, Debug, Clone)]
-pub struct ChunkTable(Vec<ChunkTableEntry>);
+pub struct ChunkTable {
+    entries: Vec<ChunkTableEntry>,
+    /// Number of points in the chunks up to, and including, each chunk
+    point_ends: Vec<u64>,
+    /// Number of bytes of the chunks up to, and including, each chunk
+    byte_ends: Vec<u64>,
+}
 
 impl ChunkTable {
     /// Size in bytes of the offset to the chunk table.
@@ -45,7 +55,11 @@ impl ChunkTable {
 
     /// Reads the chunk table from the source
     ///
-    /// The source position **must** be at the start of the point data
+    /// The source position **must** be at the start of the point data,
+    /// that is, where the offset to the chunk table is
+    /// (the `offset_to_point_data` of the LAS header).
+    /// The other functions of this crate that read LAZ point data from a source
+    /// have the same requirement.
     ///
     /// This functions set position of the `src` where the points actually starts
     /// (that is, after the chunk table offset).
@@ -95,8 +109,9 @@ impl ChunkTable {
         let mut chunk_table = Self::read(&mut src, false)?;
         src.seek(SeekFrom::Start(data_start + 8))?;
 
-        for entry in &mut chunk_table.0 {
+        for (i, entry) in chunk_table.entries.iter_mut().enumerate() {
             entry.point_count = point_count;
+            chunk_table.point_ends[i] = (i as u64 + 1) * point_count;
         }
         Ok(chunk_table)
     }
@@ -171,7 +186,7 @@ impl ChunkTable {
                 BYTE_COUNT_CONTEXT,
             )? as u64);
 
-            chunk_table.0.push(current_entry);
+            chunk_table.push(current_entry);
             previous_entry = current_entry;
         }
         Ok(chunk_table)
@@ -192,25 +207,11 @@ impl ChunkTable {
     /// point index 75_001 falls into the last chunk, but since we only know
     /// that the chunk_size is 50_000, we cannot be sure at that point it exists.
     ///
-    /// It is the responsibility of the caller to verify / handle that case.
+    /// It is the responsibility of the caller to verify / handle that case,
+    /// or to give the total point count to [`fix_last_point_count`](Self::fix_last_point_count).
     pub(crate) fn chunk_of_point(&self, point_idx: u64) -> Option<(usize, u64)> {
-        let mut chunk_of_point = 0usize;
-        let mut start_of_chunk = 0;
-        let mut tmp_count = 0;
-        for entry in &self.0 {
-            tmp_count += entry.point_count;
-            if tmp_count > point_idx {
-                break;
-            }
-            start_of_chunk += entry.byte_count;
-            chunk_of_point += 1;
-        }
-
-        if point_idx >= tmp_count {
-            None
-        } else {
-            Some((chunk_of_point, start_of_chunk))
-        }
+        let chunk_index = self.chunk_index_of_point(point_idx)?;
+        Some((chunk_index, self.first_byte_of_chunk(chunk_index)))
     }
 
     /// Returns the position one must seek to, to reach the beginning of
@@ -224,12 +225,88 @@ impl ChunkTable {
         if chunk_index > self.len() {
             None
         } else {
-            Some(
-                self.0[0..chunk_index]
-                    .iter()
-                    .map(|entry| entry.byte_count)
-                    .sum(),
-            )
+            Some(self.first_byte_of_chunk(chunk_index))
+        }
+    }
+
+    /// Returns the index of the chunk that has the point.
+    ///
+    /// Returns `None` if the point is not in any chunk.
+    ///
+    /// For **fixed-size** chunks, the last entry of a chunk table read from a file
+    /// has `chunk_size` points, see [`fix_last_point_count`](Self::fix_last_point_count).
+    pub fn chunk_index_of_point(&self, point_index: u64) -> Option<usize> {
+        let chunk_index = self.point_ends.partition_point(|end| *end <= point_index);
+        (chunk_index < self.len()).then_some(chunk_index)
+    }
+
+    /// Returns the indices of the points of the chunk,
+    /// or `None` if the chunk does not exist.
+    pub fn point_range_of_chunk(&self, chunk_index: usize) -> Option<Range<u64>> {
+        let end = *self.point_ends.get(chunk_index)?;
+        Some(end - self.entries[chunk_index].point_count..end)
+    }
+
+    /// Returns the position of the bytes of the chunk, relative to the start of the first chunk
+    /// (i.e. after the chunk table offset), or `None` if the chunk does not exist.
+    pub fn byte_range_of_chunk(&self, chunk_index: usize) -> Option<Range<u64>> {
+        let end = *self.byte_ends.get(chunk_index)?;
+        Some(end - self.entries[chunk_index].byte_count..end)
+    }
+
+    /// Returns the number of points of all the chunks
+    pub fn point_count(&self) -> u64 {
+        self.point_ends.last().copied().unwrap_or(0)
+    }
+
+    /// Returns the number of bytes of all the chunks
+    pub fn byte_count(&self) -> u64 {
+        self.byte_ends.last().copied().unwrap_or(0)
+    }
+
+    fn first_byte_of_chunk(&self, chunk_index: usize) -> u64 {
+        match chunk_index {
+            0 => 0,
+            _ => self.byte_ends[chunk_index - 1],
+        }
+    }
+
+    /// Fixes the `point_count` of the last entry using the total number
+    /// of points stored in the chunks.
+    ///
+    /// For **fixed-size** chunks, the table read from a file gives every entry
+    /// `chunk_size` points, which is wrong for the last chunk unless
+    /// the total point count is a multiple of the chunk size.
+    ///
+    /// The table of empty data has a single chunk, whose point count can be fixed to 0.
+    ///
+    /// Returns an error if the `total_point_count` is not coherent with the table.
+    pub fn fix_last_point_count(&mut self, total_point_count: u64) -> std::io::Result<()> {
+        let Some((last, head)) = self.entries.split_last_mut() else {
+            return if total_point_count == 0 {
+                Ok(())
+            } else {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "chunk table is empty but the point count is not 0",
+                ))
+            };
+        };
+        let num_points_in_head = head.iter().map(|entry| entry.point_count).sum::<u64>();
+        match total_point_count.checked_sub(num_points_in_head) {
+            Some(count) if (count > 0 || head.is_empty()) && count <= last.point_count => {
+                last.point_count = count;
+                *self.point_ends.last_mut().unwrap() = total_point_count;
+                Ok(())
+            }
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "point count {} does not match the {} chunks of the chunk table",
+                    total_point_count,
+                    self.entries.len()
+                ),
+            )),
         }
     }
 
@@ -253,7 +330,7 @@ impl ChunkTable {
             .build_initialized();
 
         let mut previous_entry = ChunkTableEntry::default();
-        for current_entry in &self.0 {
+        for current_entry in &self.entries {
             if write_point_count {
                 compressor.compress(
                     &mut encoder,
@@ -276,38 +353,49 @@ impl ChunkTable {
     }
 
     pub fn with_capacity(capacity: usize) -> Self {
-        let vec = Vec::<ChunkTableEntry>::with_capacity(capacity);
-        Self { 0: vec }
+        Self {
+            entries: Vec::with_capacity(capacity),
+            point_ends: Vec::with_capacity(capacity),
+            byte_ends: Vec::with_capacity(capacity),
+        }
     }
 
     pub fn push(&mut self, entry: ChunkTableEntry) {
-        self.0.push(entry);
+        self.point_ends.push(self.point_count() + entry.point_count);
+        self.byte_ends.push(self.byte_count() + entry.byte_count);
+        self.entries.push(entry);
     }
 
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.entries.len()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.0.is_empty()
+        self.entries.is_empty()
     }
 
     pub fn pop(&mut self) -> Option<ChunkTableEntry> {
-        self.0.pop()
+        self.point_ends.pop();
+        self.byte_ends.pop();
+        self.entries.pop()
     }
 
     pub fn extend(&mut self, other: &ChunkTable) {
-        self.0.extend(&other.0)
+        for entry in &other.entries {
+            self.push(*entry);
+        }
     }
 
     pub fn truncate(&mut self, len: usize) {
-        self.0.truncate(len);
+        self.entries.truncate(len);
+        self.point_ends.truncate(len);
+        self.byte_ends.truncate(len);
     }
 }
 
 impl AsRef<[ChunkTableEntry]> for ChunkTable {
     fn as_ref(&self) -> &[ChunkTableEntry] {
-        &self.0
+        &self.entries
     }
 }
 
@@ -316,7 +404,7 @@ impl<'a> IntoIterator for &'a ChunkTable {
     type IntoIter = std::slice::Iter<'a, ChunkTableEntry>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.0.iter()
+        self.entries.iter()
     }
 }
 
@@ -327,7 +415,27 @@ where
     type Output = <I as SliceIndex<[ChunkTableEntry]>>::Output;
 
     fn index(&self, index: I) -> &Self::Output {
-        &self.0[index]
+        &self.entries[index]
+    }
+}
+
+/// The chunk table is serialized as the sequence of its entries
+#[cfg(feature = "serde")]
+impl serde::Serialize for ChunkTable {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(&self.entries)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ChunkTable {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entries = Vec::<ChunkTableEntry>::deserialize(deserializer)?;
+        let mut chunk_table = ChunkTable::with_capacity(entries.len());
+        for entry in entries {
+            chunk_table.push(entry);
+        }
+        Ok(chunk_table)
     }
 }
 
@@ -355,3 +463,68 @@ pub(super) fn update_chunk_table_offset<W: Write + Seek>(
     dst.seek(SeekFrom::Start(start_of_chunk_table_pos))?;
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn table(point_counts: &[u64]) -> ChunkTable {
+        let mut table = ChunkTable::default();
+        for (i, point_count) in point_counts.iter().enumerate() {
+            table.push(ChunkTableEntry {
+                point_count: *point_count,
+                byte_count: 100 + i as u64,
+            });
+        }
+        table
+    }
+
+    #[test]
+    fn test_lookups() {
+        let mut table = table(&[10, 25, 5, 60]);
+        assert_eq!(table.point_count(), 100);
+        assert_eq!(table.byte_count(), 406);
+        assert_eq!(table.chunk_index_of_point(0), Some(0));
+        assert_eq!(table.chunk_index_of_point(9), Some(0));
+        assert_eq!(table.chunk_index_of_point(10), Some(1));
+        assert_eq!(table.chunk_index_of_point(39), Some(2));
+        assert_eq!(table.chunk_index_of_point(99), Some(3));
+        assert_eq!(table.chunk_index_of_point(100), None);
+        assert_eq!(table.point_range_of_chunk(2), Some(35..40));
+        assert_eq!(table.byte_range_of_chunk(2), Some(201..303));
+        assert_eq!(table.point_range_of_chunk(4), None);
+        assert_eq!(table.chunk_of_point(39), Some((2, 201)));
+        assert_eq!(table.chunk_position(4), Some(406));
+        assert_eq!(table.chunk_position(5), None);
+
+        table.pop();
+        table.extend(&self::table(&[1, 2]));
+        assert_eq!(table.point_count(), 43);
+        assert_eq!(table.byte_range_of_chunk(4), Some(403..504));
+        table.truncate(1);
+        assert_eq!(table.chunk_index_of_point(10), None);
+    }
+
+    #[test]
+    fn test_fix_last_point_count() {
+        // Fixed-size chunk table as read from a file
+        let mut table = table(&[50, 50, 50]);
+        assert_eq!(table.chunk_index_of_point(140), Some(2));
+        assert!(table.fix_last_point_count(101).is_ok());
+        assert_eq!(table.point_count(), 101);
+        assert_eq!(table.chunk_index_of_point(100), Some(2));
+        assert_eq!(table.chunk_index_of_point(101), None);
+        assert!(table.fix_last_point_count(200).is_err());
+        assert!(ChunkTable::default().fix_last_point_count(0).is_ok());
+    }
+
+    #[test]
+    fn test_fix_last_point_count_of_empty_data() {
+        // Empty data has one empty chunk
+        let mut empty = table(&[50]);
+        assert!(empty.fix_last_point_count(0).is_ok());
+        assert_eq!(empty.point_count(), 0);
+        assert_eq!(empty.chunk_index_of_point(0), None);
+        assert!(table(&[50, 50]).fix_last_point_count(50).is_err());
+    }
+}
@@ -0,0 +1,428 @@
+use crate::laszip::{LazVlr, PointLayout};
+
+/// Rules deciding when a chunk is closed, when compressing
+/// **variable-size** chunks.
+///
+/// A new chunk is started before the point that would break one of the rules.
+/// With no rules set, all the points go in the same chunk.
+///
+/// See `LasZipCompressor::set_chunking_policy` and `ParLasZipCompressor::set_chunking_policy`.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct ChunkingPolicy {
+    max_points: Option<u64>,
+    max_compressed_bytes: Option<u64>,
+    max_extent: Option<u32>,
+    max_gps_time_gap: Option<f64>,
+    split_on_point_source_id: bool,
+    split_on_scanner_channel: bool,
+}
+
+impl ChunkingPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Chunks have at most `max_points` points
+    pub fn with_max_points(mut self, max_points: u64) -> Self {
+        self.max_points = Some(max_points.max(1));
+        self
+    }
+
+    /// Chunks are closed when their compressed size would exceed `max_bytes`.
+    ///
+    /// The compressed size of the points is not known until the chunk is done,
+    /// so it is estimated from the compression ratio of the chunks written
+    /// before the current call to `compress_one` / `compress_many`
+    /// (one fourth of the uncompressed size until the first chunk is written),
+    /// the limit is a target rather than a guarantee.
+    ///
+    /// As the estimate only changes between calls, the sequential and parallel
+    /// compressors split the points in the same chunks when given the same calls.
+    pub fn with_max_compressed_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_compressed_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Chunks are closed when the extent of their points along X or Y
+    /// would exceed `max_extent`.
+    ///
+    /// The extent is in the **integer** coordinates of the point records,
+    /// that is, before applying the scale & offset of the LAS header.
+    pub fn with_max_extent(mut self, max_extent: u32) -> Self {
+        self.max_extent = Some(max_extent);
+        self
+    }
+
+    /// Chunks are closed when the gps time of two consecutive points
+    /// differ by more than `max_gap`
+    pub fn with_max_gps_time_gap(mut self, max_gap: f64) -> Self {
+        self.max_gps_time_gap = Some(max_gap);
+        self
+    }
+
+    /// Chunks are closed when the point source id changes
+    pub fn with_point_source_id_changes(mut self) -> Self {
+        self.split_on_point_source_id = true;
+        self
+    }
+
+    /// Chunks are closed when the scanner channel changes (point formats >= 6)
+    pub fn with_scanner_channel_changes(mut self) -> Self {
+        self.split_on_scanner_channel = true;
+        self
+    }
+}
+
+/// Follows the points of the current chunk to tell where the
+/// [`ChunkingPolicy`] closes chunks
+#[derive(Debug, Clone)]
+pub(crate) struct ChunkSplitter {
+    policy: ChunkingPolicy,
+    layout: PointLayout,
+    point_size: usize,
+    /// Number of points in the current chunk
+    point_count: u64,
+    min: [i32; 2],
+    max: [i32; 2],
+    last_gps_time: f64,
+    last_point_source_id: u16,
+    last_scanner_channel: u8,
+    /// Number of points & compressed bytes of the chunks written before the current batch,
+    /// used to estimate the compressed size of the current chunk
+    num_points_written: u64,
+    num_bytes_written: u64,
+    /// Number of points & compressed bytes of the chunks written during the current batch
+    num_points_pending: u64,
+    num_bytes_pending: u64,
+}
+
+impl ChunkSplitter {
+    pub(crate) fn new(policy: ChunkingPolicy, vlr: &LazVlr) -> std::io::Result<Self> {
+        if !vlr.uses_variable_size_chunks() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "A chunking policy requires variable-size chunks",
+            ));
+        }
+        Ok(Self {
+            policy,
+            layout: PointLayout::from_vlr(vlr),
+            point_size: vlr.items_size() as usize,
+            point_count: 0,
+            min: [i32::MAX; 2],
+            max: [i32::MIN; 2],
+            last_gps_time: 0.0,
+            last_point_source_id: 0,
+            last_scanner_channel: 0,
+            num_points_written: 0,
+            num_bytes_written: 0,
+            num_points_pending: 0,
+            num_bytes_pending: 0,
+        })
+    }
+
+    /// Returns whether the current chunk must be closed before adding the point
+    pub(crate) fn closes_chunk_before(&self, point: &[u8]) -> bool {
+        if self.point_count == 0 {
+            return false;
+        }
+        let policy = &self.policy;
+        if policy
+            .max_points
+            .is_some_and(|max_points| self.point_count >= max_points)
+        {
+            return true;
+        }
+        if let Some(max_bytes) = policy.max_compressed_bytes {
+            let bytes_per_point = if self.num_points_written == 0 {
+                self.point_size as f64 / 4.0
+            } else {
+                self.num_bytes_written as f64 / self.num_points_written as f64
+            };
+            if (self.point_count + 1) as f64 * bytes_per_point > max_bytes as f64 {
+                return true;
+            }
+        }
+        if let Some(max_extent) = policy.max_extent {
+            for axis in 0..2 {
+                let value = coordinate(point, axis);
+                let min = i64::from(self.min[axis].min(value));
+                let max = i64::from(self.max[axis].max(value));
+                if max - min > i64::from(max_extent) {
+                    return true;
+                }
+            }
+        }
+        if let (Some(max_gap), Some(offset)) =
+            (policy.max_gps_time_gap, self.layout.gps_time_offset())
+        {
+            if (gps_time(point, offset) - self.last_gps_time).abs() > max_gap {
+                return true;
+            }
+        }
+        if policy.split_on_point_source_id
+            && self.point_source_id(point) != self.last_point_source_id
+        {
+            return true;
+        }
+        if policy.split_on_scanner_channel
+            && self.scanner_channel(point) != self.last_scanner_channel
+        {
+            return true;
+        }
+        false
+    }
+
+    /// Adds the point to the current chunk
+    pub(crate) fn add_point(&mut self, point: &[u8]) {
+        for axis in 0..2 {
+            let value = coordinate(point, axis);
+            self.min[axis] = self.min[axis].min(value);
+            self.max[axis] = self.max[axis].max(value);
+        }
+        if let Some(offset) = self.layout.gps_time_offset() {
+            self.last_gps_time = gps_time(point, offset);
+        }
+        self.last_point_source_id = self.point_source_id(point);
+        self.last_scanner_channel = self.scanner_channel(point);
+        self.point_count += 1;
+    }
+
+    /// Starts a new, empty, chunk
+    pub(crate) fn reset(&mut self) {
+        self.point_count = 0;
+        self.min = [i32::MAX; 2];
+        self.max = [i32::MIN; 2];
+    }
+
+    /// Records the size of a chunk that was written,
+    /// it is taken into account by the estimate once the next batch starts
+    pub(crate) fn record_chunk(&mut self, point_count: u64, byte_count: u64) {
+        self.num_points_pending += point_count;
+        self.num_bytes_pending += byte_count;
+    }
+
+    /// Starts a new batch of points (a call to `compress_one` / `compress_many`)
+    pub(crate) fn start_batch(&mut self) {
+        self.num_points_written += std::mem::take(&mut self.num_points_pending);
+        self.num_bytes_written += std::mem::take(&mut self.num_bytes_pending);
+    }
+
+    fn point_source_id(&self, point: &[u8]) -> u16 {
+        let offset = if self.layout.is_extended() { 20 } else { 18 };
+        u16::from_le_bytes([point[offset], point[offset + 1]])
+    }
+
+    fn scanner_channel(&self, point: &[u8]) -> u8 {
+        if self.layout.is_extended() {
+            (point[15] >> 4) & 0x3
+        } else {
+            0
+        }
+    }
+}
+
+fn coordinate(point: &[u8], axis: usize) -> i32 {
+    i32::from_le_bytes(point[4 * axis..4 * (axis + 1)].try_into().unwrap())
+}
+
+fn gps_time(point: &[u8], offset: usize) -> f64 {
+    f64::from_le_bytes(point[offset..offset + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::laszip::ChunkTable;
+    use crate::test_support::compress;
+    use crate::{LasZipCompressor, LasZipDecompressor, LazItemRecordBuilder, LazVlrBuilder};
+
+    /// Three flight lines of 500 points, the second one having a gap in gps time
+    fn points(point_format_id: u8, point_size: usize) -> Vec<u8> {
+        let mut points = vec![0u8; 1500 * point_size];
+        for (i, point) in points.chunks_exact_mut(point_size).enumerate() {
+            let line = i / 500;
+            point[0..4].copy_from_slice(&(i as i32 % 500).to_le_bytes());
+            point[4..8].copy_from_slice(&(line as i32 * 1000).to_le_bytes());
+            let mut gps_time = i as f64;
+            if i >= 750 {
+                gps_time += 100.0;
+            }
+            if point_format_id >= 6 {
+                point[14] = 0x11;
+                point[20..22].copy_from_slice(&(line as u16).to_le_bytes());
+                point[22..30].copy_from_slice(&gps_time.to_le_bytes());
+            } else {
+                point[14] = 0x09;
+                point[18..20].copy_from_slice(&(line as u16).to_le_bytes());
+                point[20..28].copy_from_slice(&gps_time.to_le_bytes());
+            }
+        }
+        points
+    }
+
+    fn chunk_point_counts(data: &[u8], vlr: &LazVlr) -> Vec<u64> {
+        let table = ChunkTable::read_from(Cursor::new(data), vlr).unwrap();
+        table
+            .as_ref()
+            .iter()
+            .map(|entry| entry.point_count)
+            .collect()
+    }
+
+    fn check_policy(point_format_id: u8, policy: ChunkingPolicy, expected: &[u64]) {
+        let items = LazItemRecordBuilder::default_for_point_format_id(point_format_id, 0).unwrap();
+        let vlr = LazVlrBuilder::new(items).with_variable_chunk_size().build();
+        let point_size = vlr.items_size() as usize;
+        let points = points(point_format_id, point_size);
+
+        let mut compressor = LasZipCompressor::new(Cursor::new(vec![]), vlr.clone()).unwrap();
+        compressor.set_chunking_policy(policy).unwrap();
+        compressor.compress_many(&points).unwrap();
+        compressor.done().unwrap();
+        let data = compressor.into_inner().into_inner();
+        assert_eq!(chunk_point_counts(&data, &vlr), expected, "{:?}", policy);
+
+        let mut decompressor = LasZipDecompressor::new(Cursor::new(&data), vlr.clone()).unwrap();
+        let mut output = vec![0u8; points.len()];
+        decompressor.decompress_many(&mut output).unwrap();
+        assert_eq!(output, points);
+
+        #[cfg(feature = "parallel")]
+        {
+            let mut compressor =
+                crate::ParLasZipCompressor::new(Cursor::new(vec![]), vlr.clone()).unwrap();
+            compressor.set_chunking_policy(policy).unwrap();
+            for batch in points.chunks(333 * point_size) {
+                compressor.compress_many(batch).unwrap();
+            }
+            compressor.done().unwrap();
+            let par_data = compressor.into_inner().into_inner();
+            assert_eq!(par_data, data);
+        }
+    }
+
+    #[test]
+    fn test_chunking_policies() {
+        for point_format_id in [1, 6] {
+            check_policy(
+                point_format_id,
+                ChunkingPolicy::new().with_max_points(400),
+                &[400, 400, 400, 300],
+            );
+            check_policy(
+                point_format_id,
+                ChunkingPolicy::new().with_point_source_id_changes(),
+                &[500, 500, 500],
+            );
+            check_policy(
+                point_format_id,
+                ChunkingPolicy::new().with_max_gps_time_gap(10.0),
+                &[750, 750],
+            );
+            check_policy(
+                point_format_id,
+                ChunkingPolicy::new()
+                    .with_max_gps_time_gap(10.0)
+                    .with_max_extent(500),
+                &[500, 250, 250, 500],
+            );
+            check_policy(
+                point_format_id,
+                ChunkingPolicy::new()
+                    .with_max_points(300)
+                    .with_point_source_id_changes(),
+                &[300, 200, 300, 200, 300, 200],
+            );
+        }
+    }
+
+    #[test]
+    fn test_max_compressed_bytes() {
+        let items = LazItemRecordBuilder::default_for_point_format_id(1, 0).unwrap();
+        let vlr = LazVlrBuilder::new(items).with_variable_chunk_size().build();
+        let point_size = vlr.items_size() as usize;
+        let points = points(1, point_size);
+        let policy = ChunkingPolicy::new().with_max_compressed_bytes(1000);
+
+        // The estimate is updated between calls, so give the points in batches
+        let mut compressor = LasZipCompressor::new(Cursor::new(vec![]), vlr.clone()).unwrap();
+        compressor.set_chunking_policy(policy).unwrap();
+        for batch in points.chunks(50 * point_size) {
+            compressor.compress_many(batch).unwrap();
+        }
+        compressor.done().unwrap();
+        let data = compressor.into_inner().into_inner();
+        let table = ChunkTable::read_from(Cursor::new(&data), &vlr).unwrap();
+        assert!(table.len() > 1);
+        // Once the compression ratio is known, chunks stay close to the limit
+        for entry in &table.as_ref()[1..table.len() - 1] {
+            assert!(entry.byte_count <= 1100, "{:?}", entry);
+        }
+
+        #[cfg(feature = "parallel")]
+        {
+            let mut compressor =
+                crate::ParLasZipCompressor::new(Cursor::new(vec![]), vlr.clone()).unwrap();
+            compressor.set_chunking_policy(policy).unwrap();
+            for batch in points.chunks(50 * point_size) {
+                compressor.compress_many(batch).unwrap();
+            }
+            compressor.done().unwrap();
+            let par_data = compressor.into_inner().into_inner();
+            assert_eq!(par_data, data);
+        }
+
+        let fixed_vlr = LazVlrBuilder::new(vlr.items().clone())
+            .with_fixed_chunk_size(100)
+            .build();
+        let mut compressor = LasZipCompressor::new(Cursor::new(vec![]), fixed_vlr).unwrap();
+        assert!(compressor
+            .set_chunking_policy(ChunkingPolicy::new())
+            .is_err());
+    }
+
+    #[test]
+    fn test_chunking_edge_cases() {
+        // A chunk is not closed when there is no point left to put in the next one
+        check_policy(
+            1,
+            ChunkingPolicy::new().with_max_points(500),
+            &[500, 500, 500],
+        );
+        check_policy(1, ChunkingPolicy::new().with_max_points(1499), &[1499, 1]);
+
+        let items = LazItemRecordBuilder::default_for_point_format_id(1, 0).unwrap();
+        let vlr = LazVlrBuilder::new(items).with_variable_chunk_size().build();
+        let point_size = vlr.items_size() as usize;
+        let points = points(1, point_size);
+
+        // Chunks finished manually restart the count of the policy
+        let mut compressor = LasZipCompressor::new(Cursor::new(vec![]), vlr.clone()).unwrap();
+        compressor
+            .set_chunking_policy(ChunkingPolicy::new().with_max_points(400))
+            .unwrap();
+        compressor
+            .compress_many(&points[..100 * point_size])
+            .unwrap();
+        compressor.finish_current_chunk().unwrap();
+        compressor
+            .compress_many(&points[100 * point_size..])
+            .unwrap();
+        compressor.done().unwrap();
+        let data = compressor.into_inner().into_inner();
+        assert_eq!(chunk_point_counts(&data, &vlr), [100, 400, 400, 400, 200]);
+
+        // Empty
+        let mut compressor = LasZipCompressor::new(Cursor::new(vec![]), vlr.clone()).unwrap();
+        compressor
+            .set_chunking_policy(ChunkingPolicy::new().with_max_points(400))
+            .unwrap();
+        compressor.compress_many(&[]).unwrap();
+        compressor.done().unwrap();
+        let data = compressor.into_inner().into_inner();
+        assert_eq!(data, compress(&[], &vlr));
+    }
+}
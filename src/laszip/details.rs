@@ -6,7 +6,7 @@ use crate::record::{
 };
 use crate::{LasZipError, LazItem};
 
-pub(super) fn record_decompressor_from_laz_items<'a, R: Read + Seek + Send + Sync + 'a>(
+pub(crate) fn record_decompressor_from_laz_items<'a, R: Read + Seek + Send + Sync + 'a>(
     items: &Vec<LazItem>,
     input: R,
 ) -> crate::Result<Box<dyn RecordDecompressor<R> + Send + Sync + 'a>> {
@@ -35,7 +35,7 @@ pub(super) fn record_decompressor_from_laz_items<'a, R: Read + Seek + Send + Syn
     Ok(decompressor)
 }
 
-pub(super) fn record_compressor_from_laz_items<'a, W: Write + Send + Sync + 'a>(
+pub(crate) fn record_compressor_from_laz_items<'a, W: Write + Send + Sync + 'a>(
     items: &Vec<LazItem>,
     output: W,
 ) -> crate::Result<Box<dyn RecordCompressor<W> + Send + Sync + 'a>> {
@@ -0,0 +1,354 @@
+//! Extraction of a subset of LAZ data with as little recompression as possible
+//!
+//! Chunks that are entirely part of the requested subset are copied verbatim,
+//! only the chunks at the boundaries of a point range are decompressed and
+//! recompressed.
+//!
+//! The extracted data always uses **variable-size** chunks.
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use crate::laszip::chunk_table::{update_chunk_table_offset, ChunkTable, ChunkTableEntry};
+use crate::laszip::details::{
+    record_compressor_from_laz_items, record_decompressor_from_laz_items,
+};
+use crate::laszip::{CompressorType, LazVlr};
+use crate::LasZipError;
+
+/// Information about LAZ data that resulted from an extraction
+#[derive(Debug, Clone)]
+pub struct ExtractedLaz {
+    /// The vlr to be written in the header of the new file,
+    /// it is the vlr of the source, with **variable-size** chunks.
+    pub vlr: LazVlr,
+    /// The chunk table that was written
+    pub chunk_table: ChunkTable,
+    /// Total number of points extracted
+    pub point_count: u64,
+}
+
+/// Source of the chunks to extract, with its chunk table
+/// where all entries have their exact point count.
+struct ChunkSource<R> {
+    source: R,
+    vlr: LazVlr,
+    chunk_table: ChunkTable,
+    /// Position of the first chunk
+    data_start: u64,
+}
+
+impl<R: Read + Seek> ChunkSource<R> {
+    fn new(mut source: R, vlr: &LazVlr, point_count: u64) -> crate::Result<Self> {
+        if vlr.compressor != CompressorType::PointWiseChunked
+            && vlr.compressor != CompressorType::LayeredChunked
+        {
+            return Err(LasZipError::UnsupportedCompressorType(vlr.compressor));
+        }
+        let mut chunk_table = ChunkTable::read_from(&mut source, vlr)?;
+        if !vlr.uses_variable_size_chunks() {
+            chunk_table.fix_last_point_count(point_count)?;
+        }
+        let data_start = source.stream_position()?;
+        Ok(Self {
+            source,
+            vlr: vlr.clone(),
+            chunk_table,
+            data_start,
+        })
+    }
+
+    fn seek_to_chunk(&mut self, chunk_index: usize) -> std::io::Result<()> {
+        let position = self
+            .chunk_table
+            .chunk_position(chunk_index)
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("chunk {} does not exist", chunk_index),
+                )
+            })?;
+        self.source
+            .seek(SeekFrom::Start(self.data_start + position))?;
+        Ok(())
+    }
+
+    /// Copies the chunk as is into the `dst`
+    fn copy_chunk<W: Write>(&mut self, chunk_index: usize, dst: &mut W) -> std::io::Result<()> {
+        self.seek_to_chunk(chunk_index)?;
+        let byte_count = self.chunk_table[chunk_index].byte_count;
+        let num_copied = std::io::copy(&mut (&mut self.source).take(byte_count), dst)?;
+        if num_copied != byte_count {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "failed to copy the compressed chunk",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Decompresses the points `range` (relative to the chunk start)
+    /// of the chunk and compresses them to form a new chunk.
+    fn recompress_chunk_part<W: Write>(
+        &mut self,
+        chunk_index: usize,
+        range: Range<u64>,
+        dst: &mut W,
+    ) -> crate::Result<ChunkTableEntry> {
+        self.seek_to_chunk(chunk_index)?;
+        let entry = self.chunk_table[chunk_index];
+        let mut compressed = vec![0u8; entry.byte_count as usize];
+        self.source.read_exact(&mut compressed)?;
+
+        let point_size = self.vlr.items_size() as usize;
+        let mut points = vec![0u8; range.end as usize * point_size];
+        let mut decompressor =
+            record_decompressor_from_laz_items(self.vlr.items(), Cursor::new(compressed))?;
+        decompressor.decompress_many(&mut points)?;
+
+        let mut recompressed = Cursor::new(Vec::<u8>::new());
+        {
+            let mut compressor =
+                record_compressor_from_laz_items(self.vlr.items(), &mut recompressed)?;
+            compressor.compress_many(&points[range.start as usize * point_size..])?;
+            compressor.done()?;
+        }
+        let recompressed = recompressed.into_inner();
+        dst.write_all(&recompressed)?;
+        Ok(ChunkTableEntry {
+            point_count: range.end - range.start,
+            byte_count: recompressed.len() as u64,
+        })
+    }
+}
+
+/// Writes the chunk table offset, calls `write_chunks` and then writes the chunk table.
+fn write_extracted_data<W, F>(
+    mut dst: W,
+    vlr: &LazVlr,
+    write_chunks: F,
+) -> crate::Result<ExtractedLaz>
+where
+    W: Write + Seek,
+    F: FnOnce(&mut W) -> crate::Result<ChunkTable>,
+{
+    let start_pos = dst.stream_position()?;
+    // Reserve the bytes for the chunk table offset that will be updated later
+    dst.write_i64::<LittleEndian>(-1)?;
+
+    let chunk_table = write_chunks(&mut dst)?;
+
+    let vlr = vlr.clone().with_variable_chunk_size();
+    update_chunk_table_offset(&mut dst, SeekFrom::Start(start_pos))?;
+    chunk_table.write_to(&mut dst, &vlr)?;
+
+    let point_count = chunk_table
+        .as_ref()
+        .iter()
+        .map(|entry| entry.point_count)
+        .sum();
+    Ok(ExtractedLaz {
+        vlr,
+        chunk_table,
+        point_count,
+    })
+}
+
+/// Extracts the points in the `range` of indices into the `dst`
+/// as new LAZ point data.
+///
+/// Chunks entirely contained in the `range` are copied without recompression,
+/// the (at most two) chunks at the boundaries are recompressed.
+///
+/// `source` must be positioned as for [`ChunkTable::read_from`](crate::laszip::ChunkTable::read_from),
+/// `point_count` is the number of points in the source.
+///
+/// The data written is standard LAZ point data, the LAS header
+/// is not written, the caller should use the returned [`ExtractedLaz`] to do it.
+pub fn extract_points<R, W>(
+    source: R,
+    vlr: &LazVlr,
+    point_count: u64,
+    range: Range<u64>,
+    dst: W,
+) -> crate::Result<ExtractedLaz>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    if range.end > point_count || range.start > range.end {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "range {:?} is not valid for data with {} points",
+                range, point_count
+            ),
+        )
+        .into());
+    }
+    let mut chunk_source = ChunkSource::new(source, vlr, point_count)?;
+
+    write_extracted_data(dst, vlr, |dst| {
+        let mut chunk_table = ChunkTable::default();
+        let mut chunk_start = 0u64;
+        for chunk_index in 0..chunk_source.chunk_table.len() {
+            let entry = chunk_source.chunk_table[chunk_index];
+            let chunk_end = chunk_start + entry.point_count;
+            let start = range.start.max(chunk_start);
+            let end = range.end.min(chunk_end);
+            if start < end {
+                if start == chunk_start && end == chunk_end {
+                    chunk_source.copy_chunk(chunk_index, dst)?;
+                    chunk_table.push(entry);
+                } else {
+                    let new_entry = chunk_source.recompress_chunk_part(
+                        chunk_index,
+                        (start - chunk_start)..(end - chunk_start),
+                        dst,
+                    )?;
+                    chunk_table.push(new_entry);
+                }
+            }
+            chunk_start = chunk_end;
+        }
+        Ok(chunk_table)
+    })
+}
+
+/// Extracts the chunks which indices are given by `chunk_indices`
+/// into the `dst` as new LAZ point data.
+///
+/// Chunks are copied in the order they are given, without any recompression.
+///
+/// `source` must be positioned as for [`ChunkTable::read_from`](crate::laszip::ChunkTable::read_from),
+/// `point_count` is the number of points in the source.
+///
+/// The data written is standard LAZ point data, the LAS header
+/// is not written, the caller should use the returned [`ExtractedLaz`] to do it.
+pub fn extract_chunks<R, W, I>(
+    source: R,
+    vlr: &LazVlr,
+    point_count: u64,
+    chunk_indices: I,
+    dst: W,
+) -> crate::Result<ExtractedLaz>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+    I: IntoIterator<Item = usize>,
+{
+    let mut chunk_source = ChunkSource::new(source, vlr, point_count)?;
+
+    write_extracted_data(dst, vlr, |dst| {
+        let mut chunk_table = ChunkTable::default();
+        for chunk_index in chunk_indices {
+            if chunk_index >= chunk_source.chunk_table.len() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("chunk {} does not exist", chunk_index),
+                )
+                .into());
+            }
+            let entry = chunk_source.chunk_table[chunk_index];
+            if entry.point_count == 0 {
+                continue;
+            }
+            chunk_source.copy_chunk(chunk_index, dst)?;
+            chunk_table.push(entry);
+        }
+        Ok(chunk_table)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::{compress, layered_points, read_test_points, with_custom_header};
+    use crate::{LasZipDecompressor, LazItem, LazItemRecordBuilder, LazVlrBuilder};
+
+    const CHUNK_SIZE: usize = 100;
+
+    fn compressed_test_data() -> (Vec<u8>, usize, LazVlr, Cursor<Vec<u8>>) {
+        let (points, items) = read_test_points();
+        compressed_data(points, items)
+    }
+
+    fn compressed_layered_data() -> (Vec<u8>, usize, LazVlr, Cursor<Vec<u8>>) {
+        let items = LazItemRecordBuilder::default_for_point_format_id(6, 0).unwrap();
+        compressed_data(layered_points(1065), items)
+    }
+
+    fn compressed_data(
+        points: Vec<u8>,
+        items: Vec<LazItem>,
+    ) -> (Vec<u8>, usize, LazVlr, Cursor<Vec<u8>>) {
+        let vlr = with_custom_header(
+            &LazVlrBuilder::new(items)
+                .with_fixed_chunk_size(CHUNK_SIZE as u32)
+                .build(),
+        );
+        let compressed = Cursor::new(compress(&points, &vlr));
+        (points, vlr.items_size() as usize, vlr, compressed)
+    }
+
+    fn decompress(
+        mut data: Cursor<Vec<u8>>,
+        extracted: ExtractedLaz,
+        point_size: usize,
+    ) -> Vec<u8> {
+        data.set_position(0);
+        let mut decompressor = LasZipDecompressor::new(data, extracted.vlr).unwrap();
+        let mut points = vec![0u8; extracted.point_count as usize * point_size];
+        decompressor.decompress_many(&mut points).unwrap();
+        points
+    }
+
+    #[test]
+    fn test_extract_points() {
+        // The layered data also goes through the recompression of the boundary chunks
+        for (points, point_size, vlr, compressed) in
+            [compressed_test_data(), compressed_layered_data()]
+        {
+            let point_count = (points.len() / point_size) as u64;
+
+            for range in [150..730, 0..point_count, 1000..point_count, 10..20, 5..5] {
+                let mut output = Cursor::new(vec![]);
+                let extracted = extract_points(
+                    compressed.clone(),
+                    &vlr,
+                    point_count,
+                    range.clone(),
+                    &mut output,
+                )
+                .unwrap();
+                assert_eq!(extracted.point_count, range.end - range.start);
+                // The vlr is kept, apart from its chunk size
+                assert_eq!(extracted.vlr, vlr.clone().with_variable_chunk_size());
+                let extracted_points = decompress(output, extracted, point_size);
+                assert_eq!(
+                    extracted_points,
+                    &points[range.start as usize * point_size..range.end as usize * point_size]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_extract_chunks() {
+        let (points, point_size, vlr, compressed) = compressed_test_data();
+        let point_count = (points.len() / point_size) as u64;
+        let chunk_size_in_bytes = CHUNK_SIZE * point_size;
+
+        let mut output = Cursor::new(vec![]);
+        let extracted =
+            extract_chunks(compressed, &vlr, point_count, [2, 10, 5], &mut output).unwrap();
+        let extracted_points = decompress(output, extracted, point_size);
+
+        let mut expected = vec![];
+        expected.extend_from_slice(&points[2 * chunk_size_in_bytes..3 * chunk_size_in_bytes]);
+        expected.extend_from_slice(&points[10 * chunk_size_in_bytes..]);
+        expected.extend_from_slice(&points[5 * chunk_size_in_bytes..6 * chunk_size_in_bytes]);
+        assert_eq!(extracted_points, expected);
+    }
+}
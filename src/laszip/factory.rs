@@ -0,0 +1,321 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::las::selective::{DecompressionSelection, ExtraBytesSelection};
+use crate::laszip::chunk_table::ChunkTable;
+use crate::laszip::{CompressorType, LasZipDecompressor, LazDecompressorWithState, LazVlr};
+use crate::LasZipError;
+
+/// Which decompressor [`open_decompressor`] creates
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum DecompressorBackend {
+    /// Picks the backend from the point count, the thread budget and the compressor type
+    #[default]
+    Auto,
+    /// Always uses a [`LasZipDecompressor`]
+    Sequential,
+    /// Always uses a `ParLasZipDecompressor` (requires the `parallel` feature)
+    Parallel,
+}
+
+/// Options of [`open_decompressor`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DecompressorOptions {
+    backend: DecompressorBackend,
+    selection: DecompressionSelection,
+    extra_bytes_selection: ExtraBytesSelection,
+    point_count: Option<u64>,
+    num_threads: Option<usize>,
+}
+
+impl Default for DecompressorOptions {
+    fn default() -> Self {
+        Self {
+            backend: DecompressorBackend::Auto,
+            selection: DecompressionSelection::all(),
+            extra_bytes_selection: ExtraBytesSelection::none(),
+            point_count: None,
+            num_threads: None,
+        }
+    }
+}
+
+impl DecompressorOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the backend to use, [`DecompressorBackend::Auto`] by default
+    pub fn with_backend(mut self, backend: DecompressorBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Sets the fields to decompress, all fields by default
+    pub fn with_selection(mut self, selection: DecompressionSelection) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    /// Sets the extra bytes to decompress in addition to the ones of the selection,
+    /// none by default
+    pub fn with_extra_bytes_selection(mut self, selection: ExtraBytesSelection) -> Self {
+        self.extra_bytes_selection = selection;
+        self
+    }
+
+    /// Sets the number of points (as found in the LAS header)
+    ///
+    /// It is used to choose the backend and is given to
+    /// [`LazDecompressorWithState::set_point_count`] when the chunk table is available.
+    pub fn with_point_count(mut self, point_count: u64) -> Self {
+        self.point_count = Some(point_count);
+        self
+    }
+
+    /// Sets the number of threads the decompressor may use,
+    /// by default the number of threads of rayon's global thread pool.
+    ///
+    /// With 1 thread, the sequential backend is always picked.
+    pub fn with_num_threads(mut self, num_threads: usize) -> Self {
+        self.num_threads = Some(num_threads);
+        self
+    }
+
+    /// Returns whether the parallel backend is worth it for the data,
+    /// that is, whether there are at least two chunks per thread
+    fn prefers_parallel(&self, vlr: &LazVlr, chunk_table: &ChunkTable) -> bool {
+        #[cfg(feature = "parallel")]
+        let num_threads = self.num_threads.unwrap_or_else(rayon::current_num_threads);
+        #[cfg(not(feature = "parallel"))]
+        let num_threads = self.num_threads.unwrap_or(1);
+        if num_threads <= 1 {
+            return false;
+        }
+        let num_chunks = match self.point_count {
+            Some(point_count) if !vlr.uses_variable_size_chunks() => {
+                point_count.div_ceil(u64::from(vlr.chunk_size().max(1))) as usize
+            }
+            _ => chunk_table.len(),
+        };
+        num_chunks >= 2 * num_threads
+    }
+}
+
+/// Creates a decompressor, picking at runtime between the sequential
+/// [`LasZipDecompressor`] and the parallel `ParLasZipDecompressor`.
+///
+/// The `source` must be positioned as for [`ChunkTable::read_from`](crate::laszip::ChunkTable::read_from).
+///
+/// With [`DecompressorBackend::Auto`], the parallel backend is used when the
+/// `parallel` feature is enabled, the data is chunked, the chunk table can be read,
+/// and there are at least two chunks for each thread of the budget.
+///
+/// # Example
+///
+/// ```no_run
+/// # fn main() -> laz::Result<()> {
+/// use laz::{open_decompressor, DecompressorOptions};
+/// use laz::las::file::read_header_and_vlrs;
+///
+/// let mut file = std::io::BufReader::new(std::fs::File::open("file.laz")?);
+/// let (header, vlr) = read_header_and_vlrs(&mut file)?;
+/// let vlr = vlr.expect("no laszip vlr");
+/// let options = DecompressorOptions::new().with_point_count(header.num_points);
+/// let mut decompressor = open_decompressor(file, vlr, options)?;
+/// let mut points = vec![0u8; header.num_points as usize * header.point_size as usize];
+/// decompressor.decompress_many(&mut points)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn open_decompressor<'a, R: Read + Seek + Send + Sync + 'a>(
+    mut source: R,
+    vlr: LazVlr,
+    options: DecompressorOptions,
+) -> crate::Result<Box<dyn LazDecompressorWithState + Send + Sync + 'a>> {
+    if vlr.compressor_type() == CompressorType::PointWise {
+        if options.backend == DecompressorBackend::Parallel {
+            return Err(LasZipError::UnsupportedCompressorType(
+                vlr.compressor_type(),
+            ));
+        }
+        let mut decompressor = LasZipDecompressor::selective(source, vlr, options.selection)?;
+        decompressor.set_extra_bytes_selection(options.extra_bytes_selection);
+        if let Some(point_count) = options.point_count {
+            decompressor.set_point_count(point_count)?;
+        }
+        return Ok(Box::new(decompressor));
+    }
+
+    let start = source.stream_position()?;
+    let chunk_table = ChunkTable::read_from(&mut source, &vlr).ok();
+    source.seek(SeekFrom::Start(start))?;
+
+    let Some(chunk_table) = chunk_table else {
+        if options.backend == DecompressorBackend::Parallel {
+            return Err(LasZipError::MissingChunkTable);
+        }
+        // The sequential decompressor may not need the chunk table
+        let mut decompressor = LasZipDecompressor::selective(source, vlr, options.selection)?;
+        decompressor.set_extra_bytes_selection(options.extra_bytes_selection);
+        if let Some(point_count) = options.point_count {
+            decompressor.set_point_count(point_count)?;
+        }
+        return Ok(Box::new(decompressor));
+    };
+
+    let use_parallel = match options.backend {
+        DecompressorBackend::Auto => {
+            cfg!(feature = "parallel") && options.prefers_parallel(&vlr, &chunk_table)
+        }
+        DecompressorBackend::Sequential => false,
+        DecompressorBackend::Parallel => true,
+    };
+
+    let mut decompressor: Box<dyn LazDecompressorWithState + Send + Sync + 'a> = if use_parallel {
+        new_parallel(source, vlr, chunk_table, options.extra_bytes_selection)?
+    } else {
+        let mut decompressor = LasZipDecompressor::with_chunk_table(source, vlr, chunk_table)?;
+        decompressor.set_extra_bytes_selection(options.extra_bytes_selection);
+        Box::new(decompressor)
+    };
+    decompressor.set_selection(options.selection);
+    if let Some(point_count) = options.point_count {
+        decompressor.set_point_count(point_count)?;
+    }
+    Ok(decompressor)
+}
+
+#[cfg(feature = "parallel")]
+fn new_parallel<'a, R: Read + Seek + Send + Sync + 'a>(
+    source: R,
+    vlr: LazVlr,
+    chunk_table: ChunkTable,
+    extra_bytes_selection: ExtraBytesSelection,
+) -> crate::Result<Box<dyn LazDecompressorWithState + Send + Sync + 'a>> {
+    let mut decompressor =
+        crate::ParLasZipDecompressor::with_chunk_table(source, vlr, chunk_table)?;
+    decompressor.set_extra_bytes_selection(extra_bytes_selection);
+    Ok(Box::new(decompressor))
+}
+
+#[cfg(not(feature = "parallel"))]
+fn new_parallel<'a, R: Read + Seek + Send + Sync + 'a>(
+    _source: R,
+    _vlr: LazVlr,
+    _chunk_table: ChunkTable,
+    _extra_bytes_selection: ExtraBytesSelection,
+) -> crate::Result<Box<dyn LazDecompressorWithState + Send + Sync + 'a>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "the parallel decompressor requires the 'parallel' feature",
+    )
+    .into())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::test_support::{compress_in_chunks, read_test_points};
+    use crate::LazVlrBuilder;
+
+    fn points_and_vlr(variable_size: bool) -> (Vec<u8>, LazVlr) {
+        let (points, items) = read_test_points();
+        let builder = LazVlrBuilder::new(items);
+        let vlr = if variable_size {
+            builder.with_variable_chunk_size().build()
+        } else {
+            builder.with_fixed_chunk_size(100).build()
+        };
+        (points, vlr)
+    }
+
+    fn compress(points: &[u8], vlr: &LazVlr) -> Vec<u8> {
+        compress_in_chunks(points, vlr, &[100; 10])
+    }
+
+    #[test]
+    fn test_backends_are_interchangeable() {
+        let mut backends = vec![DecompressorBackend::Auto, DecompressorBackend::Sequential];
+        if cfg!(feature = "parallel") {
+            backends.push(DecompressorBackend::Parallel);
+        }
+        for variable_size in [false, true] {
+            let (points, vlr) = points_and_vlr(variable_size);
+            let point_size = vlr.items_size() as usize;
+            let point_count = (points.len() / point_size) as u64;
+            let data = compress(&points, &vlr);
+
+            for backend in backends.iter().copied() {
+                let options = DecompressorOptions::new()
+                    .with_backend(backend)
+                    .with_point_count(point_count);
+                let mut decompressor =
+                    open_decompressor(Cursor::new(data.as_slice()), vlr.clone(), options).unwrap();
+                assert_eq!(decompressor.vlr(), &vlr);
+                assert_eq!(decompressor.point_count(), Some(point_count));
+                assert_eq!(decompressor.selection(), DecompressionSelection::all());
+
+                let mut decompressed = vec![0u8; 250 * point_size];
+                decompressor.decompress_many(&mut decompressed).unwrap();
+                assert_eq!(decompressed, points[..250 * point_size]);
+                assert_eq!(decompressor.position(), 250);
+
+                decompressor.seek(1000).unwrap();
+                assert_eq!(decompressor.position(), 1000);
+                let mut decompressed = vec![0u8; 65 * point_size];
+                decompressor.decompress_many(&mut decompressed).unwrap();
+                assert_eq!(decompressed, points[1000 * point_size..]);
+                assert_eq!(decompressor.position(), point_count);
+            }
+        }
+    }
+
+    #[test]
+    fn test_auto_backend_choice() {
+        let (points, vlr) = points_and_vlr(false);
+        let chunk_table = {
+            let data = compress(&points, &vlr);
+            ChunkTable::read_from(Cursor::new(data), &vlr).unwrap()
+        };
+        // 11 chunks
+        let options = DecompressorOptions::new().with_point_count(1065);
+        assert!(!options
+            .with_num_threads(1)
+            .prefers_parallel(&vlr, &chunk_table));
+        assert!(options
+            .with_num_threads(4)
+            .prefers_parallel(&vlr, &chunk_table));
+        assert!(!options
+            .with_num_threads(6)
+            .prefers_parallel(&vlr, &chunk_table));
+    }
+
+    #[test]
+    fn test_without_chunk_table() {
+        let (points, vlr) = points_and_vlr(false);
+        let mut data = compress(&points, &vlr);
+        // Remove the chunk table, as if the writer could not write it
+        let chunk_table = ChunkTable::read_from(Cursor::new(data.as_slice()), &vlr).unwrap();
+        data.truncate(ChunkTable::OFFSET_SIZE + chunk_table.byte_count() as usize);
+        data[..8].copy_from_slice(&(-1i64).to_le_bytes());
+
+        let options = DecompressorOptions::new().with_backend(DecompressorBackend::Parallel);
+        assert!(matches!(
+            open_decompressor(Cursor::new(data.as_slice()), vlr.clone(), options),
+            Err(LasZipError::MissingChunkTable)
+        ));
+
+        let mut decompressor = open_decompressor(
+            Cursor::new(data.as_slice()),
+            vlr,
+            DecompressorOptions::new().with_num_threads(8),
+        )
+        .unwrap();
+        assert_eq!(decompressor.point_count(), None);
+        let mut decompressed = vec![0u8; points.len()];
+        decompressor.decompress_many(&mut decompressed).unwrap();
+        assert_eq!(decompressed, points);
+    }
+}
@@ -0,0 +1,531 @@
+use std::io::{Cursor, Read, Seek};
+
+use crate::las::selective::{DecompressionSelection, ExtraBytesSelection};
+use crate::laszip::details::record_decompressor_from_laz_items;
+use crate::laszip::{CompressorType, LazItemType, LazVlr};
+
+/// Filter on the points, used by the `decompress_filtered` functions
+///
+/// All the conditions that are set must be true for a point to pass the filter,
+/// a filter with no conditions lets every point pass.
+///
+/// # Cost
+///
+/// For layered data (point formats >= 6), a chunk is first decoded with only
+/// the layers needed to evaluate the filter, and its other layers are not decoded
+/// when none of its points pass the filter.
+///
+/// When at least one point passes, the other layers are decoded in a second pass.
+/// The layered decoders go through the points of a chunk once, and each layer needs
+/// the XY layer (x, y, return number, number of returns and scanner channel) to be
+/// decoded along with it, so the XY layer is decoded in both passes.
+/// The layers of the filter are only decoded in the first pass.
+/// A chunk with matching points thus costs one more decoding of its XY layer
+/// than a plain decompression.
+///
+/// Point-wise data (point formats < 6) stores all the fields of a point together,
+/// its chunks are fully decoded before the filter is evaluated.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PointFilter {
+    bounds: Option<([i32; 3], [i32; 3])>,
+    classifications: Option<Vec<u8>>,
+    return_numbers: Option<Vec<u8>>,
+    gps_time_range: Option<(f64, f64)>,
+}
+
+impl PointFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps the points inside the bounds (limits included)
+    ///
+    /// The bounds are in the **integer** coordinates of the point records,
+    /// that is, before applying the scale & offset of the LAS header.
+    pub fn with_bounds(mut self, min: [i32; 3], max: [i32; 3]) -> Self {
+        self.bounds = Some((min, max));
+        self
+    }
+
+    /// Keeps the points which classification is in the list
+    pub fn with_classifications(mut self, classifications: &[u8]) -> Self {
+        self.classifications = Some(classifications.to_vec());
+        self
+    }
+
+    /// Keeps the points which return number is in the list
+    pub fn with_return_numbers(mut self, return_numbers: &[u8]) -> Self {
+        self.return_numbers = Some(return_numbers.to_vec());
+        self
+    }
+
+    /// Keeps the points which gps time is in the range (limits included)
+    pub fn with_gps_time_range(mut self, min: f64, max: f64) -> Self {
+        self.gps_time_range = Some((min, max));
+        self
+    }
+
+    /// Returns the fields that must be decompressed to evaluate the filter
+    pub fn selection(&self) -> DecompressionSelection {
+        let mut selection = DecompressionSelection::base();
+        if self.bounds.is_some() {
+            selection = selection.decompress_z();
+        }
+        if self.classifications.is_some() {
+            selection = selection.decompress_classification();
+        }
+        if self.gps_time_range.is_some() {
+            selection = selection.decompress_gps_time();
+        }
+        selection
+    }
+
+    /// Returns whether the point passes the filter
+    pub fn matches(&self, point: &[u8], layout: &PointLayout) -> bool {
+        if let Some((min, max)) = &self.bounds {
+            for i in 0..3 {
+                let value = i32::from_le_bytes(point[4 * i..4 * (i + 1)].try_into().unwrap());
+                if value < min[i] || value > max[i] {
+                    return false;
+                }
+            }
+        }
+        if let Some(classifications) = &self.classifications {
+            let classification = if layout.is_extended {
+                point[16]
+            } else {
+                point[15] & 0x1F
+            };
+            if !classifications.contains(&classification) {
+                return false;
+            }
+        }
+        if let Some(return_numbers) = &self.return_numbers {
+            let return_number = if layout.is_extended {
+                point[14] & 0xF
+            } else {
+                point[14] & 0x7
+            };
+            if !return_numbers.contains(&return_number) {
+                return false;
+            }
+        }
+        if let Some((min, max)) = self.gps_time_range {
+            let Some(offset) = layout.gps_time_offset else {
+                return false;
+            };
+            let gps_time = f64::from_le_bytes(point[offset..offset + 8].try_into().unwrap());
+            if gps_time < min || gps_time > max {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Appends the `points` that pass the filter to `out`, returns the number appended
+    fn append_matching(
+        &self,
+        points: &[u8],
+        point_size: usize,
+        layout: &PointLayout,
+        out: &mut Vec<u8>,
+    ) -> u64 {
+        let mut num_kept = 0;
+        for point in points.chunks_exact(point_size) {
+            if self.matches(point, layout) {
+                out.extend_from_slice(point);
+                num_kept += 1;
+            }
+        }
+        num_kept
+    }
+
+    /// Removes from the `selection` the layers that are decoded to evaluate the filter
+    fn skip_filter_fields(&self, selection: DecompressionSelection) -> DecompressionSelection {
+        let mut selection = selection;
+        if self.bounds.is_some() {
+            selection = selection.skip_z();
+        }
+        if self.classifications.is_some() {
+            selection = selection.skip_classification();
+        }
+        if self.gps_time_range.is_some() {
+            selection = selection.skip_gps_time();
+        }
+        selection
+    }
+
+    /// Copies the fields decoded to evaluate the filter from `src` to `dst`,
+    /// both being points of the layout of point formats >= 6
+    fn copy_filter_fields(&self, src: &[u8], dst: &mut [u8], layout: &PointLayout) {
+        if self.bounds.is_some() {
+            dst[8..12].copy_from_slice(&src[8..12]);
+        }
+        if self.classifications.is_some() {
+            dst[16] = src[16];
+        }
+        if let (Some(_), Some(offset)) = (self.gps_time_range, layout.gps_time_offset) {
+            dst[offset..offset + 8].copy_from_slice(&src[offset..offset + 8]);
+        }
+    }
+}
+
+/// Where the fields used by a [`PointFilter`] are in a point record
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PointLayout {
+    /// Whether the point uses the layout of point formats >= 6
+    is_extended: bool,
+    gps_time_offset: Option<usize>,
+}
+
+impl PointLayout {
+    pub fn from_vlr(vlr: &LazVlr) -> Self {
+        let mut layout = Self {
+            is_extended: false,
+            gps_time_offset: None,
+        };
+        let mut offset = 0usize;
+        for item in vlr.items() {
+            match item.item_type() {
+                LazItemType::Point14 => {
+                    layout.is_extended = true;
+                    layout.gps_time_offset = Some(offset + 22);
+                }
+                LazItemType::GpsTime => layout.gps_time_offset = Some(offset),
+                _ => {}
+            }
+            offset += usize::from(item.size());
+        }
+        layout
+    }
+
+    /// Whether the point uses the layout of point formats >= 6
+    pub(crate) fn is_extended(&self) -> bool {
+        self.is_extended
+    }
+
+    /// Offset of the gps time in the point record, if the points have one
+    pub(crate) fn gps_time_offset(&self) -> Option<usize> {
+        self.gps_time_offset
+    }
+}
+
+/// Decompresses the `point_count` points of the chunk and appends
+/// the ones that pass the filter to `out`, returns the number of points appended.
+pub(crate) fn decompress_chunk_filtered(
+    chunk: &[u8],
+    point_count: usize,
+    vlr: &LazVlr,
+    filter: &PointFilter,
+    selection: DecompressionSelection,
+    extra_bytes: ExtraBytesSelection,
+    out: &mut Vec<u8>,
+) -> crate::Result<u64> {
+    decompress_chunk_filtered_from(
+        || Cursor::new(chunk),
+        point_count,
+        vlr,
+        filter,
+        selection,
+        extra_bytes,
+        out,
+    )
+}
+
+/// Same as [`decompress_chunk_filtered`], `open` is called to get a new
+/// reader of the chunk each time the chunk has to be decoded
+fn decompress_chunk_filtered_from<'a, R, F>(
+    open: F,
+    point_count: usize,
+    vlr: &LazVlr,
+    filter: &PointFilter,
+    selection: DecompressionSelection,
+    extra_bytes: ExtraBytesSelection,
+    out: &mut Vec<u8>,
+) -> crate::Result<u64>
+where
+    R: Read + Seek + Send + Sync + 'a,
+    F: Fn() -> R,
+{
+    let layout = PointLayout::from_vlr(vlr);
+    let point_size = vlr.items_size() as usize;
+    let mut points = vec![0u8; point_count * point_size];
+
+    if vlr.compressor_type() != CompressorType::LayeredChunked {
+        // Point-wise chunks store all the fields of a point together,
+        // so they are always fully decoded
+        let mut decompressor = record_decompressor_from_laz_items(vlr.items(), open())?;
+        decompressor.decompress_many(&mut points)?;
+        return Ok(filter.append_matching(&points, point_size, &layout, out));
+    }
+
+    let mut filter_points = vec![0u8; point_count * point_size];
+    let mut decompressor = record_decompressor_from_laz_items(vlr.items(), open())?;
+    decompressor.set_selection(filter.selection());
+    decompressor.decompress_many(&mut filter_points)?;
+    if !filter_points
+        .chunks_exact(point_size)
+        .any(|point| filter.matches(point, &layout))
+    {
+        return Ok(0);
+    }
+
+    // The layered decoders go through the points once, decoding all the selected layers
+    // of a point with the help of the XY layer, and the layers not selected are skipped.
+    // So the remaining layers need a second pass, which decodes the XY layer again
+    // but not the layers already decoded for the filter.
+    let remaining = filter.skip_filter_fields(selection);
+    let mut decompressor = record_decompressor_from_laz_items(vlr.items(), open())?;
+    decompressor.set_selection(remaining);
+    decompressor.set_extra_bytes_selection(extra_bytes);
+    decompressor.decompress_many(&mut points)?;
+    for (point, filter_point) in points
+        .chunks_exact_mut(point_size)
+        .zip(filter_points.chunks_exact(point_size))
+    {
+        filter.copy_filter_fields(filter_point, point, &layout);
+    }
+    Ok(filter.append_matching(&points, point_size, &layout, out))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::laszip::{ChunkTable, LayerKind, LayeredChunkLayout};
+    use crate::test_support::compress_in_chunks;
+    use crate::{LasZipDecompressor, LazItemRecordBuilder, LazVlrBuilder};
+
+    fn points(point_format_id: u8, count: usize) -> Vec<u8> {
+        let point_size = if point_format_id >= 6 { 30 } else { 28 };
+        let mut points = vec![0u8; count * point_size];
+        for (i, point) in points.chunks_exact_mut(point_size).enumerate() {
+            let i = i as i32;
+            point[0..4].copy_from_slice(&(i * 7 % 1000).to_le_bytes());
+            point[4..8].copy_from_slice(&(i * 13 % 500).to_le_bytes());
+            point[8..12].copy_from_slice(&(i % 50).to_le_bytes());
+            point[12..14].copy_from_slice(&(i as u16).to_le_bytes());
+            let return_number = (i % 3 + 1) as u8;
+            let classification = if i < 250 { 2 } else { (i % 7 + 3) as u8 };
+            if point_format_id >= 6 {
+                point[14] = return_number | (3 << 4);
+                point[16] = classification;
+                point[22..30].copy_from_slice(&(f64::from(i) * 0.25).to_le_bytes());
+            } else {
+                point[14] = return_number | (3 << 3);
+                point[15] = classification;
+                point[20..28].copy_from_slice(&(f64::from(i) * 0.25).to_le_bytes());
+            }
+        }
+        points
+    }
+
+    fn filters() -> Vec<PointFilter> {
+        vec![
+            PointFilter::new(),
+            PointFilter::new().with_classifications(&[6]),
+            PointFilter::new().with_classifications(&[1]),
+            PointFilter::new().with_return_numbers(&[2]),
+            PointFilter::new().with_bounds([0, 0, 10], [500, 250, 20]),
+            PointFilter::new().with_gps_time_range(10.0, 70.0),
+            PointFilter::new()
+                .with_classifications(&[1, 2])
+                .with_gps_time_range(0.0, 100.0),
+        ]
+    }
+
+    fn check_filtered(point_format_id: u8, vlr: LazVlr, chunk_sizes: &[usize]) {
+        let points = points(point_format_id, 1000);
+        let point_size = vlr.items_size() as usize;
+        let layout = PointLayout::from_vlr(&vlr);
+        let data = compress_in_chunks(&points, &vlr, chunk_sizes);
+
+        for filter in filters() {
+            let expected = points
+                .chunks_exact(point_size)
+                .filter(|point| filter.matches(point, &layout))
+                .flatten()
+                .copied()
+                .collect::<Vec<u8>>();
+
+            // Calls that start & end in the middle of chunks
+            let mut decompressor =
+                LasZipDecompressor::new(Cursor::new(data.as_slice()), vlr.clone()).unwrap();
+            let mut output = vec![];
+            let mut num_kept = 0;
+            for num_points in [130, 370, 500] {
+                num_kept += decompressor
+                    .decompress_filtered(num_points, &filter, &mut output)
+                    .unwrap();
+            }
+            assert_eq!(num_kept as usize * point_size, output.len());
+            assert_eq!(output, expected);
+
+            #[cfg(feature = "parallel")]
+            {
+                let mut decompressor =
+                    crate::ParLasZipDecompressor::new(Cursor::new(data.as_slice()), vlr.clone())
+                        .unwrap();
+                let mut output = vec![];
+                for num_points in [130, 370, 500] {
+                    decompressor
+                        .decompress_filtered(num_points, &filter, &mut output)
+                        .unwrap();
+                }
+                assert_eq!(output, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decompress_filtered_layered() {
+        let items = LazItemRecordBuilder::default_for_point_format_id(6, 0).unwrap();
+        let vlr = LazVlrBuilder::new(items.clone())
+            .with_variable_chunk_size()
+            .build();
+        check_filtered(6, vlr, &[100, 150, 250, 300, 200]);
+
+        let vlr = LazVlrBuilder::new(items).with_fixed_chunk_size(120).build();
+        check_filtered(6, vlr, &[]);
+    }
+
+    #[test]
+    fn test_decompress_filtered_point_wise() {
+        let items = LazItemRecordBuilder::default_for_point_format_id(1, 0).unwrap();
+        let vlr = LazVlrBuilder::new(items.clone())
+            .with_variable_chunk_size()
+            .build();
+        check_filtered(1, vlr, &[100, 150, 250, 300, 200]);
+
+        let vlr = LazVlrBuilder::new(items).with_fixed_chunk_size(120).build();
+        check_filtered(1, vlr, &[]);
+    }
+
+    /// Reader of a chunk that counts the bytes read,
+    /// the layers that are not decoded are seeked over
+    struct CountingReader<'a> {
+        inner: Cursor<&'a [u8]>,
+        num_read: &'a AtomicUsize,
+    }
+
+    impl Read for CountingReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.num_read.fetch_add(n, Ordering::Relaxed);
+            Ok(n)
+        }
+    }
+
+    impl Seek for CountingReader<'_> {
+        fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_filtered_layered_chunk_decodes_fewer_layers() {
+        let items = LazItemRecordBuilder::default_for_point_format_id(6, 0).unwrap();
+        let vlr = LazVlrBuilder::new(items).with_variable_chunk_size().build();
+        let points = points(6, 1000);
+        let data = compress_in_chunks(&points, &vlr, &[250, 250, 500]);
+        let chunk_table = ChunkTable::read_from(Cursor::new(data.as_slice()), &vlr).unwrap();
+        // The second chunk, where the classification varies
+        let start = ChunkTable::OFFSET_SIZE + chunk_table[0].byte_count as usize;
+        let chunk = &data[start..start + chunk_table[1].byte_count as usize];
+        let chunk_layout = LayeredChunkLayout::read_from(chunk, vlr.items()).unwrap();
+        let layer_size = |kind| chunk_layout.layer(kind).unwrap().size;
+        assert!(layer_size(LayerKind::Classification) > 0);
+
+        let num_read = AtomicUsize::new(0);
+        let open = || CountingReader {
+            inner: Cursor::new(chunk),
+            num_read: &num_read,
+        };
+
+        let mut decompressor = record_decompressor_from_laz_items(vlr.items(), open()).unwrap();
+        let mut decompressed = vec![0u8; 250 * 30];
+        decompressor.decompress_many(&mut decompressed).unwrap();
+        let num_read_plain = num_read.swap(0, Ordering::Relaxed);
+
+        // No point of the chunk passes, only the XY & classification layers are decoded
+        let mut out = vec![];
+        let filter = PointFilter::new().with_classifications(&[1]);
+        let count = decompress_chunk_filtered_from(
+            open,
+            250,
+            &vlr,
+            &filter,
+            DecompressionSelection::all(),
+            ExtraBytesSelection::none(),
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(count, 0);
+        let num_read_pruned = num_read.swap(0, Ordering::Relaxed);
+        assert!(num_read_pruned < num_read_plain);
+        assert_eq!(
+            num_read_plain - num_read_pruned,
+            chunk_layout
+                .layers()
+                .iter()
+                .filter(|layer| {
+                    !matches!(
+                        layer.kind,
+                        LayerKind::XyReturnsChannel | LayerKind::Classification
+                    )
+                })
+                .map(|layer| layer.size)
+                .sum::<usize>()
+        );
+
+        // Some points pass, the classification layer is not decoded a second time
+        let filter = PointFilter::new().with_classifications(&[3]);
+        let count = decompress_chunk_filtered_from(
+            open,
+            250,
+            &vlr,
+            &filter,
+            DecompressionSelection::all(),
+            ExtraBytesSelection::none(),
+            &mut out,
+        )
+        .unwrap();
+        let layout = PointLayout::from_vlr(&vlr);
+        let expected = points[250 * 30..500 * 30]
+            .chunks_exact(30)
+            .filter(|point| filter.matches(point, &layout))
+            .flatten()
+            .copied()
+            .collect::<Vec<u8>>();
+        assert!(count > 0);
+        assert_eq!(out, expected);
+        assert_eq!(
+            num_read.load(Ordering::Relaxed),
+            num_read_plain + num_read_pruned - layer_size(LayerKind::Classification)
+        );
+    }
+
+    #[test]
+    fn test_filtered_mixed_with_decompress_many() {
+        let items = LazItemRecordBuilder::default_for_point_format_id(6, 0).unwrap();
+        let vlr = LazVlrBuilder::new(items).with_variable_chunk_size().build();
+        let points = points(6, 1000);
+        let data = compress_in_chunks(&points, &vlr, &[100, 150, 250, 300, 200]);
+        let filter = PointFilter::new().with_classifications(&[2]);
+
+        let mut decompressor = LasZipDecompressor::new(Cursor::new(data.as_slice()), vlr).unwrap();
+        let mut output = vec![];
+        // Only the first 250 points have the classification 2
+        assert_eq!(
+            decompressor
+                .decompress_filtered(400, &filter, &mut output)
+                .unwrap(),
+            250
+        );
+        assert_eq!(output, points[..250 * 30]);
+        let mut rest = vec![0u8; 600 * 30];
+        decompressor.decompress_many(&mut rest).unwrap();
+        assert_eq!(rest, points[400 * 30..]);
+    }
+}
@@ -0,0 +1,388 @@
+//! Layer-level access to **layered** chunks (point formats >= 6)
+//!
+//! With [`CompressorType::LayeredChunked`], each field of the points
+//! is compressed in its own layer, which means that a field can be
+//! re-encoded without touching the others.
+//!
+//! A layered chunk is organized like this:
+//!  1) the first point, uncompressed
+//!  2) the number of points in the chunk (u32)
+//!  3) the size in bytes of each layer (u32)
+//!  4) the layers
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+
+use crate::las::point6::ClassificationLayerEncoder;
+use crate::laszip::chunk_table::{update_chunk_table_offset, ChunkTable, ChunkTableEntry};
+use crate::laszip::details::record_decompressor_from_laz_items;
+use crate::laszip::{CompressorType, LazItem, LazItemType, LazVlr};
+use crate::{DecompressionSelection, LasZipError};
+
+/// Position of the classification in a Point14
+const CLASSIFICATION_OFFSET: usize = 16;
+
+/// The field stored in a layer
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum LayerKind {
+    /// x, y, return number, number of returns and scanner channel
+    XyReturnsChannel,
+    Z,
+    Classification,
+    /// classification flags, scan direction & edge of flight line
+    Flags,
+    Intensity,
+    ScanAngle,
+    UserData,
+    PointSourceId,
+    GpsTime,
+    Rgb,
+    Nir,
+    Wavepacket,
+    /// The extra byte at the given index
+    ExtraBytes(u16),
+}
+
+impl LayerKind {
+    /// Returns the layers, in order, of chunks compressed with the `items`
+    pub(super) fn layers_of(items: &[LazItem]) -> crate::Result<Vec<Self>> {
+        let mut kinds = Vec::new();
+        for item in items {
+            if item.version != 3 && item.version != 4 {
+                return Err(LasZipError::UnsupportedLazItemVersion(
+                    item.item_type,
+                    item.version,
+                ));
+            }
+            match item.item_type {
+                LazItemType::Point14 => kinds.extend_from_slice(&[
+                    LayerKind::XyReturnsChannel,
+                    LayerKind::Z,
+                    LayerKind::Classification,
+                    LayerKind::Flags,
+                    LayerKind::Intensity,
+                    LayerKind::ScanAngle,
+                    LayerKind::UserData,
+                    LayerKind::PointSourceId,
+                    LayerKind::GpsTime,
+                ]),
+                LazItemType::RGB14 => kinds.push(LayerKind::Rgb),
+                LazItemType::RGBNIR14 => kinds.extend_from_slice(&[LayerKind::Rgb, LayerKind::Nir]),
+                LazItemType::WavePacket14 => kinds.push(LayerKind::Wavepacket),
+                LazItemType::Byte14(n) => kinds.extend((0..n).map(LayerKind::ExtraBytes)),
+                _ => {
+                    return Err(LasZipError::UnsupportedLazItemVersion(
+                        item.item_type,
+                        item.version,
+                    ))
+                }
+            }
+        }
+        Ok(kinds)
+    }
+}
+
+/// Location of a layer in a chunk
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Layer {
+    pub kind: LayerKind,
+    /// Position of the layer's bytes, relative to the start of the chunk
+    pub offset: usize,
+    /// Size of the layer in bytes.
+    ///
+    /// A size of 0 means that the field had the same value
+    /// for all the points of the chunk.
+    pub size: usize,
+    /// Position of the layer's size, relative to the start of the chunk
+    size_offset: usize,
+}
+
+/// Layout of a **layered** chunk
+#[derive(Debug, Clone)]
+pub struct LayeredChunkLayout {
+    point_count: u32,
+    layers: Vec<Layer>,
+}
+
+impl LayeredChunkLayout {
+    /// Reads the layout of the `chunk`, which must contain the bytes
+    /// of a whole chunk compressed with the `items`.
+    pub fn read_from(chunk: &[u8], items: &[LazItem]) -> crate::Result<Self> {
+        let kinds = LayerKind::layers_of(items)?;
+        let record_size = items.iter().map(|item| item.size as usize).sum::<usize>();
+
+        let invalid_chunk = || -> LasZipError {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "chunk is too small for its layers",
+            )
+            .into()
+        };
+
+        let sizes_start = record_size + 4;
+        let data_start = sizes_start + 4 * kinds.len();
+        if chunk.len() < data_start {
+            return Err(invalid_chunk());
+        }
+        let point_count = LittleEndian::read_u32(&chunk[record_size..sizes_start]);
+
+        let mut layers = Vec::with_capacity(kinds.len());
+        let mut offset = data_start;
+        for (i, kind) in kinds.into_iter().enumerate() {
+            let size_offset = sizes_start + 4 * i;
+            let size = LittleEndian::read_u32(&chunk[size_offset..size_offset + 4]) as usize;
+            layers.push(Layer {
+                kind,
+                offset,
+                size,
+                size_offset,
+            });
+            offset += size;
+        }
+        if offset > chunk.len() {
+            return Err(invalid_chunk());
+        }
+
+        Ok(Self {
+            point_count,
+            layers,
+        })
+    }
+
+    /// Number of points in the chunk (including the uncompressed first point)
+    pub fn point_count(&self) -> u32 {
+        self.point_count
+    }
+
+    /// All the layers of the chunk, in the order they are stored
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    /// Returns the layer of the given kind, if the chunk has one
+    pub fn layer(&self, kind: LayerKind) -> Option<&Layer> {
+        self.layers.iter().find(|layer| layer.kind == kind)
+    }
+}
+
+/// Edits the classification of the points of a single **layered** `chunk`
+/// and returns the new chunk.
+///
+/// The chunk is decompressed (using the `selection`, the classification is always
+/// decompressed) and `edit` is called on each point, it returns the new classification
+/// of the point.
+///
+/// Only the classification layer is re-encoded, all the other layers are copied as is.
+///
+/// # Important
+///
+/// Only the fields in the `selection`, the classification, x, y,
+/// return number, number of returns and scanner channel have valid values
+/// in the point given to `edit`.
+pub fn edit_chunk_classification<F>(
+    chunk: &[u8],
+    items: &Vec<LazItem>,
+    selection: DecompressionSelection,
+    mut edit: F,
+) -> crate::Result<Vec<u8>>
+where
+    F: FnMut(&[u8]) -> u8,
+{
+    if chunk.is_empty() {
+        // Chunks without points have no bytes
+        return Ok(vec![]);
+    }
+    match items.first() {
+        Some(item) if item.item_type == LazItemType::Point14 => {}
+        Some(item) => {
+            return Err(LasZipError::UnsupportedLazItemVersion(
+                item.item_type,
+                item.version,
+            ))
+        }
+        None => return Ok(chunk.to_vec()),
+    }
+    let layout = LayeredChunkLayout::read_from(chunk, items)?;
+    let classification_layer = *layout
+        .layer(LayerKind::Classification)
+        .expect("Point14 chunks always have a classification layer");
+
+    let mut decompressor = record_decompressor_from_laz_items(items, Cursor::new(chunk))?;
+    decompressor.set_selection(selection.decompress_classification());
+
+    let mut point = vec![0u8; decompressor.record_size()];
+    decompressor.decompress_next(&mut point)?;
+    point[CLASSIFICATION_OFFSET] = edit(&point);
+    let first_classification = point[CLASSIFICATION_OFFSET];
+    let mut encoder = ClassificationLayerEncoder::new(&point);
+    for _ in 1..layout.point_count() {
+        decompressor.decompress_next(&mut point)?;
+        point[CLASSIFICATION_OFFSET] = edit(&point);
+        encoder.encode_next(&point)?;
+    }
+    let new_layer = encoder.into_layer()?;
+
+    let mut new_chunk = Vec::with_capacity(chunk.len());
+    new_chunk.extend_from_slice(&chunk[..classification_layer.offset]);
+    new_chunk[CLASSIFICATION_OFFSET] = first_classification;
+    LittleEndian::write_u32(
+        &mut new_chunk[classification_layer.size_offset..classification_layer.size_offset + 4],
+        new_layer.len() as u32,
+    );
+    new_chunk.extend_from_slice(&new_layer);
+    new_chunk.extend_from_slice(&chunk[classification_layer.offset + classification_layer.size..]);
+    Ok(new_chunk)
+}
+
+/// Edits the classification of all the points of the `source` and writes
+/// the resulting LAZ point data to the `dst`.
+///
+/// See [`edit_chunk_classification`] for how each chunk is edited.
+///
+/// `source` must be positioned as for [`ChunkTable::read_from`](crate::laszip::ChunkTable::read_from) and its vlr must use
+/// [`CompressorType::LayeredChunked`].
+///
+/// The same `vlr` must be used to read the data written,
+/// the returned [`ChunkTable`] is the one that was written to the `dst`.
+pub fn edit_classification<R, W, F>(
+    mut source: R,
+    vlr: &LazVlr,
+    mut dst: W,
+    selection: DecompressionSelection,
+    mut edit: F,
+) -> crate::Result<ChunkTable>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+    F: FnMut(&[u8]) -> u8,
+{
+    if vlr.compressor != CompressorType::LayeredChunked {
+        return Err(LasZipError::UnsupportedCompressorType(vlr.compressor));
+    }
+    let chunk_table = ChunkTable::read_from(&mut source, vlr)?;
+
+    let start_pos = dst.stream_position()?;
+    // Reserve the bytes for the chunk table offset that will be updated later
+    dst.write_i64::<LittleEndian>(-1)?;
+
+    let mut new_chunk_table = ChunkTable::with_capacity(chunk_table.len());
+    let mut chunk = Vec::<u8>::new();
+    for entry in &chunk_table {
+        chunk.resize(entry.byte_count as usize, 0u8);
+        source.read_exact(&mut chunk)?;
+        let new_chunk = edit_chunk_classification(&chunk, vlr.items(), selection, &mut edit)?;
+        dst.write_all(&new_chunk)?;
+        new_chunk_table.push(ChunkTableEntry {
+            point_count: entry.point_count,
+            byte_count: new_chunk.len() as u64,
+        });
+    }
+
+    update_chunk_table_offset(&mut dst, SeekFrom::Start(start_pos))?;
+    new_chunk_table.write_to(&mut dst, vlr)?;
+    Ok(new_chunk_table)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{LasZipCompressor, LasZipDecompressor, LazItemRecordBuilder, LazVlrBuilder};
+
+    const POINT_SIZE: usize = 30;
+
+    /// Points with two scanner channels and a few classes
+    fn make_points(count: usize) -> Vec<u8> {
+        let mut points = vec![0u8; count * POINT_SIZE];
+        for (i, point) in points.chunks_exact_mut(POINT_SIZE).enumerate() {
+            LittleEndian::write_i32(&mut point[0..4], (i * 7) as i32);
+            LittleEndian::write_i32(&mut point[4..8], (i * 3 % 101) as i32);
+            LittleEndian::write_i32(&mut point[8..12], (i % 13) as i32);
+            LittleEndian::write_u16(&mut point[12..14], (i % 300) as u16);
+            let number_of_returns = (i % 3 + 1) as u8;
+            let return_number = (i % number_of_returns as usize + 1) as u8;
+            point[14] = return_number | number_of_returns << 4;
+            point[15] = (((i / 50) % 2) as u8) << 4;
+            point[16] = (i % 5) as u8 + 1;
+            point[17] = (i % 2) as u8;
+            LittleEndian::write_f64(&mut point[22..30], i as f64 * 0.5);
+        }
+        points
+    }
+
+    #[test]
+    fn test_edit_classification() {
+        let points = make_points(1_000);
+        let vlr =
+            LazVlrBuilder::new(LazItemRecordBuilder::default_for_point_format_id(6, 0).unwrap())
+                .with_fixed_chunk_size(300)
+                .build();
+        let mut compressor = LasZipCompressor::new(Cursor::new(vec![]), vlr.clone()).unwrap();
+        compressor.compress_many(&points).unwrap();
+        compressor.done().unwrap();
+        let mut compressed = compressor.into_inner();
+        compressed.set_position(0);
+
+        let reclassify = |point: &[u8]| {
+            if point[16] == 2 && point[8] < 5 {
+                9
+            } else {
+                point[16]
+            }
+        };
+        let mut edited = Cursor::new(vec![]);
+        edit_classification(
+            compressed,
+            &vlr,
+            &mut edited,
+            DecompressionSelection::base().decompress_z(),
+            reclassify,
+        )
+        .unwrap();
+
+        let mut expected = points.clone();
+        for point in expected.chunks_exact_mut(POINT_SIZE) {
+            point[16] = reclassify(point);
+        }
+        edited.set_position(0);
+        let mut decompressor = LasZipDecompressor::new(edited, vlr).unwrap();
+        let mut decompressed = vec![0u8; points.len()];
+        decompressor.decompress_many(&mut decompressed).unwrap();
+        assert_eq!(decompressed, expected);
+    }
+
+    #[test]
+    fn test_edit_to_constant_classification() {
+        let points = make_points(150);
+        let vlr =
+            LazVlrBuilder::new(LazItemRecordBuilder::default_for_point_format_id(6, 0).unwrap())
+                .build();
+        let mut compressor = LasZipCompressor::new(Cursor::new(vec![]), vlr.clone()).unwrap();
+        compressor.compress_many(&points).unwrap();
+        compressor.done().unwrap();
+        let mut compressed = compressor.into_inner();
+        compressed.set_position(0);
+
+        let mut edited = Cursor::new(vec![]);
+        edit_classification(
+            compressed,
+            &vlr,
+            &mut edited,
+            DecompressionSelection::base(),
+            |_| 2,
+        )
+        .unwrap();
+
+        let data = edited.get_ref();
+        let layout = LayeredChunkLayout::read_from(&data[8..], vlr.items()).unwrap();
+        assert_eq!(layout.point_count(), 150);
+        assert_eq!(layout.layer(LayerKind::Classification).unwrap().size, 0);
+
+        edited.set_position(0);
+        let mut decompressor = LasZipDecompressor::new(edited, vlr).unwrap();
+        let mut decompressed = vec![0u8; points.len()];
+        decompressor.decompress_many(&mut decompressed).unwrap();
+        assert!(decompressed
+            .chunks_exact(POINT_SIZE)
+            .all(|point| point[16] == 2));
+    }
+}
@@ -0,0 +1,255 @@
+//! Merging of LAZ data without recompression
+//!
+//! Each chunk of a LAZ file is self-contained: the arithmetic coder and
+//! the models are reset at the start of every chunk.
+//! So, as long as the [`LazItem`]s of the inputs are the same, the compressed
+//! chunks of multiple inputs can be copied one after the other to form a new
+//! valid LAZ data, the only thing that needs to be built is a new chunk table.
+//!
+//! As the last chunk of an input may not be full, the merged data always uses
+//! **variable-size** chunks.
+//!
+//! [`LazItem`]: crate::LazItem
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use crate::laszip::chunk_table::{update_chunk_table_offset, ChunkTable};
+use crate::laszip::{CompressorType, LazVlr};
+use crate::LasZipError;
+
+/// One of the LAZ data to be merged
+pub struct MergeInput<R> {
+    /// The source of the compressed data.
+    ///
+    /// It must be positioned as for [`ChunkTable::read_from`](crate::laszip::ChunkTable::read_from).
+    pub source: R,
+    /// The vlr of the LAZ data
+    pub vlr: LazVlr,
+    /// Number of points in the LAZ data.
+    ///
+    /// Needed as, for **fixed-size** chunks,
+    /// the number of points in the last chunk is not stored.
+    pub point_count: u64,
+}
+
+impl<R> MergeInput<R> {
+    pub fn new(source: R, vlr: LazVlr, point_count: u64) -> Self {
+        Self {
+            source,
+            vlr,
+            point_count,
+        }
+    }
+}
+
+/// Information about LAZ data that resulted from a merge
+#[derive(Debug, Clone)]
+pub struct MergedLaz {
+    /// The vlr to be written in the header of the merged file,
+    /// it is the vlr of the first input, with **variable-size** chunks.
+    pub vlr: LazVlr,
+    /// The chunk table that was written
+    pub chunk_table: ChunkTable,
+    /// Total number of points
+    pub point_count: u64,
+}
+
+/// Merges the compressed point data of all the `inputs` into the `dst`
+/// without decompressing or recompressing them.
+///
+/// The data written is standard LAZ point data, that means it's organized like this:
+///  1) offset to the chunk_table (i64)
+///  2) the compressed chunks of each input, in order
+///  3) the chunk table
+///
+/// The `dst` position should be where the point data starts.
+///
+/// Fails with [`LasZipError::MismatchedLazItems`] if the inputs do not all have
+/// the same items.
+///
+/// # Note
+///
+/// The LAS header of the merged file (bounds, point counts, etc) is not written,
+/// the caller should use the returned [`MergedLaz`] to write the header
+/// & the Laszip vlr.
+pub fn merge<R, W, I>(inputs: I, mut dst: W) -> crate::Result<MergedLaz>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+    I: IntoIterator<Item = MergeInput<R>>,
+{
+    let start_pos = dst.stream_position()?;
+    // Reserve the bytes for the chunk table offset that will be updated later
+    dst.write_i64::<LittleEndian>(-1)?;
+
+    let mut first_vlr: Option<LazVlr> = None;
+    let mut chunk_table = ChunkTable::default();
+    let mut point_count = 0u64;
+    for input in inputs {
+        let MergeInput {
+            mut source,
+            vlr,
+            point_count: input_point_count,
+        } = input;
+
+        if vlr.compressor != CompressorType::PointWiseChunked
+            && vlr.compressor != CompressorType::LayeredChunked
+        {
+            return Err(LasZipError::UnsupportedCompressorType(vlr.compressor));
+        }
+
+        match &first_vlr {
+            None => first_vlr = Some(vlr.clone()),
+            Some(first_vlr) if first_vlr.items() != vlr.items() => {
+                return Err(LasZipError::MismatchedLazItems)
+            }
+            Some(_) => {}
+        }
+
+        if input_point_count == 0 {
+            continue;
+        }
+
+        let mut input_chunk_table = ChunkTable::read_from(&mut source, &vlr)?;
+        if !vlr.uses_variable_size_chunks() {
+            input_chunk_table.fix_last_point_count(input_point_count)?;
+        }
+        let num_points_in_table = input_chunk_table
+            .as_ref()
+            .iter()
+            .map(|entry| entry.point_count)
+            .sum::<u64>();
+        if num_points_in_table != input_point_count {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "point count {} does not match the {} points of the chunk table",
+                    input_point_count, num_points_in_table
+                ),
+            )
+            .into());
+        }
+
+        for entry in &input_chunk_table {
+            if entry.point_count == 0 {
+                // Chunks without points (e.g. written by a `done` following
+                // a `compress_chunks`) would confuse decompressors
+                // once in the middle of the data, so we drop them.
+                source.seek(SeekFrom::Current(entry.byte_count as i64))?;
+                continue;
+            }
+            let num_copied = std::io::copy(&mut (&mut source).take(entry.byte_count), &mut dst)?;
+            if num_copied != entry.byte_count {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "failed to copy all the compressed chunks",
+                )
+                .into());
+            }
+            chunk_table.push(*entry);
+        }
+        point_count += input_point_count;
+    }
+
+    let vlr = first_vlr
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "no inputs to merge"))?
+        .with_variable_chunk_size();
+
+    update_chunk_table_offset(&mut dst, SeekFrom::Start(start_pos))?;
+    chunk_table.write_to(&mut dst, &vlr)?;
+
+    Ok(MergedLaz {
+        vlr,
+        chunk_table,
+        point_count,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::laszip::{LazItem, LazVlrBuilder};
+    use crate::test_support::{compress, layered_points, read_test_points, with_custom_header};
+    use crate::{LasZipCompressor, LasZipDecompressor, LazItemRecordBuilder};
+
+    fn check_merge(points: &[u8], items: Vec<LazItem>) {
+        let fixed_vlr = with_custom_header(
+            &LazVlrBuilder::new(items.clone())
+                .with_fixed_chunk_size(100)
+                .build(),
+        );
+        let point_size = fixed_vlr.items_size() as usize;
+        let split = 437 * point_size;
+        let variable_vlr = LazVlrBuilder::new(items).with_variable_chunk_size().build();
+        let first = Cursor::new(compress(&points[..split], &fixed_vlr));
+        let second = {
+            let mut compressor =
+                LasZipCompressor::new(Cursor::new(vec![]), variable_vlr.clone()).unwrap();
+            compressor
+                .compress_chunks(points[split..].chunks(77 * point_size))
+                .unwrap();
+            compressor.done().unwrap();
+            let mut output = compressor.into_inner();
+            output.set_position(0);
+            output
+        };
+
+        let inputs = vec![
+            MergeInput::new(first, fixed_vlr.clone(), (split / point_size) as u64),
+            MergeInput::new(
+                second,
+                variable_vlr,
+                ((points.len() - split) / point_size) as u64,
+            ),
+        ];
+        let mut merged = Cursor::new(vec![]);
+        let result = merge(inputs, &mut merged).unwrap();
+        // The vlr of the first input is kept, apart from its chunk size
+        assert_eq!(result.vlr, fixed_vlr.with_variable_chunk_size());
+        assert_eq!(result.point_count, (points.len() / point_size) as u64);
+        assert_eq!(
+            result
+                .chunk_table
+                .as_ref()
+                .iter()
+                .map(|entry| entry.point_count)
+                .sum::<u64>(),
+            result.point_count
+        );
+
+        merged.set_position(0);
+        let mut decompressor = LasZipDecompressor::new(merged, result.vlr).unwrap();
+        let mut decompressed = vec![0u8; points.len()];
+        decompressor.decompress_many(&mut decompressed).unwrap();
+        assert_eq!(decompressed, points);
+    }
+
+    #[test]
+    fn test_merge_gives_back_all_points() {
+        let (points, items) = read_test_points();
+        check_merge(&points, items);
+        check_merge(
+            &layered_points(1000),
+            LazItemRecordBuilder::default_for_point_format_id(6, 0).unwrap(),
+        );
+    }
+
+    #[test]
+    fn test_merge_mismatched_items() {
+        let point10 = LazVlr::from_laz_items(LazItemRecordBuilder::default_version_of::<
+            crate::las::Point0,
+        >(0));
+        let point6 = LazVlr::from_laz_items(LazItemRecordBuilder::default_version_of::<
+            crate::las::Point6,
+        >(0));
+        let inputs = vec![
+            MergeInput::new(Cursor::new(compress(&[], &point10)), point10, 0),
+            MergeInput::new(Cursor::new(compress(&[], &point6)), point6, 0),
+        ];
+        let result = merge(inputs, Cursor::new(vec![]));
+        assert!(matches!(result, Err(LasZipError::MismatchedLazItems)));
+    }
+}
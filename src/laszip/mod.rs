@@ -3,25 +3,58 @@
 //!
 //! It defines the LaszipCompressor & LaszipDecompressor
 //! as well as the Laszip VLr data  and how to build it
+pub use cache::{CacheStats, CachedLazReader};
 pub use chunk_table::{ChunkTable, ChunkTableEntry};
+pub use chunking::ChunkingPolicy;
+pub use extract::{extract_chunks, extract_points, ExtractedLaz};
+pub use factory::{open_decompressor, DecompressorBackend, DecompressorOptions};
+pub use filter::{PointFilter, PointLayout};
+pub use layers::{
+    edit_chunk_classification, edit_classification, Layer, LayerKind, LayeredChunkLayout,
+};
+pub use merge::{merge, MergeInput, MergedLaz};
+pub use quantization::{requantize, Quantization};
+pub use reorder::{
+    hilbert_index, morton_index, restore_order, sort_permutation, ReorderReport,
+    ReorderingCompressor, SortOrder,
+};
+pub use report::{ChunkReport, CompressedField, CompressionReport, FieldByteCount};
+pub use sampling::{PointSampler, SamplingMode};
 pub use sequential::{
     compress_buffer, decompress_buffer, LasZipAppender, LasZipCompressor, LasZipDecompressor,
+    LasZipStreamCompressor, LasZipStreamDecompressor,
 };
+pub use shared::{ReadAt, SharedLazReader};
 pub use vlr::{
-    CompressorType, DefaultVersion, LazItem, LazItemRecordBuilder, LazItemType, LazVlr,
-    LazVlrBuilder, Version1, Version2, Version3, Version4,
+    CompressorType, DefaultVersion, InvalidVlrReason, LazItem, LazItemRecordBuilder, LazItemType,
+    LazVlr, LazVlrBuilder, Version1, Version2, Version3, Version4,
 };
 
 #[cfg(feature = "parallel")]
 pub(crate) use vlr::DecompressedChunkSize;
 
+mod cache;
 mod chunk_table;
-mod details;
+mod chunking;
+pub(crate) mod details;
+mod extract;
+mod factory;
+mod filter;
+mod layers;
+mod merge;
 #[cfg(feature = "parallel")]
 pub mod parallel;
+mod quantization;
+mod reorder;
+mod report;
+mod sampling;
 mod sequential;
+mod shared;
+mod verification;
 mod vlr;
 
+use crate::las::selective::DecompressionSelection;
+
 #[deprecated(since = "0.6.0", note = "Please use laz::LazVlr::USER_ID")]
 pub const LASZIP_USER_ID: &str = LazVlr::USER_ID;
 #[deprecated(since = "0.6.0", note = "Please use laz::LazVlr::RECORD_ID")]
@@ -37,6 +70,31 @@ pub trait LazDecompressor {
     fn seek(&mut self, index: u64) -> crate::Result<()>;
 }
 
+/// Extension trait for [`LazDecompressor`] that provides access to the state of the decompression.
+///
+/// It is implemented by both [`LasZipDecompressor`] and `ParLasZipDecompressor`,
+/// which makes them interchangeable (see [`open_decompressor`]).
+pub trait LazDecompressorWithState: LazDecompressor {
+    /// Returns the vlr describing the compressed data
+    fn vlr(&self) -> &LazVlr;
+
+    /// Returns the index of the next point that will be decompressed
+    fn position(&self) -> u64;
+
+    /// Returns the number of points, if known
+    fn point_count(&self) -> Option<u64>;
+
+    /// Sets the number of points (as found in the LAS header)
+    fn set_point_count(&mut self, point_count: u64) -> crate::Result<()>;
+
+    /// Returns the fields that are decompressed
+    fn selection(&self) -> DecompressionSelection;
+
+    /// Changes the fields that are decompressed, it may only take effect
+    /// from the next chunk
+    fn set_selection(&mut self, selection: DecompressionSelection);
+}
+
 pub trait LazCompressor {
     fn compress_one(&mut self, point: &[u8]) -> crate::Result<()>;
 
@@ -47,6 +105,16 @@ pub trait LazCompressor {
     fn done(&mut self) -> crate::Result<()>;
 }
 
+/// Extension trait for [`LazCompressor`] that provides access to the state of the compression.
+pub trait LazCompressorWithState: LazCompressor {
+    /// Returns the vlr describing the compressed data
+    fn vlr(&self) -> &LazVlr;
+
+    /// Returns the index of the next point that will be compressed,
+    /// that is, the number of points given to the compressor so far
+    fn position(&self) -> u64;
+}
+
 /// Extension trait for [`LazCompressor`] that provides access to the underlying writer.
 ///
 /// This trait is useful when you need to work with compressors generically
@@ -4,8 +4,11 @@ use byteorder::{LittleEndian, WriteBytesExt};
 use rayon::prelude::*;
 
 use crate::laszip::chunk_table::{update_chunk_table_offset, ChunkTable, ChunkTableEntry};
+use crate::laszip::chunking::{ChunkSplitter, ChunkingPolicy};
 use crate::laszip::details::record_compressor_from_laz_items;
-use crate::laszip::{CompressorType, DecompressedChunkSize};
+use crate::laszip::report::{point_wise_items, ChunkReport, CompressionReport};
+use crate::laszip::verification::find_mismatch;
+use crate::laszip::{CompressorType, DecompressedChunkSize, Quantization};
 use crate::{LasZipError, LazVlr};
 
 /// LasZip compressor that compresses using multiple threads
@@ -48,6 +51,11 @@ pub struct ParLasZipCompressor<W> {
     // The rest is compressed when done is called, forming the last chunk
     rest: Vec<u8>,
     dest: W,
+    /// Whether chunks are decompressed & compared with their input after being compressed
+    verify: bool,
+    /// Closes the chunks following a policy, if set
+    chunking: Option<ChunkSplitter>,
+    report: Option<CompressionReport>,
 }
 
 impl<W: Write + Seek + Send + Sync> ParLasZipCompressor<W> {
@@ -72,6 +80,9 @@ impl<W: Write + Seek + Send + Sync> ParLasZipCompressor<W> {
             table_offset: -1,
             rest,
             dest,
+            verify: false,
+            chunking: None,
+            report: None,
         })
     }
 
@@ -92,14 +103,19 @@ impl<W: Write + Seek + Send + Sync> ParLasZipCompressor<W> {
     ///
     /// # Important
     ///
-    /// This **must** be called **only** when writing **fixed-size** chunks.
+    /// This **must** be called **only** when writing **fixed-size** chunks,
+    /// or **variable-size** chunks with a [`ChunkingPolicy`] set.
     /// This will **panic** otherwise.
     ///
     /// # Note
     ///
     /// For this function to actually use multiple threads, the `points`
-    /// buffer shall hold more points that the vlr's `chunk_size`.
+    /// buffer shall hold more points that the vlr's `chunk_size`
+    /// (or form more than one chunk with the policy).
     pub fn compress_many(&mut self, points: &[u8]) -> std::io::Result<()> {
+        if self.chunking.is_some() {
+            return self.compress_many_with_policy(points);
+        }
         assert!(!self.vlr.uses_variable_size_chunks());
         if self.table_offset == -1 {
             self.reserve_offset_to_chunk_table()?;
@@ -126,12 +142,7 @@ impl<W: Write + Seek + Send + Sync> ParLasZipCompressor<W> {
 
             debug_assert_eq!(self.rest.len(), chunk_size_in_bytes);
             // We have a complete chunk, lets compress it now
-            let chunk_size = compress_one_chunk(&self.rest, &self.vlr, &mut self.dest)?;
-            self.chunk_table.push(ChunkTableEntry {
-                point_count: self.vlr.chunk_size() as u64,
-                byte_count: chunk_size,
-            });
-            self.rest.clear();
+            self.compress_rest().map_err(std::io::Error::other)?;
 
             compressible_buf = &compressible_buf[missing_bytes..]
         }
@@ -147,14 +158,43 @@ impl<W: Write + Seek + Send + Sync> ParLasZipCompressor<W> {
         }
 
         if !compressible_buf.is_empty() {
-            let chunk_table = par_compress(&mut self.dest, compressible_buf, &self.vlr)
-                .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+            let chunk_table = par_compress_chunks(
+                &mut self.dest,
+                compressible_buf.par_chunks(chunk_size_in_bytes),
+                &self.vlr,
+                self.verify,
+                self.chunk_table.len(),
+                self.report.as_mut(),
+            )
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
             self.chunk_table.extend(&chunk_table);
         }
 
         Ok(())
     }
 
+    /// Compresses the points contained in the `points` slice, with their X, Y, Z
+    /// replaced by the `coordinates` quantized using the `quantization`.
+    ///
+    /// Fails if the number of coordinates is not the number of points,
+    /// or if a coordinate cannot be quantized.
+    ///
+    /// The same rules as [`compress_many`](Self::compress_many) apply.
+    pub fn compress_many_quantized(
+        &mut self,
+        points: &[u8],
+        coordinates: &[[f64; 3]],
+        quantization: &Quantization,
+    ) -> std::io::Result<()> {
+        let mut points = points.to_vec();
+        quantization.set_coordinates_of_many(
+            &mut points,
+            self.vlr.items_size() as usize,
+            coordinates,
+        )?;
+        self.compress_many(&points)
+    }
+
     /// Compresses multiple chunks using multiple threads.
     ///
     /// # Important
@@ -176,8 +216,15 @@ impl<W: Write + Seek + Send + Sync> ParLasZipCompressor<W> {
         if self.table_offset == -1 {
             self.reserve_offset_to_chunk_table()?;
         }
-        let chunk_table = par_compress_chunks(&mut self.dest, chunks, &self.vlr)
-            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+        let chunk_table = par_compress_chunks(
+            &mut self.dest,
+            chunks,
+            &self.vlr,
+            self.verify,
+            self.chunk_table.len(),
+            self.report.as_mut(),
+        )
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
         self.chunk_table.extend(&chunk_table);
         Ok(())
     }
@@ -189,11 +236,7 @@ impl<W: Write + Seek + Send + Sync> ParLasZipCompressor<W> {
     /// - update the offset to the chunk_table
     pub fn done(&mut self) -> crate::Result<()> {
         if self.rest.len() != 0 {
-            let last_chunk_size = compress_one_chunk(&self.rest, &self.vlr, &mut self.dest)?;
-            self.chunk_table.push(ChunkTableEntry {
-                point_count: self.vlr.chunk_size() as u64,
-                byte_count: last_chunk_size,
-            });
+            self.compress_rest()?;
         }
 
         if self.table_offset == -1 && self.chunk_table.is_empty() {
@@ -205,10 +248,66 @@ impl<W: Write + Seek + Send + Sync> ParLasZipCompressor<W> {
         Ok(())
     }
 
+    /// Sets the policy used to close chunks when points are given to
+    /// [`compress_many`](Self::compress_many).
+    ///
+    /// The points of the chunk that is not yet closed are kept until the next call
+    /// to [`compress_many`](Self::compress_many) or [`done`](Self::done).
+    ///
+    /// This should be called before compressing any point.
+    ///
+    /// Fails if the vlr does not use **variable-size** chunks.
+    pub fn set_chunking_policy(&mut self, policy: ChunkingPolicy) -> crate::Result<()> {
+        self.chunking = Some(ChunkSplitter::new(policy, &self.vlr)?);
+        Ok(())
+    }
+
+    /// Enables the verification of the chunks written.
+    ///
+    /// Each chunk is decompressed right after being compressed (in the same thread),
+    /// and the decompressed points are compared with the input points.
+    /// On the first difference, the compression fails with a
+    /// [`LasZipError::VerificationFailed`] that tells the chunk, point & byte
+    /// (wrapped in an io error for [`compress_many`] and [`compress_chunks`]).
+    ///
+    /// This should be called before compressing any point.
+    ///
+    /// [`compress_many`]: Self::compress_many
+    /// [`compress_chunks`]: Self::compress_chunks
+    pub fn enable_verification(&mut self) {
+        self.verify = true;
+    }
+
+    /// Enables the creation of a [`CompressionReport`] of the chunks written,
+    /// see [`report`](Self::report).
+    ///
+    /// This should be called before compressing any point.
+    ///
+    /// As for the [`LasZipCompressor`](crate::LasZipCompressor::enable_report),
+    /// the report of **layered** chunks only has the point & byte counts of the chunks.
+    pub fn enable_report(&mut self) {
+        if self.report.is_none() {
+            self.report = Some(CompressionReport::default());
+        }
+    }
+
+    /// Returns the report of the chunks written so far,
+    /// if [`enable_report`](Self::enable_report) was called.
+    pub fn report(&self) -> Option<&CompressionReport> {
+        self.report.as_ref()
+    }
+
     pub fn vlr(&self) -> &LazVlr {
         &self.vlr
     }
 
+    /// Returns the index of the next point that will be compressed,
+    /// that is, the number of points given to the compressor so far.
+    pub fn position(&self) -> u64 {
+        let point_size = self.vlr.items_size();
+        self.chunk_table.point_count() + self.rest.len() as u64 / point_size
+    }
+
     pub fn into_inner(self) -> W {
         self.dest
     }
@@ -231,6 +330,67 @@ impl<W: Write + Seek + Send + Sync> ParLasZipCompressor<W> {
     pub(crate) fn chunk_table(&self) -> &ChunkTable {
         &self.chunk_table
     }
+
+    fn compress_many_with_policy(&mut self, points: &[u8]) -> std::io::Result<()> {
+        if self.table_offset == -1 {
+            self.reserve_offset_to_chunk_table()?;
+        }
+        let point_size = self.vlr.items_size() as usize;
+        let chunking = self.chunking.as_mut().unwrap();
+        chunking.start_batch();
+
+        // The rest holds the points of the chunk that is not closed yet
+        let mut boundaries = vec![];
+        let offset = self.rest.len();
+        for (i, point) in points.chunks_exact(point_size).enumerate() {
+            if chunking.closes_chunk_before(point) {
+                chunking.reset();
+                boundaries.push(offset + i * point_size);
+            }
+            chunking.add_point(point);
+        }
+        self.rest.extend_from_slice(points);
+        let Some(last_boundary) = boundaries.last().copied() else {
+            return Ok(());
+        };
+
+        let mut chunks = Vec::with_capacity(boundaries.len());
+        let mut start = 0;
+        for end in boundaries {
+            chunks.push(&self.rest[start..end]);
+            start = end;
+        }
+        let chunk_table = par_compress_chunks(
+            &mut self.dest,
+            chunks,
+            &self.vlr,
+            self.verify,
+            self.chunk_table.len(),
+            self.report.as_mut(),
+        )
+        .map_err(std::io::Error::other)?;
+        for entry in chunk_table.as_ref() {
+            chunking.record_chunk(entry.point_count, entry.byte_count);
+        }
+        self.chunk_table.extend(&chunk_table);
+        self.rest.drain(..last_boundary);
+        Ok(())
+    }
+
+    /// Compresses & writes the points of the rest buffer as one chunk
+    fn compress_rest(&mut self) -> crate::Result<()> {
+        let chunk_table = par_compress_chunks(
+            &mut self.dest,
+            vec![self.rest.as_slice()],
+            &self.vlr,
+            self.verify,
+            self.chunk_table.len(),
+            self.report.as_mut(),
+        )?;
+        self.chunk_table.extend(&chunk_table);
+        self.rest.clear();
+        Ok(())
+    }
 }
 
 impl<W: Write + Seek + Send + Sync> crate::LazCompressor for ParLasZipCompressor<W> {
@@ -255,6 +415,16 @@ impl<W: Write + Seek + Send + Sync> crate::LazCompressor for ParLasZipCompressor
     }
 }
 
+impl<W: Write + Seek + Send + Sync> crate::LazCompressorWithState for ParLasZipCompressor<W> {
+    fn vlr(&self) -> &LazVlr {
+        ParLasZipCompressor::vlr(self)
+    }
+
+    fn position(&self) -> u64 {
+        ParLasZipCompressor::position(self)
+    }
+}
+
 impl<W: Write + Seek + Send + Sync> crate::LazCompressorWithInner<W> for ParLasZipCompressor<W> {
     fn into_inner(self) -> W {
         self.into_inner()
@@ -314,13 +484,21 @@ pub fn par_compress<W: Write>(
     let chunk_size_in_bytes = points_per_chunk * point_size;
 
     let all_slices = uncompressed_points.par_chunks(chunk_size_in_bytes);
-    par_compress_chunks(dst, all_slices, laz_vlr)
+    par_compress_chunks(dst, all_slices, laz_vlr, false, 0, None)
 }
 
+/// Compresses the chunks in parallel and writes them in order.
+///
+/// When `verify` is true, each chunk is decompressed & compared with its input,
+/// `first_chunk_index` is the index of the first chunk in the chunk table, used in errors.
+/// The chunks are added to the `report`, if any.
 fn par_compress_chunks<'a, W, Chunks, Item>(
     dst: &mut W,
     chunks: Chunks,
     laz_vlr: &LazVlr,
+    verify: bool,
+    first_chunk_index: usize,
+    mut report: Option<&mut CompressionReport>,
 ) -> crate::Result<ChunkTable>
 where
     W: Write,
@@ -329,54 +507,70 @@ where
 {
     use std::io::Cursor;
 
+    let count_field_bytes = report.is_some();
     let chunks = chunks
         .into_par_iter()
         .map(|data| {
             let slc = data.as_ref();
             let mut output = Cursor::new(Vec::<u8>::new());
-            compress_one_chunk(slc, laz_vlr, &mut output)?;
+            let field_byte_counts =
+                compress_one_chunk(slc, laz_vlr, &mut output, count_field_bytes)?;
             let vec = output.into_inner();
-            Ok((slc.len(), vec))
+            let mismatch = if verify {
+                find_mismatch(&vec, slc, laz_vlr)?
+            } else {
+                None
+            };
+            Ok((slc.len(), vec, mismatch, field_byte_counts))
         })
-        .collect::<Vec<crate::Result<(usize, Vec<u8>)>>>();
+        .collect::<Vec<crate::Result<_>>>();
 
     let mut chunk_table = ChunkTable::with_capacity(chunks.len());
     let point_size = laz_vlr.items_size() as usize;
     for chunk_result in chunks {
-        let (input_size, compressed_data) = chunk_result?;
-        let point_count = if laz_vlr.uses_variable_size_chunks() {
-            (input_size / point_size) as u64
-        } else {
-            laz_vlr.chunk_size() as u64
-        };
+        let (input_size, compressed_data, mismatch, field_byte_counts) = chunk_result?;
+        if let Some(mismatch) = mismatch {
+            return Err(mismatch.into_error(first_chunk_index + chunk_table.len()));
+        }
         let entry = ChunkTableEntry {
-            point_count,
+            point_count: (input_size / point_size) as u64,
             byte_count: compressed_data.len() as u64,
         };
         chunk_table.push(entry);
         dst.write_all(&compressed_data)?;
+        if let Some(report) = report.as_deref_mut() {
+            report.chunks.push(ChunkReport {
+                point_count: entry.point_count,
+                byte_count: entry.byte_count,
+                fields: field_byte_counts
+                    .map(|byte_counts| point_wise_items(laz_vlr.items(), byte_counts))
+                    .unwrap_or_default(),
+            });
+        }
     }
     Ok(chunk_table)
 }
 
-fn compress_one_chunk<W: Write + Seek + Send + Sync>(
+/// Compresses the chunk, returning the bytes used by each item
+/// if `count_field_bytes` is true (and supported by the items)
+fn compress_one_chunk<W: Write + Send + Sync>(
     chunk_data: &[u8],
     vlr: &LazVlr,
     mut dest: &mut W,
-) -> std::io::Result<u64> {
-    let start = dest.seek(SeekFrom::Current(0))?;
-    {
-        let mut compressor = record_compressor_from_laz_items(&vlr.items(), &mut dest).unwrap();
-        compressor.compress_many(chunk_data)?;
-        compressor.done()?;
+    count_field_bytes: bool,
+) -> std::io::Result<Option<Vec<u64>>> {
+    let mut compressor = record_compressor_from_laz_items(&vlr.items(), &mut dest).unwrap();
+    if count_field_bytes {
+        compressor.enable_field_byte_counts();
     }
-    let end = dest.seek(SeekFrom::Current(0))?;
-    Ok(end - start)
+    compressor.compress_many(chunk_data)?;
+    compressor.done()?;
+    Ok(compressor.field_byte_counts())
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{LazItemRecordBuilder, LazItemType};
+    use crate::{LazItemRecordBuilder, LazItemType, LazVlrBuilder};
 
     use super::*;
 
@@ -415,4 +609,70 @@ mod test {
         compressor.compress_many(&points).unwrap();
         assert_eq!(compressor.table_offset, 0);
     }
+
+    #[test]
+    fn test_position_matches_sequential() {
+        let vlr = LazVlrBuilder::new(
+            LazItemRecordBuilder::new()
+                .add_item(LazItemType::Point10)
+                .build(),
+        )
+        .with_fixed_chunk_size(10)
+        .build();
+        let point_size = vlr.items_size() as usize;
+        let points = vec![0u8; 25 * point_size];
+
+        let mut compressors: Vec<Box<dyn crate::LazCompressorWithState>> = vec![
+            Box::new(
+                crate::LasZipCompressor::new(std::io::Cursor::new(vec![]), vlr.clone()).unwrap(),
+            ),
+            Box::new(ParLasZipCompressor::new(std::io::Cursor::new(vec![]), vlr).unwrap()),
+        ];
+        for compressor in &mut compressors {
+            assert_eq!(compressor.position(), 0);
+            compressor.compress_one(&points[..point_size]).unwrap();
+            assert_eq!(compressor.position(), 1);
+            compressor.compress_many(&points[point_size..]).unwrap();
+            assert_eq!(compressor.position(), 25);
+            compressor.done().unwrap();
+            assert_eq!(compressor.position(), 25);
+            assert_eq!(compressor.vlr().chunk_size(), 10);
+        }
+    }
+
+    #[test]
+    fn test_report_matches_sequential() {
+        let (points, items) = crate::test_support::read_test_points();
+        let fixed_vlr = LazVlrBuilder::new(items.clone())
+            .with_fixed_chunk_size(250)
+            .build();
+        let variable_vlr = LazVlrBuilder::new(items).with_variable_chunk_size().build();
+        let point_size = fixed_vlr.items_size() as usize;
+
+        for vlr in [fixed_vlr, variable_vlr] {
+            let mut compressor =
+                crate::LasZipCompressor::new(std::io::Cursor::new(vec![]), vlr.clone()).unwrap();
+            let mut par_compressor =
+                ParLasZipCompressor::new(std::io::Cursor::new(vec![]), vlr.clone()).unwrap();
+            compressor.enable_report();
+            par_compressor.enable_report();
+            if vlr.uses_variable_size_chunks() {
+                compressor
+                    .compress_chunks(points.chunks(300 * point_size))
+                    .unwrap();
+                par_compressor
+                    .compress_chunks(points.par_chunks(300 * point_size))
+                    .unwrap();
+            } else {
+                compressor.compress_many(&points).unwrap();
+                par_compressor.compress_many(&points).unwrap();
+                compressor.done().unwrap();
+                par_compressor.done().unwrap();
+            }
+
+            let report = par_compressor.report().unwrap();
+            assert_eq!(report, compressor.report().unwrap());
+            assert!(report.chunks.iter().all(|chunk| !chunk.fields.is_empty()));
+        }
+    }
 }
@@ -2,10 +2,12 @@ use std::io::{Read, Seek, SeekFrom};
 
 use rayon::prelude::*;
 
-use crate::byteslice::ChunksIrregularMut;
-use crate::las::selective::DecompressionSelection;
+use crate::byteslice::{ChunksIrregular, ChunksIrregularMut};
+use crate::las::selective::{DecompressionSelection, ExtraBytesSelection};
 use crate::laszip::chunk_table::{ChunkTable, ChunkTableEntry};
 use crate::laszip::details::record_decompressor_from_laz_items;
+use crate::laszip::filter::{decompress_chunk_filtered, PointFilter, PointLayout};
+use crate::laszip::sampling::{decompress_chunk_sampled, PointSampler};
 use crate::laszip::CompressorType;
 use crate::{LasZipError, LazVlr};
 
@@ -31,6 +33,11 @@ pub struct ParLasZipDecompressor<R> {
     source: R,
     // Contains which fields the user wants to decompress or not
     selection: DecompressionSelection,
+    extra_bytes_selection: ExtraBytesSelection,
+    // Index of the next point to be decompressed
+    position: u64,
+    // Only known with variable-size chunks, or when set by the user
+    point_count: Option<u64>,
 }
 
 impl<R: Read + Seek> ParLasZipDecompressor<R> {
@@ -61,6 +68,46 @@ impl<R: Read + Seek> ParLasZipDecompressor<R> {
         }
         let chunk_table = ChunkTable::read_from(&mut source, &vlr)?;
         let start_of_data = source.seek(SeekFrom::Current(0))?;
+        Ok(Self::from_parts(
+            source,
+            vlr,
+            chunk_table,
+            start_of_data,
+            selection,
+        ))
+    }
+
+    /// Creates a decompressor using a chunk table that was read before
+    /// (e.g. cached), instead of reading it from the source.
+    ///
+    /// The `source` must be positioned as for [`ChunkTable::read_from`](crate::laszip::ChunkTable::read_from).
+    pub fn with_chunk_table(
+        mut source: R,
+        vlr: LazVlr,
+        chunk_table: ChunkTable,
+    ) -> crate::Result<Self> {
+        if vlr.compressor != CompressorType::PointWiseChunked
+            && vlr.compressor != CompressorType::LayeredChunked
+        {
+            return Err(LasZipError::UnsupportedCompressorType(vlr.compressor));
+        }
+        let start_of_data = source.seek(SeekFrom::Current(ChunkTable::OFFSET_SIZE as i64))?;
+        Ok(Self::from_parts(
+            source,
+            vlr,
+            chunk_table,
+            start_of_data,
+            DecompressionSelection::all(),
+        ))
+    }
+
+    fn from_parts(
+        source: R,
+        vlr: LazVlr,
+        chunk_table: ChunkTable,
+        start_of_data: u64,
+        selection: DecompressionSelection,
+    ) -> Self {
         let biggest_chunk = chunk_table
             .as_ref()
             .into_iter()
@@ -70,7 +117,12 @@ impl<R: Read + Seek> ParLasZipDecompressor<R> {
         let vec = Vec::<u8>::with_capacity(biggest_chunk as usize);
         let rest = std::io::Cursor::new(vec);
 
-        Ok(Self {
+        let point_count = vlr
+            .uses_variable_size_chunks()
+            .then(|| chunk_table.point_count());
+        Self {
+            position: 0,
+            point_count,
             source,
             vlr,
             chunk_table,
@@ -79,7 +131,8 @@ impl<R: Read + Seek> ParLasZipDecompressor<R> {
             last_chunk_read: -1,
             start_of_data,
             selection,
-        })
+            extra_bytes_selection: ExtraBytesSelection::none(),
+        }
     }
 
     /// Decompresses many points using multiple threads
@@ -87,6 +140,12 @@ impl<R: Read + Seek> ParLasZipDecompressor<R> {
     /// For this function to actually use multiple threads, the `points`
     /// buffer shall hold more points that the vlr's `chunk_size`.
     pub fn decompress_many(&mut self, out: &mut [u8]) -> crate::Result<()> {
+        self.decompress_many_impl(out)?;
+        self.position += out.len() as u64 / self.vlr.items_size();
+        Ok(())
+    }
+
+    fn decompress_many_impl(&mut self, out: &mut [u8]) -> crate::Result<()> {
         let point_size = self.vlr.items_size() as usize;
         assert_eq!(out.len() % point_size, 0);
 
@@ -168,16 +227,18 @@ impl<R: Read + Seek> ParLasZipDecompressor<R> {
         let rest = &mut self.rest;
         let vlr = &self.vlr;
         let selection = self.selection;
+        let extra_bytes_selection = self.extra_bytes_selection;
         let chunk_table_len = self.chunk_table.len();
 
         let (res1, res2) = rayon::join(
             || -> crate::Result<()> {
-                par_decompress_selective(
+                par_decompress_chunks(
                     head_chunks,
                     head_output,
                     &vlr,
                     head_chunks_table,
                     selection,
+                    extra_bytes_selection,
                 )
             },
             || -> crate::Result<()> {
@@ -185,6 +246,7 @@ impl<R: Read + Seek> ParLasZipDecompressor<R> {
                 let mut decompressor =
                     record_decompressor_from_laz_items(&vlr.items(), &mut last_src)?;
                 decompressor.set_selection(selection);
+                decompressor.set_extra_bytes_selection(extra_bytes_selection);
                 // Decompress what we can in the caller's buffer
                 decompressor.decompress_many(tail_output)?;
                 // Then, decompress what we did not, into our rest buffer
@@ -212,8 +274,234 @@ impl<R: Read + Seek> ParLasZipDecompressor<R> {
         Ok(())
     }
 
+    /// Decompresses the next `num_points` points using multiple threads and appends
+    /// the ones that pass the `filter` to `out`, returns the number of points appended.
+    ///
+    /// Just like [`LasZipDecompressor::decompress_filtered`], the chunks fully contained
+    /// in the `num_points` are decompressed in parallel, and for layered data,
+    /// their remaining layers are skipped when none of their points pass
+    /// (see [`PointFilter`] for what it costs when some points pass).
+    ///
+    /// [`LasZipDecompressor::decompress_filtered`]: crate::LasZipDecompressor::decompress_filtered
+    pub fn decompress_filtered(
+        &mut self,
+        num_points: u64,
+        filter: &PointFilter,
+        out: &mut Vec<u8>,
+    ) -> crate::Result<u64> {
+        let point_size = self.vlr.items_size() as usize;
+        let layout = PointLayout::from_vlr(&self.vlr);
+        let mut num_left = num_points as usize;
+        let mut num_kept = 0;
+
+        // 1. The points left in the rest of the last chunk
+        let num_in_rest = (self.rest.get_ref().len() - self.rest.position() as usize) / point_size;
+        if num_in_rest > 0 {
+            let mut points = vec![0u8; num_in_rest.min(num_left) * point_size];
+            self.rest.read_exact(&mut points)?;
+            for point in points.chunks_exact(point_size) {
+                if filter.matches(point, &layout) {
+                    out.extend_from_slice(point);
+                    num_kept += 1;
+                }
+            }
+            num_left -= num_in_rest.min(num_left);
+        }
+        self.position += num_points - num_left as u64;
+        if num_left == 0 {
+            return Ok(num_kept);
+        }
+        self.rest.get_mut().clear();
+        self.rest.set_position(0);
+
+        // 2. The chunks fully contained in the points requested
+        let start_index = (self.last_chunk_read + 1) as usize;
+        let mut end_index = start_index;
+        let mut num_bytes_to_read = 0usize;
+        for entry in &self.chunk_table[start_index..] {
+            let is_last_fixed_size_chunk =
+                !self.vlr.uses_variable_size_chunks() && end_index + 1 == self.chunk_table.len();
+            if entry.point_count as usize > num_left || is_last_fixed_size_chunk {
+                break;
+            }
+            num_left -= entry.point_count as usize;
+            num_bytes_to_read += entry.byte_count as usize;
+            end_index += 1;
+        }
+        self.internal_buffer.resize(num_bytes_to_read, 0u8);
+        self.source.read_exact(&mut self.internal_buffer)?;
+
+        let sizes = self.chunk_table[start_index..end_index]
+            .iter()
+            .map(|entry| entry.byte_count as usize);
+        let jobs = ChunksIrregular::new(&self.internal_buffer, sizes)
+            .zip(&self.chunk_table[start_index..end_index])
+            .collect::<Vec<_>>();
+        let vlr = &self.vlr;
+        let selection = self.selection;
+        let extra_bytes_selection = self.extra_bytes_selection;
+        let chunk_outputs = jobs
+            .into_par_iter()
+            .map(|(chunk, entry)| {
+                let mut chunk_out = vec![];
+                let count = decompress_chunk_filtered(
+                    chunk,
+                    entry.point_count as usize,
+                    vlr,
+                    filter,
+                    selection,
+                    extra_bytes_selection,
+                    &mut chunk_out,
+                )?;
+                Ok((count, chunk_out))
+            })
+            .collect::<crate::Result<Vec<(u64, Vec<u8>)>>>()?;
+        for (count, chunk_out) in chunk_outputs {
+            out.extend_from_slice(&chunk_out);
+            num_kept += count;
+        }
+        self.last_chunk_read = end_index as isize - 1;
+        self.position += self.chunk_table[start_index..end_index]
+            .iter()
+            .map(|entry| entry.point_count)
+            .sum::<u64>();
+
+        // 3. The start of the next chunk, the fields of the filter are needed
+        if num_left > 0 {
+            let mut points = vec![0u8; num_left * point_size];
+            let user_selection = self.selection;
+            self.selection = user_selection.union(filter.selection());
+            let result = self.decompress_many(&mut points);
+            self.selection = user_selection;
+            result?;
+            for point in points.chunks_exact(point_size) {
+                if filter.matches(point, &layout) {
+                    out.extend_from_slice(point);
+                    num_kept += 1;
+                }
+            }
+        }
+        Ok(num_kept)
+    }
+
+    /// Decompresses the next `num_points` points using multiple threads and appends
+    /// the ones kept by the `sampler` to `out`, returns the number of points appended.
+    ///
+    /// Chunks fully contained in the `num_points` without any point to keep
+    /// are skipped without being read.
+    pub fn decompress_sampled(
+        &mut self,
+        num_points: u64,
+        sampler: &mut PointSampler,
+        out: &mut Vec<u8>,
+    ) -> crate::Result<u64> {
+        let point_size = self.vlr.items_size() as usize;
+        let mut num_left = num_points as usize;
+        let mut num_kept = 0;
+
+        // 1. The points left in the rest of the last chunk
+        let num_in_rest = (self.rest.get_ref().len() - self.rest.position() as usize) / point_size;
+        if num_in_rest > 0 {
+            let chunk_point_count = self.chunk_table[self.last_chunk_read as usize].point_count;
+            let first_index = chunk_point_count.saturating_sub(num_in_rest as u64);
+            let mut points = vec![0u8; num_in_rest.min(num_left) * point_size];
+            self.rest.read_exact(&mut points)?;
+            for (i, point) in points.chunks_exact(point_size).enumerate() {
+                if sampler.keeps_point(first_index + i as u64) {
+                    out.extend_from_slice(point);
+                    num_kept += 1;
+                }
+            }
+            num_left -= num_in_rest.min(num_left);
+        }
+        self.position += num_points - num_left as u64;
+        if num_left == 0 {
+            return Ok(num_kept);
+        }
+        self.rest.get_mut().clear();
+        self.rest.set_position(0);
+
+        // 2. The chunks fully contained in the points requested,
+        // only the ones with points to keep are read
+        let start_index = (self.last_chunk_read + 1) as usize;
+        let mut end_index = start_index;
+        let mut jobs = vec![];
+        self.internal_buffer.clear();
+        for entry in &self.chunk_table[start_index..] {
+            let is_last_fixed_size_chunk =
+                !self.vlr.uses_variable_size_chunks() && end_index + 1 == self.chunk_table.len();
+            if entry.point_count as usize > num_left || is_last_fixed_size_chunk {
+                break;
+            }
+            let kept = sampler.kept_in_chunk(entry.point_count);
+            if kept.is_empty() {
+                self.source
+                    .seek(SeekFrom::Current(entry.byte_count as i64))?;
+            } else {
+                let start = self.internal_buffer.len();
+                self.internal_buffer
+                    .resize(start + entry.byte_count as usize, 0u8);
+                self.source.read_exact(&mut self.internal_buffer[start..])?;
+                num_kept += kept.len() as u64;
+                jobs.push((entry.byte_count as usize, kept));
+            }
+            num_left -= entry.point_count as usize;
+            end_index += 1;
+        }
+
+        let sizes = jobs.iter().map(|(byte_count, _)| *byte_count);
+        let jobs = ChunksIrregular::new(&self.internal_buffer, sizes)
+            .zip(jobs.iter().map(|(_, kept)| kept))
+            .collect::<Vec<_>>();
+        let vlr = &self.vlr;
+        let selection = self.selection;
+        let extra_bytes_selection = self.extra_bytes_selection;
+        let chunk_outputs = jobs
+            .into_par_iter()
+            .map(|(chunk, kept)| {
+                let mut chunk_out = vec![];
+                decompress_chunk_sampled(
+                    chunk,
+                    kept,
+                    vlr,
+                    selection,
+                    extra_bytes_selection,
+                    &mut chunk_out,
+                )?;
+                Ok(chunk_out)
+            })
+            .collect::<crate::Result<Vec<Vec<u8>>>>()?;
+        for chunk_out in chunk_outputs {
+            out.extend_from_slice(&chunk_out);
+        }
+        self.last_chunk_read = end_index as isize - 1;
+        self.position += self.chunk_table[start_index..end_index]
+            .iter()
+            .map(|entry| entry.point_count)
+            .sum::<u64>();
+
+        // 3. The start of the next chunk
+        if num_left > 0 {
+            let mut points = vec![0u8; num_left * point_size];
+            self.decompress_many(&mut points)?;
+            for (i, point) in points.chunks_exact(point_size).enumerate() {
+                if sampler.keeps_point(i as u64) {
+                    out.extend_from_slice(point);
+                    num_kept += 1;
+                }
+            }
+        }
+        Ok(num_kept)
+    }
+
     /// Seeks to the position of the point at the given index
     pub fn seek(&mut self, index: u64) -> crate::Result<()> {
+        self.seek_impl(index)?;
+        self.position = index;
+        Ok(())
+    }
+
+    fn seek_impl(&mut self, index: u64) -> crate::Result<()> {
         // Throw away what's in the rest buffer
         self.rest.set_position(0);
         self.rest.get_mut().clear();
@@ -228,6 +516,11 @@ impl<R: Read + Seek> ParLasZipDecompressor<R> {
             }
         };
 
+        let first_point_of_chunk = self
+            .chunk_table
+            .point_range_of_chunk(chunk_of_point)
+            .unwrap()
+            .start;
         let start_of_chunk_pos = self.start_of_data + byte_offset;
         self.source.seek(SeekFrom::Start(start_of_chunk_pos))?;
         self.internal_buffer
@@ -251,7 +544,7 @@ impl<R: Read + Seek> ParLasZipDecompressor<R> {
             let num_bytes_decompressed =
                 decompressor.decompress_until_end_of_file(self.rest.get_mut())?;
             let num_points_in_last_chunk = num_bytes_decompressed / self.vlr.items_size() as usize;
-            let pos_in_chunk = index % self.vlr.chunk_size() as u64;
+            let pos_in_chunk = index - first_point_of_chunk;
             if pos_in_chunk as usize >= num_points_in_last_chunk as usize {
                 // Make the rest appear as fully consumed to
                 // force EOF error on next decompression
@@ -263,12 +556,60 @@ impl<R: Read + Seek> ParLasZipDecompressor<R> {
         }
         // This effectively discard points that were
         // before the one we just seeked to
-        let pos_in_chunk = index % self.vlr.chunk_size() as u64;
+        let pos_in_chunk = index - first_point_of_chunk;
         self.rest.set_position(pos_in_chunk * self.vlr.items_size());
         self.last_chunk_read = chunk_of_point as isize;
         Ok(())
     }
 
+    /// Returns the vlr used.
+    pub fn vlr(&self) -> &LazVlr {
+        &self.vlr
+    }
+
+    /// Returns the index of the next point that will be decompressed
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Returns the number of points, if known
+    ///
+    /// It is known for **variable-size** chunks,
+    /// or after a call to [`Self::set_point_count`].
+    pub fn point_count(&self) -> Option<u64> {
+        self.point_count
+    }
+
+    /// Sets the number of points (as found in the LAS header),
+    /// fixing the point count of the last chunk of **fixed-size** chunks.
+    pub fn set_point_count(&mut self, point_count: u64) -> crate::Result<()> {
+        self.chunk_table.fix_last_point_count(point_count)?;
+        self.point_count = Some(point_count);
+        Ok(())
+    }
+
+    /// Returns the fields that are decompressed
+    pub fn selection(&self) -> DecompressionSelection {
+        self.selection
+    }
+
+    /// Changes the fields that are decompressed, starting from the next chunk
+    /// that is decompressed (points of the current chunk were already decompressed)
+    pub fn set_selection(&mut self, selection: DecompressionSelection) {
+        self.selection = selection;
+    }
+
+    /// Returns the extra bytes that are decompressed in addition to the ones of the selection
+    pub fn extra_bytes_selection(&self) -> ExtraBytesSelection {
+        self.extra_bytes_selection
+    }
+
+    /// Changes the extra bytes that are decompressed in addition to the ones of the
+    /// selection, starting from the same chunk as [`Self::set_selection`] would.
+    pub fn set_extra_bytes_selection(&mut self, selection: ExtraBytesSelection) {
+        self.extra_bytes_selection = selection;
+    }
+
     pub fn into_inner(self) -> R {
         self.source
     }
@@ -296,6 +637,32 @@ impl<R: Read + Seek> crate::laszip::LazDecompressor for ParLasZipDecompressor<R>
     }
 }
 
+impl<R: Read + Seek> crate::laszip::LazDecompressorWithState for ParLasZipDecompressor<R> {
+    fn vlr(&self) -> &LazVlr {
+        ParLasZipDecompressor::vlr(self)
+    }
+
+    fn position(&self) -> u64 {
+        ParLasZipDecompressor::position(self)
+    }
+
+    fn point_count(&self) -> Option<u64> {
+        ParLasZipDecompressor::point_count(self)
+    }
+
+    fn set_point_count(&mut self, point_count: u64) -> crate::Result<()> {
+        ParLasZipDecompressor::set_point_count(self, point_count)
+    }
+
+    fn selection(&self) -> DecompressionSelection {
+        ParLasZipDecompressor::selection(self)
+    }
+
+    fn set_selection(&mut self, selection: DecompressionSelection) {
+        ParLasZipDecompressor::set_selection(self, selection)
+    }
+}
+
 /// Decompresses all points from the buffer in parallel.
 ///
 /// Each chunk is sent for decompression in a thread.
@@ -345,7 +712,25 @@ pub fn par_decompress_selective(
     chunk_table: &[ChunkTableEntry],
     selection: DecompressionSelection,
 ) -> crate::Result<()> {
-    use crate::byteslice::ChunksIrregular;
+    par_decompress_chunks(
+        compressed_points,
+        decompressed_points,
+        laz_vlr,
+        chunk_table,
+        selection,
+        ExtraBytesSelection::none(),
+    )
+}
+
+/// [`par_decompress_selective`] with a selection of extra bytes
+fn par_decompress_chunks(
+    compressed_points: &[u8],
+    decompressed_points: &mut [u8],
+    laz_vlr: &LazVlr,
+    chunk_table: &[ChunkTableEntry],
+    selection: DecompressionSelection,
+    extra_bytes_selection: ExtraBytesSelection,
+) -> crate::Result<()> {
     let sizes = chunk_table.iter().map(|entry| entry.byte_count as usize);
     let counts = chunk_table
         .iter()
@@ -363,6 +748,7 @@ pub fn par_decompress_selective(
             let src = std::io::Cursor::new(chunk_in);
             let mut record_decompressor = record_decompressor_from_laz_items(laz_vlr.items(), src)?;
             record_decompressor.set_selection(selection);
+            record_decompressor.set_extra_bytes_selection(extra_bytes_selection);
             record_decompressor.decompress_many(chunk_out)?;
             Ok(())
         })
@@ -2,7 +2,11 @@ pub use appender::ParLasZipAppender;
 pub use compression::{par_compress, par_compress_buffer, ParLasZipCompressor};
 pub use decompression::{par_decompress, par_decompress_selective};
 pub use decompression::{par_decompress_buffer, ParLasZipDecompressor};
+pub use quantization::par_requantize;
+pub use rechunk::{par_rechunk, RechunkPolicy};
 
 mod appender;
 mod compression;
 mod decompression;
+mod quantization;
+mod rechunk;
@@ -0,0 +1,167 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use rayon::prelude::*;
+
+use crate::laszip::chunk_table::ChunkTable;
+use crate::laszip::Quantization;
+use crate::{LazVlr, ParLasZipCompressor, ParLasZipDecompressor};
+
+/// Decompresses the point data from `source` and compresses it to `dst` with the
+/// coordinates converted from the `from` quantization to the `to` quantization,
+/// using multiple threads.
+///
+/// Just like [`requantize`](crate::requantize), the chunks of the source are kept
+/// and the output is the same.
+///
+/// Chunks are processed in batches of as many chunks as there are threads,
+/// so memory usage stays bounded regardless of the number of points.
+pub fn par_requantize<R, W>(
+    mut source: R,
+    vlr: &LazVlr,
+    point_count: u64,
+    from: &Quantization,
+    to: &Quantization,
+    dst: W,
+) -> crate::Result<W>
+where
+    R: Read + Seek,
+    W: Write + Seek + Send + Sync,
+{
+    let start = source.stream_position()?;
+    let mut chunk_table = ChunkTable::read_from(&mut source, vlr)?;
+    if !vlr.uses_variable_size_chunks() {
+        chunk_table.fix_last_point_count(point_count)?;
+    }
+    source.seek(SeekFrom::Start(start))?;
+
+    let point_size = vlr.items_size() as usize;
+    let mut decompressor =
+        ParLasZipDecompressor::with_chunk_table(source, vlr.clone(), chunk_table.clone())?;
+    let mut compressor = ParLasZipCompressor::new(dst, vlr.clone())?;
+    let chunks = chunk_table
+        .as_ref()
+        .iter()
+        .filter(|entry| entry.point_count != 0)
+        .collect::<Vec<_>>();
+    let mut points = vec![];
+    for batch in chunks.chunks(rayon::current_num_threads()) {
+        let num_points = batch.iter().map(|entry| entry.point_count).sum::<u64>();
+        points.resize(num_points as usize * point_size, 0u8);
+        decompressor.decompress_many(&mut points)?;
+        points
+            .par_chunks_exact_mut(point_size)
+            .try_for_each(|point| from.requantize(point, to))?;
+        if vlr.uses_variable_size_chunks() {
+            let mut rest = points.as_slice();
+            let batch_points = batch
+                .iter()
+                .map(|entry| {
+                    let (chunk, tail) = rest.split_at(entry.point_count as usize * point_size);
+                    rest = tail;
+                    chunk
+                })
+                .collect::<Vec<_>>();
+            compressor.compress_chunks(batch_points)?;
+        } else {
+            compressor.compress_many(&points)?;
+        }
+    }
+    compressor.done()?;
+    Ok(compressor.into_inner())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::test_support::{read_test_points, TEST_LAS_PATH};
+    use crate::{requantize, LasZipCompressor, LazItemRecordBuilder, LazVlrBuilder};
+
+    #[test]
+    fn test_par_requantize_is_same_as_requantize() {
+        let mut file = std::fs::File::open(TEST_LAS_PATH).unwrap();
+        let from = Quantization::read_from_header(&mut file).unwrap();
+        let (points, items) = read_test_points();
+        let point_size = LazVlr::from_laz_items(items.clone()).items_size() as usize;
+        let point_count = (points.len() / point_size) as u64;
+        let coordinates = from.coordinates_of_many(&points, point_size);
+        let to = Quantization::new([0.1, 0.1, 0.1], [0.0, 0.0, 0.0]);
+
+        let fixed_vlr = LazVlrBuilder::new(items.clone())
+            .with_fixed_chunk_size(100)
+            .build();
+        let variable_vlr = LazVlrBuilder::new(items).with_variable_chunk_size().build();
+        for vlr in [fixed_vlr, variable_vlr] {
+            let mut compressor = LasZipCompressor::new(Cursor::new(vec![]), vlr.clone()).unwrap();
+            let mut par_compressor =
+                ParLasZipCompressor::new(Cursor::new(vec![]), vlr.clone()).unwrap();
+            if vlr.uses_variable_size_chunks() {
+                for (chunk, chunk_coordinates) in
+                    points.chunks(150 * point_size).zip(coordinates.chunks(150))
+                {
+                    compressor
+                        .compress_many_quantized(chunk, chunk_coordinates, &from)
+                        .unwrap();
+                    // The last chunk is closed by done
+                    if chunk.len() == 150 * point_size {
+                        compressor.finish_current_chunk().unwrap();
+                    }
+                }
+                par_compressor
+                    .compress_chunks(points.par_chunks(150 * point_size))
+                    .unwrap();
+            } else {
+                compressor
+                    .compress_many_quantized(&points, &coordinates, &from)
+                    .unwrap();
+                par_compressor
+                    .compress_many_quantized(&points, &coordinates, &from)
+                    .unwrap();
+            }
+            compressor.done().unwrap();
+            par_compressor.done().unwrap();
+            let compressed = compressor.into_inner().into_inner();
+            assert_eq!(par_compressor.into_inner().into_inner(), compressed);
+
+            let expected = requantize(
+                Cursor::new(compressed.as_slice()),
+                &vlr,
+                point_count,
+                &from,
+                &to,
+                Cursor::new(vec![]),
+            )
+            .unwrap();
+            let actual = par_requantize(
+                Cursor::new(compressed.as_slice()),
+                &vlr,
+                point_count,
+                &from,
+                &to,
+                Cursor::new(vec![]),
+            )
+            .unwrap();
+            assert_eq!(actual.into_inner(), expected.into_inner());
+        }
+    }
+
+    #[test]
+    fn test_par_compress_many_quantized_checks_coordinates() {
+        let vlr =
+            LazVlrBuilder::new(LazItemRecordBuilder::default_for_point_format_id(0, 0).unwrap())
+                .build();
+        let quantization = Quantization::new([0.01; 3], [0.0; 3]);
+        let points = vec![0u8; 2 * vlr.items_size() as usize];
+        let mut compressor = ParLasZipCompressor::new(Cursor::new(vec![]), vlr).unwrap();
+        assert!(compressor
+            .compress_many_quantized(&points, &[[1.0; 3]], &quantization)
+            .is_err());
+        assert!(compressor
+            .compress_many_quantized(&points, &[[1.0; 3], [1e12; 3]], &quantization)
+            .is_err());
+        compressor
+            .compress_many_quantized(&points, &[[1.0; 3], [2.0; 3]], &quantization)
+            .unwrap();
+    }
+}
@@ -0,0 +1,141 @@
+use std::io::{Read, Seek, Write};
+
+use rayon::prelude::*;
+
+use crate::{LazVlr, ParLasZipCompressor, ParLasZipDecompressor};
+
+/// How the points should be chunked by [`par_rechunk`]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RechunkPolicy {
+    /// **fixed-size** chunks with the given number of points per chunk
+    Fixed(u32),
+    /// **variable-size** chunks, each chunk will have the given number of points
+    /// (except the last one which may have less).
+    ///
+    /// This is useful to produce a file with variable-size chunks,
+    /// that can later be modified (e.g. by appending) with other chunk sizes.
+    Variable(u32),
+}
+
+impl RechunkPolicy {
+    fn points_per_chunk(self) -> u32 {
+        match self {
+            RechunkPolicy::Fixed(n) | RechunkPolicy::Variable(n) => n,
+        }
+    }
+}
+
+/// Rewrites the compressed points of the `source` into the `dst`
+/// using the chunking given by the `policy`.
+///
+/// Points are decompressed & compressed in batches using multiple threads,
+/// so memory usage stays bounded regardless of the number of points.
+///
+/// `source` must be positioned as for [`ChunkTable::read_from`](crate::laszip::ChunkTable::read_from),
+/// `point_count` is the number of points in the source.
+///
+/// Returns the new vlr, it only differs from the `vlr` by its chunk size,
+/// so it can be written in place of the original one.
+pub fn par_rechunk<R, W>(
+    source: R,
+    vlr: &LazVlr,
+    point_count: u64,
+    dst: W,
+    policy: RechunkPolicy,
+) -> crate::Result<LazVlr>
+where
+    R: Read + Seek,
+    W: Write + Seek + Send + Sync,
+{
+    let points_per_chunk = policy.points_per_chunk();
+    if points_per_chunk == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "the number of points per chunk must be greater than 0",
+        )
+        .into());
+    }
+
+    let new_vlr = match policy {
+        RechunkPolicy::Fixed(n) => vlr.clone().with_fixed_chunk_size(n),
+        RechunkPolicy::Variable(_) => vlr.clone().with_variable_chunk_size(),
+    };
+
+    let mut decompressor = ParLasZipDecompressor::new(source, vlr.clone())?;
+    let mut compressor = ParLasZipCompressor::new(dst, new_vlr.clone())?;
+
+    // Work on as many chunks as there are threads at once,
+    // so that all threads are busy.
+    let point_size = vlr.items_size() as usize;
+    let chunk_size_in_bytes = points_per_chunk as usize * point_size;
+    let num_points_per_batch = points_per_chunk as u64 * rayon::current_num_threads() as u64;
+    let mut buffer = Vec::<u8>::new();
+
+    let mut num_points_left = point_count;
+    while num_points_left > 0 {
+        let num_points_in_batch = num_points_per_batch.min(num_points_left);
+        buffer.resize(num_points_in_batch as usize * point_size, 0u8);
+        decompressor.decompress_many(&mut buffer)?;
+
+        // Batches are made of whole chunks, except the last one
+        // so there are no leftovers to carry to the next batch.
+        match policy {
+            RechunkPolicy::Fixed(_) => compressor.compress_many(&buffer)?,
+            RechunkPolicy::Variable(_) => {
+                compressor.compress_chunks(buffer.par_chunks(chunk_size_in_bytes))?
+            }
+        }
+        num_points_left -= num_points_in_batch;
+    }
+
+    compressor.done()?;
+    Ok(new_vlr)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::test_support::{layered_points, read_test_points, with_custom_header};
+    use crate::{LasZipDecompressor, LazItem, LazItemRecordBuilder, LazVlrBuilder};
+
+    fn check_rechunk(points: &[u8], items: Vec<LazItem>) {
+        let vlr = with_custom_header(&LazVlrBuilder::new(items).build());
+        let point_count = points.len() as u64 / vlr.items_size();
+        let mut compressed = Cursor::new(vec![]);
+        crate::compress_buffer(&mut compressed, points, vlr.clone()).unwrap();
+
+        for policy in [
+            RechunkPolicy::Fixed(7),
+            RechunkPolicy::Variable(33),
+            RechunkPolicy::Fixed(100_000),
+        ] {
+            compressed.set_position(0);
+            let mut rechunked = Cursor::new(vec![]);
+            let new_vlr =
+                par_rechunk(&mut compressed, &vlr, point_count, &mut rechunked, policy).unwrap();
+            let expected_vlr = match policy {
+                RechunkPolicy::Fixed(n) => vlr.clone().with_fixed_chunk_size(n),
+                RechunkPolicy::Variable(_) => vlr.clone().with_variable_chunk_size(),
+            };
+            assert_eq!(new_vlr, expected_vlr);
+
+            rechunked.set_position(0);
+            let mut decompressor = LasZipDecompressor::new(rechunked, new_vlr).unwrap();
+            let mut decompressed = vec![0u8; points.len()];
+            decompressor.decompress_many(&mut decompressed).unwrap();
+            assert_eq!(decompressed, points);
+        }
+    }
+
+    #[test]
+    fn test_rechunk() {
+        let (points, items) = read_test_points();
+        check_rechunk(&points, items);
+        check_rechunk(
+            &layered_points(1000),
+            LazItemRecordBuilder::default_for_point_format_id(6, 0).unwrap(),
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! Conversion between the integer coordinates stored in point records
+//! and world coordinates
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::laszip::chunk_table::ChunkTable;
+use crate::laszip::{LasZipCompressor, LasZipDecompressor, LazVlr};
+
+/// Scale & offset of the coordinates, as found in the LAS header
+///
+/// The world coordinate of a point is `raw * scale + offset`, where `raw`
+/// is the `i32` X, Y or Z stored in the point record.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Quantization {
+    pub scale: [f64; 3],
+    pub offset: [f64; 3],
+}
+
+impl Quantization {
+    pub fn new(scale: [f64; 3], offset: [f64; 3]) -> Self {
+        Self { scale, offset }
+    }
+
+    /// Reads the scale & offset from the LAS header at the start of `src`
+    pub fn read_from_header<R: Read + Seek>(mut src: R) -> std::io::Result<Self> {
+        src.seek(SeekFrom::Start(131))?;
+        let mut values = [0f64; 6];
+        for value in values.iter_mut() {
+            *value = src.read_f64::<LittleEndian>()?;
+        }
+        Ok(Self {
+            scale: [values[0], values[1], values[2]],
+            offset: [values[3], values[4], values[5]],
+        })
+    }
+
+    /// Returns the world coordinates of the raw coordinates
+    pub fn dequantize(&self, raw: [i32; 3]) -> [f64; 3] {
+        [0, 1, 2].map(|i| f64::from(raw[i]) * self.scale[i] + self.offset[i])
+    }
+
+    /// Returns the raw coordinates closest to the world coordinates
+    ///
+    /// Fails if a coordinate cannot be represented as an `i32`.
+    pub fn quantize(&self, xyz: [f64; 3]) -> std::io::Result<[i32; 3]> {
+        let mut raw = [0i32; 3];
+        for i in 0..3 {
+            let value = ((xyz[i] - self.offset[i]) / self.scale[i]).round();
+            if !(f64::from(i32::MIN)..=f64::from(i32::MAX)).contains(&value) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "coordinate {} cannot be represented with scale {} and offset {}",
+                        xyz[i], self.scale[i], self.offset[i]
+                    ),
+                ));
+            }
+            raw[i] = value as i32;
+        }
+        Ok(raw)
+    }
+
+    /// Returns the world coordinates of the point record
+    pub fn coordinates(&self, point: &[u8]) -> [f64; 3] {
+        let raw =
+            [0, 1, 2].map(|i| i32::from_le_bytes(point[4 * i..4 * (i + 1)].try_into().unwrap()));
+        self.dequantize(raw)
+    }
+
+    /// Returns the world coordinates of all the point records in `points`
+    pub fn coordinates_of_many(&self, points: &[u8], point_size: usize) -> Vec<[f64; 3]> {
+        points
+            .chunks_exact(point_size)
+            .map(|point| self.coordinates(point))
+            .collect()
+    }
+
+    /// Sets the X, Y, Z of the point record from the world coordinates
+    pub fn set_coordinates(&self, point: &mut [u8], xyz: [f64; 3]) -> std::io::Result<()> {
+        let raw = self.quantize(xyz)?;
+        for (i, value) in raw.iter().enumerate() {
+            point[4 * i..4 * (i + 1)].copy_from_slice(&value.to_le_bytes());
+        }
+        Ok(())
+    }
+
+    /// Sets the X, Y, Z of all the point records in `points` from the `coordinates`
+    ///
+    /// Fails if the number of coordinates is not the number of points,
+    /// or if a coordinate cannot be quantized.
+    pub fn set_coordinates_of_many(
+        &self,
+        points: &mut [u8],
+        point_size: usize,
+        coordinates: &[[f64; 3]],
+    ) -> std::io::Result<()> {
+        if points.len() != coordinates.len() * point_size {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "{} coordinates given for {} points",
+                    coordinates.len(),
+                    points.len() / point_size
+                ),
+            ));
+        }
+        for (point, xyz) in points.chunks_exact_mut(point_size).zip(coordinates) {
+            self.set_coordinates(point, *xyz)?;
+        }
+        Ok(())
+    }
+
+    /// Converts the X, Y, Z of the point record, from this quantization to the `target` one
+    pub fn requantize(&self, point: &mut [u8], target: &Quantization) -> std::io::Result<()> {
+        target.set_coordinates(point, self.coordinates(point))
+    }
+}
+
+/// Decompresses the point data from `source` and compresses it to `dst` with the
+/// coordinates converted from the `from` quantization to the `to` quantization.
+///
+/// The chunks of the source are kept, so the chunk table of the new data has
+/// the same point counts.
+///
+/// `source` must be positioned as for [`ChunkTable::read_from`](crate::laszip::ChunkTable::read_from),
+/// `point_count` is the number of points in the source.
+///
+/// Only the point data is written, the caller has to write a header
+/// with the new scale & offset.
+pub fn requantize<R, W>(
+    mut source: R,
+    vlr: &LazVlr,
+    point_count: u64,
+    from: &Quantization,
+    to: &Quantization,
+    dst: W,
+) -> crate::Result<W>
+where
+    R: Read + Seek + Send + Sync,
+    W: Write + Seek + Send + Sync,
+{
+    let start = source.stream_position()?;
+    let mut chunk_table = ChunkTable::read_from(&mut source, vlr)?;
+    if !vlr.uses_variable_size_chunks() {
+        chunk_table.fix_last_point_count(point_count)?;
+    }
+    source.seek(SeekFrom::Start(start))?;
+
+    let point_size = vlr.items_size() as usize;
+    let mut decompressor = LasZipDecompressor::new(source, vlr.clone())?;
+    let mut compressor = LasZipCompressor::new(dst, vlr.clone())?;
+    let chunks = chunk_table
+        .as_ref()
+        .iter()
+        .filter(|entry| entry.point_count != 0)
+        .collect::<Vec<_>>();
+    let mut points = vec![];
+    for (i, entry) in chunks.iter().enumerate() {
+        points.resize(entry.point_count as usize * point_size, 0u8);
+        decompressor.decompress_many(&mut points)?;
+        for point in points.chunks_exact_mut(point_size) {
+            from.requantize(point, to)?;
+        }
+        compressor.compress_many(&points)?;
+        if vlr.uses_variable_size_chunks() && i + 1 != chunks.len() {
+            compressor.finish_current_chunk()?;
+        }
+    }
+    compressor.done()?;
+    Ok(compressor.into_inner())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::test_support::{compress, compress_in_chunks, test_points_and_vlr, TEST_LAS_PATH};
+
+    #[test]
+    fn test_quantize() {
+        let quantization = Quantization::new([0.01, 0.01, 0.001], [1000.0, 2000.0, 0.0]);
+        let raw = quantization
+            .quantize([1000.014, 1999.996, 12.3456])
+            .unwrap();
+        assert_eq!(raw, [1, 0, 12346]);
+        assert_eq!(
+            quantization.dequantize([100, -100, 1000]),
+            [1001.0, 1999.0, 1.0]
+        );
+        assert!(quantization.quantize([1e9, 0.0, 0.0]).is_err());
+        assert!(quantization.quantize([f64::NAN, 0.0, 0.0]).is_err());
+
+        let mut point = [0u8; 20];
+        quantization
+            .set_coordinates(&mut point, [1001.0, 1999.0, 1.0])
+            .unwrap();
+        assert_eq!(quantization.coordinates(&point), [1001.0, 1999.0, 1.0]);
+    }
+
+    #[test]
+    fn test_requantize() {
+        let mut file = std::fs::File::open(TEST_LAS_PATH).unwrap();
+        let from = Quantization::read_from_header(&mut file).unwrap();
+        let (points, vlr) = test_points_and_vlr(300);
+        let point_size = vlr.items_size() as usize;
+        let point_count = points.len() / point_size;
+
+        // Quantizing the world coordinates with the same scale & offset gives back the points
+        let coordinates = from.coordinates_of_many(&points, point_size);
+        let mut compressor = LasZipCompressor::new(Cursor::new(vec![]), vlr.clone()).unwrap();
+        assert!(compressor
+            .compress_many_quantized(&points, &coordinates[1..], &from)
+            .is_err());
+        compressor
+            .compress_many_quantized(&points, &coordinates, &from)
+            .unwrap();
+        compressor.done().unwrap();
+        let mut compressed = compressor.into_inner();
+        compressed.set_position(0);
+
+        let to = Quantization::new([0.1, 0.1, 0.1], [0.0, 0.0, 0.0]);
+        let mut requantized = requantize(
+            compressed,
+            &vlr,
+            point_count as u64,
+            &from,
+            &to,
+            Cursor::new(vec![]),
+        )
+        .unwrap();
+        requantized.set_position(0);
+
+        let mut decompressor = LasZipDecompressor::new(requantized, vlr).unwrap();
+        let mut output = vec![0u8; points.len()];
+        decompressor.decompress_many(&mut output).unwrap();
+        for (original, new) in points
+            .chunks_exact(point_size)
+            .zip(output.chunks_exact(point_size))
+        {
+            assert_eq!(original[12..], new[12..]);
+            let expected = from.coordinates(original);
+            let actual = to.coordinates(new);
+            for i in 0..3 {
+                assert!((expected[i] - actual[i]).abs() <= 0.05 + 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_requantize_keeps_variable_size_chunks() {
+        let (points, vlr) = test_points_and_vlr(100);
+        let vlr = vlr.with_variable_chunk_size();
+        let point_size = vlr.items_size() as usize;
+        let point_count = (points.len() / point_size) as u64;
+        let data = compress_in_chunks(&points, &vlr, &[10, 500, 1]);
+
+        let from =
+            Quantization::read_from_header(std::fs::File::open(TEST_LAS_PATH).unwrap()).unwrap();
+        let requantized = requantize(
+            Cursor::new(data),
+            &vlr,
+            point_count,
+            &from,
+            &from,
+            Cursor::new(vec![]),
+        )
+        .unwrap()
+        .into_inner();
+
+        let chunk_table = ChunkTable::read_from(Cursor::new(requantized.as_slice()), &vlr).unwrap();
+        let point_counts = chunk_table
+            .as_ref()
+            .iter()
+            .map(|entry| entry.point_count)
+            .collect::<Vec<_>>();
+        assert_eq!(point_counts, [10, 500, 1, point_count - 511]);
+        let mut decompressor = LasZipDecompressor::new(Cursor::new(requantized), vlr).unwrap();
+        let mut output = vec![0u8; points.len()];
+        decompressor.decompress_many(&mut output).unwrap();
+        assert_eq!(output, points);
+    }
+
+    #[test]
+    fn test_requantize_empty() {
+        let (_, vlr) = test_points_and_vlr(100);
+        let quantization = Quantization::new([0.01; 3], [0.0; 3]);
+        let empty = compress(&[], &vlr);
+        let requantized = requantize(
+            Cursor::new(empty.clone()),
+            &vlr,
+            0,
+            &quantization,
+            &quantization,
+            Cursor::new(vec![]),
+        )
+        .unwrap()
+        .into_inner();
+        assert_eq!(requantized, empty);
+    }
+}
@@ -0,0 +1,501 @@
+//! Reordering of the points before compression
+//!
+//! The predictors of the point compressors model the differences between
+//! consecutive points, spatially coherent points compress better.
+use std::io::{Cursor, Seek, Write};
+
+use crate::laszip::details::record_compressor_from_laz_items;
+use crate::laszip::{LasZipCompressor, LazVlr, PointLayout};
+
+/// Number of points sorted together when the vlr uses variable-size chunks
+const DEFAULT_WINDOW_SIZE: usize = 50_000;
+
+/// Number of bits used for each coordinate in the curve indices
+const CURVE_BITS: u32 = 21;
+
+/// Order in which the points of a window are sorted
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SortOrder {
+    /// Along a Morton (Z-order) curve of the X, Y, Z
+    Morton,
+    /// Along a Hilbert curve of the X, Y, Z
+    Hilbert,
+    /// By increasing GPS time
+    GpsTime,
+}
+
+/// Sizes of the windows compressed in their original order and once reordered
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct ReorderReport {
+    /// Number of compressed bytes of the windows in their original order
+    pub original_byte_count: u64,
+    /// Number of compressed bytes of the windows once reordered
+    pub reordered_byte_count: u64,
+}
+
+impl ReorderReport {
+    /// Returns how many times smaller the reordered data is
+    pub fn ratio_improvement(&self) -> f64 {
+        self.original_byte_count as f64 / self.reordered_byte_count as f64
+    }
+}
+
+/// Returns the Morton index of the cell
+pub fn morton_index(cell: [u32; 3]) -> u64 {
+    interleave(cell)
+}
+
+/// Returns the Hilbert index of the cell, the coordinates of the cell
+/// must be below `2^21`.
+pub fn hilbert_index(cell: [u32; 3]) -> u64 {
+    // Skilling's "Programming the Hilbert curve" (AxestoTranspose)
+    let mut x = cell;
+    let m = 1u32 << (CURVE_BITS - 1);
+
+    let mut q = m;
+    while q > 1 {
+        let p = q - 1;
+        for i in 0..3 {
+            if x[i] & q != 0 {
+                x[0] ^= p;
+            } else {
+                let t = (x[0] ^ x[i]) & p;
+                x[0] ^= t;
+                x[i] ^= t;
+            }
+        }
+        q >>= 1;
+    }
+
+    for i in 1..3 {
+        x[i] ^= x[i - 1];
+    }
+    let mut t = 0;
+    q = m;
+    while q > 1 {
+        if x[2] & q != 0 {
+            t ^= q - 1;
+        }
+        q >>= 1;
+    }
+    for value in x.iter_mut() {
+        *value ^= t;
+    }
+    interleave(x)
+}
+
+fn interleave(cell: [u32; 3]) -> u64 {
+    let mut index = 0u64;
+    for bit in (0..CURVE_BITS).rev() {
+        for value in cell {
+            index = (index << 1) | u64::from((value >> bit) & 1);
+        }
+    }
+    index
+}
+
+fn raw_coordinates(point: &[u8]) -> [i32; 3] {
+    [0, 1, 2].map(|i| i32::from_le_bytes(point[4 * i..4 * (i + 1)].try_into().unwrap()))
+}
+
+/// Returns the order in which the points should be written:
+/// the i-th point of the reordered points is `points[permutation[i]]`.
+///
+/// The curve indices are computed on the bounding box of the points.
+/// Points with the same key keep their relative order.
+pub fn sort_permutation(
+    points: &[u8],
+    point_size: usize,
+    layout: &PointLayout,
+    order: SortOrder,
+) -> std::io::Result<Vec<usize>> {
+    let mut permutation = (0..points.len() / point_size).collect::<Vec<_>>();
+    let point = |i: usize| &points[i * point_size..(i + 1) * point_size];
+    match order {
+        SortOrder::GpsTime => {
+            let Some(offset) = layout.gps_time_offset() else {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "Points do not have a gps time",
+                ));
+            };
+            let gps_time =
+                |i: usize| f64::from_le_bytes(point(i)[offset..offset + 8].try_into().unwrap());
+            permutation.sort_by(|a, b| gps_time(*a).total_cmp(&gps_time(*b)));
+        }
+        SortOrder::Morton | SortOrder::Hilbert => {
+            let mut min = [i32::MAX; 3];
+            let mut max = [i32::MIN; 3];
+            for i in 0..permutation.len() {
+                let xyz = raw_coordinates(point(i));
+                for axis in 0..3 {
+                    min[axis] = min[axis].min(xyz[axis]);
+                    max[axis] = max[axis].max(xyz[axis]);
+                }
+            }
+            // The same shift is used on all axes to keep the cells cubic
+            let largest_range = (0..3)
+                .map(|axis| (i64::from(max[axis]) - i64::from(min[axis])) as u64)
+                .max()
+                .unwrap_or(0);
+            let range_bits = u64::BITS - largest_range.leading_zeros();
+            let shift = range_bits.saturating_sub(CURVE_BITS);
+            let curve_index = if order == SortOrder::Morton {
+                morton_index
+            } else {
+                hilbert_index
+            };
+            let keys = (0..permutation.len())
+                .map(|i| {
+                    let xyz = raw_coordinates(point(i));
+                    let cell = [0, 1, 2].map(|axis| {
+                        ((i64::from(xyz[axis]) - i64::from(min[axis])) as u64 >> shift) as u32
+                    });
+                    curve_index(cell)
+                })
+                .collect::<Vec<_>>();
+            permutation.sort_by_key(|i| keys[*i]);
+        }
+    }
+    Ok(permutation)
+}
+
+/// Puts back the points in their original order, `permutation` being
+/// the one recorded by the [`ReorderingCompressor`].
+pub fn restore_order(points: &[u8], permutation: &[u64], point_size: usize) -> Vec<u8> {
+    debug_assert_eq!(points.len(), permutation.len() * point_size);
+    let mut restored = vec![0u8; points.len()];
+    for (point, original_index) in points.chunks_exact(point_size).zip(permutation) {
+        let start = *original_index as usize * point_size;
+        restored[start..start + point_size].copy_from_slice(point);
+    }
+    restored
+}
+
+/// Compresses the points after having sorted them, window by window,
+/// in a [`SortOrder`].
+///
+/// By default the windows are the chunks of the vlr for **fixed-size** chunks,
+/// and windows of 50 000 points, each compressed as one chunk, for **variable-size** chunks.
+///
+/// The reordered points are what gets decompressed, use
+/// [`with_permutation`](Self::with_permutation) and [`restore_order`]
+/// to be able to get back the original order.
+pub struct ReorderingCompressor<'a, W: Write + Seek + Send + Sync + 'a> {
+    compressor: LasZipCompressor<'a, W>,
+    order: SortOrder,
+    layout: PointLayout,
+    window_size: usize,
+    /// Points of the current window
+    window: Vec<u8>,
+    /// Whether the chunk of the previous window must be finished
+    /// before compressing the next one (variable-size chunks only)
+    finish_chunk: bool,
+    num_points_seen: u64,
+    permutation: Option<Vec<u64>>,
+    report: Option<ReorderReport>,
+}
+
+impl<'a, W: Write + Seek + Send + Sync + 'a> ReorderingCompressor<'a, W> {
+    /// Fails if the order is [`SortOrder::GpsTime`] and the points have no gps time
+    pub fn new(compressor: LasZipCompressor<'a, W>, order: SortOrder) -> crate::Result<Self> {
+        let layout = PointLayout::from_vlr(compressor.vlr());
+        if order == SortOrder::GpsTime && layout.gps_time_offset().is_none() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Points do not have a gps time",
+            )
+            .into());
+        }
+        let window_size = if compressor.vlr().uses_variable_size_chunks() {
+            DEFAULT_WINDOW_SIZE
+        } else {
+            compressor.vlr().chunk_size() as usize
+        };
+        Ok(Self {
+            compressor,
+            order,
+            layout,
+            window_size,
+            window: Vec::new(),
+            finish_chunk: false,
+            num_points_seen: 0,
+            permutation: None,
+            report: None,
+        })
+    }
+
+    /// Sets the number of points sorted together.
+    ///
+    /// With **fixed-size** chunks, the window size should be a multiple of the chunk size.
+    pub fn with_window_size(mut self, window_size: usize) -> Self {
+        self.window_size = window_size.max(1);
+        self
+    }
+
+    /// Records the permutation applied to the points, see [`permutation`](Self::permutation).
+    pub fn with_permutation(mut self) -> Self {
+        self.permutation = Some(Vec::new());
+        self
+    }
+
+    /// Enables the [`ReorderReport`], see [`report`](Self::report).
+    ///
+    /// Each window is also compressed in its original order to measure
+    /// the improvement, which doubles the compression work.
+    pub fn with_report(mut self) -> Self {
+        self.report = Some(ReorderReport::default());
+        self
+    }
+
+    /// Adds the points, they are compressed when a window is complete
+    pub fn compress_many(&mut self, points: &[u8]) -> std::io::Result<()> {
+        let point_size = self.compressor.vlr().items_size() as usize;
+        let window_size_in_bytes = self.window_size * point_size;
+        let mut points = points;
+        while !points.is_empty() {
+            let num_bytes = (window_size_in_bytes - self.window.len()).min(points.len());
+            self.window.extend_from_slice(&points[..num_bytes]);
+            points = &points[num_bytes..];
+            if self.window.len() == window_size_in_bytes {
+                self.compress_window()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Compresses the last window and finishes the compression,
+    /// see [`LasZipCompressor::done`].
+    pub fn done(&mut self) -> std::io::Result<()> {
+        if !self.window.is_empty() {
+            self.compress_window()?;
+        }
+        self.compressor.done()
+    }
+
+    /// Returns, for each point compressed so far, the index it had in the input,
+    /// if [`with_permutation`](Self::with_permutation) was used.
+    pub fn permutation(&self) -> Option<&[u64]> {
+        self.permutation.as_deref()
+    }
+
+    /// Returns the sizes of the windows compressed so far,
+    /// if [`with_report`](Self::with_report) was used.
+    pub fn report(&self) -> Option<&ReorderReport> {
+        self.report.as_ref()
+    }
+
+    pub fn compressor(&self) -> &LasZipCompressor<'a, W> {
+        &self.compressor
+    }
+
+    pub fn into_compressor(self) -> LasZipCompressor<'a, W> {
+        self.compressor
+    }
+
+    fn compress_window(&mut self) -> std::io::Result<()> {
+        let vlr = self.compressor.vlr();
+        let point_size = vlr.items_size() as usize;
+        let permutation = sort_permutation(&self.window, point_size, &self.layout, self.order)?;
+        let mut reordered = Vec::with_capacity(self.window.len());
+        for index in &permutation {
+            reordered.extend_from_slice(&self.window[index * point_size..(index + 1) * point_size]);
+        }
+
+        if let Some(report) = &mut self.report {
+            report.original_byte_count += compressed_size(&self.window, vlr)?;
+            report.reordered_byte_count += compressed_size(&reordered, vlr)?;
+        }
+        if let Some(recorded) = &mut self.permutation {
+            recorded.extend(
+                permutation
+                    .iter()
+                    .map(|index| self.num_points_seen + *index as u64),
+            );
+        }
+
+        if self.finish_chunk {
+            self.compressor.finish_current_chunk()?;
+        }
+        self.compressor.compress_many(&reordered)?;
+        self.finish_chunk = self.compressor.vlr().uses_variable_size_chunks();
+        self.num_points_seen += permutation.len() as u64;
+        self.window.clear();
+        Ok(())
+    }
+}
+
+/// Returns the number of bytes of the points compressed as one chunk
+fn compressed_size(points: &[u8], vlr: &LazVlr) -> std::io::Result<u64> {
+    let mut compressor = record_compressor_from_laz_items(vlr.items(), Cursor::new(Vec::new()))
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+    compressor.compress_many(points)?;
+    compressor.done()?;
+    Ok(compressor.get().get_ref().len() as u64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::laszip::ChunkTable;
+    use crate::test_support::{compress, test_points_and_vlr};
+    use crate::{LasZipDecompressor, LazItemRecordBuilder, LazVlrBuilder};
+
+    #[test]
+    fn test_hilbert_curve_is_continuous() {
+        // On a 4x4x4 grid (the highest bits being 0), consecutive
+        // indices are neighbour cells
+        let mut cells = vec![];
+        for x in 0..4 {
+            for y in 0..4 {
+                for z in 0..4 {
+                    cells.push([x, y, z]);
+                }
+            }
+        }
+        cells.sort_by_key(|cell| hilbert_index(*cell));
+        for (index, cell) in cells.iter().enumerate() {
+            assert_eq!(hilbert_index(*cell), index as u64);
+        }
+        for pair in cells.windows(2) {
+            let distance = (0..3)
+                .map(|axis| pair[0][axis].abs_diff(pair[1][axis]))
+                .sum::<u32>();
+            assert_eq!(distance, 1, "{:?}", pair);
+        }
+        assert_eq!(morton_index([1, 0, 0]), 4);
+        assert_eq!(morton_index([1, 1, 1]), 7);
+    }
+
+    #[test]
+    fn test_reordering_compressor() {
+        let items = LazItemRecordBuilder::default_for_point_format_id(1, 0).unwrap();
+        let point_size = 28;
+        // Points of a grid, shuffled
+        let mut cells = (0..4096usize).collect::<Vec<_>>();
+        let mut state = 42u64;
+        for i in (1..cells.len()).rev() {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+            cells.swap(i, (state >> 33) as usize % (i + 1));
+        }
+        let mut points = vec![0u8; 4096 * point_size];
+        for (point, cell) in points.chunks_exact_mut(point_size).zip(cells) {
+            let xyz = [cell % 16, (cell / 16) % 16, cell / 256].map(|v| v as i32 * 100);
+            for axis in 0..3 {
+                point[4 * axis..4 * (axis + 1)].copy_from_slice(&xyz[axis].to_le_bytes());
+            }
+            point[14] = 0x11;
+            point[20..28].copy_from_slice(&(cell as f64).to_le_bytes());
+        }
+
+        for vlr in [
+            LazVlrBuilder::new(items.clone())
+                .with_fixed_chunk_size(1000)
+                .build(),
+            LazVlrBuilder::new(items.clone())
+                .with_variable_chunk_size()
+                .build(),
+        ] {
+            for order in [SortOrder::Morton, SortOrder::Hilbert] {
+                let compressor =
+                    LasZipCompressor::new(Cursor::new(Vec::new()), vlr.clone()).unwrap();
+                let mut compressor = ReorderingCompressor::new(compressor, order)
+                    .unwrap()
+                    .with_window_size(2000)
+                    .with_permutation()
+                    .with_report();
+                compressor
+                    .compress_many(&points[..100 * point_size])
+                    .unwrap();
+                compressor
+                    .compress_many(&points[100 * point_size..])
+                    .unwrap();
+                compressor.done().unwrap();
+                let report = *compressor.report().unwrap();
+                assert!(report.ratio_improvement() > 1.0, "{:?}", report);
+                let permutation = compressor.permutation().unwrap().to_vec();
+
+                let data = compressor.into_compressor().into_inner().into_inner();
+                let mut decompressor =
+                    LasZipDecompressor::new(Cursor::new(data), vlr.clone()).unwrap();
+                let mut output = vec![0u8; points.len()];
+                decompressor.decompress_many(&mut output).unwrap();
+                assert_ne!(output, points);
+                assert_eq!(restore_order(&output, &permutation, point_size), points);
+            }
+        }
+
+        let vlr = LazVlrBuilder::new(items)
+            .with_fixed_chunk_size(1000)
+            .build();
+        let compressor = LasZipCompressor::new(Cursor::new(Vec::new()), vlr.clone()).unwrap();
+        let mut compressor = ReorderingCompressor::new(compressor, SortOrder::GpsTime)
+            .unwrap()
+            .with_window_size(4096);
+        compressor.compress_many(&points).unwrap();
+        compressor.done().unwrap();
+        let data = compressor.into_compressor().into_inner().into_inner();
+        let mut decompressor = LasZipDecompressor::new(Cursor::new(data), vlr).unwrap();
+        let mut output = vec![0u8; points.len()];
+        decompressor.decompress_many(&mut output).unwrap();
+        let gps_times = output
+            .chunks_exact(point_size)
+            .map(|point| f64::from_le_bytes(point[20..28].try_into().unwrap()))
+            .collect::<Vec<_>>();
+        assert!(gps_times.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        let vlr =
+            LazVlrBuilder::new(LazItemRecordBuilder::default_for_point_format_id(0, 0).unwrap())
+                .build();
+        let compressor = LasZipCompressor::new(Cursor::new(Vec::new()), vlr).unwrap();
+        assert!(ReorderingCompressor::new(compressor, SortOrder::GpsTime).is_err());
+    }
+
+    #[test]
+    fn test_reordering_last_partial_window() {
+        // 1065 points: two full windows of 500 points, then one of 65 points
+        let (points, vlr) = test_points_and_vlr(100);
+        let vlr = vlr.with_variable_chunk_size();
+        let point_size = vlr.items_size() as usize;
+        let compressor = LasZipCompressor::new(Cursor::new(Vec::new()), vlr.clone()).unwrap();
+        let mut compressor = ReorderingCompressor::new(compressor, SortOrder::GpsTime)
+            .unwrap()
+            .with_window_size(500)
+            .with_permutation();
+        compressor.compress_many(&points).unwrap();
+        compressor.done().unwrap();
+        let permutation = compressor.permutation().unwrap().to_vec();
+        assert_eq!(permutation.len(), 1065);
+        // Points are only moved inside their window
+        assert!(permutation[1000..].iter().all(|index| *index >= 1000));
+
+        let data = compressor.into_compressor().into_inner().into_inner();
+        let chunk_table = ChunkTable::read_from(Cursor::new(data.as_slice()), &vlr).unwrap();
+        let point_counts = chunk_table
+            .as_ref()
+            .iter()
+            .map(|entry| entry.point_count)
+            .collect::<Vec<_>>();
+        assert_eq!(point_counts, [500, 500, 65]);
+        let mut decompressor = LasZipDecompressor::new(Cursor::new(data), vlr).unwrap();
+        let mut output = vec![0u8; points.len()];
+        decompressor.decompress_many(&mut output).unwrap();
+        assert_eq!(restore_order(&output, &permutation, point_size), points);
+    }
+
+    #[test]
+    fn test_reordering_empty() {
+        let (_, vlr) = test_points_and_vlr(100);
+        let compressor = LasZipCompressor::new(Cursor::new(Vec::new()), vlr.clone()).unwrap();
+        let mut compressor = ReorderingCompressor::new(compressor, SortOrder::Hilbert)
+            .unwrap()
+            .with_permutation()
+            .with_report();
+        compressor.compress_many(&[]).unwrap();
+        compressor.done().unwrap();
+        assert_eq!(compressor.permutation(), Some(&[][..]));
+        assert_eq!(compressor.report(), Some(&ReorderReport::default()));
+        let data = compressor.into_compressor().into_inner().into_inner();
+        assert_eq!(data, compress(&[], &vlr));
+    }
+}
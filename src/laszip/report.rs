@@ -0,0 +1,255 @@
+//! Statistics about where the bytes of compressed data go
+use std::io::{Cursor, Read, Seek};
+
+use crate::laszip::chunk_table::ChunkTable;
+use crate::laszip::details::{
+    record_compressor_from_laz_items, record_decompressor_from_laz_items,
+};
+use crate::laszip::layers::{LayerKind, LayeredChunkLayout};
+use crate::laszip::{CompressorType, LazItem, LazItemType, LazVlr};
+use crate::LasZipError;
+
+/// A part of the compressed data that is accounted separately
+///
+/// Layered chunks are accounted per field (each layer is one field, or a group of
+/// fields such as the channel, returns & XY), point-wise chunks only per LAZ item.
+/// Reports of point-wise chunks thus have no per-field breakdown, and are not
+/// comparable field by field with reports of layered chunks.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CompressedField {
+    /// A layer of a **layered** chunk (point formats >= 6)
+    Layer(LayerKind),
+    /// A whole LAZ item of a **point-wise** chunk (point formats <= 5),
+    /// e.g. all the fields of a `Point10` (X, Y, Z, intensity, ...) together.
+    Item(LazItemType),
+}
+
+/// Number of compressed bytes of a layer or of a LAZ item, see [`CompressedField`]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct FieldByteCount {
+    pub field: CompressedField,
+    pub byte_count: u64,
+}
+
+/// Statistics of a single chunk
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ChunkReport {
+    pub point_count: u64,
+    /// Total size of the chunk in bytes
+    pub byte_count: u64,
+    /// Bytes used by each field.
+    ///
+    /// The uncompressed first point and, for layered chunks, the layer sizes
+    /// are not part of any field, so the sum of the fields is less than
+    /// the `byte_count`.
+    ///
+    /// For point-wise chunks, the bytes are counted per LAZ item
+    /// (e.g. all the fields of a `Point10` together), not per field of the point,
+    /// and as all the items share the same encoder, the counts are estimations.
+    pub fields: Vec<FieldByteCount>,
+}
+
+/// Statistics about the compressed size of the data, per chunk
+///
+/// The sizes are given per layer for layered chunks and per LAZ item (not per field)
+/// for point-wise chunks, see [`CompressedField`].
+///
+/// It can be read from compressed data with [`Self::read_from`], or made while compressing
+/// with the `enable_report` method of the [`LasZipCompressor`](crate::LasZipCompressor)
+/// or of the `ParLasZipCompressor`.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct CompressionReport {
+    pub chunks: Vec<ChunkReport>,
+}
+
+impl CompressionReport {
+    /// Creates the report of existing compressed data.
+    ///
+    /// For layered chunks only the layer sizes need to be read,
+    /// point-wise chunks however have to be decompressed and re-compressed
+    /// to account the bytes of each item.
+    ///
+    /// `source` must be positioned as for [`ChunkTable::read_from`](crate::laszip::ChunkTable::read_from),
+    /// `point_count` is the number of points in the source.
+    pub fn read_from<R: Read + Seek + Send + Sync>(
+        mut source: R,
+        vlr: &LazVlr,
+        point_count: u64,
+    ) -> crate::Result<Self> {
+        if vlr.compressor != CompressorType::PointWiseChunked
+            && vlr.compressor != CompressorType::LayeredChunked
+        {
+            return Err(LasZipError::UnsupportedCompressorType(vlr.compressor));
+        }
+        let mut chunk_table = ChunkTable::read_from(&mut source, vlr)?;
+        if !vlr.uses_variable_size_chunks() {
+            chunk_table.fix_last_point_count(point_count)?;
+        }
+
+        let mut chunks = Vec::with_capacity(chunk_table.len());
+        let mut chunk = Vec::<u8>::new();
+        for entry in &chunk_table {
+            chunk.resize(entry.byte_count as usize, 0u8);
+            source.read_exact(&mut chunk)?;
+
+            let fields = if entry.point_count == 0 {
+                vec![]
+            } else if vlr.compressor == CompressorType::LayeredChunked {
+                LayeredChunkLayout::read_from(&chunk, vlr.items())?
+                    .layers()
+                    .iter()
+                    .map(|layer| FieldByteCount {
+                        field: CompressedField::Layer(layer.kind),
+                        byte_count: layer.size as u64,
+                    })
+                    .collect()
+            } else {
+                point_wise_chunk_fields(&chunk, vlr.items(), entry.point_count)?
+            };
+            chunks.push(ChunkReport {
+                point_count: entry.point_count,
+                byte_count: entry.byte_count,
+                fields,
+            });
+        }
+        Ok(Self { chunks })
+    }
+
+    /// Total number of points
+    pub fn point_count(&self) -> u64 {
+        self.chunks.iter().map(|chunk| chunk.point_count).sum()
+    }
+
+    /// Total number of bytes of all the chunks
+    pub fn byte_count(&self) -> u64 {
+        self.chunks.iter().map(|chunk| chunk.byte_count).sum()
+    }
+
+    /// Total number of bytes of each field, over all the chunks
+    pub fn field_totals(&self) -> Vec<FieldByteCount> {
+        let mut totals = Vec::<FieldByteCount>::new();
+        for field in self.chunks.iter().flat_map(|chunk| &chunk.fields) {
+            match totals.iter_mut().find(|total| total.field == field.field) {
+                Some(total) => total.byte_count += field.byte_count,
+                None => totals.push(*field),
+            }
+        }
+        totals
+    }
+}
+
+/// Associates the bytes counts of the item compressors of a point-wise
+/// record compressor to their item.
+pub(crate) fn point_wise_items(items: &[LazItem], byte_counts: Vec<u64>) -> Vec<FieldByteCount> {
+    items
+        .iter()
+        .zip(byte_counts)
+        .map(|(item, byte_count)| FieldByteCount {
+            field: CompressedField::Item(item.item_type),
+            byte_count,
+        })
+        .collect()
+}
+
+/// Decompresses the point-wise `chunk` and re-compresses it,
+/// counting the bytes of each item.
+fn point_wise_chunk_fields(
+    chunk: &[u8],
+    items: &Vec<LazItem>,
+    point_count: u64,
+) -> crate::Result<Vec<FieldByteCount>> {
+    let mut decompressor = record_decompressor_from_laz_items(items, Cursor::new(chunk))?;
+    let mut compressor = record_compressor_from_laz_items(items, std::io::sink())?;
+    compressor.enable_field_byte_counts();
+
+    let mut point = vec![0u8; decompressor.record_size()];
+    for _ in 0..point_count {
+        decompressor.decompress_next(&mut point)?;
+        compressor.compress_next(&point)?;
+    }
+    let byte_counts = compressor
+        .field_byte_counts()
+        .expect("point-wise compressors count the bytes of their fields");
+    Ok(point_wise_items(items, byte_counts))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::test_points_and_vlr;
+    use crate::{LasZipCompressor, LazItemRecordBuilder, LazVlrBuilder};
+
+    #[test]
+    fn test_point_wise_report() {
+        let (points, vlr) = test_points_and_vlr(250);
+        let point_size = vlr.items_size();
+        let point_count = points.len() as u64 / point_size;
+
+        let mut compressor = LasZipCompressor::new(Cursor::new(vec![]), vlr.clone()).unwrap();
+        compressor.enable_report();
+        compressor.compress_many(&points).unwrap();
+        compressor.done().unwrap();
+        let compressor_report = compressor.report().unwrap().clone();
+        let mut compressed = compressor.into_inner();
+        compressed.set_position(0);
+
+        let report = CompressionReport::read_from(compressed, &vlr, point_count).unwrap();
+        assert_eq!(report, compressor_report);
+        assert_eq!(report.point_count(), point_count);
+        assert_eq!(report.chunks.len(), 5);
+
+        for chunk in &report.chunks {
+            assert_eq!(chunk.fields.len(), vlr.items().len());
+            let fields_byte_count = chunk.fields.iter().map(|f| f.byte_count).sum::<u64>();
+            // The fields do not account for the raw first point
+            // and the few bytes that ends the encoding
+            assert!(fields_byte_count <= chunk.byte_count);
+            assert!(chunk.byte_count - fields_byte_count <= point_size + 8);
+        }
+
+        let totals = report.field_totals();
+        assert_eq!(totals.len(), vlr.items().len());
+        assert_eq!(totals[0].field, CompressedField::Item(LazItemType::Point10));
+    }
+
+    #[test]
+    fn test_layered_report() {
+        let point_size = 30;
+        let points = (0..500u32)
+            .flat_map(|i| {
+                let mut point = vec![0u8; point_size];
+                point[0..4].copy_from_slice(&(i * 3).to_le_bytes());
+                point[14] = 0x11;
+                point[16] = (i % 3) as u8;
+                point
+            })
+            .collect::<Vec<u8>>();
+        let vlr =
+            LazVlrBuilder::new(LazItemRecordBuilder::default_for_point_format_id(6, 0).unwrap())
+                .build();
+        let mut compressed = Cursor::new(vec![]);
+        crate::compress_buffer(&mut compressed, &points, vlr.clone()).unwrap();
+        compressed.set_position(0);
+
+        let report = CompressionReport::read_from(compressed, &vlr, 500).unwrap();
+        assert_eq!(report.chunks.len(), 1);
+        let chunk = &report.chunks[0];
+        let fields_byte_count = chunk.fields.iter().map(|f| f.byte_count).sum::<u64>();
+        assert_eq!(
+            chunk.byte_count - fields_byte_count,
+            (point_size + 4 + 4 * chunk.fields.len()) as u64
+        );
+        let byte_count_of = |kind| {
+            chunk
+                .fields
+                .iter()
+                .find(|f| f.field == CompressedField::Layer(kind))
+                .unwrap()
+                .byte_count
+        };
+        assert!(byte_count_of(LayerKind::XyReturnsChannel) > 0);
+        assert!(byte_count_of(LayerKind::Classification) > 0);
+        assert_eq!(byte_count_of(LayerKind::Intensity), 0);
+        assert_eq!(byte_count_of(LayerKind::GpsTime), 0);
+    }
+}
@@ -0,0 +1,315 @@
+use std::io::Cursor;
+
+use crate::las::selective::{DecompressionSelection, ExtraBytesSelection};
+use crate::laszip::details::record_decompressor_from_laz_items;
+use crate::laszip::LazVlr;
+
+/// How points are sampled by the `decompress_sampled` functions
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SamplingMode {
+    /// Keeps one point every `n` points
+    Stride(u64),
+    /// Keeps each point with the given probability (between 0 and 1),
+    /// the same seed always gives the same sample.
+    Random { fraction: f64, seed: u64 },
+    /// Keeps the first `k` points of each chunk
+    FirstPerChunk(u64),
+    /// Keeps all the points of one chunk every `n` chunks
+    ChunkStride(u64),
+}
+
+/// Decides which points are kept when decompressing with a [`SamplingMode`]
+///
+/// The sampler keeps track of the points it has seen, so the same sampler
+/// must be used for successive calls on the same decompressor.
+///
+/// When the chunk table is available, chunks that have no point to keep
+/// are skipped without reading their bytes, and chunks are only decompressed
+/// up to their last kept point.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointSampler {
+    mode: SamplingMode,
+    num_chunks_seen: u64,
+    num_points_seen: u64,
+    random_state: u64,
+}
+
+impl PointSampler {
+    pub fn new(mode: SamplingMode) -> Self {
+        let random_state = match mode {
+            // xorshift's state must not be 0
+            SamplingMode::Random { seed, .. } => seed.max(1),
+            _ => 1,
+        };
+        Self {
+            mode,
+            num_chunks_seen: 0,
+            num_points_seen: 0,
+            random_state,
+        }
+    }
+
+    pub fn mode(&self) -> SamplingMode {
+        self.mode
+    }
+
+    /// Returns whether the point is kept, `index_in_chunk` is the position
+    /// of the point in its chunk.
+    ///
+    /// Must be called once for each point, in order.
+    pub fn keeps_point(&mut self, index_in_chunk: u64) -> bool {
+        if index_in_chunk == 0 {
+            self.num_chunks_seen += 1;
+        }
+        let point_index = self.num_points_seen;
+        self.num_points_seen += 1;
+        match self.mode {
+            SamplingMode::Stride(n) => point_index % n.max(1) == 0,
+            SamplingMode::Random { fraction, .. } => {
+                // xorshift64*
+                self.random_state ^= self.random_state >> 12;
+                self.random_state ^= self.random_state << 25;
+                self.random_state ^= self.random_state >> 27;
+                let value = self.random_state.wrapping_mul(0x2545_F491_4F6C_DD1D);
+                ((value >> 11) as f64 / (1u64 << 53) as f64) < fraction
+            }
+            SamplingMode::FirstPerChunk(k) => index_in_chunk < k,
+            SamplingMode::ChunkStride(n) => (self.num_chunks_seen - 1) % n.max(1) == 0,
+        }
+    }
+
+    /// Returns the indices of the points kept in a chunk of `point_count` points
+    pub(crate) fn kept_in_chunk(&mut self, point_count: u64) -> Vec<u64> {
+        (0..point_count)
+            .filter(|index| self.keeps_point(*index))
+            .collect()
+    }
+}
+
+/// Decompresses the chunk up to its last kept point, and appends
+/// the kept points to `out`.
+pub(crate) fn decompress_chunk_sampled(
+    chunk: &[u8],
+    kept: &[u64],
+    vlr: &LazVlr,
+    selection: DecompressionSelection,
+    extra_bytes: ExtraBytesSelection,
+    out: &mut Vec<u8>,
+) -> crate::Result<()> {
+    let Some(last) = kept.last() else {
+        return Ok(());
+    };
+    let point_size = vlr.items_size() as usize;
+    let mut points = vec![0u8; (*last as usize + 1) * point_size];
+    let mut decompressor = record_decompressor_from_laz_items(vlr.items(), Cursor::new(chunk))?;
+    decompressor.set_selection(selection);
+    decompressor.set_extra_bytes_selection(extra_bytes);
+    decompressor.decompress_many(&mut points)?;
+    for index in kept {
+        let start = *index as usize * point_size;
+        out.extend_from_slice(&points[start..start + point_size]);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Seek, SeekFrom};
+
+    use super::*;
+    use crate::test_support::{compress, compress_in_chunks, test_points_and_vlr};
+    use crate::{LasZipDecompressor, LazItemRecordBuilder, LazVlrBuilder};
+
+    /// Source that counts the bytes read
+    struct CountingSource<'a> {
+        inner: Cursor<&'a [u8]>,
+        num_bytes_read: u64,
+    }
+
+    impl Read for CountingSource<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.num_bytes_read += n as u64;
+            Ok(n)
+        }
+    }
+
+    impl Seek for CountingSource<'_> {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    const CHUNK_SIZES: [u64; 5] = [100, 150, 250, 300, 200];
+
+    fn points(point_size: usize) -> Vec<u8> {
+        let mut points = vec![0u8; 1000 * point_size];
+        for (i, point) in points.chunks_exact_mut(point_size).enumerate() {
+            point[0..4].copy_from_slice(&(i as i32 * 3).to_le_bytes());
+            point[4..8].copy_from_slice(&(i as i32 % 17).to_le_bytes());
+            point[12..14].copy_from_slice(&(i as u16).to_le_bytes());
+            point[14] = 0x11;
+        }
+        points
+    }
+
+    fn compress_variable_size(point_format_id: u8) -> (Vec<u8>, Vec<u8>, LazVlr) {
+        let items = LazItemRecordBuilder::default_for_point_format_id(point_format_id, 0).unwrap();
+        let vlr = LazVlrBuilder::new(items).with_variable_chunk_size().build();
+        let point_size = vlr.items_size() as usize;
+        let points = points(point_size);
+        let data = compress_in_chunks(&points, &vlr, &CHUNK_SIZES.map(|size| size as usize));
+        (points, data, vlr)
+    }
+
+    fn expected_sample(points: &[u8], point_size: usize, mode: SamplingMode) -> Vec<u8> {
+        let mut sampler = PointSampler::new(mode);
+        let mut output = vec![];
+        let mut point_chunks = points.chunks_exact(point_size);
+        for size in CHUNK_SIZES {
+            for index in 0..size {
+                let point = point_chunks.next().unwrap();
+                if sampler.keeps_point(index) {
+                    output.extend_from_slice(point);
+                }
+            }
+        }
+        output
+    }
+
+    fn modes() -> [SamplingMode; 5] {
+        [
+            SamplingMode::Stride(7),
+            SamplingMode::Stride(400),
+            SamplingMode::Random {
+                fraction: 0.1,
+                seed: 42,
+            },
+            SamplingMode::FirstPerChunk(10),
+            SamplingMode::ChunkStride(2),
+        ]
+    }
+
+    #[test]
+    fn test_decompress_sampled() {
+        for point_format_id in [1, 6] {
+            let (points, data, vlr) = compress_variable_size(point_format_id);
+            let point_size = vlr.items_size() as usize;
+            for mode in modes() {
+                let expected = expected_sample(&points, point_size, mode);
+
+                let mut decompressor =
+                    LasZipDecompressor::new(Cursor::new(data.as_slice()), vlr.clone()).unwrap();
+                let mut sampler = PointSampler::new(mode);
+                let mut output = vec![];
+                let mut num_kept = 0;
+                for num_points in [50, 450, 500] {
+                    num_kept += decompressor
+                        .decompress_sampled(num_points, &mut sampler, &mut output)
+                        .unwrap();
+                }
+                assert_eq!(num_kept as usize * point_size, output.len());
+                assert_eq!(output, expected, "{:?}", mode);
+
+                #[cfg(feature = "parallel")]
+                {
+                    let mut decompressor = crate::ParLasZipDecompressor::new(
+                        Cursor::new(data.as_slice()),
+                        vlr.clone(),
+                    )
+                    .unwrap();
+                    let mut sampler = PointSampler::new(mode);
+                    let mut output = vec![];
+                    for num_points in [50, 450, 500] {
+                        decompressor
+                            .decompress_sampled(num_points, &mut sampler, &mut output)
+                            .unwrap();
+                    }
+                    assert_eq!(output, expected, "{:?}", mode);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_skipped_chunks_are_not_read() {
+        let (_, data, vlr) = compress_variable_size(6);
+        let source = CountingSource {
+            inner: Cursor::new(data.as_slice()),
+            num_bytes_read: 0,
+        };
+        let mut decompressor = LasZipDecompressor::new(source, vlr).unwrap();
+        // Points 0, 250, 500 and 750 are kept, only chunks 0, 2 and 3 are read
+        let mut sampler = PointSampler::new(SamplingMode::Stride(250));
+        let mut output = vec![];
+        let num_kept = decompressor
+            .decompress_sampled(1000, &mut sampler, &mut output)
+            .unwrap();
+        assert_eq!(num_kept, 4);
+
+        let table =
+            crate::laszip::ChunkTable::read_from(Cursor::new(data.as_slice()), decompressor.vlr())
+                .unwrap();
+        let table = table.as_ref();
+        let num_bytes_read = decompressor.get().num_bytes_read;
+        let chunk_table_size =
+            data.len() as u64 - 8 - table.iter().map(|entry| entry.byte_count).sum::<u64>();
+        assert_eq!(
+            num_bytes_read,
+            8 + chunk_table_size + table[0].byte_count + table[2].byte_count + table[3].byte_count
+        );
+    }
+
+    #[test]
+    fn test_decompress_sampled_last_partial_chunk() {
+        // 1065 points in fixed-size chunks of 100, the last chunk has 65 points
+        let (points, vlr) = test_points_and_vlr(100);
+        let point_size = vlr.items_size() as usize;
+        let point_count = (points.len() / point_size) as u64;
+        let data = compress(&points, &vlr);
+        for (mode, expected) in [
+            (
+                SamplingMode::ChunkStride(5),
+                [
+                    &points[..100 * point_size],
+                    &points[500 * point_size..600 * point_size],
+                    &points[1000 * point_size..],
+                ]
+                .concat(),
+            ),
+            (
+                SamplingMode::FirstPerChunk(70),
+                points
+                    .chunks(100 * point_size)
+                    .flat_map(|chunk| &chunk[..chunk.len().min(70 * point_size)])
+                    .copied()
+                    .collect(),
+            ),
+        ] {
+            let mut decompressor =
+                LasZipDecompressor::new(Cursor::new(data.as_slice()), vlr.clone()).unwrap();
+            let mut sampler = PointSampler::new(mode);
+            let mut output = vec![];
+            let num_kept = decompressor
+                .decompress_sampled(point_count, &mut sampler, &mut output)
+                .unwrap();
+            assert_eq!(num_kept as usize * point_size, output.len());
+            assert_eq!(output, expected, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn test_decompress_sampled_empty() {
+        let (_, vlr) = test_points_and_vlr(100);
+        let data = compress(&[], &vlr);
+        let mut decompressor = LasZipDecompressor::new(Cursor::new(data), vlr).unwrap();
+        let mut sampler = PointSampler::new(SamplingMode::Stride(1));
+        let mut output = vec![];
+        let num_kept = decompressor
+            .decompress_sampled(0, &mut sampler, &mut output)
+            .unwrap();
+        assert_eq!(num_kept, 0);
+        assert!(output.is_empty());
+        assert_eq!(sampler, PointSampler::new(SamplingMode::Stride(1)));
+    }
+}
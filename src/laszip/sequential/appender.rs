@@ -52,10 +52,7 @@ where
 
         data.seek(SeekFrom::Current(chunk_start_pos.try_into().unwrap()))?;
 
-        let points_before_chunk = chunk_table[..chunk_index]
-            .iter()
-            .map(|entry| entry.point_count)
-            .sum::<u64>();
+        let points_before_chunk = chunk_table.point_range_of_chunk(chunk_index).unwrap().start;
         // This cannot overflow as with the chunk_index will be the last known chunk
         // if point_count was out of count
         let mut points_to_read = point_count - points_before_chunk;
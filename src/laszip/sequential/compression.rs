@@ -1,8 +1,12 @@
-use std::io::{Seek, SeekFrom, Write};
+use std::io::{Read, Seek, SeekFrom, Write};
 
 use byteorder::{LittleEndian, WriteBytesExt};
 
 use crate::laszip::chunk_table::{ChunkTable, ChunkTableEntry};
+use crate::laszip::chunking::{ChunkSplitter, ChunkingPolicy};
+use crate::laszip::quantization::Quantization;
+use crate::laszip::report::{point_wise_items, ChunkReport, CompressionReport};
+use crate::laszip::verification::verify_chunk;
 use crate::laszip::CompressorType;
 use crate::record::RecordCompressor;
 use crate::LasZipError;
@@ -50,6 +54,33 @@ pub struct LasZipCompressor<'a, W: Write + Send + 'a> {
     /// Position (offset from beginning)
     /// where the current chunk started
     chunk_start_pos: u64,
+    /// Statistics of the chunks written so far, if enabled
+    report: Option<CompressionReport>,
+    /// Verification of the chunks written, if enabled
+    verifier: Option<ChunkVerifier<W>>,
+    /// Closes the chunks following a policy, if set
+    chunking: Option<ChunkSplitter>,
+}
+
+/// Keeps the input points of the current chunk, to compare them
+/// with the points decompressed from the chunk once it is written
+struct ChunkVerifier<W> {
+    points: Vec<u8>,
+    /// Reads back the bytes of a chunk given its start position and byte count
+    read_chunk: fn(&mut W, u64, u64) -> std::io::Result<Vec<u8>>,
+}
+
+fn read_chunk<W: Read + Seek>(
+    stream: &mut W,
+    start: u64,
+    byte_count: u64,
+) -> std::io::Result<Vec<u8>> {
+    let end = stream.stream_position()?;
+    stream.seek(SeekFrom::Start(start))?;
+    let mut chunk = vec![0u8; byte_count as usize];
+    stream.read_exact(&mut chunk)?;
+    stream.seek(SeekFrom::Start(end))?;
+    Ok(chunk)
 }
 
 impl<'a, W: Write + Seek + Send + Sync + 'a> LasZipCompressor<'a, W> {
@@ -69,6 +100,9 @@ impl<'a, W: Write + Seek + Send + Sync + 'a> LasZipCompressor<'a, W> {
             start_pos: 0,
             chunk_table: ChunkTable::default(),
             current_chunk_entry: ChunkTableEntry::default(),
+            report: None,
+            verifier: None,
+            chunking: None,
         })
     }
 
@@ -91,6 +125,24 @@ impl<'a, W: Write + Seek + Send + Sync + 'a> LasZipCompressor<'a, W> {
     /// - The fields/dimensions are in the same order as the LAS spec says
     /// - The data in the buffer is in Little Endian order
     pub fn compress_one(&mut self, input: &[u8]) -> std::io::Result<()> {
+        if let Some(chunking) = &mut self.chunking {
+            chunking.start_batch();
+        }
+        self.compress_point(input)
+    }
+
+    /// Compress all the points contained in the `input` slice
+    pub fn compress_many(&mut self, input: &[u8]) -> std::io::Result<()> {
+        if let Some(chunking) = &mut self.chunking {
+            chunking.start_batch();
+        }
+        for point in input.chunks_exact(self.vlr.items_size() as usize) {
+            self.compress_point(point)?;
+        }
+        Ok(())
+    }
+
+    fn compress_point(&mut self, input: &[u8]) -> std::io::Result<()> {
         if self.chunk_start_pos == 0 {
             self.reserve_offset_to_chunk_table()?;
         }
@@ -100,18 +152,41 @@ impl<'a, W: Write + Seek + Send + Sync + 'a> LasZipCompressor<'a, W> {
         if self.current_chunk_entry.point_count == self.vlr.chunk_size() as u64 {
             self.finish_current_chunk_impl()?;
         }
+        if let Some(chunking) = &self.chunking {
+            if chunking.closes_chunk_before(input) {
+                self.finish_current_chunk_impl()?;
+            }
+        }
+        if let Some(chunking) = &mut self.chunking {
+            chunking.add_point(input);
+        }
 
         self.record_compressor.compress_next(&input)?;
+        if let Some(verifier) = &mut self.verifier {
+            verifier.points.extend_from_slice(input);
+        }
         self.current_chunk_entry.point_count += 1;
         Ok(())
     }
 
-    /// Compress all the points contained in the `input` slice
-    pub fn compress_many(&mut self, input: &[u8]) -> std::io::Result<()> {
-        for point in input.chunks_exact(self.vlr.items_size() as usize) {
-            self.compress_one(point)?;
-        }
-        Ok(())
+    /// Compresses the points contained in the `input` slice, with their X, Y, Z
+    /// replaced by the `coordinates` quantized using the `quantization`.
+    ///
+    /// Fails if the number of coordinates is not the number of points,
+    /// or if a coordinate cannot be quantized.
+    pub fn compress_many_quantized(
+        &mut self,
+        input: &[u8],
+        coordinates: &[[f64; 3]],
+        quantization: &Quantization,
+    ) -> std::io::Result<()> {
+        let mut points = input.to_vec();
+        quantization.set_coordinates_of_many(
+            &mut points,
+            self.vlr.items_size() as usize,
+            coordinates,
+        )?;
+        self.compress_many(&points)
     }
 
     /// Compresses multiple chunks
@@ -139,10 +214,14 @@ impl<'a, W: Write + Seek + Send + Sync + 'a> LasZipCompressor<'a, W> {
             self.reserve_offset_to_chunk_table()?;
         }
         self.record_compressor.done()?;
+        let field_byte_counts = self.record_compressor.field_byte_counts();
         self.update_chunk_table()?;
+        self.add_chunk_to_report(field_byte_counts);
+        self.verify_last_chunk()?;
         let stream = self.record_compressor.get_mut();
         chunk_table::update_chunk_table_offset(stream, SeekFrom::Start(self.start_pos))?;
         self.chunk_table.write_to(stream, &self.vlr)?;
+        self.current_chunk_entry = ChunkTableEntry::default();
         Ok(())
     }
 
@@ -183,11 +262,52 @@ impl<'a, W: Write + Seek + Send + Sync + 'a> LasZipCompressor<'a, W> {
         Ok(())
     }
 
+    /// Enables the creation of a [`CompressionReport`] of the chunks written,
+    /// see [`report`](Self::report).
+    ///
+    /// This should be called before compressing any point.
+    ///
+    /// For **point-wise** chunks (point formats <= 5), the bytes are counted
+    /// per LAZ item, not per field.
+    ///
+    /// For **layered** chunks (point formats >= 6), the report only has the
+    /// point & byte counts of the chunks, [`CompressionReport::read_from`] on the
+    /// written data gives the size of each layer.
+    pub fn enable_report(&mut self) {
+        self.record_compressor.enable_field_byte_counts();
+        if self.report.is_none() {
+            self.report = Some(CompressionReport::default());
+        }
+    }
+
+    /// Sets the policy used to close chunks automatically,
+    /// in addition to the calls to [`finish_current_chunk`](Self::finish_current_chunk).
+    ///
+    /// This should be called before compressing any point.
+    ///
+    /// Fails if the vlr does not use **variable-size** chunks.
+    pub fn set_chunking_policy(&mut self, policy: ChunkingPolicy) -> crate::Result<()> {
+        self.chunking = Some(ChunkSplitter::new(policy, &self.vlr)?);
+        Ok(())
+    }
+
+    /// Returns the report of the chunks finished so far,
+    /// if [`enable_report`](Self::enable_report) was called.
+    pub fn report(&self) -> Option<&CompressionReport> {
+        self.report.as_ref()
+    }
+
     /// Returns the vlr used by this compressor
     pub fn vlr(&self) -> &LazVlr {
         &self.vlr
     }
 
+    /// Returns the index of the next point that will be compressed,
+    /// that is, the number of points compressed so far.
+    pub fn position(&self) -> u64 {
+        self.chunk_table.point_count() + self.current_chunk_entry.point_count
+    }
+
     pub fn into_inner(self) -> W {
         self.record_compressor.box_into_inner()
     }
@@ -226,14 +346,77 @@ impl<'a, W: Write + Seek + Send + Sync + 'a> LasZipCompressor<'a, W> {
     #[inline]
     fn finish_current_chunk_impl(&mut self) -> std::io::Result<()> {
         self.record_compressor.done()?;
+        let field_byte_counts = self.record_compressor.field_byte_counts();
         self.record_compressor.reset();
         self.record_compressor
             .set_fields_from(&self.vlr.items())
             .unwrap();
         self.update_chunk_table()?;
+        self.add_chunk_to_report(field_byte_counts);
+        self.verify_last_chunk()?;
+        if let Some(chunking) = &mut self.chunking {
+            chunking.reset();
+            chunking.record_chunk(
+                self.current_chunk_entry.point_count,
+                self.current_chunk_entry.byte_count,
+            );
+        }
         self.current_chunk_entry = ChunkTableEntry::default();
         Ok(())
     }
+
+    /// Decompresses the chunk that was just finished and compares it
+    /// with its input points, if verification is enabled
+    fn verify_last_chunk(&mut self) -> std::io::Result<()> {
+        let Some(verifier) = &mut self.verifier else {
+            return Ok(());
+        };
+        let chunk_index = self.chunk_table.len() - 1;
+        let byte_count = self.chunk_table.as_ref()[chunk_index].byte_count;
+        let stream = self.record_compressor.get_mut();
+        let chunk = (verifier.read_chunk)(stream, self.chunk_start_pos - byte_count, byte_count)?;
+        let result = verify_chunk(&chunk, &verifier.points, &self.vlr, chunk_index);
+        verifier.points.clear();
+        result.map_err(|err| match err {
+            LasZipError::IoError(err) => err,
+            err => std::io::Error::new(std::io::ErrorKind::InvalidData, err),
+        })
+    }
+
+    /// Adds the chunk that was just finished to the report, if enabled
+    fn add_chunk_to_report(&mut self, field_byte_counts: Option<Vec<u64>>) {
+        if let Some(report) = &mut self.report {
+            let fields = field_byte_counts
+                .map(|byte_counts| point_wise_items(self.vlr.items(), byte_counts))
+                .unwrap_or_default();
+            report.chunks.push(ChunkReport {
+                point_count: self.current_chunk_entry.point_count,
+                byte_count: self.current_chunk_entry.byte_count,
+                fields,
+            });
+        }
+    }
+}
+
+impl<'a, W: Read + Write + Seek + Send + Sync + 'a> LasZipCompressor<'a, W> {
+    /// Enables the verification of the chunks written.
+    ///
+    /// Each chunk is read back & decompressed as soon as it is finished,
+    /// and the decompressed points are compared with the points given to the compressor.
+    /// On the first difference, the compression fails with an [`InvalidData`] error
+    /// wrapping a [`LasZipError::VerificationFailed`] that tells the chunk, point & byte.
+    ///
+    /// This should be called before compressing any point.
+    ///
+    /// [`InvalidData`]: std::io::ErrorKind::InvalidData
+    pub fn enable_verification(&mut self) {
+        if self.verifier.is_none() {
+            self.verifier = Some(ChunkVerifier {
+                points: Vec::new(),
+                read_chunk: read_chunk::<W>,
+            });
+        }
+    }
 }
 
 impl<'a, W: Write + Seek + Send + Sync + 'a> crate::LazCompressor for LasZipCompressor<'a, W> {
@@ -258,6 +441,18 @@ impl<'a, W: Write + Seek + Send + Sync + 'a> crate::LazCompressor for LasZipComp
     }
 }
 
+impl<'a, W: Write + Seek + Send + Sync + 'a> crate::LazCompressorWithState
+    for LasZipCompressor<'a, W>
+{
+    fn vlr(&self) -> &LazVlr {
+        LasZipCompressor::vlr(self)
+    }
+
+    fn position(&self) -> u64 {
+        LasZipCompressor::position(self)
+    }
+}
+
 impl<'a, W: Write + Seek + Send + Sync + 'a> crate::LazCompressorWithInner<W>
     for LasZipCompressor<'a, W>
 {
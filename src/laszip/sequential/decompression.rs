@@ -1,11 +1,13 @@
 use std::io::{Read, Seek, SeekFrom};
 
 use crate::errors::LasZipError::MissingChunkTable;
-use crate::las::selective::DecompressionSelection;
+use crate::las::selective::{DecompressionSelection, ExtraBytesSelection};
 use crate::record::RecordDecompressor;
 use crate::LasZipError;
 
-use crate::laszip::chunk_table::ChunkTable;
+use crate::laszip::chunk_table::{ChunkTable, ChunkTableEntry};
+use crate::laszip::filter::{decompress_chunk_filtered, PointFilter, PointLayout};
+use crate::laszip::sampling::{decompress_chunk_sampled, PointSampler};
 use crate::laszip::{details, CompressorType, LazVlr};
 
 /// SeekInfo aggregates the two information needed to be able to seek
@@ -50,12 +52,17 @@ pub struct LasZipDecompressor<'a, R: Read + Seek + 'a> {
     record_decompressor: Box<dyn RecordDecompressor<R> + Send + Sync + 'a>,
     // Contains which fields the user wants to decompress or not
     selection: DecompressionSelection,
+    extra_bytes_selection: ExtraBytesSelection,
     // Allowed to be None if the source was not seekable and
     // chunks are not of variable size
     seek_info: Option<SeekInfo>,
     current_chunk: usize,
     chunk_points_read: u64,
     num_points_in_chunk: u64,
+    // Index of the next point to be decompressed
+    position: u64,
+    // Only known with variable-size chunks, or when set by the user
+    point_count: Option<u64>,
 }
 
 impl<'a, R: Read + Seek + Send + Sync + 'a> LasZipDecompressor<'a, R> {
@@ -75,6 +82,7 @@ impl<'a, R: Read + Seek + Send + Sync + 'a> LasZipDecompressor<'a, R> {
         selection: DecompressionSelection,
     ) -> crate::Result<Self> {
         // The chunk table is not always mandatory when just reading data.
+        let start = source.stream_position().ok();
         let seek_info = match vlr.compressor {
             CompressorType::PointWise => {
                 // Everything is in one chunk, so we don't need a table
@@ -90,8 +98,7 @@ impl<'a, R: Read + Seek + Send + Sync + 'a> LasZipDecompressor<'a, R> {
                         // So we _may_ still be at the start of point data,
                         // we need to skip the chunk table offset otherwise
                         // decompression won't be correct.
-                        let mut tmp = [0u8; ChunkTable::OFFSET_SIZE];
-                        source.read_exact(&mut tmp)?;
+                        skip_chunk_table_offset(&mut source, start)?;
                         None
                     }
                     (Err(err), true) => {
@@ -108,8 +115,7 @@ impl<'a, R: Read + Seek + Send + Sync + 'a> LasZipDecompressor<'a, R> {
                 let seek_info = SeekInfo::read_from(&mut source, &vlr).ok();
                 if seek_info.is_none() {
                     // Same as in PointWiseChunked
-                    let mut tmp = [0u8; ChunkTable::OFFSET_SIZE];
-                    source.read_exact(&mut tmp)?;
+                    skip_chunk_table_offset(&mut source, start)?;
                 }
                 seek_info
             }
@@ -118,18 +124,56 @@ impl<'a, R: Read + Seek + Send + Sync + 'a> LasZipDecompressor<'a, R> {
             }
         };
 
+        Self::from_parts(source, vlr, selection, seek_info)
+    }
+
+    /// Creates a decompressor using a chunk table that was read before
+    /// (e.g. cached), instead of reading it from the source.
+    ///
+    /// The `source` must be positioned as for [`ChunkTable::read_from`](crate::laszip::ChunkTable::read_from).
+    pub fn with_chunk_table(
+        mut source: R,
+        vlr: LazVlr,
+        chunk_table: ChunkTable,
+    ) -> crate::Result<Self> {
+        if vlr.compressor != CompressorType::PointWiseChunked
+            && vlr.compressor != CompressorType::LayeredChunked
+        {
+            return Err(LasZipError::UnsupportedCompressorType(vlr.compressor));
+        }
+        let data_start = source.seek(SeekFrom::Current(ChunkTable::OFFSET_SIZE as i64))?;
+        let seek_info = SeekInfo {
+            data_start,
+            chunk_table,
+        };
+        Self::from_parts(source, vlr, DecompressionSelection::all(), Some(seek_info))
+    }
+
+    fn from_parts(
+        source: R,
+        vlr: LazVlr,
+        selection: DecompressionSelection,
+        seek_info: Option<SeekInfo>,
+    ) -> crate::Result<Self> {
         let mut record_decompressor =
             details::record_decompressor_from_laz_items(&vlr.items(), source)?;
         record_decompressor.set_selection(selection);
+        let point_count = seek_info
+            .as_ref()
+            .filter(|_| vlr.uses_variable_size_chunks())
+            .map(|seek_info| seek_info.chunk_table.point_count());
 
         Ok(Self {
             vlr,
             record_decompressor,
             selection,
+            extra_bytes_selection: ExtraBytesSelection::none(),
             seek_info,
             current_chunk: 0,
             chunk_points_read: 0,
             num_points_in_chunk: 1,
+            position: 0,
+            point_count,
         })
     }
 
@@ -146,6 +190,7 @@ impl<'a, R: Read + Seek + Send + Sync + 'a> LasZipDecompressor<'a, R> {
 
         self.record_decompressor.decompress_next(&mut out)?;
         self.chunk_points_read += 1;
+        self.position += 1;
 
         if self.chunk_points_read == 1 {
             if self.vlr.uses_variable_size_chunks() {
@@ -182,6 +227,163 @@ impl<'a, R: Read + Seek + Send + Sync + 'a> LasZipDecompressor<'a, R> {
         Ok(())
     }
 
+    /// Decompresses the next `num_points` points and appends the ones
+    /// that pass the `filter` to `out`, returns the number of points appended.
+    ///
+    /// For layered data with a chunk table, the chunks fully contained in the
+    /// `num_points` are first decoded with only the layers needed by the filter,
+    /// and their remaining layers are skipped when none of their points pass
+    /// (see [`PointFilter`] for what it costs when some points pass).
+    ///
+    /// The fields needed by the filter are decompressed even if they are not part
+    /// of the selection, except for the points of a chunk that was already
+    /// partially decompressed before the call.
+    pub fn decompress_filtered(
+        &mut self,
+        num_points: u64,
+        filter: &PointFilter,
+        out: &mut Vec<u8>,
+    ) -> crate::Result<u64> {
+        let user_selection = self.selection;
+        self.selection = user_selection.union(filter.selection());
+        if self.chunk_points_read == 0 {
+            self.record_decompressor.set_selection(self.selection);
+        }
+        let result = self.decompress_filtered_impl(num_points, filter, user_selection, out);
+        self.selection = user_selection;
+        if self.chunk_points_read == 0 {
+            self.record_decompressor.set_selection(self.selection);
+        }
+        result
+    }
+
+    fn decompress_filtered_impl(
+        &mut self,
+        num_points: u64,
+        filter: &PointFilter,
+        user_selection: DecompressionSelection,
+        out: &mut Vec<u8>,
+    ) -> crate::Result<u64> {
+        let layout = PointLayout::from_vlr(&self.vlr);
+        let mut point = vec![0u8; self.vlr.items_size() as usize];
+        let mut num_left = num_points;
+        let mut num_kept = 0;
+        while num_left > 0 {
+            if let Some((chunk_index, entry)) = self.next_whole_chunk(num_left) {
+                let mut chunk = vec![0u8; entry.byte_count as usize];
+                self.get_mut().read_exact(&mut chunk)?;
+                num_kept += decompress_chunk_filtered(
+                    &chunk,
+                    entry.point_count as usize,
+                    &self.vlr,
+                    filter,
+                    user_selection,
+                    self.extra_bytes_selection,
+                    out,
+                )?;
+                // The next call to decompress_one will start the next chunk
+                self.current_chunk = chunk_index;
+                self.chunk_points_read = entry.point_count;
+                self.num_points_in_chunk = entry.point_count;
+                self.position += entry.point_count;
+                num_left -= entry.point_count;
+            } else {
+                self.decompress_one(&mut point)?;
+                if filter.matches(&point, &layout) {
+                    out.extend_from_slice(&point);
+                    num_kept += 1;
+                }
+                num_left -= 1;
+            }
+        }
+        Ok(num_kept)
+    }
+
+    /// Decompresses the next `num_points` points and appends the ones
+    /// kept by the `sampler` to `out`, returns the number of points appended.
+    ///
+    /// When the chunk table is available, chunks fully contained in the `num_points`
+    /// without any point to keep are skipped without being read.
+    pub fn decompress_sampled(
+        &mut self,
+        num_points: u64,
+        sampler: &mut PointSampler,
+        out: &mut Vec<u8>,
+    ) -> crate::Result<u64> {
+        let point_size = self.vlr.items_size() as usize;
+        let mut point = vec![0u8; point_size];
+        let mut num_left = num_points;
+        let mut num_kept = 0;
+        while num_left > 0 {
+            if let Some((chunk_index, entry)) = self.next_chunk_in(num_left) {
+                let kept = sampler.kept_in_chunk(entry.point_count);
+                if kept.is_empty() {
+                    self.get_mut()
+                        .seek(SeekFrom::Current(entry.byte_count as i64))?;
+                } else {
+                    let mut chunk = vec![0u8; entry.byte_count as usize];
+                    self.get_mut().read_exact(&mut chunk)?;
+                    decompress_chunk_sampled(
+                        &chunk,
+                        &kept,
+                        &self.vlr,
+                        self.selection,
+                        self.extra_bytes_selection,
+                        out,
+                    )?;
+                }
+                // The next call to decompress_one will start the next chunk
+                self.current_chunk = chunk_index;
+                self.chunk_points_read = entry.point_count;
+                self.num_points_in_chunk = entry.point_count;
+                self.position += entry.point_count;
+                num_left -= entry.point_count;
+                num_kept += kept.len() as u64;
+            } else {
+                let index_in_chunk = if self.chunk_points_read == self.num_points_in_chunk {
+                    0
+                } else {
+                    self.chunk_points_read
+                };
+                self.decompress_one(&mut point)?;
+                if sampler.keeps_point(index_in_chunk) {
+                    out.extend_from_slice(&point);
+                    num_kept += 1;
+                }
+                num_left -= 1;
+            }
+        }
+        Ok(num_kept)
+    }
+
+    /// Returns the next chunk if the decompressor is at the start of a layered
+    /// chunk which points are all part of the next `num_points`.
+    fn next_whole_chunk(&self, num_points: u64) -> Option<(usize, ChunkTableEntry)> {
+        if self.vlr.compressor != CompressorType::LayeredChunked {
+            return None;
+        }
+        self.next_chunk_in(num_points)
+    }
+
+    /// Returns the next chunk if the decompressor is at the start of a
+    /// chunk which points are all part of the next `num_points`.
+    fn next_chunk_in(&self, num_points: u64) -> Option<(usize, ChunkTableEntry)> {
+        let chunk_table = &self.seek_info.as_ref()?.chunk_table;
+        let chunk_index = if self.chunk_points_read == 0 {
+            self.current_chunk
+        } else if self.chunk_points_read == self.num_points_in_chunk {
+            self.current_chunk + 1
+        } else {
+            return None;
+        };
+        // With fixed-size chunks, the point count of the last chunk is unknown
+        if !self.vlr.uses_variable_size_chunks() && chunk_index + 1 >= chunk_table.len() {
+            return None;
+        }
+        let entry = *chunk_table.as_ref().get(chunk_index)?;
+        (entry.point_count <= num_points).then_some((chunk_index, entry))
+    }
+
     /// Seeks to the point designed by the index
     ///
     /// # Important
@@ -191,6 +393,12 @@ impl<'a, R: Read + Seek + Send + Sync + 'a> LasZipDecompressor<'a, R> {
     /// and then we have to decompress points in the chunk until we reach the
     /// one we want.
     pub fn seek(&mut self, point_idx: u64) -> crate::Result<()> {
+        self.seek_impl(point_idx)?;
+        self.position = point_idx;
+        Ok(())
+    }
+
+    fn seek_impl(&mut self, point_idx: u64) -> crate::Result<()> {
         let SeekInfo {
             data_start,
             chunk_table,
@@ -202,7 +410,11 @@ impl<'a, R: Read + Seek + Send + Sync + 'a> LasZipDecompressor<'a, R> {
 
         if let Some((chunk_of_point, start_of_chunk)) = chunk_info {
             self.current_chunk = chunk_of_point as usize;
-            let delta = point_idx % chunk_table[self.current_chunk].point_count;
+            let delta = point_idx
+                - chunk_table
+                    .point_range_of_chunk(chunk_of_point)
+                    .unwrap()
+                    .start;
             let seeked_point_belong_to_last_chunk = chunk_of_point == (chunk_table.len() - 1);
             // When the index of the point belongs to the last chunk
             // we try to be careful as that point may not exist, and there may be a
@@ -260,6 +472,58 @@ impl<'a, R: Read + Seek + Send + Sync + 'a> LasZipDecompressor<'a, R> {
         &self.vlr
     }
 
+    /// Returns the index of the next point that will be decompressed
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Returns the number of points, if known
+    ///
+    /// It is known for **variable-size** chunks when the chunk table could be read,
+    /// or after a call to [`Self::set_point_count`].
+    pub fn point_count(&self) -> Option<u64> {
+        self.point_count
+    }
+
+    /// Sets the number of points (as found in the LAS header),
+    /// fixing the point count of the last chunk of **fixed-size** chunks.
+    pub fn set_point_count(&mut self, point_count: u64) -> crate::Result<()> {
+        if let Some(seek_info) = self.seek_info.as_mut() {
+            seek_info.chunk_table.fix_last_point_count(point_count)?;
+        }
+        self.point_count = Some(point_count);
+        Ok(())
+    }
+
+    /// Returns the fields that are decompressed
+    pub fn selection(&self) -> DecompressionSelection {
+        self.selection
+    }
+
+    /// Changes the fields that are decompressed, starting from the next chunk
+    /// (or from the next point, if no point of the current chunk was decompressed)
+    pub fn set_selection(&mut self, selection: DecompressionSelection) {
+        self.selection = selection;
+        if self.chunk_points_read == 0 {
+            self.record_decompressor.set_selection(selection);
+        }
+    }
+
+    /// Returns the extra bytes that are decompressed in addition to the ones of the selection
+    pub fn extra_bytes_selection(&self) -> ExtraBytesSelection {
+        self.extra_bytes_selection
+    }
+
+    /// Changes the extra bytes that are decompressed in addition to the ones of the
+    /// selection, starting from the same point as [`Self::set_selection`] would.
+    pub fn set_extra_bytes_selection(&mut self, selection: ExtraBytesSelection) {
+        self.extra_bytes_selection = selection;
+        if self.chunk_points_read == 0 {
+            self.record_decompressor
+                .set_extra_bytes_selection(selection);
+        }
+    }
+
     /// Consumes the decompressor and returns the data source.
     pub fn into_inner(self) -> R {
         self.record_decompressor.box_into_inner()
@@ -283,6 +547,8 @@ impl<'a, R: Read + Seek + Send + Sync + 'a> LasZipDecompressor<'a, R> {
             .set_fields_from(&self.vlr.items())
             .unwrap();
         self.record_decompressor.set_selection(self.selection);
+        self.record_decompressor
+            .set_extra_bytes_selection(self.extra_bytes_selection);
     }
 }
 
@@ -303,6 +569,53 @@ impl<'a, R: Read + Seek + Send + Sync + 'a> crate::LazDecompressor for LasZipDec
     }
 }
 
+impl<'a, R: Read + Seek + Send + Sync + 'a> crate::LazDecompressorWithState
+    for LasZipDecompressor<'a, R>
+{
+    fn vlr(&self) -> &LazVlr {
+        LasZipDecompressor::vlr(self)
+    }
+
+    fn position(&self) -> u64 {
+        LasZipDecompressor::position(self)
+    }
+
+    fn point_count(&self) -> Option<u64> {
+        LasZipDecompressor::point_count(self)
+    }
+
+    fn set_point_count(&mut self, point_count: u64) -> crate::Result<()> {
+        LasZipDecompressor::set_point_count(self, point_count)
+    }
+
+    fn selection(&self) -> DecompressionSelection {
+        LasZipDecompressor::selection(self)
+    }
+
+    fn set_selection(&mut self, selection: DecompressionSelection) {
+        LasZipDecompressor::set_selection(self, selection)
+    }
+}
+
+/// Moves the source after the offset to the chunk table, after a failed attempt
+/// at reading the chunk table that started at `start`.
+fn skip_chunk_table_offset<R: Read + Seek>(
+    source: &mut R,
+    start: Option<u64>,
+) -> std::io::Result<()> {
+    // Reading the chunk table may have moved the source anywhere
+    if let Some(start) = start {
+        if source
+            .seek(SeekFrom::Start(start + ChunkTable::OFFSET_SIZE as u64))
+            .is_ok()
+        {
+            return Ok(());
+        }
+    }
+    let mut tmp = [0u8; ChunkTable::OFFSET_SIZE];
+    source.read_exact(&mut tmp)
+}
+
 /// Decompresses all points from the buffer
 ///
 /// The `compressed_points_data` slice must contain all the laszip data
@@ -1,7 +1,9 @@
 pub(crate) mod appender;
 mod compression;
 mod decompression;
+mod stream;
 
 pub use appender::LasZipAppender;
 pub use compression::{compress_buffer, LasZipCompressor};
 pub use decompression::{decompress_buffer, LasZipDecompressor};
+pub use stream::{LasZipStreamCompressor, LasZipStreamDecompressor};
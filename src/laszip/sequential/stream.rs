@@ -0,0 +1,562 @@
+//! Decompression from sources and compression to destinations that cannot seek
+//! (pipes, stdin / stdout, sockets, ...)
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use crate::las::selective::DecompressionSelection;
+use crate::laszip::chunk_table::{ChunkTable, ChunkTableEntry};
+use crate::laszip::{details, CompressorType, LazVlr};
+use crate::record::{RecordCompressor, RecordDecompressor};
+use crate::LasZipError;
+
+/// Wraps a stream to give it the little of [`Seek`] that the record
+/// (de)compressors need: getting the current position and moving forward.
+///
+/// Seeking forward in a source is done by reading and discarding the bytes.
+pub(crate) struct ForwardOnly<T> {
+    inner: T,
+    position: u64,
+}
+
+impl<T> ForwardOnly<T> {
+    pub(crate) fn new(inner: T) -> Self {
+        Self { inner, position: 0 }
+    }
+
+    pub(crate) fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for ForwardOnly<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read> Seek for ForwardOnly<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match pos {
+            SeekFrom::Current(n) if n >= 0 => {
+                let skipped =
+                    std::io::copy(&mut (&mut self.inner).take(n as u64), &mut std::io::sink())?;
+                self.position += skipped;
+                if skipped != n as u64 {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "failed to skip bytes, reached the end of the stream",
+                    ));
+                }
+                Ok(self.position)
+            }
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "stream can only move forward",
+            )),
+        }
+    }
+}
+
+/// Wraps a destination to give it the little of [`Seek`] that the record
+/// compressors need: getting the current position.
+pub(crate) struct PositionTracker<W> {
+    inner: W,
+    position: u64,
+}
+
+impl<W> PositionTracker<W> {
+    pub(crate) fn new(inner: W, position: u64) -> Self {
+        Self { inner, position }
+    }
+
+    pub(crate) fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for PositionTracker<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Seek for PositionTracker<W> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match pos {
+            SeekFrom::Current(0) => Ok(self.position),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "destination cannot seek",
+            )),
+        }
+    }
+}
+
+/// LasZip decompressor for sources that are not seekable.
+///
+/// Points are decompressed in order, without using the chunk table
+/// (which may be at the end of the data), chunk boundaries are found using the
+/// chunk size of the [`LazVlr`] for **fixed-size** chunks, or the point count stored
+/// at the start of **layered** chunks (point formats >= 6).
+///
+/// As the chunk table is needed to know where point-wise (point formats <= 5)
+/// **variable-size** chunks end, such data is not supported.
+///
+/// Seeking is not supported, use [`LasZipDecompressor`](crate::LasZipDecompressor)
+/// if the source is seekable.
+pub struct LasZipStreamDecompressor<'a, R: Read + 'a> {
+    vlr: LazVlr,
+    record_decompressor: Box<dyn RecordDecompressor<ForwardOnly<R>> + Send + Sync + 'a>,
+    selection: DecompressionSelection,
+    chunk_points_read: u64,
+    num_points_in_chunk: u64,
+}
+
+impl<'a, R: Read + Send + Sync + 'a> LasZipStreamDecompressor<'a, R> {
+    /// Creates a new instance from a data source of compressed points
+    /// and the LazVlr describing the compressed data.
+    ///
+    /// The `source` must be positioned as for [`ChunkTable::read_from`](crate::laszip::ChunkTable::read_from).
+    pub fn new(source: R, vlr: LazVlr) -> crate::Result<Self> {
+        Self::selective(source, vlr, DecompressionSelection::all())
+    }
+
+    /// Creates a new decompressor, that will only decompress
+    /// fields that are selected by the `selection`.
+    pub fn selective(
+        source: R,
+        vlr: LazVlr,
+        selection: DecompressionSelection,
+    ) -> crate::Result<Self> {
+        let mut source = ForwardOnly::new(source);
+        match vlr.compressor {
+            CompressorType::PointWise => {}
+            CompressorType::PointWiseChunked if vlr.uses_variable_size_chunks() => {
+                return Err(LasZipError::MissingChunkTable);
+            }
+            CompressorType::PointWiseChunked | CompressorType::LayeredChunked => {
+                // The offset to the chunk table is of no use to us
+                let mut offset = [0u8; ChunkTable::OFFSET_SIZE];
+                source.read_exact(&mut offset)?;
+            }
+            _ => {
+                return Err(LasZipError::UnsupportedCompressorType(vlr.compressor));
+            }
+        }
+
+        let mut record_decompressor =
+            details::record_decompressor_from_laz_items(vlr.items(), source)?;
+        record_decompressor.set_selection(selection);
+
+        Ok(Self {
+            vlr,
+            record_decompressor,
+            selection,
+            chunk_points_read: 0,
+            num_points_in_chunk: 1,
+        })
+    }
+
+    /// Decompress the next point and write the uncompressed data to the out buffer.
+    ///
+    /// - The buffer should have at least enough byte to store the decompressed data
+    /// - The data is written in the buffer exactly as it would have been in a LAS File
+    ///   in Little Endian order,
+    pub fn decompress_one(&mut self, out: &mut [u8]) -> std::io::Result<()> {
+        if self.chunk_points_read == self.num_points_in_chunk {
+            self.reset_for_new_chunk();
+        }
+
+        self.record_decompressor.decompress_next(out)?;
+        self.chunk_points_read += 1;
+
+        if self.chunk_points_read == 1 {
+            self.num_points_in_chunk = match self.vlr.compressor {
+                CompressorType::LayeredChunked => self.record_decompressor.record_count(),
+                CompressorType::PointWise => u64::from(u32::MAX),
+                _ => u64::from(self.vlr.chunk_size()),
+            };
+        }
+        Ok(())
+    }
+
+    /// Decompress as many points as the `out` slice can hold
+    pub fn decompress_many(&mut self, out: &mut [u8]) -> std::io::Result<()> {
+        for point in out.chunks_exact_mut(self.vlr.items_size() as usize) {
+            self.decompress_one(point)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the vlr used.
+    pub fn vlr(&self) -> &LazVlr {
+        &self.vlr
+    }
+
+    /// Returns the number of bytes read from the source so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.record_decompressor.get().position
+    }
+
+    /// Consumes the decompressor and returns the data source.
+    pub fn into_inner(self) -> R {
+        self.record_decompressor.box_into_inner().into_inner()
+    }
+
+    /// Returns a reference to the data source.
+    pub fn get(&self) -> &R {
+        &self.record_decompressor.get().inner
+    }
+
+    fn reset_for_new_chunk(&mut self) {
+        self.chunk_points_read = 0;
+        self.record_decompressor.reset();
+        // we can safely unwrap here, as set_field would have failed in the ::new()
+        self.record_decompressor
+            .set_fields_from(self.vlr.items())
+            .unwrap();
+        self.record_decompressor.set_selection(self.selection);
+    }
+}
+
+/// LasZip compressor for destinations that are not seekable.
+///
+/// As the offset to the chunk table, written before the points, cannot be updated
+/// once the chunk table is written, a placeholder (`-1`) is written instead, and the
+/// actual offset is appended after the chunk table, at the very end of the data.
+/// This crate's decompressors and LASzip both find the chunk table this way.
+///
+/// The offset being counted from the start of the file, use
+/// [`with_position`](Self::with_position) if bytes (e.g. the LAS header and VLRs)
+/// were written to the destination before the points.
+///
+/// This supports both **variable-size** and **fixed-size** chunks,
+/// see [`LasZipCompressor`](crate::LasZipCompressor) for how to use each of them.
+pub struct LasZipStreamCompressor<'a, W: Write + Send + 'a> {
+    vlr: LazVlr,
+    record_compressor: Box<dyn RecordCompressor<PositionTracker<W>> + Send + Sync + 'a>,
+    /// Position of the offset to the chunk table, `None` until it is written
+    start_pos: Option<u64>,
+    chunk_table: ChunkTable,
+    current_chunk_entry: ChunkTableEntry,
+    chunk_start_pos: u64,
+}
+
+impl<'a, W: Write + Send + Sync + 'a> LasZipStreamCompressor<'a, W> {
+    /// Creates a compressor using the provided vlr, the `output` is expected to
+    /// be at the start of the file.
+    pub fn new(output: W, vlr: LazVlr) -> crate::Result<Self> {
+        Self::with_position(output, vlr, 0)
+    }
+
+    /// Creates a compressor using the provided vlr, `position` is the number of bytes
+    /// that were already written to the `output` (where the point data starts in the file).
+    pub fn with_position(output: W, vlr: LazVlr, position: u64) -> crate::Result<Self> {
+        if vlr.compressor != CompressorType::PointWiseChunked
+            && vlr.compressor != CompressorType::LayeredChunked
+        {
+            return Err(LasZipError::UnsupportedCompressorType(vlr.compressor));
+        }
+
+        let record_compressor = details::record_compressor_from_laz_items(
+            vlr.items(),
+            PositionTracker::new(output, position),
+        )?;
+        Ok(Self {
+            vlr,
+            record_compressor,
+            start_pos: None,
+            chunk_table: ChunkTable::default(),
+            current_chunk_entry: ChunkTableEntry::default(),
+            chunk_start_pos: position,
+        })
+    }
+
+    /// Compress the point and write the compressed data to the destination.
+    pub fn compress_one(&mut self, input: &[u8]) -> std::io::Result<()> {
+        if self.start_pos.is_none() {
+            self.reserve_offset_to_chunk_table()?;
+        }
+
+        // Since in variable-size chunks mode the vlr.chunk_size() is
+        // u32::max this should not interfere.
+        if self.current_chunk_entry.point_count == self.vlr.chunk_size() as u64 {
+            self.finish_current_chunk_impl()?;
+        }
+
+        self.record_compressor.compress_next(input)?;
+        self.current_chunk_entry.point_count += 1;
+        Ok(())
+    }
+
+    /// Compress all the points contained in the `input` slice
+    pub fn compress_many(&mut self, input: &[u8]) -> std::io::Result<()> {
+        for point in input.chunks_exact(self.vlr.items_size() as usize) {
+            self.compress_one(point)?;
+        }
+        Ok(())
+    }
+
+    /// Compresses multiple chunks
+    ///
+    /// # Important
+    ///
+    /// This **must** be called **only** when writing **variable-size** chunks.
+    pub fn compress_chunks<Chunks, Item>(&mut self, chunks: Chunks) -> std::io::Result<()>
+    where
+        Item: AsRef<[u8]>,
+        Chunks: IntoIterator<Item = Item>,
+    {
+        debug_assert!(self.vlr.uses_variable_size_chunks());
+        for chunk in chunks.into_iter() {
+            self.compress_many(chunk.as_ref())?;
+            self.finish_current_chunk_impl()?;
+        }
+        Ok(())
+    }
+
+    /// Finishes the current chunk.
+    ///
+    /// Only call this when writing **variable-size** chunks.
+    pub fn finish_current_chunk(&mut self) -> std::io::Result<()> {
+        debug_assert!(
+            self.vlr.uses_variable_size_chunks(),
+            "finish_current_chunk called on a file which is not in variable-size chunks mode"
+        );
+        self.finish_current_chunk_impl()
+    }
+
+    /// Writes the placeholder offset to the chunk table.
+    ///
+    /// This method will automatically be called on the first point being compressed.
+    pub fn reserve_offset_to_chunk_table(&mut self) -> std::io::Result<()> {
+        debug_assert!(self.start_pos.is_none());
+        let stream = self.record_compressor.get_mut();
+        self.start_pos = Some(stream.position);
+        stream.write_i64::<LittleEndian>(-1)?;
+        self.chunk_start_pos = stream.position;
+        Ok(())
+    }
+
+    /// Must be called when you have compressed all your points.
+    ///
+    /// Writes the chunk table, followed by its offset.
+    pub fn done(&mut self) -> std::io::Result<()> {
+        if self.start_pos.is_none() {
+            self.reserve_offset_to_chunk_table()?;
+        }
+        self.record_compressor.done()?;
+        self.update_chunk_table();
+        let stream = self.record_compressor.get_mut();
+        let chunk_table_pos = stream.position;
+        self.chunk_table.write_to(&mut *stream, &self.vlr)?;
+        stream.write_i64::<LittleEndian>(chunk_table_pos as i64)?;
+        self.current_chunk_entry = ChunkTableEntry::default();
+        stream.flush()
+    }
+
+    /// Returns the vlr used by this compressor
+    pub fn vlr(&self) -> &LazVlr {
+        &self.vlr
+    }
+
+    /// Returns the position in the output, that is, the `position` given
+    /// at creation plus the number of bytes written so far.
+    pub fn position(&self) -> u64 {
+        self.record_compressor.get().position
+    }
+
+    pub fn into_inner(self) -> W {
+        self.record_compressor.box_into_inner().into_inner()
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.record_compressor.get_mut().inner
+    }
+
+    pub fn get(&self) -> &W {
+        &self.record_compressor.get().inner
+    }
+
+    fn update_chunk_table(&mut self) {
+        let current_pos = self.record_compressor.get().position;
+        self.current_chunk_entry.byte_count = current_pos - self.chunk_start_pos;
+        self.chunk_start_pos = current_pos;
+        self.chunk_table.push(self.current_chunk_entry);
+    }
+
+    fn finish_current_chunk_impl(&mut self) -> std::io::Result<()> {
+        self.record_compressor.done()?;
+        self.record_compressor.reset();
+        self.record_compressor
+            .set_fields_from(self.vlr.items())
+            .unwrap();
+        self.update_chunk_table();
+        self.current_chunk_entry = ChunkTableEntry::default();
+        Ok(())
+    }
+}
+
+impl<'a, W: Write + Send + Sync + 'a> crate::LazCompressor for LasZipStreamCompressor<'a, W> {
+    fn compress_one(&mut self, point: &[u8]) -> crate::Result<()> {
+        LasZipStreamCompressor::compress_one(self, point)?;
+        Ok(())
+    }
+
+    fn compress_many(&mut self, points: &[u8]) -> crate::Result<()> {
+        LasZipStreamCompressor::compress_many(self, points)?;
+        Ok(())
+    }
+
+    fn reserve_offset_to_chunk_table(&mut self) -> crate::Result<()> {
+        LasZipStreamCompressor::reserve_offset_to_chunk_table(self)?;
+        Ok(())
+    }
+
+    fn done(&mut self) -> crate::Result<()> {
+        LasZipStreamCompressor::done(self)?;
+        Ok(())
+    }
+}
+
+impl<'a, W: Write + Send + Sync + 'a> crate::LazCompressorWithState
+    for LasZipStreamCompressor<'a, W>
+{
+    fn vlr(&self) -> &LazVlr {
+        LasZipStreamCompressor::vlr(self)
+    }
+
+    /// Returns the number of points compressed so far, unlike
+    /// [`LasZipStreamCompressor::position`] which is a byte position.
+    fn position(&self) -> u64 {
+        self.chunk_table.point_count() + self.current_chunk_entry.point_count
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::test_support::{compress, layered_points, test_points_and_vlr, Pipe, Sink};
+    use crate::{LasZipCompressor, LazItemRecordBuilder, LazVlrBuilder};
+
+    #[test]
+    fn test_stream_decompress_layered() {
+        let points = layered_points(1000);
+        let items = LazItemRecordBuilder::default_for_point_format_id(6, 0).unwrap();
+        for vlr in [
+            LazVlrBuilder::new(items.clone())
+                .with_fixed_chunk_size(150)
+                .build(),
+            LazVlrBuilder::new(items).with_variable_chunk_size().build(),
+        ] {
+            let compressed = if vlr.uses_variable_size_chunks() {
+                let mut compressor =
+                    LasZipCompressor::new(Cursor::new(vec![]), vlr.clone()).unwrap();
+                compressor.compress_chunks(points.chunks(30 * 333)).unwrap();
+                compressor.done().unwrap();
+                compressor.into_inner().into_inner()
+            } else {
+                compress(&points, &vlr)
+            };
+
+            let mut decompressor =
+                LasZipStreamDecompressor::new(Pipe(Cursor::new(compressed.clone())), vlr.clone())
+                    .unwrap();
+            let mut decompressed = vec![0u8; points.len()];
+            decompressor.decompress_many(&mut decompressed).unwrap();
+            assert_eq!(decompressed, points);
+
+            // Skipped layers are read and discarded
+            let selection = DecompressionSelection::base().decompress_gps_time();
+            let mut decompressor =
+                LasZipStreamDecompressor::selective(Pipe(Cursor::new(compressed)), vlr, selection)
+                    .unwrap();
+            decompressor.decompress_many(&mut decompressed).unwrap();
+            for (point, expected) in decompressed.chunks(30).zip(points.chunks(30)) {
+                assert_eq!(point[0..8], expected[0..8]);
+                assert_eq!(point[22..30], expected[22..30]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_stream_decompress_variable_point_wise_unsupported() {
+        let items = LazItemRecordBuilder::default_for_point_format_id(1, 0).unwrap();
+        let vlr = LazVlrBuilder::new(items).with_variable_chunk_size().build();
+        let result = LasZipStreamDecompressor::new(Pipe(Cursor::new(vec![])), vlr);
+        assert!(matches!(result, Err(LasZipError::MissingChunkTable)));
+    }
+
+    #[test]
+    fn test_stream_compress() {
+        let (points, vlr) = test_points_and_vlr(100);
+        let point_size = vlr.items_size() as usize;
+
+        // Simulates a header written before the points
+        let header = vec![42u8; 227];
+        let mut compressor = LasZipStreamCompressor::with_position(
+            Sink(header.clone()),
+            vlr.clone(),
+            header.len() as u64,
+        )
+        .unwrap();
+        compressor.compress_many(&points).unwrap();
+        compressor.done().unwrap();
+        assert_eq!(compressor.position(), compressor.get().0.len() as u64);
+        let data = compressor.into_inner().0;
+        assert!(data.starts_with(&header));
+
+        // Apart from the offsets, the data is the same as with a seekable destination
+        let seekable = compress(&points, &vlr);
+        let streamed = &data[header.len()..];
+        assert_eq!(streamed.len(), seekable.len() + 8);
+        assert_eq!(streamed[..8], (-1i64).to_le_bytes());
+        assert_eq!(streamed[8..seekable.len()], seekable[8..]);
+        let chunk_table_offset = i64::from_le_bytes(seekable[..8].try_into().unwrap());
+        assert_eq!(
+            streamed[seekable.len()..],
+            (chunk_table_offset + header.len() as i64).to_le_bytes()
+        );
+
+        // The chunk table is found, so seeking works
+        let mut source = Cursor::new(data);
+        source.set_position(header.len() as u64);
+        let mut decompressor = crate::LasZipDecompressor::new(source, vlr).unwrap();
+        let mut point = vec![0u8; point_size];
+        decompressor.seek(842).unwrap();
+        decompressor.decompress_one(&mut point).unwrap();
+        assert_eq!(point, points[842 * point_size..843 * point_size]);
+    }
+
+    #[test]
+    fn test_stream_compress_layered_variable_size() {
+        let points = layered_points(1000);
+        let items = LazItemRecordBuilder::default_for_point_format_id(6, 0).unwrap();
+        let vlr = LazVlrBuilder::new(items).with_variable_chunk_size().build();
+
+        let mut compressor = LasZipStreamCompressor::new(Sink(vec![]), vlr.clone()).unwrap();
+        compressor.compress_chunks(points.chunks(30 * 333)).unwrap();
+        compressor.done().unwrap();
+        let data = compressor.into_inner().0;
+
+        let mut source = Cursor::new(data);
+        let chunk_table = ChunkTable::read_from(&mut source, &vlr).unwrap();
+        assert_eq!(chunk_table.len(), 5);
+        source.set_position(0);
+        let mut decompressor = crate::LasZipDecompressor::new(source, vlr).unwrap();
+        let mut decompressed = vec![0u8; points.len()];
+        decompressor.decompress_many(&mut decompressed).unwrap();
+        assert_eq!(decompressed, points);
+    }
+}
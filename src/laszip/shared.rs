@@ -0,0 +1,393 @@
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::sync::Arc;
+
+use crate::las::file::read_header_and_vlrs;
+use crate::las::selective::DecompressionSelection;
+use crate::laszip::chunk_table::ChunkTable;
+use crate::laszip::details::record_decompressor_from_laz_items;
+use crate::laszip::LazVlr;
+
+/// A source of bytes that can be read at any offset through a shared reference,
+/// so that multiple threads can read it at the same time without locks.
+pub trait ReadAt {
+    /// Reads exactly `buffer.len()` bytes starting at `offset`
+    fn read_exact_at(&self, buffer: &mut [u8], offset: u64) -> std::io::Result<()>;
+
+    /// Returns the number of bytes of the source, if known.
+    ///
+    /// It is only needed for data written without the offset to the chunk table
+    /// at its start (see [`LasZipStreamCompressor`](crate::LasZipStreamCompressor)).
+    fn size(&self) -> std::io::Result<u64> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "the size of the source is not known",
+        ))
+    }
+}
+
+impl ReadAt for [u8] {
+    fn read_exact_at(&self, buffer: &mut [u8], offset: u64) -> std::io::Result<()> {
+        let start = usize::try_from(offset).unwrap_or(usize::MAX);
+        match start
+            .checked_add(buffer.len())
+            .and_then(|end| self.get(start..end))
+        {
+            Some(bytes) => {
+                buffer.copy_from_slice(bytes);
+                Ok(())
+            }
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "failed to fill whole buffer",
+            )),
+        }
+    }
+
+    fn size(&self) -> std::io::Result<u64> {
+        Ok(self.len() as u64)
+    }
+}
+
+impl ReadAt for Vec<u8> {
+    fn read_exact_at(&self, buffer: &mut [u8], offset: u64) -> std::io::Result<()> {
+        self.as_slice().read_exact_at(buffer, offset)
+    }
+
+    fn size(&self) -> std::io::Result<u64> {
+        Ok(self.len() as u64)
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for &T {
+    fn read_exact_at(&self, buffer: &mut [u8], offset: u64) -> std::io::Result<()> {
+        (**self).read_exact_at(buffer, offset)
+    }
+
+    fn size(&self) -> std::io::Result<u64> {
+        (**self).size()
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for Arc<T> {
+    fn read_exact_at(&self, buffer: &mut [u8], offset: u64) -> std::io::Result<()> {
+        (**self).read_exact_at(buffer, offset)
+    }
+
+    fn size(&self) -> std::io::Result<u64> {
+        (**self).size()
+    }
+}
+
+#[cfg(unix)]
+impl ReadAt for std::fs::File {
+    fn read_exact_at(&self, buffer: &mut [u8], offset: u64) -> std::io::Result<()> {
+        std::os::unix::fs::FileExt::read_exact_at(self, buffer, offset)
+    }
+
+    fn size(&self) -> std::io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+}
+
+#[cfg(windows)]
+impl ReadAt for std::fs::File {
+    fn read_exact_at(&self, mut buffer: &mut [u8], mut offset: u64) -> std::io::Result<()> {
+        while !buffer.is_empty() {
+            match std::os::windows::fs::FileExt::seek_read(self, buffer, offset) {
+                Ok(0) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "failed to fill whole buffer",
+                    ))
+                }
+                Ok(n) => {
+                    buffer = &mut buffer[n..];
+                    offset += n as u64;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> std::io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+}
+
+/// Gives a `Read + Seek` view of a [`ReadAt`] source
+struct ReadAtCursor<'a, S: ?Sized> {
+    source: &'a S,
+    position: u64,
+    /// Size of the source, if known
+    size: Option<u64>,
+}
+
+impl<'a, S: ReadAt + ?Sized> ReadAtCursor<'a, S> {
+    fn new(source: &'a S, position: u64) -> Self {
+        Self {
+            source,
+            position,
+            size: source.size().ok(),
+        }
+    }
+}
+
+impl<S: ReadAt + ?Sized> Read for ReadAtCursor<'_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // As any reader, reading past the end is a short read rather than an error
+        let len = match self.size {
+            Some(size) => usize::try_from(size.saturating_sub(self.position))
+                .map_or(buf.len(), |remaining| remaining.min(buf.len())),
+            None => buf.len(),
+        };
+        if len == 0 {
+            return Ok(0);
+        }
+        self.source.read_exact_at(&mut buf[..len], self.position)?;
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl<S: ReadAt + ?Sized> Seek for ReadAtCursor<'_, S> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+            SeekFrom::End(delta) => match self.size {
+                Some(size) => size.checked_add_signed(delta),
+                None => self.source.size()?.checked_add_signed(delta),
+            },
+        };
+        self.position = position.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+/// Reader of LAZ data that can be shared between threads.
+///
+/// The header, the LAZ vlr and the chunk table are read once, then any thread
+/// can decompress any chunk through a shared reference: chunks are read with
+/// positional reads ([`ReadAt`]), so there are no locks around the source.
+///
+/// ```no_run
+/// # fn main() -> laz::Result<()> {
+/// let file = std::fs::File::open("points.laz")?;
+/// let reader = laz::SharedLazReader::new(file)?;
+/// std::thread::scope(|scope| {
+///     for chunk_index in 0..reader.num_chunks() {
+///         let reader = &reader;
+///         scope.spawn(move || reader.decompress_chunk(chunk_index));
+///     }
+/// });
+/// # Ok(())
+/// # }
+/// ```
+pub struct SharedLazReader<S> {
+    source: S,
+    vlr: LazVlr,
+    chunk_table: ChunkTable,
+    /// Position of the first chunk in the source
+    data_start: u64,
+}
+
+impl<S: ReadAt> SharedLazReader<S> {
+    /// Reads the header, the LAZ vlr and the chunk table of the LAZ file
+    pub fn new(source: S) -> crate::Result<Self> {
+        let mut cursor = ReadAtCursor::new(&source, 0);
+        let (header, vlr) = read_header_and_vlrs(&mut cursor)?;
+        let vlr = vlr.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "the file does not have a LAZ vlr",
+            )
+        })?;
+        Self::from_point_data(
+            source,
+            vlr,
+            u64::from(header.offset_to_points),
+            header.num_points,
+        )
+    }
+
+    /// Reads the chunk table of the LAZ point data that starts at `offset`
+    /// (see [`ChunkTable::read_from`](crate::laszip::ChunkTable::read_from)).
+    pub fn from_point_data(
+        source: S,
+        vlr: LazVlr,
+        offset: u64,
+        point_count: u64,
+    ) -> crate::Result<Self> {
+        let mut cursor = ReadAtCursor::new(&source, offset);
+        let mut chunk_table = ChunkTable::read_from(&mut cursor, &vlr)?;
+        if !vlr.uses_variable_size_chunks() {
+            chunk_table.fix_last_point_count(point_count)?;
+        }
+
+        Ok(Self {
+            source,
+            vlr,
+            chunk_table,
+            data_start: offset + std::mem::size_of::<i64>() as u64,
+        })
+    }
+
+    pub fn vlr(&self) -> &LazVlr {
+        &self.vlr
+    }
+
+    pub fn chunk_table(&self) -> &ChunkTable {
+        &self.chunk_table
+    }
+
+    pub fn num_chunks(&self) -> usize {
+        self.chunk_table.len()
+    }
+
+    /// Returns the number of points
+    pub fn point_count(&self) -> u64 {
+        self.chunk_table.point_count()
+    }
+
+    /// Returns the index of the chunk which has the point
+    pub fn chunk_of_point(&self, index: u64) -> Option<usize> {
+        self.chunk_table.chunk_index_of_point(index)
+    }
+
+    /// Decompresses all the points of the chunk
+    pub fn decompress_chunk(&self, chunk_index: usize) -> crate::Result<Vec<u8>> {
+        self.decompress_chunk_selective(chunk_index, DecompressionSelection::all())
+    }
+
+    /// Decompresses the points of the chunk, only decompressing the selected
+    /// fields of layered chunks
+    pub fn decompress_chunk_selective(
+        &self,
+        chunk_index: usize,
+        selection: DecompressionSelection,
+    ) -> crate::Result<Vec<u8>> {
+        let Some(entry) = self.chunk_table.as_ref().get(chunk_index) else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "chunk index {} is out of bounds, there are {} chunks",
+                    chunk_index,
+                    self.num_chunks()
+                ),
+            )
+            .into());
+        };
+        let bytes = self.chunk_table.byte_range_of_chunk(chunk_index).unwrap();
+        let mut compressed = vec![0u8; entry.byte_count as usize];
+        self.source
+            .read_exact_at(&mut compressed, self.data_start + bytes.start)?;
+
+        let mut points = vec![0u8; entry.point_count as usize * self.vlr.items_size() as usize];
+        let mut decompressor =
+            record_decompressor_from_laz_items(self.vlr.items(), Cursor::new(compressed))?;
+        decompressor.set_selection(selection);
+        decompressor.decompress_many(&mut points)?;
+        Ok(points)
+    }
+
+    /// Decompresses the `len` points starting at the index `start`
+    pub fn read_points(&self, start: u64, len: u64) -> crate::Result<Vec<u8>> {
+        let end = start.saturating_add(len);
+        if end > self.point_count() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "points {}..{} are out of bounds, there are {} points",
+                    start,
+                    end,
+                    self.point_count()
+                ),
+            )
+            .into());
+        }
+        let point_size = self.vlr.items_size() as usize;
+        let mut points = Vec::with_capacity(len as usize * point_size);
+        let mut index = start;
+        while index < end {
+            let chunk_index = self.chunk_of_point(index).unwrap();
+            let chunk = self.decompress_chunk(chunk_index)?;
+            let chunk_points = self.chunk_table.point_range_of_chunk(chunk_index).unwrap();
+            let first_point = chunk_points.start;
+            let chunk_end = chunk_points.end.min(end);
+            points.extend_from_slice(
+                &chunk[(index - first_point) as usize * point_size
+                    ..(chunk_end - first_point) as usize * point_size],
+            );
+            index = chunk_end;
+        }
+        Ok(points)
+    }
+
+    pub fn get(&self) -> &S {
+        &self.source
+    }
+
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::{compress, compress_in_chunks, test_points_and_vlr};
+
+    #[test]
+    fn test_read_at_cursor_short_reads() {
+        let data = (0..10u8).collect::<Vec<u8>>();
+        let mut cursor = ReadAtCursor::new(data.as_slice(), 6);
+        let mut buffer = [0u8; 8];
+        assert_eq!(cursor.read(&mut buffer).unwrap(), 4);
+        assert_eq!(buffer[..4], [6, 7, 8, 9]);
+        assert_eq!(cursor.read(&mut buffer).unwrap(), 0);
+
+        cursor.seek(SeekFrom::End(-3)).unwrap();
+        let mut rest = vec![];
+        cursor.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, [7, 8, 9]);
+
+        cursor.seek(SeekFrom::Start(20)).unwrap();
+        assert_eq!(cursor.read(&mut buffer).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_shared_reader_variable_size_chunks() {
+        let (points, vlr) = test_points_and_vlr(100);
+        let vlr = vlr.with_variable_chunk_size();
+        let point_size = vlr.items_size() as usize;
+        let data = compress_in_chunks(&points, &vlr, &[1, 1000]);
+
+        // The point count is not needed, it is in the chunk table
+        let reader = SharedLazReader::from_point_data(data.as_slice(), vlr, 0, 0).unwrap();
+        assert_eq!(reader.num_chunks(), 3);
+        assert_eq!(reader.point_count() as usize * point_size, points.len());
+        assert_eq!(reader.chunk_of_point(0), Some(0));
+        assert_eq!(reader.chunk_of_point(1), Some(1));
+        assert_eq!(reader.chunk_of_point(1001), Some(2));
+        assert_eq!(reader.decompress_chunk(0).unwrap(), points[..point_size]);
+        assert_eq!(reader.read_points(0, reader.point_count()).unwrap(), points);
+    }
+
+    #[test]
+    fn test_shared_reader_empty() {
+        let (_, vlr) = test_points_and_vlr(100);
+        let data = compress(&[], &vlr);
+        let reader = SharedLazReader::from_point_data(data.as_slice(), vlr, 0, 0).unwrap();
+        assert_eq!(reader.point_count(), 0);
+        assert_eq!(reader.chunk_of_point(0), None);
+        assert!(reader.read_points(0, 0).unwrap().is_empty());
+        assert!(reader.read_points(0, 1).is_err());
+    }
+}
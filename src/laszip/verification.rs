@@ -0,0 +1,214 @@
+use std::io::Cursor;
+
+use crate::laszip::details::record_decompressor_from_laz_items;
+use crate::laszip::LazVlr;
+use crate::LasZipError;
+
+/// First difference between the input points of a chunk
+/// and the points decompressed from it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) struct PointMismatch {
+    point_index: u64,
+    byte_offset: usize,
+    expected: u8,
+    actual: u8,
+}
+
+impl PointMismatch {
+    pub(crate) fn into_error(self, chunk_index: usize) -> LasZipError {
+        LasZipError::VerificationFailed {
+            chunk_index,
+            point_index: self.point_index,
+            byte_offset: self.byte_offset,
+            expected: self.expected,
+            actual: self.actual,
+        }
+    }
+}
+
+/// Decompresses the `chunk` and compares the result with the `points`
+/// that were compressed to produce it.
+///
+/// The chunk is padded with zeros before being decompressed: the decoder
+/// of a corrupted chunk may read more bytes than the chunk has, and we want
+/// to report the first wrong point rather than an unexpected end of file.
+pub(crate) fn find_mismatch(
+    chunk: &[u8],
+    points: &[u8],
+    vlr: &LazVlr,
+) -> crate::Result<Option<PointMismatch>> {
+    if points.is_empty() {
+        return Ok(None);
+    }
+    let point_size = vlr.items_size() as usize;
+    let mut decompressed = vec![0u8; points.len()];
+    let mut padded = Vec::with_capacity(chunk.len() + points.len());
+    padded.extend_from_slice(chunk);
+    padded.resize(chunk.len() + points.len(), 0u8);
+    let mut decompressor = record_decompressor_from_laz_items(vlr.items(), Cursor::new(padded))?;
+    decompressor.decompress_many(&mut decompressed)?;
+
+    let mismatch = points
+        .iter()
+        .zip(decompressed.iter())
+        .position(|(expected, actual)| expected != actual)
+        .map(|offset| PointMismatch {
+            point_index: (offset / point_size) as u64,
+            byte_offset: offset % point_size,
+            expected: points[offset],
+            actual: decompressed[offset],
+        });
+    Ok(mismatch)
+}
+
+/// Same as [`find_mismatch`], with the mismatch turned into an error
+pub(crate) fn verify_chunk(
+    chunk: &[u8],
+    points: &[u8],
+    vlr: &LazVlr,
+    chunk_index: usize,
+) -> crate::Result<()> {
+    match find_mismatch(chunk, points, vlr)? {
+        Some(mismatch) => Err(mismatch.into_error(chunk_index)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    use super::*;
+    use crate::test_support::{compress, compress_in_chunks, test_points_and_vlr};
+    use crate::LasZipCompressor;
+
+    /// Destination that flips the bits of the byte written at `corrupted_pos`
+    struct CorruptingDest {
+        inner: Cursor<Vec<u8>>,
+        corrupted_pos: u64,
+    }
+
+    impl Write for CorruptingDest {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let pos = self.inner.position();
+            let mut buf = buf.to_vec();
+            if (pos..pos + buf.len() as u64).contains(&self.corrupted_pos) {
+                buf[(self.corrupted_pos - pos) as usize] ^= 0x01;
+            }
+            self.inner.write(&buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    impl Read for CorruptingDest {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.inner.read(buf)
+        }
+    }
+
+    impl Seek for CorruptingDest {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_verification_passes() {
+        let (points, vlr) = test_points_and_vlr(250);
+
+        let expected = compress(&points, &vlr);
+
+        let mut compressor = LasZipCompressor::new(Cursor::new(vec![]), vlr.clone()).unwrap();
+        compressor.enable_verification();
+        compressor.compress_many(&points).unwrap();
+        compressor.done().unwrap();
+        assert_eq!(compressor.into_inner().into_inner(), expected);
+
+        #[cfg(feature = "parallel")]
+        {
+            let mut compressor = crate::ParLasZipCompressor::new(Cursor::new(vec![]), vlr).unwrap();
+            compressor.enable_verification();
+            let point_size = compressor.vlr().items_size() as usize;
+            compressor
+                .compress_many(&points[..10 * point_size])
+                .unwrap();
+            compressor
+                .compress_many(&points[10 * point_size..])
+                .unwrap();
+            compressor.done().unwrap();
+            assert_eq!(compressor.into_inner().into_inner(), expected);
+        }
+    }
+
+    #[test]
+    fn test_verification_of_variable_size_chunks() {
+        let (points, vlr) = test_points_and_vlr(250);
+        let vlr = vlr.with_variable_chunk_size();
+        let point_size = vlr.items_size() as usize;
+        let expected = compress_in_chunks(&points, &vlr, &[1, 700]);
+
+        let mut compressor = LasZipCompressor::new(Cursor::new(vec![]), vlr).unwrap();
+        compressor.enable_verification();
+        compressor.compress_many(&points[..point_size]).unwrap();
+        compressor.finish_current_chunk().unwrap();
+        compressor
+            .compress_many(&points[point_size..701 * point_size])
+            .unwrap();
+        compressor.finish_current_chunk().unwrap();
+        // The last chunk is only verified by `done`
+        compressor
+            .compress_many(&points[701 * point_size..])
+            .unwrap();
+        compressor.done().unwrap();
+        assert_eq!(compressor.into_inner().into_inner(), expected);
+    }
+
+    #[test]
+    fn test_verification_of_empty_data() {
+        let (_, vlr) = test_points_and_vlr(250);
+        let mut compressor = LasZipCompressor::new(Cursor::new(vec![]), vlr.clone()).unwrap();
+        compressor.enable_verification();
+        compressor.done().unwrap();
+        assert_eq!(compressor.into_inner().into_inner(), compress(&[], &vlr));
+    }
+
+    #[test]
+    fn test_verification_fails_on_corrupted_chunk() {
+        let (points, vlr) = test_points_and_vlr(250);
+        let point_size = vlr.items_size() as usize;
+        let dest = CorruptingDest {
+            inner: Cursor::new(vec![]),
+            corrupted_pos: 6000,
+        };
+        let mut compressor = LasZipCompressor::new(dest, vlr.clone()).unwrap();
+        compressor.enable_verification();
+        // The first chunk is not corrupted
+        compressor
+            .compress_many(&points[..250 * point_size])
+            .unwrap();
+        let error = compressor
+            .compress_many(&points[250 * point_size..])
+            .and_then(|_| compressor.done())
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        let error = error
+            .into_inner()
+            .unwrap()
+            .downcast::<LasZipError>()
+            .unwrap();
+        let LasZipError::VerificationFailed {
+            chunk_index,
+            expected,
+            actual,
+            ..
+        } = *error
+        else {
+            panic!("unexpected error: {}", error);
+        };
+        assert_eq!(chunk_index, 1);
+        assert_ne!(expected, actual);
+    }
+}
@@ -1,3 +1,4 @@
+use std::fmt;
 use std::io::{Read, Write};
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
@@ -11,6 +12,7 @@ use crate::LasZipError;
 const DEFAULT_CHUNK_SIZE: usize = 50_000;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct Version {
     major: u8,
     minor: u8,
@@ -46,6 +48,7 @@ impl Default for Version {
 
 /// The different type of data / fields found in the definition of LAS points
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LazItemType {
     // => Below this point, the compressor version is 1 or 2
     /// ExtraBytes for LAS versions <= 1.3 & point format <= 5
@@ -104,6 +107,30 @@ impl LazItemType {
         }
     }
 
+    /// Whether the item is one of the items of point formats >= 6
+    fn is_extended(self) -> bool {
+        matches!(
+            self,
+            LazItemType::Point14
+                | LazItemType::RGB14
+                | LazItemType::RGBNIR14
+                | LazItemType::WavePacket14
+                | LazItemType::Byte14(_)
+        )
+    }
+
+    /// Returns the kind of the item, items of the same kind
+    /// cannot be used more than once in a point record.
+    fn kind(self) -> u8 {
+        match self {
+            LazItemType::Point10 | LazItemType::Point14 => 0,
+            LazItemType::GpsTime => 1,
+            LazItemType::RGB12 | LazItemType::RGB14 | LazItemType::RGBNIR14 => 2,
+            LazItemType::WavePacket13 | LazItemType::WavePacket14 => 3,
+            LazItemType::Byte(_) | LazItemType::Byte14(_) => 4,
+        }
+    }
+
     fn default_version(self) -> u16 {
         match self {
             LazItemType::Byte(_) => 2,
@@ -137,11 +164,187 @@ impl From<LazItemType> for u16 {
     }
 }
 
+/// The rule broken by an invalid [`LazVlr`], see [`LasZipError::InvalidVlr`]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum InvalidVlrReason {
+    /// There are no items
+    NoItems,
+    /// The first item is not `Point10` or `Point14`
+    FirstItemNotPoint(LazItemType),
+    /// An item of the same kind (point, gps time, colors, wavepacket or extra bytes)
+    /// is already in the list
+    DuplicateItem {
+        index: usize,
+        item_type: LazItemType,
+    },
+    /// The item is placed after an item that must come after it,
+    /// the order is: point, gps time, colors, wavepacket then extra bytes
+    ItemOutOfOrder {
+        index: usize,
+        item_type: LazItemType,
+    },
+    /// Items of point formats <= 5 are mixed with items of point formats >= 6
+    /// (e.g. `Point10` with `RGB14`)
+    MixedItemFamilies {
+        index: usize,
+        item_type: LazItemType,
+    },
+    /// The version cannot be used for the item type
+    /// (1 or 2 for point formats <= 5, 3 or 4 for point formats >= 6)
+    UnsupportedItemVersion {
+        index: usize,
+        item_type: LazItemType,
+        version: u16,
+    },
+    /// Items of point formats >= 6 do not all use the same version
+    MixedItemVersions {
+        index: usize,
+        version: u16,
+        expected: u16,
+    },
+    /// The size of the item is not the size of its type (extra bytes must not be empty)
+    InvalidItemSize {
+        index: usize,
+        item_type: LazItemType,
+        size: u16,
+    },
+    /// The point format of the header does not use these items
+    PointFormatMismatch { point_format_id: u8 },
+    /// The point size of the header is not the size of the items
+    PointSizeMismatch { items_size: u64, point_size: u16 },
+}
+
+impl fmt::Display for InvalidVlrReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvalidVlrReason::NoItems => write!(f, "there are no items"),
+            InvalidVlrReason::FirstItemNotPoint(item_type) => {
+                write!(f, "the first item is {:?} instead of a point", item_type)
+            }
+            InvalidVlrReason::DuplicateItem { index, item_type } => write!(
+                f,
+                "item {} ({:?}) is of the same kind as a previous item",
+                index, item_type
+            ),
+            InvalidVlrReason::ItemOutOfOrder { index, item_type } => write!(
+                f,
+                "item {} ({:?}) is placed after an item that must come after it",
+                index, item_type
+            ),
+            InvalidVlrReason::MixedItemFamilies { index, item_type } => write!(
+                f,
+                "item {} ({:?}) is not of the same point format family as the first item",
+                index, item_type
+            ),
+            InvalidVlrReason::UnsupportedItemVersion {
+                index,
+                item_type,
+                version,
+            } => write!(
+                f,
+                "item {} ({:?}) cannot use the version {}",
+                index, item_type, version
+            ),
+            InvalidVlrReason::MixedItemVersions {
+                index,
+                version,
+                expected,
+            } => write!(
+                f,
+                "item {} uses the version {}, the first item uses the version {}",
+                index, version, expected
+            ),
+            InvalidVlrReason::InvalidItemSize {
+                index,
+                item_type,
+                size,
+            } => write!(
+                f,
+                "item {} ({:?}) has an invalid size of {} bytes",
+                index, item_type, size
+            ),
+            InvalidVlrReason::PointFormatMismatch { point_format_id } => write!(
+                f,
+                "the items do not match the point format {}",
+                point_format_id
+            ),
+            InvalidVlrReason::PointSizeMismatch {
+                items_size,
+                point_size,
+            } => write!(
+                f,
+                "the items are {} bytes, the point size is {} bytes",
+                items_size, point_size
+            ),
+        }
+    }
+}
+
+/// Checks the rules on the items of a [`LazVlr`]
+fn validate_laz_items(items: &[LazItem]) -> Result<(), InvalidVlrReason> {
+    let first_item = items.first().ok_or(InvalidVlrReason::NoItems)?;
+    if !matches!(
+        first_item.item_type,
+        LazItemType::Point10 | LazItemType::Point14
+    ) {
+        return Err(InvalidVlrReason::FirstItemNotPoint(first_item.item_type));
+    }
+
+    let is_extended = first_item.item_type.is_extended();
+    let mut seen_kinds = [false; 5];
+    let mut previous_kind = 0;
+    for (index, item) in items.iter().enumerate() {
+        let item_type = item.item_type;
+        if item_type.is_extended() != is_extended {
+            return Err(InvalidVlrReason::MixedItemFamilies { index, item_type });
+        }
+        let kind = usize::from(item_type.kind());
+        if seen_kinds[kind] {
+            return Err(InvalidVlrReason::DuplicateItem { index, item_type });
+        }
+        seen_kinds[kind] = true;
+        // LASzip compresses the items in this order
+        if kind < previous_kind {
+            return Err(InvalidVlrReason::ItemOutOfOrder { index, item_type });
+        }
+        previous_kind = kind;
+
+        let supported_versions = if is_extended { 3..=4 } else { 1..=2 };
+        if !supported_versions.contains(&item.version) {
+            return Err(InvalidVlrReason::UnsupportedItemVersion {
+                index,
+                item_type,
+                version: item.version,
+            });
+        }
+        // LASzip writes version 1 wavepackets with version 2 items,
+        // so only the versions of layered items must all be the same
+        if is_extended && item.version != first_item.version {
+            return Err(InvalidVlrReason::MixedItemVersions {
+                index,
+                version: item.version,
+                expected: first_item.version,
+            });
+        }
+
+        if item.size != item_type.size() || item.size == 0 {
+            return Err(InvalidVlrReason::InvalidItemSize {
+                index,
+                item_type,
+                size: item.size,
+            });
+        }
+    }
+    Ok(())
+}
+
 /// Struct stored as part of the laszip's vlr record_data
 ///
 /// This gives information about the dimension compressed
 /// and the version used for the compression.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LazItem {
     // coded on a u16
     pub(crate) item_type: LazItemType,
@@ -346,6 +549,7 @@ fn write_laz_items_to<W: Write>(laz_items: &Vec<LazItem>, mut dst: &mut W) -> st
 
 /// The possibilities for how the compressed data is organized.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CompressorType {
     None = 0,
     /// No chunks, or rather only 1 chunk with all the points
@@ -391,6 +595,8 @@ impl Default for CompressorType {
 ///
 /// To create one from scratch, see the [`LazVlrBuilder`]
 #[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "LazVlrRepr"))]
 pub struct LazVlr {
     // coded on u16
     pub(super) compressor: CompressorType,
@@ -410,6 +616,44 @@ pub struct LazVlr {
     items: Vec<LazItem>,
 }
 
+/// The fields of a [`LazVlr`] as deserialized, before they are checked
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct LazVlrRepr {
+    compressor: CompressorType,
+    coder: u16,
+    version: Version,
+    options: u32,
+    chunk_size: u32,
+    number_of_special_evlrs: i64,
+    offset_to_special_evlrs: i64,
+    items: Vec<LazItem>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<LazVlrRepr> for LazVlr {
+    type Error = LasZipError;
+
+    /// Like [`LazVlr::read_from`], a chunk size of 0 means variable-size chunks
+    fn try_from(repr: LazVlrRepr) -> crate::Result<Self> {
+        let vlr = Self {
+            compressor: repr.compressor,
+            coder: repr.coder,
+            version: repr.version,
+            options: repr.options,
+            chunk_size: match repr.chunk_size {
+                0 => Self::VARIABLE_CHUNK_SIZE,
+                v => v,
+            },
+            number_of_special_evlrs: repr.number_of_special_evlrs,
+            offset_to_special_evlrs: repr.offset_to_special_evlrs,
+            items: repr.items,
+        };
+        vlr.validate()?;
+        Ok(vlr)
+    }
+}
+
 impl LazVlr {
     /// The user id of the LasZip VLR header.
     pub const USER_ID: &'static str = "laszip encoded";
@@ -426,7 +670,8 @@ impl LazVlr {
     ///
     /// # panics
     ///
-    /// Will panic if `items` is empty or contains invalid items.
+    /// Will panic if `items` is empty or contains invalid items,
+    /// see [`LazVlrBuilder::try_build`] for a fallible version.
     pub fn from_laz_items(items: Vec<LazItem>) -> Self {
         let first_item = items
             .first()
@@ -489,6 +734,12 @@ impl LazVlr {
         Ok(())
     }
 
+    /// Returns the type of compressor used
+    #[inline]
+    pub fn compressor_type(&self) -> CompressorType {
+        self.compressor
+    }
+
     #[inline]
     /// Returns whether the chunk size is variable.
     pub fn uses_variable_size_chunks(&self) -> bool {
@@ -503,6 +754,23 @@ impl LazVlr {
         self.chunk_size
     }
 
+    /// Returns the same vlr, with fixed-size chunks of `chunk_size` points.
+    ///
+    /// The other fields (compressor, version, options, items) are kept,
+    /// unlike when building a new vlr from the items with the [`LazVlrBuilder`].
+    pub fn with_fixed_chunk_size(mut self, chunk_size: u32) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Returns the same vlr, with variable-size chunks.
+    ///
+    /// The other fields are kept, see [`Self::with_fixed_chunk_size`].
+    pub fn with_variable_chunk_size(mut self) -> Self {
+        self.chunk_size = Self::VARIABLE_CHUNK_SIZE;
+        self
+    }
+
     /// Returns the items compressed by this VLR
     #[inline]
     pub fn items(&self) -> &Vec<LazItem> {
@@ -516,6 +784,48 @@ impl LazVlr {
         u64::from(self.items.iter().map(|item| item.size).sum::<u16>())
     }
 
+    /// Checks that the items make sense: the first item is a point, items are not
+    /// repeated nor mixed between point formats <= 5 and >= 6, and their versions
+    /// and sizes are supported.
+    pub fn validate(&self) -> crate::Result<()> {
+        validate_laz_items(&self.items).map_err(LasZipError::InvalidVlr)
+    }
+
+    /// Checks that the vlr is valid and that its items are the ones of the point format,
+    /// with the given point size (extra bytes included), as found in the LAS header.
+    ///
+    /// The item versions are not checked against the point format.
+    pub fn validate_against(&self, point_format_id: u8, point_size: u16) -> crate::Result<()> {
+        self.validate()?;
+        let base_size = LazItemRecordBuilder::default_for_point_format_id(point_format_id, 0)?
+            .iter()
+            .map(|item| u64::from(item.size))
+            .sum::<u64>();
+        let items_size = self.items_size();
+        if items_size != u64::from(point_size) {
+            return Err(LasZipError::InvalidVlr(
+                InvalidVlrReason::PointSizeMismatch {
+                    items_size,
+                    point_size,
+                },
+            ));
+        }
+        let num_extra_bytes = (items_size.saturating_sub(base_size)) as u16;
+        let expected_items =
+            LazItemRecordBuilder::default_for_point_format_id(point_format_id, num_extra_bytes)?;
+        if base_size > items_size
+            || !expected_items
+                .iter()
+                .map(LazItem::item_type)
+                .eq(self.items.iter().map(LazItem::item_type))
+        {
+            return Err(LasZipError::InvalidVlr(
+                InvalidVlrReason::PointFormatMismatch { point_format_id },
+            ));
+        }
+        Ok(())
+    }
+
     /// returns how many bytes a decompressed chunk contains
     #[cfg(feature = "parallel")]
     #[inline]
@@ -623,12 +933,35 @@ impl LazVlrBuilder {
         self
     }
 
+    /// Builds the vlr
+    ///
+    /// # panics
+    ///
+    /// Will panic if there are no items or if the version of the first item is not known,
+    /// other invalid items are **not** detected, see [`Self::try_build`].
     pub fn build(self) -> LazVlr {
         let mut vlr = LazVlr::from_laz_items(self.items);
         vlr.chunk_size = self.chunk_size;
         vlr
     }
 
+    /// Builds the vlr, after checking that the items are valid (see [`LazVlr::validate`])
+    ///
+    /// # Example
+    /// ```
+    /// use laz::{LasZipError, LazItemRecordBuilder, LazItemType, LazVlrBuilder};
+    /// let items = LazItemRecordBuilder::new()
+    ///     .add_item(LazItemType::Point10)
+    ///     .add_item(LazItemType::RGB14)
+    ///     .build();
+    /// let result = LazVlrBuilder::new(items).try_build();
+    /// assert!(matches!(result, Err(LasZipError::InvalidVlr(_))));
+    /// ```
+    pub fn try_build(self) -> crate::Result<LazVlr> {
+        validate_laz_items(&self.items).map_err(LasZipError::InvalidVlr)?;
+        Ok(self.build())
+    }
+
     #[deprecated(
         since = "0.6.0",
         note = "Please use LazVlrBuilder::with_fixed_chunk_size"
@@ -665,4 +998,154 @@ mod tests {
         assert!(parsed.uses_variable_size_chunks());
         assert_eq!(parsed.chunk_size(), LazVlr::VARIABLE_CHUNK_SIZE);
     }
+
+    fn invalid_reason(items: Vec<LazItem>) -> InvalidVlrReason {
+        match LazVlrBuilder::new(items).try_build() {
+            Err(LasZipError::InvalidVlr(reason)) => reason,
+            other => panic!("expected an invalid vlr, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn try_build_accepts_default_items() {
+        for point_format_id in 0..=10 {
+            for num_extra_bytes in [0, 3] {
+                let vlr = LazVlrBuilder::default()
+                    .with_point_format(point_format_id, num_extra_bytes)
+                    .unwrap()
+                    .try_build()
+                    .unwrap();
+                let point_size = vlr.items_size() as u16;
+                vlr.validate_against(point_format_id, point_size).unwrap();
+            }
+        }
+        // LASzip uses version 1 wavepackets with version 2 items
+        let mut items = LazItemRecordBuilder::default_for_point_format_id(4, 0).unwrap();
+        items[2].version = 1;
+        LazVlrBuilder::new(items).try_build().unwrap();
+    }
+
+    #[test]
+    fn try_build_rejects_invalid_items() {
+        assert_eq!(invalid_reason(vec![]), InvalidVlrReason::NoItems);
+        assert_eq!(
+            invalid_reason(vec![LazItem::new(LazItemType::GpsTime, 2)]),
+            InvalidVlrReason::FirstItemNotPoint(LazItemType::GpsTime)
+        );
+        assert_eq!(
+            invalid_reason(vec![
+                LazItem::new(LazItemType::Point10, 2),
+                LazItem::new(LazItemType::RGB14, 3),
+            ]),
+            InvalidVlrReason::MixedItemFamilies {
+                index: 1,
+                item_type: LazItemType::RGB14
+            }
+        );
+        assert_eq!(
+            invalid_reason(vec![
+                LazItem::new(LazItemType::Point10, 2),
+                LazItem::new(LazItemType::GpsTime, 3),
+            ]),
+            InvalidVlrReason::UnsupportedItemVersion {
+                index: 1,
+                item_type: LazItemType::GpsTime,
+                version: 3
+            }
+        );
+        assert_eq!(
+            invalid_reason(vec![
+                LazItem::new(LazItemType::Point14, 3),
+                LazItem::new(LazItemType::RGB14, 4),
+            ]),
+            InvalidVlrReason::MixedItemVersions {
+                index: 1,
+                version: 4,
+                expected: 3
+            }
+        );
+        assert_eq!(
+            invalid_reason(vec![
+                LazItem::new(LazItemType::Point14, 3),
+                LazItem::new(LazItemType::RGB14, 3),
+                LazItem::new(LazItemType::RGBNIR14, 3),
+            ]),
+            InvalidVlrReason::DuplicateItem {
+                index: 2,
+                item_type: LazItemType::RGBNIR14
+            }
+        );
+        assert_eq!(
+            invalid_reason(vec![
+                LazItem::new(LazItemType::Point14, 3),
+                LazItem::new(LazItemType::Byte14(0), 3),
+            ]),
+            InvalidVlrReason::InvalidItemSize {
+                index: 1,
+                item_type: LazItemType::Byte14(0),
+                size: 0
+            }
+        );
+        assert_eq!(
+            invalid_reason(vec![
+                LazItem::new(LazItemType::Point10, 2),
+                LazItem::new(LazItemType::RGB12, 2),
+                LazItem::new(LazItemType::GpsTime, 2),
+            ]),
+            InvalidVlrReason::ItemOutOfOrder {
+                index: 2,
+                item_type: LazItemType::GpsTime
+            }
+        );
+        assert_eq!(
+            invalid_reason(vec![
+                LazItem::new(LazItemType::Point14, 3),
+                LazItem::new(LazItemType::Byte14(2), 3),
+                LazItem::new(LazItemType::RGB14, 3),
+            ]),
+            InvalidVlrReason::ItemOutOfOrder {
+                index: 2,
+                item_type: LazItemType::RGB14
+            }
+        );
+        let mut byte_item = LazItem::new(LazItemType::Byte14(4), 3);
+        byte_item.size = 2;
+        assert_eq!(
+            invalid_reason(vec![LazItem::new(LazItemType::Point14, 3), byte_item]),
+            InvalidVlrReason::InvalidItemSize {
+                index: 1,
+                item_type: LazItemType::Byte14(4),
+                size: 2
+            }
+        );
+    }
+
+    #[test]
+    fn validate_against_header() {
+        let vlr = LazVlrBuilder::default()
+            .with_point_format(3, 2)
+            .unwrap()
+            .build();
+        vlr.validate_against(3, 36).unwrap();
+        assert!(matches!(
+            vlr.validate_against(3, 34),
+            Err(LasZipError::InvalidVlr(
+                InvalidVlrReason::PointSizeMismatch {
+                    items_size: 36,
+                    point_size: 34
+                }
+            ))
+        ));
+        // Point format 1 with 10 extra bytes has the same size as point format 3 with 2
+        assert!(matches!(
+            vlr.validate_against(1, 36),
+            Err(LasZipError::InvalidVlr(
+                InvalidVlrReason::PointFormatMismatch { point_format_id: 1 }
+            ))
+        ));
+        assert!(matches!(
+            vlr.validate_against(11, 36),
+            Err(LasZipError::UnsupportedPointFormat(11))
+        ));
+    }
 }
@@ -106,21 +106,43 @@
 //!
 //! - [`par_compress_buffer`]
 //! - [`par_decompress_buffer`]
+//! - [`par_rechunk`]
+//! - [`par_requantize`]
 
 pub use errors::LasZipError;
-pub use las::selective::DecompressionSelection;
+pub use las::extra_bytes::ExtraBytesAttribute;
+pub use las::selective::{DecompressionSelection, ExtraBytesSelection};
 #[cfg(feature = "parallel")]
 pub use laszip::parallel::{
     par_compress_buffer, par_decompress, par_decompress_buffer, par_decompress_selective,
-    ParLasZipAppender, ParLasZipCompressor, ParLasZipDecompressor,
+    par_rechunk, par_requantize, ParLasZipAppender, ParLasZipCompressor, ParLasZipDecompressor,
+    RechunkPolicy,
 };
+pub use laszip::ChunkingPolicy;
 pub use laszip::{compress_buffer, decompress_buffer};
 pub use laszip::{
-    LasZipAppender, LasZipCompressor, LasZipDecompressor, LazCompressor, LazCompressorWithInner,
-    LazDecompressor, LazItem, LazItemRecordBuilder, LazItemType, LazVlr, LazVlrBuilder,
+    edit_chunk_classification, edit_classification, Layer, LayerKind, LayeredChunkLayout,
 };
+pub use laszip::{extract_chunks, extract_points, ExtractedLaz};
+pub use laszip::{merge, MergeInput, MergedLaz};
+pub use laszip::{open_decompressor, DecompressorBackend, DecompressorOptions};
+pub use laszip::{requantize, Quantization};
+pub use laszip::{restore_order, ReorderReport, ReorderingCompressor, SortOrder};
+pub use laszip::{CacheStats, CachedLazReader};
+pub use laszip::{ChunkReport, CompressedField, CompressionReport, FieldByteCount};
+pub use laszip::{
+    InvalidVlrReason, LasZipAppender, LasZipCompressor, LasZipDecompressor, LasZipStreamCompressor,
+    LasZipStreamDecompressor, LazCompressor, LazCompressorWithInner, LazCompressorWithState,
+    LazDecompressor, LazDecompressorWithState, LazItem, LazItemRecordBuilder, LazItemType, LazVlr,
+    LazVlrBuilder,
+};
+pub use laszip::{PointFilter, PointLayout, PointSampler, SamplingMode};
+pub use laszip::{ReadAt, SharedLazReader};
 
+#[cfg(feature = "capi")]
+pub mod capi;
 pub mod compressors;
+pub mod copc;
 pub mod decoders;
 pub mod decompressors;
 pub mod encoders;
@@ -128,6 +150,7 @@ pub mod laszip;
 pub mod models;
 pub mod packers;
 pub mod record;
+pub mod streams;
 
 mod byteslice;
 pub mod errors;
@@ -135,6 +158,8 @@ pub mod las;
 
 #[cfg(test)]
 mod test;
+#[doc(hidden)]
+pub mod test_support;
 
 /// Shortcut for `Result`s of this crate.
 pub type Result<T> = std::result::Result<T, LasZipError>;
@@ -8,7 +8,7 @@ use crate::byteslice::{ChunksIrregular, ChunksIrregularMut};
 use crate::decoders;
 use crate::encoders;
 use crate::las;
-use crate::las::selective::DecompressionSelection;
+use crate::las::selective::{DecompressionSelection, ExtraBytesSelection};
 use crate::laszip::{LazItem, LazItemType};
 use crate::LasZipError;
 
@@ -55,6 +55,11 @@ pub trait LayeredFieldDecompressor<R: Read> {
     /// Will be called before any of the methods that are defined below.
     fn set_selection(&mut self, selection: DecompressionSelection);
 
+    /// Which extra bytes the user wants to decompress, in addition to the `selection`.
+    ///
+    /// Only used by the extra bytes field, will be called before any of the methods below.
+    fn set_extra_bytes_selection(&mut self, _selection: ExtraBytesSelection) {}
+
     /// Decompress the first point's field from the `src`, and pack it into the `first_point` slice
     ///
     /// The `first_point` slice will have a len of exactly `self_of_field()` bytes.
@@ -108,6 +113,13 @@ pub trait RecordDecompressor<R> {
     /// Must be called before decompressing any points (otherwise it will be ignored)
     fn set_selection(&mut self, selection: DecompressionSelection);
 
+    /// Sets the extra bytes the user wants to decompress, in addition to the ones
+    /// of the selection
+    ///
+    /// Just like [`Self::set_selection`], may be ignored and must be called
+    /// before decompressing any points.
+    fn set_extra_bytes_selection(&mut self, _selection: ExtraBytesSelection) {}
+
     /// Decompress the next point and pack the result in the `out` slice
     fn decompress_next(&mut self, out: &mut [u8]) -> std::io::Result<()>;
 
@@ -351,6 +363,8 @@ pub struct LayeredPointRecordDecompressor<'a, R: Read + Seek> {
     fields_sizes: Vec<usize>,
     record_size: usize,
     context: usize,
+    /// Number of points in the current chunk, as read at its start
+    record_count: u64,
 }
 
 impl<'a, R: Read + Seek> LayeredPointRecordDecompressor<'a, R> {
@@ -364,6 +378,7 @@ impl<'a, R: Read + Seek> LayeredPointRecordDecompressor<'a, R> {
             fields_sizes: vec![],
             record_size: 0,
             context: 0,
+            record_count: 0,
         }
     }
 
@@ -450,10 +465,22 @@ impl<'a, R: Read + Seek> RecordDecompressor<R> for LayeredPointRecordDecompresso
         }
     }
 
+    fn set_extra_bytes_selection(&mut self, selection: ExtraBytesSelection) {
+        if self.is_first_decompression {
+            for field_decompressor in &mut self.field_decompressors {
+                field_decompressor.set_extra_bytes_selection(selection)
+            }
+        }
+    }
+
     fn record_size(&self) -> usize {
         self.record_size
     }
 
+    fn record_count(&self) -> u64 {
+        self.record_count
+    }
+
     fn decompress_next(&mut self, out: &mut [u8]) -> std::io::Result<()> {
         let decompressors_and_data =
             self.field_decompressors
@@ -472,7 +499,7 @@ impl<'a, R: Read + Seek> RecordDecompressor<R> for LayeredPointRecordDecompresso
                 )?;
             }
 
-            let _count = self.input.read_u32::<LittleEndian>()?;
+            self.record_count = u64::from(self.input.read_u32::<LittleEndian>()?);
             for field in &mut self.field_decompressors {
                 field.read_layers_sizes(&mut self.input)?;
             }
@@ -491,6 +518,7 @@ impl<'a, R: Read + Seek> RecordDecompressor<R> for LayeredPointRecordDecompresso
 
     fn reset(&mut self) {
         self.is_first_decompression = true;
+        self.record_count = 0;
         self.field_decompressors.clear();
         self.record_size = 0;
         self.fields_sizes.clear();
@@ -589,6 +617,23 @@ pub trait RecordCompressor<W> {
     /// Resets the compressor to its initial state
     fn reset(&mut self);
 
+    /// Enables the counting of the bytes used by each field compressor,
+    /// see [`Self::field_byte_counts`].
+    ///
+    /// It stays enabled after a [`Self::reset`].
+    fn enable_field_byte_counts(&mut self) {
+        // Nothing to do for compressors that do not support it
+    }
+
+    /// Returns the number of bytes used by each field compressor
+    /// since the last reset, or `None` if not supported or enabled.
+    ///
+    /// As the fields share the same encoder, the counts are estimations
+    /// and do not include the uncompressed first point.
+    fn field_byte_counts(&self) -> Option<Vec<u64>> {
+        None
+    }
+
     /// Returns a mutable reference to the owned stream
     fn get_mut(&mut self) -> &mut W;
 
@@ -612,6 +657,8 @@ pub struct SequentialPointRecordCompressor<'a, W: Write> {
     encoder: encoders::ArithmeticEncoder<W>,
     record_size: usize,
     fields_sizes: Vec<usize>,
+    /// Number of bits used by each field compressor, when enabled
+    fields_bits: Option<Vec<f64>>,
 }
 
 impl<'a, W: Write> SequentialPointRecordCompressor<'a, W> {
@@ -622,6 +669,7 @@ impl<'a, W: Write> SequentialPointRecordCompressor<'a, W> {
             encoder: encoders::ArithmeticEncoder::new(output),
             record_size: 0,
             fields_sizes: vec![],
+            fields_bits: None,
         }
     }
 
@@ -630,6 +678,9 @@ impl<'a, W: Write> SequentialPointRecordCompressor<'a, W> {
         self.record_size += size;
         self.fields_sizes.push(size);
         self.field_compressors.push(Box::new(field));
+        if let Some(fields_bits) = &mut self.fields_bits {
+            fields_bits.push(0.0);
+        }
     }
 
     // This is used in our tests, but not in other code
@@ -639,6 +690,9 @@ impl<'a, W: Write> SequentialPointRecordCompressor<'a, W> {
         self.record_size += size;
         self.fields_sizes.push(size);
         self.field_compressors.push(c);
+        if let Some(fields_bits) = &mut self.fields_bits {
+            fields_bits.push(0.0);
+        }
     }
 }
 
@@ -717,6 +771,14 @@ impl<'a, W: Write> RecordCompressor<W> for SequentialPointRecordCompressor<'a, W
                 field_compressor.compress_first(self.encoder.get_mut(), field_data)?;
             }
             self.is_first_compression = false;
+        } else if let Some(fields_bits) = &mut self.fields_bits {
+            for ((field_compressor, field_data), field_bits) in
+                field_compressors_and_data.zip(fields_bits.iter_mut())
+            {
+                let num_bits_before = self.encoder.num_encoded_bits();
+                field_compressor.compress_with(&mut self.encoder, field_data)?;
+                *field_bits += self.encoder.num_encoded_bits() - num_bits_before;
+            }
         } else {
             for (field_compressor, field_data) in field_compressors_and_data {
                 field_compressor.compress_with(&mut self.encoder, field_data)?;
@@ -735,6 +797,24 @@ impl<'a, W: Write> RecordCompressor<W> for SequentialPointRecordCompressor<'a, W
         self.field_compressors.clear();
         self.fields_sizes.clear();
         self.record_size = 0;
+        if let Some(fields_bits) = &mut self.fields_bits {
+            fields_bits.clear();
+        }
+    }
+
+    fn enable_field_byte_counts(&mut self) {
+        if self.fields_bits.is_none() {
+            self.fields_bits = Some(vec![0.0; self.field_compressors.len()]);
+        }
+    }
+
+    fn field_byte_counts(&self) -> Option<Vec<u64>> {
+        self.fields_bits.as_ref().map(|fields_bits| {
+            fields_bits
+                .iter()
+                .map(|bits| (bits / 8.0).round() as u64)
+                .collect()
+        })
     }
 
     fn get_mut(&mut self) -> &mut W {
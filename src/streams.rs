@@ -0,0 +1,546 @@
+//! General purpose integer and symbol streams
+//!
+//! These types expose the adaptive arithmetic coder used by LASzip
+//! for data that is not LAS points (trajectories, IMU records, waveform samples, ...).
+//!
+//! - [`IntegerStreamEncoder`] / [`IntegerStreamDecoder`] code integers as the difference
+//!   to the previous integer of the same context (delta prediction), which works well for
+//!   slowly varying signals.
+//! - [`SymbolStreamEncoder`] / [`SymbolStreamDecoder`] code symbols from a small alphabet
+//!   (flags, classes, states) with one adaptive probability model per context.
+//!
+//! Contexts let interleaved signals keep separate predictions and statistics:
+//! for example, the x, y and z of an IMU can each use their own context.
+//!
+//! # Round trip guarantee
+//!
+//! Decoding gives back exactly the values that were encoded, as long as:
+//!
+//! - the decoder is created with the same parameters as the encoder
+//!   (value type, number of contexts, number of symbols),
+//! - values are decoded in the same order and with the same contexts as they were encoded,
+//! - the encoder was finished with `done`.
+//!
+//! The streams do not store the number of values, it is up to the caller to know
+//! how many values to decode.
+//!
+//! The encoded bytes only depend on the values, contexts and parameters,
+//! they are stable across versions of this crate (the tests check them against
+//! reference streams).
+//!
+//! # Example
+//!
+//! ```
+//! use laz::streams::{IntegerStreamDecoder, IntegerStreamEncoder};
+//!
+//! # fn main() -> std::io::Result<()> {
+//! let samples: Vec<i16> = vec![-12, -10, -7, -3, 2, 8, 15];
+//!
+//! let mut encoder = IntegerStreamEncoder::<i16, _>::new(Vec::new(), 1)?;
+//! for &sample in &samples {
+//!     encoder.encode(sample)?;
+//! }
+//! let bytes = encoder.done()?;
+//!
+//! let mut decoder = IntegerStreamDecoder::<i16, _>::new(bytes.as_slice(), 1)?;
+//! for &sample in &samples {
+//!     assert_eq!(decoder.decode()?, sample);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+
+use crate::compressors::{IntegerCompressor, DEFAULT_BITS_HIGH};
+use crate::decoders::ArithmeticDecoder;
+use crate::decompressors::IntegerDecompressor;
+use crate::encoders::ArithmeticEncoder;
+use crate::models::ArithmeticModel;
+
+/// Integer types that can be coded in an integer stream
+///
+/// This trait is sealed, it is implemented for
+/// `i8`, `u8`, `i16`, `u16`, `i32` and `u32`.
+pub trait StreamInteger: Copy + private::Sealed {
+    /// Number of bits of the type
+    const BITS: u32;
+
+    #[doc(hidden)]
+    fn to_code(self) -> i32;
+
+    #[doc(hidden)]
+    fn from_code(code: i32) -> Self;
+}
+
+mod private {
+    pub trait Sealed {}
+}
+
+macro_rules! impl_stream_integer {
+    ($($int:ty => $unsigned:ty),*) => {
+        $(
+            impl private::Sealed for $int {}
+
+            impl StreamInteger for $int {
+                const BITS: u32 = <$int>::BITS;
+
+                // The integer compressor works on the unsigned representation
+                // of values that have less than 32 bits.
+                fn to_code(self) -> i32 {
+                    self as $unsigned as i32
+                }
+
+                fn from_code(code: i32) -> Self {
+                    code as $unsigned as $int
+                }
+            }
+        )*
+    };
+}
+
+impl_stream_integer!(i8 => u8, u8 => u8, i16 => u16, u16 => u16, i32 => u32, u32 => u32);
+
+/// Largest number of symbols a symbol stream supports
+pub const MAX_SYMBOLS: u32 = 1 << 11;
+
+fn check_contexts(contexts: u32) -> std::io::Result<()> {
+    if contexts == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "a stream needs at least one context",
+        ));
+    }
+    Ok(())
+}
+
+fn check_context(context: u32, contexts: u32) -> std::io::Result<()> {
+    if context >= contexts {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "context {} is out of range, the stream has {} contexts",
+                context, contexts
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn check_num_symbols(num_symbols: u32) -> std::io::Result<()> {
+    if !(2..=MAX_SYMBOLS).contains(&num_symbols) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "the number of symbols must be in 2..={}, got {}",
+                MAX_SYMBOLS, num_symbols
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Encodes integers of type `T`, each predicted from the previous one of its context
+///
+/// The first value of each context is predicted as 0.
+/// Differences wrap around, so any sequence of values is supported.
+pub struct IntegerStreamEncoder<T: StreamInteger, W: Write> {
+    encoder: ArithmeticEncoder<W>,
+    compressor: IntegerCompressor,
+    last_values: Vec<i32>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: StreamInteger, W: Write> IntegerStreamEncoder<T, W> {
+    /// Creates an encoder writing to `dst` with the given number of contexts
+    ///
+    /// Returns an error if `contexts` is 0.
+    pub fn new(dst: W, contexts: u32) -> std::io::Result<Self> {
+        check_contexts(contexts)?;
+        let mut compressor = IntegerCompressor::new(T::BITS, contexts, DEFAULT_BITS_HIGH, 0);
+        compressor.init();
+        Ok(Self {
+            encoder: ArithmeticEncoder::new(dst),
+            compressor,
+            last_values: vec![0; contexts as usize],
+            _marker: PhantomData,
+        })
+    }
+
+    /// Number of contexts of the stream
+    pub fn contexts(&self) -> u32 {
+        self.last_values.len() as u32
+    }
+
+    /// Encodes a value in the context 0
+    pub fn encode(&mut self, value: T) -> std::io::Result<()> {
+        self.encode_in_context(value, 0)
+    }
+
+    /// Encodes a value in the given context
+    ///
+    /// Returns an error if `context` is not less than the number of contexts.
+    pub fn encode_in_context(&mut self, value: T, context: u32) -> std::io::Result<()> {
+        check_context(context, self.contexts())?;
+        let last = &mut self.last_values[context as usize];
+        let real = value.to_code();
+        self.compressor
+            .compress(&mut self.encoder, *last, real, context)?;
+        *last = real;
+        Ok(())
+    }
+
+    /// Encodes all the values in the context 0
+    pub fn encode_many(&mut self, values: &[T]) -> std::io::Result<()> {
+        values.iter().try_for_each(|&value| self.encode(value))
+    }
+
+    /// Writes the final bytes of the stream and returns the destination
+    ///
+    /// The stream cannot be decoded if this is not called.
+    pub fn done(mut self) -> std::io::Result<W> {
+        self.encoder.done()?;
+        Ok(self.encoder.into_inner())
+    }
+}
+
+/// Decodes integers written by an [`IntegerStreamEncoder`]
+pub struct IntegerStreamDecoder<T: StreamInteger, R: Read> {
+    decoder: ArithmeticDecoder<R>,
+    decompressor: IntegerDecompressor,
+    last_values: Vec<i32>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: StreamInteger, R: Read> IntegerStreamDecoder<T, R> {
+    /// Creates a decoder reading from `src`
+    ///
+    /// `contexts` must be the number of contexts the stream was encoded with.
+    /// Returns an error if `contexts` is 0 or if the start of the stream cannot be read.
+    pub fn new(src: R, contexts: u32) -> std::io::Result<Self> {
+        check_contexts(contexts)?;
+        let mut decompressor = IntegerDecompressor::new(T::BITS, contexts, DEFAULT_BITS_HIGH, 0);
+        decompressor.init();
+        let mut decoder = ArithmeticDecoder::new(src);
+        decoder.read_init_bytes()?;
+        Ok(Self {
+            decoder,
+            decompressor,
+            last_values: vec![0; contexts as usize],
+            _marker: PhantomData,
+        })
+    }
+
+    /// Number of contexts of the stream
+    pub fn contexts(&self) -> u32 {
+        self.last_values.len() as u32
+    }
+
+    /// Decodes a value from the context 0
+    pub fn decode(&mut self) -> std::io::Result<T> {
+        self.decode_in_context(0)
+    }
+
+    /// Decodes a value from the given context
+    ///
+    /// Returns an error if `context` is not less than the number of contexts.
+    pub fn decode_in_context(&mut self, context: u32) -> std::io::Result<T> {
+        check_context(context, self.contexts())?;
+        let last = &mut self.last_values[context as usize];
+        *last = self
+            .decompressor
+            .decompress(&mut self.decoder, *last, context)?;
+        Ok(T::from_code(*last))
+    }
+
+    /// Decodes values from the context 0 until `values` is filled
+    pub fn decode_many(&mut self, values: &mut [T]) -> std::io::Result<()> {
+        for value in values.iter_mut() {
+            *value = self.decode()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the source
+    pub fn into_inner(self) -> R {
+        self.decoder.into_inner()
+    }
+}
+
+/// Encodes symbols in `0..num_symbols` with one adaptive model per context
+pub struct SymbolStreamEncoder<W: Write> {
+    encoder: ArithmeticEncoder<W>,
+    models: Vec<ArithmeticModel>,
+    num_symbols: u32,
+}
+
+impl<W: Write> SymbolStreamEncoder<W> {
+    /// Creates an encoder writing to `dst`
+    ///
+    /// Returns an error if `num_symbols` is not in `2..=`[`MAX_SYMBOLS`]
+    /// or if `contexts` is 0.
+    pub fn new(dst: W, num_symbols: u32, contexts: u32) -> std::io::Result<Self> {
+        check_num_symbols(num_symbols)?;
+        check_contexts(contexts)?;
+        Ok(Self {
+            encoder: ArithmeticEncoder::new(dst),
+            models: (0..contexts)
+                .map(|_| ArithmeticModel::new(num_symbols, false, &[]))
+                .collect(),
+            num_symbols,
+        })
+    }
+
+    /// Number of symbols of the alphabet
+    pub fn num_symbols(&self) -> u32 {
+        self.num_symbols
+    }
+
+    /// Number of contexts of the stream
+    pub fn contexts(&self) -> u32 {
+        self.models.len() as u32
+    }
+
+    /// Encodes a symbol in the context 0
+    pub fn encode(&mut self, symbol: u32) -> std::io::Result<()> {
+        self.encode_in_context(symbol, 0)
+    }
+
+    /// Encodes a symbol in the given context
+    ///
+    /// Returns an error if `symbol` is not less than the number of symbols,
+    /// or if `context` is not less than the number of contexts.
+    pub fn encode_in_context(&mut self, symbol: u32, context: u32) -> std::io::Result<()> {
+        check_context(context, self.contexts())?;
+        if symbol >= self.num_symbols {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "symbol {} is out of range, the stream has {} symbols",
+                    symbol, self.num_symbols
+                ),
+            ));
+        }
+        self.encoder
+            .encode_symbol(&mut self.models[context as usize], symbol)
+    }
+
+    /// Writes the final bytes of the stream and returns the destination
+    ///
+    /// The stream cannot be decoded if this is not called.
+    pub fn done(mut self) -> std::io::Result<W> {
+        self.encoder.done()?;
+        Ok(self.encoder.into_inner())
+    }
+}
+
+/// Decodes symbols written by a [`SymbolStreamEncoder`]
+pub struct SymbolStreamDecoder<R: Read> {
+    decoder: ArithmeticDecoder<R>,
+    models: Vec<ArithmeticModel>,
+    num_symbols: u32,
+}
+
+impl<R: Read> SymbolStreamDecoder<R> {
+    /// Creates a decoder reading from `src`
+    ///
+    /// `num_symbols` and `contexts` must be the ones the stream was encoded with.
+    /// Returns an error if they are invalid or if the start of the stream cannot be read.
+    pub fn new(src: R, num_symbols: u32, contexts: u32) -> std::io::Result<Self> {
+        check_num_symbols(num_symbols)?;
+        check_contexts(contexts)?;
+        let mut decoder = ArithmeticDecoder::new(src);
+        decoder.read_init_bytes()?;
+        Ok(Self {
+            decoder,
+            models: (0..contexts)
+                .map(|_| ArithmeticModel::new(num_symbols, false, &[]))
+                .collect(),
+            num_symbols,
+        })
+    }
+
+    /// Number of symbols of the alphabet
+    pub fn num_symbols(&self) -> u32 {
+        self.num_symbols
+    }
+
+    /// Number of contexts of the stream
+    pub fn contexts(&self) -> u32 {
+        self.models.len() as u32
+    }
+
+    /// Decodes a symbol from the context 0
+    pub fn decode(&mut self) -> std::io::Result<u32> {
+        self.decode_in_context(0)
+    }
+
+    /// Decodes a symbol from the given context
+    ///
+    /// Returns an error if `context` is not less than the number of contexts.
+    pub fn decode_in_context(&mut self, context: u32) -> std::io::Result<u32> {
+        check_context(context, self.contexts())?;
+        self.decoder
+            .decode_symbol(&mut self.models[context as usize])
+    }
+
+    /// Returns the source
+    pub fn into_inner(self) -> R {
+        self.decoder.into_inner()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn round_trip<T: StreamInteger + PartialEq + std::fmt::Debug>(values: &[T]) {
+        let mut encoder = IntegerStreamEncoder::<T, _>::new(Vec::new(), 1).unwrap();
+        encoder.encode_many(values).unwrap();
+        let bytes = encoder.done().unwrap();
+
+        let mut decoder = IntegerStreamDecoder::<T, _>::new(bytes.as_slice(), 1).unwrap();
+        let mut decoded = vec![values[0]; values.len()];
+        decoder.decode_many(&mut decoded).unwrap();
+        assert_eq!(decoded, values);
+    }
+
+    #[test]
+    fn test_integer_round_trip_extremes() {
+        round_trip(&[0i8, i8::MIN, i8::MAX, -1, 1, i8::MIN, 0]);
+        round_trip(&[0u8, u8::MAX, 0, 1, u8::MAX - 1]);
+        round_trip(&[0i16, i16::MIN, i16::MAX, -1, i16::MIN, 12]);
+        round_trip(&[0u16, u16::MAX, 0, 1, 32768]);
+        round_trip(&[0i32, i32::MIN, i32::MAX, -1, i32::MIN, 0, i32::MAX]);
+        round_trip(&[0u32, u32::MAX, 0, 1, 1 << 31, u32::MAX]);
+    }
+
+    #[test]
+    fn test_integer_contexts() {
+        // Three interleaved signals, each in its own context
+        let signals: Vec<[i32; 3]> = (0..2000)
+            .map(|i| [i * 3, 100_000 - i, (i % 17) * 1000 - 8000])
+            .collect();
+
+        let mut encoder = IntegerStreamEncoder::<i32, _>::new(Vec::new(), 3).unwrap();
+        for values in &signals {
+            for (context, &value) in values.iter().enumerate() {
+                encoder.encode_in_context(value, context as u32).unwrap();
+            }
+        }
+        assert!(encoder.encode_in_context(0, 3).is_err());
+        let bytes = encoder.done().unwrap();
+        // The deltas are predictable, so the stream is much smaller than the raw data
+        assert!(bytes.len() < signals.len() * 12 / 10);
+
+        let mut decoder = IntegerStreamDecoder::<i32, _>::new(bytes.as_slice(), 3).unwrap();
+        for values in &signals {
+            for (context, &value) in values.iter().enumerate() {
+                assert_eq!(decoder.decode_in_context(context as u32).unwrap(), value);
+            }
+        }
+        assert!(decoder.decode_in_context(3).is_err());
+    }
+
+    #[test]
+    fn test_empty_stream() {
+        let bytes = IntegerStreamEncoder::<u16, _>::new(Vec::new(), 1)
+            .unwrap()
+            .done()
+            .unwrap();
+        assert!(IntegerStreamDecoder::<u16, _>::new(bytes.as_slice(), 1).is_ok());
+
+        let bytes = SymbolStreamEncoder::new(Vec::new(), 4, 1)
+            .unwrap()
+            .done()
+            .unwrap();
+        assert!(SymbolStreamDecoder::new(bytes.as_slice(), 4, 1).is_ok());
+    }
+
+    #[test]
+    fn test_symbol_round_trip() {
+        let symbols: Vec<(u32, u32)> = (0..5000u32)
+            .map(|i| (i % 7 % 3, (i * 31 + i / 5) % 300))
+            .collect();
+
+        let mut encoder = SymbolStreamEncoder::new(Vec::new(), 300, 3).unwrap();
+        for &(context, symbol) in &symbols {
+            encoder.encode_in_context(symbol, context).unwrap();
+        }
+        assert!(encoder.encode(300).is_err());
+        assert!(encoder.encode_in_context(0, 3).is_err());
+        let bytes = encoder.done().unwrap();
+
+        let mut decoder = SymbolStreamDecoder::new(bytes.as_slice(), 300, 3).unwrap();
+        for &(context, symbol) in &symbols {
+            assert_eq!(decoder.decode_in_context(context).unwrap(), symbol);
+        }
+    }
+
+    /// The encoded bytes must not change between versions,
+    /// streams written by a previous version have to be decoded the same way
+    #[test]
+    fn test_golden_bytes() {
+        let integers: [(i16, u32); 7] = [
+            (-12, 0),
+            (-10, 0),
+            (500, 1),
+            (-7, 0),
+            (480, 1),
+            (-3, 0),
+            (470, 1),
+        ];
+        let expected = [63, 39, 209, 135, 153, 166, 187, 72, 0, 0];
+        let mut encoder = IntegerStreamEncoder::<i16, _>::new(Vec::new(), 2).unwrap();
+        for (value, context) in integers {
+            encoder.encode_in_context(value, context).unwrap();
+        }
+        assert_eq!(encoder.done().unwrap(), expected);
+        let mut decoder = IntegerStreamDecoder::<i16, _>::new(&expected[..], 2).unwrap();
+        for (value, context) in integers {
+            assert_eq!(decoder.decode_in_context(context).unwrap(), value);
+        }
+
+        let integers = [0u32, u32::MAX, 1, 1 << 31, 42];
+        let expected = [
+            0, 30, 235, 78, 219, 238, 56, 200, 216, 204, 106, 140, 0, 0, 0,
+        ];
+        let mut encoder = IntegerStreamEncoder::<u32, _>::new(Vec::new(), 1).unwrap();
+        encoder.encode_many(&integers).unwrap();
+        assert_eq!(encoder.done().unwrap(), expected);
+        let mut decoder = IntegerStreamDecoder::<u32, _>::new(&expected[..], 1).unwrap();
+        let mut decoded = [0u32; 5];
+        decoder.decode_many(&mut decoded).unwrap();
+        assert_eq!(decoded, integers);
+
+        let symbols = [
+            (0, 0),
+            (4, 0),
+            (1, 1),
+            (1, 1),
+            (3, 0),
+            (2, 1),
+            (0, 0),
+            (4, 1),
+        ];
+        let expected = [43, 177, 56, 0, 0, 0];
+        let mut encoder = SymbolStreamEncoder::new(Vec::new(), 5, 2).unwrap();
+        for (symbol, context) in symbols {
+            encoder.encode_in_context(symbol, context).unwrap();
+        }
+        assert_eq!(encoder.done().unwrap(), expected);
+        let mut decoder = SymbolStreamDecoder::new(&expected[..], 5, 2).unwrap();
+        for (symbol, context) in symbols {
+            assert_eq!(decoder.decode_in_context(context).unwrap(), symbol);
+        }
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        assert!(IntegerStreamEncoder::<i32, _>::new(Vec::new(), 0).is_err());
+        assert!(SymbolStreamEncoder::new(Vec::new(), 1, 1).is_err());
+        assert!(SymbolStreamEncoder::new(Vec::new(), MAX_SYMBOLS + 1, 1).is_err());
+        assert!(SymbolStreamEncoder::new(Vec::new(), MAX_SYMBOLS, 0).is_err());
+        assert!(SymbolStreamDecoder::new(&[0u8; 4][..], 2, 0).is_err());
+    }
+}
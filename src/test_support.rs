@@ -0,0 +1,109 @@
+//! Helpers shared by the unit tests and the integration tests (in `tests/`).
+//!
+//! This is not part of the public API.
+
+use std::io::{Cursor, Read, Write};
+
+use crate::las::file::SimpleReader;
+use crate::{LasZipCompressor, LazItem, LazItemRecordBuilder, LazVlr, LazVlrBuilder};
+
+/// The LAS file used by the tests, 1065 points of format 3
+pub const TEST_LAS_PATH: &str = "tests/data/point-time-color.las";
+
+/// Returns the points of the test LAS file and the default items of its point format
+pub fn read_test_points() -> (Vec<u8>, Vec<LazItem>) {
+    let mut reader = SimpleReader::new(std::fs::File::open(TEST_LAS_PATH).unwrap()).unwrap();
+    let mut points = vec![];
+    reader.read_to_end(&mut points).unwrap();
+    let items = LazItemRecordBuilder::default_for_point_format_id(reader.header.point_format_id, 0)
+        .unwrap();
+    (points, items)
+}
+
+/// Returns the points of the test LAS file and a vlr using fixed-size chunks of `chunk_size`
+pub fn test_points_and_vlr(chunk_size: u32) -> (Vec<u8>, LazVlr) {
+    let (points, items) = read_test_points();
+    let vlr = LazVlrBuilder::new(items)
+        .with_fixed_chunk_size(chunk_size)
+        .build();
+    (points, vlr)
+}
+
+/// Compresses the points with one call to `compress_many`
+pub fn compress(points: &[u8], vlr: &LazVlr) -> Vec<u8> {
+    let mut compressor = LasZipCompressor::new(Cursor::new(vec![]), vlr.clone()).unwrap();
+    compressor.compress_many(points).unwrap();
+    compressor.done().unwrap();
+    compressor.into_inner().into_inner()
+}
+
+/// Compresses the points, closing a chunk after each of the `chunk_sizes` (in points)
+/// when the vlr uses variable-size chunks, the remaining points make the last chunk.
+///
+/// With fixed-size chunks, this is the same as [`compress`].
+pub fn compress_in_chunks(points: &[u8], vlr: &LazVlr, chunk_sizes: &[usize]) -> Vec<u8> {
+    if !vlr.uses_variable_size_chunks() {
+        return compress(points, vlr);
+    }
+    let point_size = vlr.items_size() as usize;
+    let mut compressor = LasZipCompressor::new(Cursor::new(vec![]), vlr.clone()).unwrap();
+    let mut start = 0;
+    for size in chunk_sizes {
+        let end = start + size * point_size;
+        compressor.compress_many(&points[start..end]).unwrap();
+        compressor.finish_current_chunk().unwrap();
+        start = end;
+    }
+    if start < points.len() {
+        compressor.compress_many(&points[start..]).unwrap();
+    }
+    compressor.done().unwrap();
+    compressor.into_inner().into_inner()
+}
+
+/// Returns `count` points of format 6, which uses the layered compression
+pub fn layered_points(count: u32) -> Vec<u8> {
+    (0..count)
+        .flat_map(|i| {
+            let mut point = vec![0u8; 30];
+            point[0..4].copy_from_slice(&(i * 3).to_le_bytes());
+            point[8..12].copy_from_slice(&(i % 17).to_le_bytes());
+            point[14] = 0x11;
+            point[16] = (i % 3) as u8;
+            point[22..30].copy_from_slice(&(i as f64).to_le_bytes());
+            point
+        })
+        .collect()
+}
+
+/// Returns the vlr with a LASzip version & options that differ from the default ones
+pub fn with_custom_header(vlr: &LazVlr) -> LazVlr {
+    let mut data = vec![];
+    vlr.write_to(&mut data).unwrap();
+    // Version (u8, u8, u16) then options (u32), after the compressor & coder (u16, u16)
+    data[4..8].copy_from_slice(&[3, 4, 12, 0]);
+    data[8..12].copy_from_slice(&1u32.to_le_bytes());
+    LazVlr::from_buffer(data).unwrap()
+}
+
+/// A source that cannot seek
+pub struct Pipe(pub Cursor<Vec<u8>>);
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+/// A destination that cannot seek
+pub struct Sink(pub Vec<u8>);
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
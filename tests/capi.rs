@@ -0,0 +1,59 @@
+//! Compiles and runs a C program against the C API
+#![cfg(feature = "capi")]
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Builds the crate as a static library and returns its path
+fn build_static_lib(target_dir: &Path) -> PathBuf {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let status = Command::new(cargo)
+        .args([
+            "rustc",
+            "--lib",
+            "--features",
+            "capi",
+            "--crate-type",
+            "staticlib",
+        ])
+        .arg("--target-dir")
+        .arg(target_dir)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .status()
+        .expect("failed to run cargo");
+    assert!(status.success(), "failed to build the static library");
+    target_dir.join("debug").join("liblaz.a")
+}
+
+#[test]
+fn test_c_program() {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let tmp_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("capi");
+    std::fs::create_dir_all(&tmp_dir).unwrap();
+
+    let lib = build_static_lib(&tmp_dir.join("target"));
+    let program = tmp_dir.join("test_capi");
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(cc)
+        .arg(manifest_dir.join("tests/capi/test_capi.c"))
+        .arg("-I")
+        .arg(manifest_dir.join("include"))
+        .arg(&lib)
+        .args(["-lpthread", "-ldl", "-lm", "-o"])
+        .arg(&program)
+        .status()
+        .expect("failed to run the C compiler");
+    assert!(status.success(), "failed to compile the C program");
+
+    let output = Command::new(&program)
+        .arg(manifest_dir.join("tests/data"))
+        .arg(&tmp_dir)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "C program failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "OK");
+}
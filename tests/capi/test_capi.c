@@ -0,0 +1,309 @@
+/*
+ * Exercises the C API the way an application using the LASzip DLL would.
+ *
+ * usage: test_capi <data dir> <output dir>
+ */
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "laszip_api.h"
+
+#define CHECK(pointer, call)                                                   \
+  do {                                                                         \
+    if (call) {                                                                \
+      laszip_CHAR* error = NULL;                                               \
+      laszip_get_error(pointer, &error);                                       \
+      fprintf(stderr, "%s:%d: %s failed: %s\n", __FILE__, __LINE__, #call,     \
+              error ? error : "(no message)");                                 \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define ASSERT(condition)                                                      \
+  do {                                                                         \
+    if (!(condition)) {                                                        \
+      fprintf(stderr, "%s:%d: assertion failed: %s\n", __FILE__, __LINE__,     \
+              #condition);                                                     \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+static int same_point(const laszip_point_struct* a, const laszip_point_struct* b) {
+  return a->X == b->X && a->Y == b->Y && a->Z == b->Z && a->intensity == b->intensity &&
+         a->return_number == b->return_number && a->number_of_returns == b->number_of_returns &&
+         a->scan_direction_flag == b->scan_direction_flag &&
+         a->edge_of_flight_line == b->edge_of_flight_line &&
+         a->classification == b->classification && a->synthetic_flag == b->synthetic_flag &&
+         a->keypoint_flag == b->keypoint_flag && a->withheld_flag == b->withheld_flag &&
+         a->scan_angle_rank == b->scan_angle_rank && a->user_data == b->user_data &&
+         a->point_source_ID == b->point_source_ID && a->gps_time == b->gps_time &&
+         memcmp(a->rgb, b->rgb, sizeof(a->rgb)) == 0 &&
+         a->extended_classification == b->extended_classification &&
+         a->extended_scan_angle == b->extended_scan_angle &&
+         a->extended_return_number == b->extended_return_number &&
+         a->extended_number_of_returns == b->extended_number_of_returns &&
+         a->extended_scanner_channel == b->extended_scanner_channel &&
+         a->extended_classification_flags == b->extended_classification_flags;
+}
+
+/* Reads the LAZ file and checks it has the same points as the LAS file */
+static void test_read(const char* las_path, const char* laz_path) {
+  laszip_POINTER las_reader, laz_reader;
+  laszip_header_struct *las_header, *laz_header;
+  laszip_point_struct *las_point, *laz_point;
+  laszip_BOOL is_compressed;
+  laszip_I64 i, count;
+
+  CHECK(NULL, laszip_create(&las_reader));
+  CHECK(NULL, laszip_create(&laz_reader));
+
+  CHECK(las_reader, laszip_open_reader(las_reader, las_path, &is_compressed));
+  ASSERT(!is_compressed);
+  CHECK(laz_reader, laszip_open_reader(laz_reader, laz_path, &is_compressed));
+  ASSERT(is_compressed);
+
+  CHECK(las_reader, laszip_get_header_pointer(las_reader, &las_header));
+  CHECK(laz_reader, laszip_get_header_pointer(laz_reader, &laz_header));
+  ASSERT(las_header->number_of_point_records == laz_header->number_of_point_records);
+  ASSERT(las_header->point_data_format == laz_header->point_data_format);
+  ASSERT(las_header->number_of_variable_length_records ==
+         laz_header->number_of_variable_length_records);
+  ASSERT(las_header->x_scale_factor == laz_header->x_scale_factor);
+
+  CHECK(las_reader, laszip_get_point_pointer(las_reader, &las_point));
+  CHECK(laz_reader, laszip_get_point_pointer(laz_reader, &laz_point));
+  for (i = 0; i < (laszip_I64)las_header->number_of_point_records; i++) {
+    CHECK(las_reader, laszip_read_point(las_reader));
+    CHECK(laz_reader, laszip_read_point(laz_reader));
+    ASSERT(same_point(las_point, laz_point));
+  }
+  CHECK(laz_reader, laszip_get_point_count(laz_reader, &count));
+  ASSERT(count == (laszip_I64)las_header->number_of_point_records);
+
+  /* Seek back into the file */
+  CHECK(las_reader, laszip_seek_point(las_reader, count / 2 + 3));
+  CHECK(laz_reader, laszip_seek_point(laz_reader, count / 2 + 3));
+  CHECK(las_reader, laszip_read_point(las_reader));
+  CHECK(laz_reader, laszip_read_point(laz_reader));
+  ASSERT(same_point(las_point, laz_point));
+
+  CHECK(las_reader, laszip_close_reader(las_reader));
+  CHECK(laz_reader, laszip_close_reader(laz_reader));
+  CHECK(las_reader, laszip_destroy(las_reader));
+  CHECK(laz_reader, laszip_destroy(laz_reader));
+}
+
+/* Re-writes the LAS file as LAZ (using the inventory) and reads it back */
+static void test_rewrite(const char* las_path, const char* laz_path) {
+  laszip_POINTER reader, writer;
+  laszip_header_struct *header, *written_header;
+  laszip_point_struct *point, *written_point;
+  laszip_BOOL is_compressed;
+  laszip_I64 i, num_points;
+  laszip_F64 min_x, max_z;
+  laszip_CHAR* error;
+  laszip_U8 vlr_data[4] = {1, 2, 3, 4};
+
+  CHECK(NULL, laszip_create(&reader));
+  CHECK(reader, laszip_open_reader(reader, las_path, &is_compressed));
+  CHECK(reader, laszip_get_header_pointer(reader, &header));
+  CHECK(reader, laszip_get_point_pointer(reader, &point));
+  num_points = header->number_of_point_records;
+  min_x = header->min_x;
+  max_z = header->max_z;
+
+  CHECK(NULL, laszip_create(&writer));
+  CHECK(writer, laszip_set_header(writer, header));
+  CHECK(writer, laszip_add_vlr(writer, "laz-rs test", 42, 4, "test vlr", vlr_data));
+  CHECK(writer, laszip_get_header_pointer(writer, &written_header));
+  /* the inventory will fill these */
+  written_header->number_of_point_records = 0;
+  written_header->min_x = 0.0;
+  written_header->max_z = 0.0;
+  CHECK(writer, laszip_set_chunk_size(writer, 100));
+  CHECK(writer, laszip_open_writer(writer, laz_path, 1));
+  for (i = 0; i < num_points; i++) {
+    CHECK(reader, laszip_read_point(reader));
+    CHECK(writer, laszip_set_point(writer, point));
+    CHECK(writer, laszip_write_point(writer));
+    CHECK(writer, laszip_update_inventory(writer));
+  }
+  CHECK(writer, laszip_close_writer(writer));
+  CHECK(writer, laszip_destroy(writer));
+
+  CHECK(reader, laszip_seek_point(reader, 0));
+  CHECK(NULL, laszip_create(&writer));
+  CHECK(writer, laszip_open_reader(writer, laz_path, &is_compressed));
+  ASSERT(is_compressed);
+  CHECK(writer, laszip_get_header_pointer(writer, &written_header));
+  CHECK(writer, laszip_get_point_pointer(writer, &written_point));
+  ASSERT(written_header->number_of_point_records == num_points);
+  ASSERT(written_header->min_x == min_x);
+  ASSERT(written_header->max_z == max_z);
+  ASSERT(written_header->number_of_variable_length_records ==
+         header->number_of_variable_length_records + 1);
+  ASSERT(written_header->vlrs[written_header->number_of_variable_length_records - 1]
+             .record_id == 42);
+  ASSERT(memcmp(written_header->vlrs[written_header->number_of_variable_length_records - 1].data,
+                vlr_data, 4) == 0);
+  for (i = 0; i < num_points; i++) {
+    CHECK(reader, laszip_read_point(reader));
+    CHECK(writer, laszip_read_point(writer));
+    ASSERT(same_point(point, written_point));
+  }
+
+  /* Errors are reported */
+  ASSERT(laszip_seek_point(writer, num_points + 10) == 1);
+  CHECK(writer, laszip_get_error(writer, &error));
+  ASSERT(error != NULL && strlen(error) > 0);
+  /* Unsupported functions are exported but fail */
+  ASSERT(laszip_open_reader_stream(writer, NULL, &is_compressed) == 1);
+  CHECK(writer, laszip_get_error(writer, &error));
+  ASSERT(error != NULL && strlen(error) > 0);
+
+  CHECK(writer, laszip_close_reader(writer));
+  CHECK(writer, laszip_destroy(writer));
+  CHECK(reader, laszip_close_reader(reader));
+  CHECK(reader, laszip_destroy(reader));
+}
+
+/* Writes LAS 1.4 points, with extra bytes, and reads them back */
+static void test_point_format_6(const char* laz_path) {
+  laszip_POINTER writer, reader;
+  laszip_header_struct* header;
+  laszip_point_struct *point, *read_point;
+  laszip_BOOL is_compressed;
+  laszip_F64 coordinates[3];
+  laszip_I64 i;
+  const laszip_I64 num_points = 2000;
+  laszip_geokey_struct geokeys[2] = {{1024, 0, 1, 1}, {3072, 0, 1, 32631}};
+  laszip_F64 geodouble_params[1] = {1.0};
+  laszip_CHAR geoascii_params[] = "WGS 84 / UTM zone 31N|";
+
+  CHECK(NULL, laszip_create(&writer));
+  CHECK(writer, laszip_get_header_pointer(writer, &header));
+  header->version_minor = 4;
+  header->x_scale_factor = 0.001;
+  header->y_scale_factor = 0.001;
+  header->z_scale_factor = 0.001;
+  CHECK(writer, laszip_set_point_type_and_size(writer, 6, 32));
+  CHECK(writer, laszip_set_geokeys(writer, 2, geokeys));
+  CHECK(writer, laszip_set_geodouble_params(writer, 1, geodouble_params));
+  CHECK(writer, laszip_set_geoascii_params(writer, sizeof(geoascii_params), geoascii_params));
+  CHECK(writer, laszip_get_point_pointer(writer, &point));
+  ASSERT(point->num_extra_bytes == 2);
+  CHECK(writer, laszip_open_writer(writer, laz_path, 1));
+  for (i = 0; i < num_points; i++) {
+    coordinates[0] = i * 0.5;
+    coordinates[1] = 1000.0 - i * 0.25;
+    coordinates[2] = (i % 50) * 0.125;
+    CHECK(writer, laszip_set_coordinates(writer, coordinates));
+    point->intensity = (laszip_U16)(i * 7);
+    point->extended_return_number = 1 + i % 10;
+    point->extended_number_of_returns = 10;
+    point->extended_classification = (laszip_U8)(i % 40);
+    point->classification = 0;
+    point->extended_classification_flags = i % 8;
+    point->synthetic_flag = (i % 8) & 1;
+    point->keypoint_flag = ((i % 8) >> 1) & 1;
+    point->withheld_flag = ((i % 8) >> 2) & 1;
+    point->extended_scanner_channel = i % 4;
+    point->extended_scan_angle = (laszip_I16)(i - 1000);
+    point->gps_time = i * 0.001;
+    point->extra_bytes[0] = (laszip_U8)i;
+    point->extra_bytes[1] = (laszip_U8)(i >> 8);
+    CHECK(writer, laszip_write_point(writer));
+    CHECK(writer, laszip_update_inventory(writer));
+  }
+  CHECK(writer, laszip_close_writer(writer));
+
+  CHECK(NULL, laszip_create(&reader));
+  CHECK(reader, laszip_decompress_selective(
+                    reader, laszip_DECOMPRESS_SELECTIVE_Z | laszip_DECOMPRESS_SELECTIVE_GPS_TIME |
+                                laszip_DECOMPRESS_SELECTIVE_BYTE0 |
+                                laszip_DECOMPRESS_SELECTIVE_BYTE1));
+  CHECK(reader, laszip_open_reader(reader, laz_path, &is_compressed));
+  ASSERT(is_compressed);
+  CHECK(reader, laszip_get_header_pointer(reader, &header));
+  ASSERT(header->point_data_format == 6);
+  ASSERT(header->point_data_record_length == 32);
+  ASSERT(header->number_of_point_records == 0);
+  ASSERT(header->extended_number_of_point_records == num_points);
+  ASSERT(header->extended_number_of_points_by_return[0] == num_points / 10);
+  ASSERT(header->number_of_variable_length_records == 3);
+  ASSERT(strcmp(header->vlrs[0].user_id, "LASF_Projection") == 0);
+  ASSERT(header->vlrs[0].record_id == 34735);
+  ASSERT(header->vlrs[0].record_length_after_header == 3 * 8);
+  /* the key directory header: version 1.1.0 with 2 keys */
+  ASSERT(header->vlrs[0].data[0] == 1 && header->vlrs[0].data[6] == 2);
+  ASSERT(memcmp(header->vlrs[0].data + 8, geokeys, sizeof(geokeys)) == 0);
+  ASSERT(header->vlrs[1].record_id == 34736);
+  ASSERT(memcmp(header->vlrs[1].data, geodouble_params, sizeof(geodouble_params)) == 0);
+  ASSERT(header->vlrs[2].record_id == 34737);
+  ASSERT(memcmp(header->vlrs[2].data, geoascii_params, sizeof(geoascii_params)) == 0);
+  CHECK(reader, laszip_get_point_pointer(reader, &read_point));
+  CHECK(reader, laszip_seek_point(reader, 1234));
+  for (i = 1234; i < num_points; i++) {
+    CHECK(reader, laszip_read_point(reader));
+    CHECK(reader, laszip_get_coordinates(reader, coordinates));
+    ASSERT(coordinates[0] == i * 0.5);
+    ASSERT(coordinates[2] == (i % 50) * 0.125);
+    ASSERT(read_point->extended_return_number == 1 + i % 10);
+    ASSERT(read_point->return_number == (1 + i % 10 > 6 ? (1 + i % 10 == 10 ? 7 : 6) : 1 + i % 10));
+    ASSERT(read_point->number_of_returns == 7);
+    ASSERT(read_point->gps_time == i * 0.001);
+    ASSERT(read_point->extra_bytes[0] == (laszip_U8)i);
+    ASSERT(read_point->extra_bytes[1] == (laszip_U8)(i >> 8));
+  }
+  CHECK(reader, laszip_close_reader(reader));
+
+  /* Now everything */
+  CHECK(reader, laszip_decompress_selective(reader, laszip_DECOMPRESS_SELECTIVE_ALL));
+  CHECK(reader, laszip_open_reader(reader, laz_path, &is_compressed));
+  CHECK(reader, laszip_get_point_pointer(reader, &read_point));
+  for (i = 0; i < num_points; i++) {
+    CHECK(reader, laszip_read_point(reader));
+    ASSERT(read_point->intensity == (laszip_U16)(i * 7));
+    ASSERT(read_point->extended_classification == i % 40);
+    ASSERT(read_point->classification == (i % 40 < 32 ? i % 40 : 0));
+    ASSERT(read_point->extended_classification_flags == i % 8);
+    ASSERT(read_point->synthetic_flag == ((i % 8) & 1));
+    ASSERT(read_point->extended_scanner_channel == i % 4);
+    ASSERT(read_point->extended_scan_angle == i - 1000);
+  }
+  CHECK(reader, laszip_close_reader(reader));
+
+  CHECK(reader, laszip_destroy(reader));
+  CHECK(writer, laszip_destroy(writer));
+}
+
+int main(int argc, char* argv[]) {
+  char las_path[4096], laz_path[4096], output_path[4096];
+  laszip_U8 major, minor;
+  laszip_U16 revision;
+  laszip_U32 build;
+
+  if (argc != 3) {
+    fprintf(stderr, "usage: %s <data dir> <output dir>\n", argv[0]);
+    return 2;
+  }
+
+  CHECK(NULL, laszip_load_dll());
+  CHECK(NULL, laszip_get_version(&major, &minor, &revision, &build));
+  ASSERT(major == LASZIP_API_VERSION_MAJOR && minor == LASZIP_API_VERSION_MINOR);
+
+  snprintf(las_path, sizeof(las_path), "%s/point-time-color.las", argv[1]);
+  snprintf(laz_path, sizeof(laz_path), "%s/point-time-color.laz", argv[1]);
+  test_read(las_path, laz_path);
+
+  snprintf(output_path, sizeof(output_path), "%s/rewritten.laz", argv[2]);
+  test_rewrite(las_path, output_path);
+
+  snprintf(output_path, sizeof(output_path), "%s/point-format-6.laz", argv[2]);
+  test_point_format_6(output_path);
+
+  CHECK(NULL, laszip_unload_dll());
+  printf("OK\n");
+  return 0;
+}
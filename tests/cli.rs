@@ -0,0 +1,104 @@
+#[cfg(feature = "cli")]
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{Read, Seek, SeekFrom};
+    use std::path::{Path, PathBuf};
+    use std::process::{Command, Output};
+
+    use laz::las::file::{read_vlrs_and_get_laszip_vlr, QuickHeader};
+    use laz::LasZipStreamCompressor;
+
+    const LAS_PATH: &str = "tests/data/point-time-color.las";
+
+    fn laz(args: &[&Path]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_laz"))
+            .args(args)
+            .output()
+            .unwrap()
+    }
+
+    fn tmp_path(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_TARGET_TMPDIR")).join(name)
+    }
+
+    /// Returns the point data (and what follows) of a LAS or LAZ file
+    fn read_points(path: &Path) -> Vec<u8> {
+        let mut file = File::open(path).unwrap();
+        let header = QuickHeader::read_from(&mut file).unwrap();
+        file.seek(SeekFrom::Start(u64::from(header.offset_to_points)))
+            .unwrap();
+        let mut points = Vec::new();
+        file.read_to_end(&mut points).unwrap();
+        points
+    }
+
+    #[test]
+    fn test_compress_verify_decompress() {
+        let laz_path = tmp_path("cli-point-time-color.laz");
+        let las_path = tmp_path("cli-point-time-color.las");
+
+        let output = laz(&[Path::new("compress"), Path::new(LAS_PATH), &laz_path]);
+        assert!(output.status.success(), "{:?}", output);
+
+        for parallel in [None, Some(Path::new("--parallel"))] {
+            let mut args = vec![Path::new("verify"), &laz_path];
+            args.extend(parallel);
+            let output = laz(&args);
+            assert!(output.status.success(), "{:?}", output);
+        }
+
+        let output = laz(&[Path::new("decompress"), &laz_path, &las_path]);
+        assert!(output.status.success(), "{:?}", output);
+        assert_eq!(read_points(&las_path), read_points(Path::new(LAS_PATH)));
+    }
+
+    #[test]
+    fn test_verify_chunk_table_offset_at_the_end() {
+        let laz_path = tmp_path("cli-compressed.laz");
+        let stream_laz_path = tmp_path("cli-stream-compressed.laz");
+
+        let output = laz(&[Path::new("compress"), Path::new(LAS_PATH), &laz_path]);
+        assert!(output.status.success(), "{:?}", output);
+
+        // Rewrite the points as a non-seekable writer would
+        let mut file = File::open(&laz_path).unwrap();
+        let header = QuickHeader::read_from(&mut file).unwrap();
+        let vlr = read_vlrs_and_get_laszip_vlr(&mut file, &header).unwrap();
+        let mut bytes = std::fs::read(&laz_path).unwrap();
+        bytes.truncate(header.offset_to_points as usize);
+        let points = read_points(Path::new(LAS_PATH));
+        let mut compressor =
+            LasZipStreamCompressor::with_position(bytes, vlr, u64::from(header.offset_to_points))
+                .unwrap();
+        compressor.compress_many(&points).unwrap();
+        compressor.done().unwrap();
+        let bytes = compressor.into_inner();
+        let offset_pos = header.offset_to_points as usize;
+        assert_eq!(bytes[offset_pos..offset_pos + 8], (-1i64).to_le_bytes());
+        std::fs::write(&stream_laz_path, bytes).unwrap();
+
+        for parallel in [None, Some(Path::new("--parallel"))] {
+            let mut args = vec![Path::new("verify"), &stream_laz_path];
+            args.extend(parallel);
+            let output = laz(&args);
+            assert!(output.status.success(), "{:?}", output);
+        }
+    }
+
+    #[test]
+    fn test_verify_detects_wrong_point_count() {
+        let laz_path = tmp_path("cli-wrong-point-count.laz");
+
+        let output = laz(&[Path::new("compress"), Path::new(LAS_PATH), &laz_path]);
+        assert!(output.status.success(), "{:?}", output);
+
+        // Legacy number of points (at byte 107 of the header), more than one chunk
+        let mut bytes = std::fs::read(&laz_path).unwrap();
+        bytes[107..111].copy_from_slice(&60_000u32.to_le_bytes());
+        std::fs::write(&laz_path, bytes).unwrap();
+
+        let output = laz(&[Path::new("verify"), &laz_path]);
+        assert!(!output.status.success(), "{:?}", output);
+    }
+}
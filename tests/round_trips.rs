@@ -0,0 +1,88 @@
+//! Round trips of the test files through the different readers & decompressors
+
+use std::fs::File;
+use std::io::Cursor;
+use std::sync::Arc;
+
+use laz::laszip::ChunkTable;
+use laz::test_support::{compress, read_test_points, test_points_and_vlr, Pipe};
+use laz::{LasZipDecompressor, LasZipStreamDecompressor, ReadAt, SharedLazReader};
+
+#[test]
+fn test_decompress_without_readable_chunk_table() {
+    let (points, vlr) = test_points_and_vlr(100);
+
+    let mut data = compress(&points, &vlr);
+    // Remove the chunk table, as if the writer could not write it
+    let chunk_table = ChunkTable::read_from(Cursor::new(data.as_slice()), &vlr).unwrap();
+    data.truncate(ChunkTable::OFFSET_SIZE + chunk_table.byte_count() as usize);
+    data[..8].copy_from_slice(&(-1i64).to_le_bytes());
+
+    let mut decompressor = LasZipDecompressor::new(Cursor::new(data.as_slice()), vlr).unwrap();
+    let mut decompressed = vec![0u8; points.len()];
+    decompressor.decompress_many(&mut decompressed).unwrap();
+    assert_eq!(decompressed, points);
+}
+
+#[test]
+fn test_stream_decompress_point_wise() {
+    let (points, vlr) = test_points_and_vlr(100);
+    let compressed = compress(&points, &vlr);
+
+    let mut decompressor =
+        LasZipStreamDecompressor::new(Pipe(Cursor::new(compressed)), vlr).unwrap();
+    let mut decompressed = vec![0u8; points.len()];
+    decompressor.decompress_many(&mut decompressed).unwrap();
+    assert_eq!(decompressed, points);
+}
+
+fn check_reader<S: ReadAt + Sync>(reader: SharedLazReader<S>, expected: &[u8]) {
+    let point_size = reader.vlr().items_size() as usize;
+    assert_eq!(reader.point_count() as usize * point_size, expected.len());
+
+    // All chunks are decompressed at the same time, sharing the reader
+    let chunks = std::thread::scope(|scope| {
+        let handles = (0..reader.num_chunks())
+            .map(|index| {
+                let reader = &reader;
+                scope.spawn(move || reader.decompress_chunk(index).unwrap())
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(chunks.concat(), expected);
+
+    let start = 99;
+    let points = reader.read_points(start, 3).unwrap();
+    assert_eq!(
+        points,
+        expected[start as usize * point_size..(start as usize + 3) * point_size]
+    );
+    assert!(reader.read_points(reader.point_count(), 1).is_err());
+    assert!(reader.decompress_chunk(reader.num_chunks()).is_err());
+}
+
+#[test]
+fn test_shared_reader() {
+    let (expected, _) = read_test_points();
+
+    let data = std::fs::read("tests/data/point-time-color.laz").unwrap();
+    check_reader(SharedLazReader::new(data.as_slice()).unwrap(), &expected);
+    check_reader(SharedLazReader::new(Arc::new(data)).unwrap(), &expected);
+
+    let file = File::open("tests/data/point-time-color.laz").unwrap();
+    check_reader(SharedLazReader::new(file).unwrap(), &expected);
+
+    // Point data only, with many chunks
+    let (_, vlr) = test_points_and_vlr(100);
+    let data = compress(&expected, &vlr);
+    let point_count = (expected.len() / vlr.items_size() as usize) as u64;
+    let reader = SharedLazReader::from_point_data(data, vlr, 0, point_count).unwrap();
+    assert_eq!(reader.num_chunks(), 11);
+    assert_eq!(reader.chunk_of_point(100), Some(1));
+    assert_eq!(reader.chunk_of_point(point_count), None);
+    check_reader(reader, &expected);
+}
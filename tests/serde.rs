@@ -0,0 +1,100 @@
+//! Round trips of the LAZ vlr & chunk table through serde
+#![cfg(feature = "serde")]
+
+use std::io::{Cursor, Seek, SeekFrom};
+
+use laz::laszip::ChunkTable;
+use laz::test_support::{compress_in_chunks, read_test_points};
+use laz::{
+    DecompressionSelection, ExtraBytesSelection, LasZipDecompressor, LazItemRecordBuilder, LazVlr,
+    LazVlrBuilder,
+};
+
+#[test]
+fn test_decompress_with_deserialized_vlr_and_chunk_table() {
+    let (points, items) = read_test_points();
+
+    for vlr in [
+        LazVlrBuilder::new(items.clone())
+            .with_fixed_chunk_size(150)
+            .build(),
+        LazVlrBuilder::new(items).with_variable_chunk_size().build(),
+    ] {
+        let data = compress_in_chunks(&points, &vlr, &[150; 7]);
+        let mut source = Cursor::new(data.as_slice());
+        let mut chunk_table = ChunkTable::read_from(&mut source, &vlr).unwrap();
+        if !vlr.uses_variable_size_chunks() {
+            chunk_table
+                .fix_last_point_count((points.len() / vlr.items_size() as usize) as u64)
+                .unwrap();
+        }
+
+        let vlr_json = serde_json::to_string(&vlr).unwrap();
+        let table_json = serde_json::to_string(&chunk_table).unwrap();
+        let vlr: LazVlr = serde_json::from_str(&vlr_json).unwrap();
+        let chunk_table: ChunkTable = serde_json::from_str(&table_json).unwrap();
+        assert_eq!(chunk_table.len(), 8);
+        assert_eq!(
+            chunk_table.point_count(),
+            (points.len() / vlr.items_size() as usize) as u64
+        );
+
+        source.seek(SeekFrom::Start(0)).unwrap();
+        let mut decompressor =
+            LasZipDecompressor::with_chunk_table(source, vlr.clone(), chunk_table.clone()).unwrap();
+        let point_size = vlr.items_size() as usize;
+        decompressor.seek(1000).unwrap();
+        let mut decompressed = vec![0u8; 65 * point_size];
+        decompressor.decompress_many(&mut decompressed).unwrap();
+        assert_eq!(decompressed, points[1000 * point_size..]);
+
+        #[cfg(feature = "parallel")]
+        {
+            let mut decompressor = laz::ParLasZipDecompressor::with_chunk_table(
+                Cursor::new(data.as_slice()),
+                vlr.clone(),
+                chunk_table,
+            )
+            .unwrap();
+            let mut decompressed = vec![0u8; points.len()];
+            decompressor.decompress_many(&mut decompressed).unwrap();
+            assert_eq!(decompressed, points);
+        }
+    }
+
+    let selection = DecompressionSelection::all();
+    let json = serde_json::to_string(&selection).unwrap();
+    assert_eq!(json, u32::MAX.to_string());
+    assert_eq!(
+        serde_json::from_str::<DecompressionSelection>(&json).unwrap(),
+        selection
+    );
+
+    let extra_bytes = ExtraBytesSelection::none()
+        .decompress_byte(2)
+        .decompress_byte(130);
+    let json = serde_json::to_string(&extra_bytes).unwrap();
+    assert_eq!(
+        serde_json::from_str::<ExtraBytesSelection>(&json).unwrap(),
+        extra_bytes
+    );
+}
+
+#[test]
+fn test_deserialize_invalid_vlr() {
+    let items = LazItemRecordBuilder::default_for_point_format_id(3, 0).unwrap();
+    let vlr = LazVlrBuilder::new(items).build();
+    let mut json: serde_json::Value = serde_json::to_value(&vlr).unwrap();
+
+    // A chunk size of 0 means variable-size chunks, as in the LAZ vlr
+    json["chunk_size"] = 0.into();
+    let parsed: LazVlr = serde_json::from_value(json.clone()).unwrap();
+    assert!(parsed.uses_variable_size_chunks());
+
+    // The first item must be a point
+    json["items"].as_array_mut().unwrap().remove(0);
+    assert!(serde_json::from_value::<LazVlr>(json.clone()).is_err());
+
+    json["items"] = serde_json::Value::Array(vec![]);
+    assert!(serde_json::from_value::<LazVlr>(json).is_err());
+}
@@ -154,3 +154,60 @@ fn test_variable_size_chunks_parallel_decompression() {
     decompressor.decompress_many(&mut points_out).unwrap();
     check_chunks(&points_out, &chunks);
 }
+
+/// Test seeking to points of variable size chunks, which do not all
+/// have the same number of points.
+#[test]
+fn test_variable_size_chunks_seek() {
+    let las_file = BufReader::new(File::open("tests/data/point-time-color.las").unwrap());
+    let mut las_reader = SimpleReader::new(las_file).unwrap();
+    let mut las_points_bytes = Vec::<u8>::new();
+    las_reader.read_to_end(&mut las_points_bytes).unwrap();
+    let point_size = las_reader.header.point_size as usize;
+
+    let chunk_sizes = [10usize, 25, 5, 60];
+    let mut chunks = Vec::<&[u8]>::new();
+    let mut start = 0;
+    for chunk_size in chunk_sizes {
+        chunks.push(&las_points_bytes[start..start + chunk_size * point_size]);
+        start += chunk_size * point_size;
+    }
+
+    let laz_vlr = LazVlrBuilder::new(
+        LazItemRecordBuilder::default_for_point_format_id(las_reader.header.point_format_id, 0)
+            .unwrap(),
+    )
+    .with_variable_chunk_size()
+    .build();
+    let mut compressor =
+        LasZipCompressor::new(Cursor::new(Vec::<u8>::new()), laz_vlr.clone()).unwrap();
+    compressor.compress_chunks(&chunks).unwrap();
+    compressor.done().unwrap();
+    let compressed = compressor.into_inner().into_inner();
+
+    let mut decompressor =
+        LasZipDecompressor::new(Cursor::new(compressed.as_slice()), laz_vlr.clone()).unwrap();
+    let mut point = vec![0u8; point_size];
+    for index in [37usize, 3, 99, 40, 10, 0] {
+        decompressor.seek(index as u64).unwrap();
+        decompressor.decompress_one(&mut point).unwrap();
+        assert_eq!(
+            point,
+            &las_points_bytes[index * point_size..(index + 1) * point_size]
+        );
+    }
+
+    #[cfg(feature = "parallel")]
+    {
+        let mut decompressor =
+            laz::ParLasZipDecompressor::new(Cursor::new(compressed.as_slice()), laz_vlr).unwrap();
+        for index in [37usize, 3, 99, 40, 10, 0] {
+            decompressor.seek(index as u64).unwrap();
+            decompressor.decompress_many(&mut point).unwrap();
+            assert_eq!(
+                point,
+                &las_points_bytes[index * point_size..(index + 1) * point_size]
+            );
+        }
+    }
+}